@@ -137,7 +137,7 @@ pub extern fn count_events(midi_ptr: *mut MIDI) -> u32 {
 
 
 #[no_mangle]
-pub extern fn replace_event(midi_ptr: *mut MIDI, event_id: u64, bytes_ptr: *mut u8, byte_length: u8) {
+pub extern fn replace_event(midi_ptr: *mut MIDI, event_id: u64, bytes_ptr: *mut u8, byte_length: u8) -> u8 {
 
     let mut midi = unsafe { mem::ManuallyDrop::new(Box::from_raw(midi_ptr)) };
 
@@ -145,14 +145,20 @@ pub extern fn replace_event(midi_ptr: *mut MIDI, event_id: u64, bytes_ptr: *mut
         Vec::from_raw_parts(bytes_ptr, byte_length as usize, byte_length as usize)
     };
 
-    match MIDIEvent::from_bytes(&mut sub_bytes, 0) {
+    // 1 if the event was replaced, 0 if the bytes are invalid or there's no such event
+    let replaced = match MIDIEvent::from_bytes(&mut sub_bytes, 0) {
         Ok(new_midi_event) => {
-            midi.replace_event(event_id, new_midi_event);
+            match midi.replace_event(event_id, new_midi_event) {
+                Ok(_) => 1,
+                Err(_e) => 0
+            }
         }
-        Err(_) => ()
-    }
+        Err(_e) => 0
+    };
 
     mem::forget(sub_bytes);
+
+    replaced
 }
 
 
@@ -398,8 +404,8 @@ fn get_midi_type_code(midievent: MIDIEvent) -> u8 {
 
         ChannelPrefix(_) => 9,
         SetTempo(_) => 10,
-        SMPTEOffset(_, _, _, _, _) => 11,
-        TimeSignature(_, _, _, _) => 12,
+        SMPTEOffset(_) => 11,
+        TimeSignature(_) => 12,
         KeySignature(_) => 13,
         SequencerSpecific(_) => 14,
 
@@ -496,7 +502,7 @@ fn get_midi_type_code(midievent: MIDIEvent) -> u8 {
         OmniOn(_) => 102,
         MonophonicOperation(_, _) => 103,
         PolyphonicOperation(_) => 104,
-        TimeCode(_, _, _, _, _) => 105
+        TimeCode(_) => 105
     }
 }
 
@@ -520,10 +526,11 @@ fn get_midi_property(midievent: MIDIEvent, property_index: u8) -> Vec<u8> {
         }
 
         ChannelPrefix(channel) => {
-            vec![channel]
+            vec![channel.get()]
         }
 
-        SetTempo(uspqn) => {
+        SetTempo(tempo) => {
+            let uspqn = tempo.get();
             vec![
                 ((uspqn / 256u32.pow(2)) % 256) as u8,
                 ((uspqn / 256u32.pow(1)) % 256) as u8,
@@ -531,25 +538,19 @@ fn get_midi_property(midievent: MIDIEvent, property_index: u8) -> Vec<u8> {
             ]
         }
 
-        SMPTEOffset(hour, minute, second, ff, fr) => {
-            let output = match property_index {
-                0 => { hour }
-                1 => { minute }
-                2 => { second }
-                3 => { ff }
-                4 => { fr }
-                _ => { 0 }
-            };
-
-            vec![output]
+        SMPTEOffset(timecode) => {
+            match timecode.as_bytes().get(property_index as usize) {
+                Some(byte) => { vec![*byte] }
+                None => { vec![0] }
+            }
         }
 
-        TimeSignature(numerator, denominator, cpm, thirtysecondths_per_quarter) => {
+        TimeSignature(meter) => {
             let output = match property_index {
-                0 => { numerator }
-                1 => { denominator }
-                2 => { cpm }
-                3 => { thirtysecondths_per_quarter }
+                0 => { meter.get_numerator() }
+                1 => { meter.get_denominator_exponent() }
+                2 => { meter.get_clocks_per_click() }
+                3 => { meter.get_thirty_seconds_per_quarter() }
                 _ => { 0 }
             };
 
@@ -561,9 +562,9 @@ fn get_midi_property(midievent: MIDIEvent, property_index: u8) -> Vec<u8> {
         NoteOff(channel, note, velocity) |
         AfterTouch(channel, note, velocity) => {
             match property_index {
-                0 => { vec![channel] }
-                1 => { vec![note] }
-                2 => { vec![velocity] }
+                0 => { vec![channel.get()] }
+                1 => { vec![note.get()] }
+                2 => { vec![velocity.get()] }
                 _ => { vec![] }
             }
         }
@@ -571,34 +572,34 @@ fn get_midi_property(midievent: MIDIEvent, property_index: u8) -> Vec<u8> {
 
         ControlChange(channel, controller, value) => {
             match property_index {
-                0 => { vec![channel] }
-                1 => { vec![controller] }
-                2 => { vec![value] }
+                0 => { vec![channel.get()] }
+                1 => { vec![controller.get()] }
+                2 => { vec![value.get()] }
                 _ => { vec![] }
             }
         }
 
         ProgramChange(channel, program) => {
             match property_index {
-                0 => { vec![channel] }
-                1 => { vec![program] }
+                0 => { vec![channel.get()] }
+                1 => { vec![program.get()] }
                 _ => { vec![] }
             }
         }
 
         ChannelPressure(channel, pressure) => {
             match property_index {
-                0 => { vec![channel] }
-                1 => { vec![pressure] }
+                0 => { vec![channel.get()] }
+                1 => { vec![pressure.get()] }
                 _ => { vec![] }
             }
         }
 
         PitchWheelChange(channel, value) => {
             match property_index {
-                0 => { vec![ channel ] }
+                0 => { vec![ channel.get() ] }
                 1 => {
-                    let unsigned_value = get_pitchwheel_value(value);
+                    let unsigned_value = value.get();
                     vec![
                         (unsigned_value / 256) as u8,
                         (unsigned_value % 256) as u8
@@ -623,14 +624,14 @@ fn get_midi_property(midievent: MIDIEvent, property_index: u8) -> Vec<u8> {
 
         SongPositionPointer(beat) => {
             vec![
-                (beat / 256) as u8,
-                (beat % 256) as u8
+                (beat.get() / 256) as u8,
+                (beat.get() % 256) as u8
             ]
         }
 
         SongSelect(song) => {
             vec![
-                song & 0x7F
+                song.get()
             ]
         }
 
@@ -695,11 +696,18 @@ fn get_midi_property(midievent: MIDIEvent, property_index: u8) -> Vec<u8> {
         NonRegisteredParameterNumber(channel, value) |
         RegisteredParameterNumberLSB(channel, value) |
         NonRegisteredParameterNumberLSB(channel, value) |
-        LocalControl(channel, value) |
         MonophonicOperation(channel, value) => {
             match property_index {
-                0 => { vec![channel] }
-                1 => { vec![value] }
+                0 => { vec![channel.get()] }
+                1 => { vec![value.get()] }
+                _ => { vec![] }
+            }
+        }
+
+        LocalControl(channel, on) => {
+            match property_index {
+                0 => { vec![channel.get()] }
+                1 => { vec![if on { 0x7F } else { 0x00 }] }
                 _ => { vec![] }
             }
         }
@@ -712,39 +720,25 @@ fn get_midi_property(midievent: MIDIEvent, property_index: u8) -> Vec<u8> {
         OmniOff(channel) |
         OmniOn(channel) |
         PolyphonicOperation(channel) => {
-            vec![channel]
+            vec![channel.get()]
         }
 
-        TimeCode(rate, hour, minute, second, frame) => {
+        TimeCode(timecode) => {
             match property_index {
-                0 => {
-                    let coded = if rate == 24.0 {
-                        0
-                    } else if rate == 25.0 {
-                        1
-                    } else if rate == 29.97 {
-                        2
-                    } else if rate == 30.0 {
-                        3
-                    } else {
-                        3
-                    };
-
-                    vec![coded]
-                }
-                1 => { vec![hour] }
-                2 => { vec![minute] }
-                3 => { vec![second] }
-                4 => { vec![frame] }
+                0 => { vec![timecode.rate.get_bits()] }
+                1 => { vec![timecode.hours] }
+                2 => { vec![timecode.minutes] }
+                3 => { vec![timecode.seconds] }
+                4 => { vec![timecode.frames] }
                 _ => { vec![] }
             }
         }
 
         KeySignature(key) => {
-            let (mi, sf) = get_mi_sf(&key);
+            let (sf, mi) = key.get_sf_mi();
             match property_index {
                 0 => { vec![mi] }
-                1 => { vec![sf] }
+                1 => { vec![sf as u8] }
                 _ => { vec![] }
             }
         }
@@ -779,11 +773,11 @@ fn get_midi_property_count(midievent: MIDIEvent) -> u8 {
             1
         }
 
-        SMPTEOffset(_hour, _minute, _second, _ff, _fr) => {
+        SMPTEOffset(_timecode) => {
             5
         }
 
-        TimeSignature(_numerator, _denominator, _cpm, _thirtysecondths_per_quarter) => {
+        TimeSignature(_meter) => {
             4
         }
 
@@ -889,11 +883,14 @@ fn get_midi_property_count(midievent: MIDIEvent) -> u8 {
         NonRegisteredParameterNumber(_channel, _value) |
         RegisteredParameterNumberLSB(_channel, _value) |
         NonRegisteredParameterNumberLSB(_channel, _value) |
-        LocalControl(_channel, _value) |
         MonophonicOperation(_channel, _value) => {
             2
         }
 
+        LocalControl(_channel, _on) => {
+            2
+        }
+
         DataIncrement(_channel) |
         DataDecrement(_channel) |
         AllControllersOff(_channel) |
@@ -905,7 +902,7 @@ fn get_midi_property_count(midievent: MIDIEvent) -> u8 {
             1
         }
 
-        TimeCode(_rate, _hour, _minute, _second, _frame) => {
+        TimeCode(_timecode) => {
             5
         }
 
@@ -467,36 +467,38 @@ class TimeSignature(MIDIEvent):
 
 class KeySignature(MIDIEvent):
     """Pythonic version of the KeySignature event found in MIDI spec."""
+    # sf is the signed number of sharps (positive) or flats (negative), stored as a byte
     misf_map = {
-        "A": (0, 3),
-        "A#": (0, 8 | 2),
-        "Bb": (0, 8 | 2),
-        "B": (0, 5),
+        "Cb": (0, -7 & 0xFF),
+        "Gb": (0, -6 & 0xFF),
+        "Db": (0, -5 & 0xFF),
+        "Ab": (0, -4 & 0xFF),
+        "Eb": (0, -3 & 0xFF),
+        "Bb": (0, -2 & 0xFF),
+        "F": (0, -1 & 0xFF),
         "C": (0, 0),
-        "C#": (0, 7),
-        "Db": (0, 7),
+        "G": (0, 1),
         "D": (0, 2),
-        "D#": (0, 8 | 3),
-        "Eb": (0, 8 | 3),
+        "A": (0, 3),
         "E": (0, 4),
-        "F": (0, 8 | 1),
+        "B": (0, 5),
         "F#": (0, 6),
-        "Gb": (0, 6),
+        "C#": (0, 7),
+        "Abm": (1, -7 & 0xFF),
+        "Ebm": (1, -6 & 0xFF),
+        "Bbm": (1, -5 & 0xFF),
+        "Fm": (1, -4 & 0xFF),
+        "Cm": (1, -3 & 0xFF),
+        "Gm": (1, -2 & 0xFF),
+        "Dm": (1, -1 & 0xFF),
         "Am": (1, 0),
-        "A#m": (1, 7),
-        "Bbm": (1, 7),
+        "Em": (1, 1),
         "Bm": (1, 2),
-        "Cm": (1, 8 | 3),
+        "F#m": (1, 3),
         "C#m": (1, 4),
-        "Dbm": (1, 4),
-        "Dm": (1, 8 | 1),
+        "G#m": (1, 5),
         "D#m": (1, 6),
-        "Ebm": (1, 6),
-        "Em": (1, 1),
-        "Fm": (1, 8 | 4),
-        "F#m": (1, 3),
-        "Gbm": (1, 3),
-        "Gm": (1, 8 | 2)
+        "A#m": (1, 7)
     }
 
     def __bytes__(self):
@@ -519,7 +521,7 @@ class KeySignature(MIDIEvent):
     @classmethod
     def from_properties(cls, *props):
         """Build the MIDIEvent from given list of properties"""
-        return cls.from_mi_sf(props[0], props[1])
+        return cls.from_mi_sf(props[0][0], props[1][0])
 
     def get_key(self):
         return self.key
@@ -831,7 +833,7 @@ class LocalControl(VariableControlChange):
     CONTROL_BYTE = 0x7A
 class MonophonicOperation(VariableControlChange):
     """Pythonic version of the MonophonicOperation event found in MIDI spec."""
-    CONTROL_BYTE = 0xFE
+    CONTROL_BYTE = 0x7E
 
 class DataIncrement(InvariableControlChange):
     """Pythonic version of the DataIncrement event found in MIDI spec."""
@@ -857,7 +859,7 @@ class OmniOn(InvariableControlChange):
     CONTROL_BYTE = 0x7D
 class PolyphonicOperation(InvariableControlChange):
     """Pythonic version of the PolyphonicOperation event found in MIDI spec."""
-    CONTROL_BYTE = 0xFF
+    CONTROL_BYTE = 0x7F
 
 class BankSelect(VariableControlChange):
     """Pythonic version of the BankSelect event found in MIDI spec."""
@@ -1060,7 +1062,7 @@ class PitchWheelChange(MIDIEvent):
     def __bytes__(self):
         unsigned_value = self.get_unsigned_value()
         least = unsigned_value & 0x007F
-        most = (unsigned_value >> 8) & 0x007F
+        most = (unsigned_value >> 7) & 0x007F
         return bytes([(0xE0 | self.channel), least, most])
 
     def __init__(self, value, **kwargs):
@@ -1075,7 +1077,7 @@ class PitchWheelChange(MIDIEvent):
 
         prop = props[1]
         unsigned_value = (prop[0] * 256) + prop[1]
-        value = ((unsigned_value * 2) / 0x3FFF) - 1
+        value = cls.get_normalized_value(unsigned_value)
 
         return cls(
             value,
@@ -1096,10 +1098,21 @@ class PitchWheelChange(MIDIEvent):
 
     def get_unsigned_value(self):
         """ get value as integer in range (0, 0x3FFF) """
-        if self.value == 0:
-            output = 0x2000
+        value = min(1, max(-1, self.value))
+        if value < 0:
+            offset = round(value * 0x2000)
+        else:
+            offset = round(value * 0x1FFF)
+        return offset + 0x2000
+
+    @staticmethod
+    def get_normalized_value(unsigned_value):
+        """ get integer in range (0, 0x3FFF) as float in range [-1, 1] """
+        offset = unsigned_value - 0x2000
+        if offset < 0:
+            output = offset / 0x2000
         else:
-            output = int(((self.value + 1) * 0x3FFF) // 2)
+            output = offset / 0x1FFF
         return output
 
 class SystemExclusive(MIDIEvent):
@@ -1246,11 +1259,14 @@ class TimeCode(MIDIEvent):
         )
 
     def __bytes__(self):
+        # MTC full frame, sent as a universal real time system exclusive message
         return bytes([
-            (self.rate << 5) + self.hour,
+            0xF0, 0x7F, 0x7F, 0x01, 0x01,
+            ((self.rate & 0x03) << 5) | (self.hour & 0x1F),
             self.minute & 0x3F,
             self.second & 0x3F,
-            self.frame & 0x1F
+            self.frame & 0x1F,
+            0xF7
         ])
 
 class MIDI:
@@ -1374,7 +1390,7 @@ class MIDIFactory:
         uint8_t get_event_type(MIDI, uint64_t);
         uint64_t create_event(MIDI, uint8_t, uint64_t, const uint8_t*, uint8_t);
 
-        void replace_event(MIDI, uint64_t, const uint8_t*, uint8_t);
+        uint8_t replace_event(MIDI, uint64_t, const uint8_t*, uint8_t);
         void set_event_position(MIDI, uint64_t, uint8_t, uint64_t);
 
         uint64_t get_event_tick(MIDI, uint64_t);
@@ -1824,8 +1840,8 @@ class MIDIController:
             lsb = self.get_next_byte()
             msb = self.get_next_byte()
 
-            unsigned = (msb << 8) + (lsb & 0x7F)
-            value = ((0x3FFF * unsigned) - 2) / 2
+            unsigned = (msb << 7) + (lsb & 0x7F)
+            value = PitchWheelChange.get_normalized_value(unsigned)
 
             output = PitchWheelChange(channel=channel, value=value)
 
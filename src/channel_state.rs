@@ -0,0 +1,298 @@
+use std::collections::HashMap;
+
+use crate::{MIDI, MIDIEvent, MIDIBytes, Channel, U7, PitchBend, build_control_change};
+
+// Parameter number 0x7F/0x7F (RPN Null) means no parameter is selected
+const NULL_PARAMETER: U7 = U7::new_masked(0x7F);
+
+/// Snapshot of everything a device needs to know about a channel at a given point in a song.
+///
+/// Used to 'chase' a song when starting playback (or slicing) somewhere other than the beginning.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChannelState {
+    pub channel: Channel,
+    pub program: Option<U7>,
+    pub bank_select: Option<U7>,
+    pub bank_select_lsb: Option<U7>,
+    /// Latest value of each controller, keyed by controller number.
+    /// Bank select, parameter numbers, data entry and channel mode messages are tracked separately.
+    pub controllers: HashMap<U7, U7>,
+    pub pitch_bend: PitchBend,
+    pub channel_pressure: Option<U7>,
+    /// Registered parameter values, keyed by (MSB, LSB) parameter number, stored as (MSB, LSB) values
+    pub registered_parameters: HashMap<(U7, U7), (U7, Option<U7>)>,
+    /// Non-registered parameter values, keyed by (MSB, LSB) parameter number, stored as (MSB, LSB) values
+    pub non_registered_parameters: HashMap<(U7, U7), (U7, Option<U7>)>,
+    /// Currently selected parameter number as (is_registered, MSB, LSB). 0x7F/0x7F is the null parameter.
+    pub selected_parameter: (bool, U7, U7),
+    /// Sounding notes and their velocities
+    pub active_notes: HashMap<U7, U7>,
+    /// Polyphonic aftertouch of sounding notes
+    pub note_pressure: HashMap<U7, U7>
+}
+
+impl ChannelState {
+    pub fn new(channel: Channel) -> ChannelState {
+        ChannelState {
+            channel,
+            program: None,
+            bank_select: None,
+            bank_select_lsb: None,
+            controllers: HashMap::new(),
+            pitch_bend: PitchBend::CENTER,
+            channel_pressure: None,
+            registered_parameters: HashMap::new(),
+            non_registered_parameters: HashMap::new(),
+            selected_parameter: (true, NULL_PARAMETER, NULL_PARAMETER),
+            active_notes: HashMap::new(),
+            note_pressure: HashMap::new()
+        }
+    }
+
+    /// Update the state with an event. Events on other channels and non-channel events are ignored.
+    pub fn apply(&mut self, event: &MIDIEvent) {
+        let bytes = match event {
+            MIDIEvent::PitchWheelChange(channel, value) => {
+                if *channel == self.channel {
+                    self.pitch_bend = *value;
+                }
+                return;
+            }
+            _ => {
+                event.as_bytes()
+            }
+        };
+
+        if bytes.len() < 2 || bytes[0] < 0x80 || bytes[0] >= 0xF0 || Channel::from_status(bytes[0]) != self.channel {
+            return;
+        }
+
+        let data_a = U7::new_masked(bytes[1]);
+        let data_b = U7::new_masked(*bytes.get(2).unwrap_or(&0));
+        match bytes[0] & 0xF0 {
+            0x80 => {
+                self.active_notes.remove(&data_a);
+                self.note_pressure.remove(&data_a);
+            }
+            0x90 => {
+                if data_b.get() == 0 {
+                    self.active_notes.remove(&data_a);
+                    self.note_pressure.remove(&data_a);
+                } else {
+                    self.active_notes.insert(data_a, data_b);
+                }
+            }
+            0xA0 if self.active_notes.contains_key(&data_a) => {
+                self.note_pressure.insert(data_a, data_b);
+            }
+            0xB0 => {
+                self.apply_controller(data_a, data_b);
+            }
+            0xC0 => {
+                self.program = Some(data_a);
+            }
+            0xD0 => {
+                self.channel_pressure = Some(data_a);
+            }
+            _ => ()
+        }
+    }
+
+    fn apply_controller(&mut self, controller: U7, value: U7) {
+        match controller.get() {
+            0x00 => {
+                self.bank_select = Some(value);
+            }
+            0x20 => {
+                self.bank_select_lsb = Some(value);
+            }
+            0x06 => {
+                if let Some(entry) = self.get_selected_parameter_mut() {
+                    *entry = (value, None);
+                }
+            }
+            0x26 => {
+                if let Some(entry) = self.get_selected_parameter_mut() {
+                    entry.1 = Some(value);
+                }
+            }
+            0x60 | 0x61 => {
+                if let Some(entry) = self.get_selected_parameter_mut() {
+                    let current = ((entry.0.get() as i32) << 7) + (entry.1.unwrap_or_default().get() as i32);
+                    let adjusted = if controller.get() == 0x60 {
+                        std::cmp::min(current + 1, 0x3FFF)
+                    } else {
+                        std::cmp::max(current - 1, 0)
+                    };
+                    *entry = (U7::new_masked((adjusted >> 7) as u8), Some(U7::new_masked(adjusted as u8)));
+                }
+            }
+            0x62 => {
+                self.selected_parameter = (false, self.get_selected_msb(false), value);
+            }
+            0x63 => {
+                self.selected_parameter = (false, value, self.get_selected_lsb(false));
+            }
+            0x64 => {
+                self.selected_parameter = (true, self.get_selected_msb(true), value);
+            }
+            0x65 => {
+                self.selected_parameter = (true, value, self.get_selected_lsb(true));
+            }
+            0x78 | 0x7B..=0x7F => {
+                self.active_notes.clear();
+                self.note_pressure.clear();
+            }
+            0x79 => {
+                self.controllers.clear();
+                self.pitch_bend = PitchBend::CENTER;
+                self.channel_pressure = None;
+                self.note_pressure.clear();
+                self.selected_parameter = (true, NULL_PARAMETER, NULL_PARAMETER);
+            }
+            0x7A => (),
+            _ => {
+                self.controllers.insert(controller, value);
+            }
+        }
+    }
+
+    fn get_selected_msb(&self, registered: bool) -> U7 {
+        let (is_registered, msb, _) = self.selected_parameter;
+        if is_registered == registered {
+            msb
+        } else {
+            NULL_PARAMETER
+        }
+    }
+
+    fn get_selected_lsb(&self, registered: bool) -> U7 {
+        let (is_registered, _, lsb) = self.selected_parameter;
+        if is_registered == registered {
+            lsb
+        } else {
+            NULL_PARAMETER
+        }
+    }
+
+    fn get_selected_parameter_mut(&mut self) -> Option<&mut (U7, Option<U7>)> {
+        let (registered, msb, lsb) = self.selected_parameter;
+        if msb == NULL_PARAMETER && lsb == NULL_PARAMETER {
+            None
+        } else if registered {
+            Some(self.registered_parameters.entry((msb, lsb)).or_insert((U7::default(), None)))
+        } else {
+            Some(self.non_registered_parameters.entry((msb, lsb)).or_insert((U7::default(), None)))
+        }
+    }
+
+    /// Get the minimal sequence of events needed to put a device's channel into this state.
+    /// Assumes the device's channel has been reset beforehand.
+    pub fn to_events(&self) -> Vec<MIDIEvent> {
+        let channel = self.channel;
+        let mut output = Vec::new();
+
+        if let Some(value) = self.bank_select {
+            output.push(MIDIEvent::BankSelect(channel, value));
+        }
+        if let Some(value) = self.bank_select_lsb {
+            output.push(MIDIEvent::BankSelectLSB(channel, value));
+        }
+        if let Some(program) = self.program {
+            output.push(MIDIEvent::ProgramChange(channel, program));
+        }
+
+        let mut controllers: Vec<(&U7, &U7)> = self.controllers.iter().collect();
+        controllers.sort();
+        for (controller, value) in controllers.iter() {
+            output.push(build_control_change(channel, **controller, **value));
+        }
+
+        let mut registered: Vec<_> = self.registered_parameters.iter().collect();
+        registered.sort();
+        for ((param_msb, param_lsb), (value_msb, value_lsb)) in registered.iter() {
+            output.push(MIDIEvent::RegisteredParameterNumber(channel, *param_msb));
+            output.push(MIDIEvent::RegisteredParameterNumberLSB(channel, *param_lsb));
+            output.push(MIDIEvent::DataEntry(channel, *value_msb));
+            if let Some(lsb) = value_lsb {
+                output.push(MIDIEvent::DataEntryLSB(channel, *lsb));
+            }
+        }
+
+        let mut non_registered: Vec<_> = self.non_registered_parameters.iter().collect();
+        non_registered.sort();
+        for ((param_msb, param_lsb), (value_msb, value_lsb)) in non_registered.iter() {
+            output.push(MIDIEvent::NonRegisteredParameterNumber(channel, *param_msb));
+            output.push(MIDIEvent::NonRegisteredParameterNumberLSB(channel, *param_lsb));
+            output.push(MIDIEvent::DataEntry(channel, *value_msb));
+            if let Some(lsb) = value_lsb {
+                output.push(MIDIEvent::DataEntryLSB(channel, *lsb));
+            }
+        }
+
+        // Leave the same parameter selected as in the original stream
+        let params_written = !registered.is_empty() || !non_registered.is_empty();
+        let (is_registered, msb, lsb) = self.selected_parameter;
+        if params_written || msb != NULL_PARAMETER || lsb != NULL_PARAMETER {
+            if is_registered {
+                output.push(MIDIEvent::RegisteredParameterNumber(channel, msb));
+                output.push(MIDIEvent::RegisteredParameterNumberLSB(channel, lsb));
+            } else {
+                output.push(MIDIEvent::NonRegisteredParameterNumber(channel, msb));
+                output.push(MIDIEvent::NonRegisteredParameterNumberLSB(channel, lsb));
+            }
+        }
+
+        if self.pitch_bend != PitchBend::CENTER {
+            output.push(MIDIEvent::PitchWheelChange(channel, self.pitch_bend));
+        }
+        if let Some(pressure) = self.channel_pressure {
+            output.push(MIDIEvent::ChannelPressure(channel, pressure));
+        }
+
+        let mut notes: Vec<(&U7, &U7)> = self.active_notes.iter().collect();
+        notes.sort();
+        for (note, velocity) in notes.iter() {
+            output.push(MIDIEvent::NoteOn(channel, **note, **velocity));
+            if let Some(pressure) = self.note_pressure.get(note) {
+                output.push(MIDIEvent::AfterTouch(channel, **note, *pressure));
+            }
+        }
+
+        output
+    }
+}
+
+impl MIDI {
+    /// Get the state of every channel (0-15) immediately before any events at `tick` are played
+    pub fn get_channel_states(&self, tick: usize) -> Vec<ChannelState> {
+        let mut states: Vec<ChannelState> = Channel::all().map(ChannelState::new).collect();
+        for (event_tick, _track, event_id) in self.get_ordered_event_ids().iter() {
+            if *event_tick >= tick {
+                break;
+            }
+            if let Some(event) = self.events.get(event_id) {
+                for state in states.iter_mut() {
+                    state.apply(event);
+                }
+            }
+        }
+
+        states
+    }
+
+    /// Get the state of a single channel immediately before any events at `tick` are played
+    pub fn get_channel_state(&self, channel: Channel, tick: usize) -> ChannelState {
+        let mut state = ChannelState::new(channel);
+        for (event_tick, _track, event_id) in self.get_ordered_event_ids().iter() {
+            if *event_tick >= tick {
+                break;
+            }
+            if let Some(event) = self.events.get(event_id) {
+                state.apply(event);
+            }
+        }
+
+        state
+    }
+}
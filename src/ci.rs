@@ -0,0 +1,568 @@
+use std::convert::TryFrom;
+
+use crate::{MIDIEvent, ApresError, U7, U14, ManufacturerId};
+
+pub const CI_SUB_ID: u8 = 0x0D;
+/// MIDI-CI message version written in new messages
+pub const CI_VERSION: u8 = 0x02;
+/// MUID that addresses every device
+pub const BROADCAST_MUID: u32 = 0x0FFF_FFFF;
+/// Device ID that addresses the whole function block. 0x7E addresses the group, 0x00-0x0F a channel.
+pub const FUNCTION_BLOCK: U7 = U7::new_masked(0x7F);
+
+/// Capability category bits, sent in discovery
+pub const PROFILE_CONFIGURATION: u8 = 0x04;
+pub const PROPERTY_EXCHANGE: u8 = 0x08;
+pub const PROCESS_INQUIRY: u8 = 0x10;
+
+const NON_REAL_TIME: u8 = 0x7E;
+
+/// Who a device is, as given in discovery
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CIDeviceIdentity {
+    pub manufacturer: ManufacturerId,
+    pub family: U14,
+    pub model: U14,
+    /// Software revision, as 4 bytes
+    pub version: [u8; 4]
+}
+
+/// 5 byte profile ID. Standard profiles start with 0x7E.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ProfileId(pub [u8; 5]);
+
+/// One chunk of a property exchange message. The header (JSON) is only sent in the first chunk.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PropertyChunk {
+    pub request_id: u8,
+    pub header: Vec<u8>,
+    pub chunk_count: u16,
+    /// Counted from 1
+    pub chunk_number: u16,
+    pub data: Vec<u8>
+}
+
+/// MIDI-CI message body, with its sub-ID#2
+#[derive(Clone, Debug, PartialEq)]
+pub enum CIMessageKind {
+    /// Sent to the broadcast MUID to find devices
+    Discovery { identity: CIDeviceIdentity, categories: u8, max_sysex_size: u32, output_path: u8 },
+    /// `function_block` is 0x7F when the device isn't in a function block
+    DiscoveryReply { identity: CIDeviceIdentity, categories: u8, max_sysex_size: u32, output_path: u8, function_block: u8 },
+    /// The device using `target` should stop using it
+    InvalidateMUID { target: u32 },
+    ProfileInquiry,
+    ProfileInquiryReply { enabled: Vec<ProfileId>, disabled: Vec<ProfileId> },
+    /// `channels` is how many channels the profile should use, or 0 for the addressed channel only
+    SetProfileOn { profile: ProfileId, channels: U14 },
+    SetProfileOff { profile: ProfileId },
+    ProfileEnabled { profile: ProfileId, channels: U14 },
+    ProfileDisabled { profile: ProfileId, channels: U14 },
+    PropertyExchangeCapabilities { simultaneous_requests: u8, major_version: u8, minor_version: u8 },
+    PropertyExchangeCapabilitiesReply { simultaneous_requests: u8, major_version: u8, minor_version: u8 },
+    GetPropertyData(PropertyChunk),
+    GetPropertyDataReply(PropertyChunk),
+    SetPropertyData(PropertyChunk),
+    SetPropertyDataReply(PropertyChunk)
+}
+
+/// MIDI Capability Inquiry message, sent as a universal non real time system exclusive message.
+/// MUIDs are 28-bit.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CIMessage {
+    pub device_id: U7,
+    /// MIDI-CI message version
+    pub version: u8,
+    pub source: u32,
+    pub destination: u32,
+    pub kind: CIMessageKind
+}
+
+/// Answers MIDI-CI discovery and profile configuration on behalf of a device
+#[derive(Clone, Debug, PartialEq)]
+pub struct CIResponder {
+    pub muid: u32,
+    pub identity: CIDeviceIdentity,
+    pub categories: u8,
+    pub max_sysex_size: u32,
+    pub function_block: u8,
+    /// Profiles the device supports, and whether each is enabled
+    pub profiles: Vec<(ProfileId, bool)>
+}
+
+// 28-bit values are sent as 4 7-bit bytes, least significant first
+fn get_u28_bytes(value: u32) -> Result<[u8; 4], ApresError> {
+    if value > BROADCAST_MUID {
+        return Err(ApresError::ValueOutOfRange(value, BROADCAST_MUID));
+    }
+
+    Ok([
+        (value & 0x7F) as u8,
+        ((value >> 7) & 0x7F) as u8,
+        ((value >> 14) & 0x7F) as u8,
+        ((value >> 21) & 0x7F) as u8
+    ])
+}
+
+fn get_u28(bytes: &[u8]) -> u32 {
+    bytes.iter()
+        .take(4)
+        .enumerate()
+        .fold(0, |value, (i, byte)| value | ((*byte as u32) << (i * 7)))
+}
+
+// Counts and lengths are 14-bit, which caps headers and chunks at 16383 bytes
+fn get_u14_bytes(value: usize) -> Result<[u8; 2], ApresError> {
+    if value > 0x3FFF {
+        return Err(ApresError::ValueOutOfRange(value as u32, 0x3FFF));
+    }
+
+    Ok([(value & 0x7F) as u8, ((value >> 7) & 0x7F) as u8])
+}
+
+fn get_u14(bytes: &[u8]) -> u16 {
+    (bytes[0] as u16) | ((bytes[1] as u16) << 7)
+}
+
+// Reads fields in order, failing once the data runs out
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Reader<'a> {
+        Reader { bytes, position: 0 }
+    }
+
+    fn take(&mut self, count: usize) -> Option<&'a [u8]> {
+        let output = self.bytes.get(self.position..self.position + count)?;
+        self.position += count;
+        Some(output)
+    }
+
+    fn byte(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+
+    // Fields added in later versions of MIDI-CI are zero when missing
+    fn byte_or_zero(&mut self) -> u8 {
+        self.byte().unwrap_or(0)
+    }
+
+    fn u14(&mut self) -> Option<u16> {
+        Some(get_u14(self.take(2)?))
+    }
+
+    fn u28(&mut self) -> Option<u32> {
+        Some(get_u28(self.take(4)?))
+    }
+
+    fn profile(&mut self) -> Option<ProfileId> {
+        let bytes = self.take(5)?;
+        Some(ProfileId([bytes[0], bytes[1], bytes[2], bytes[3], bytes[4]]))
+    }
+
+    fn profiles(&mut self) -> Option<Vec<ProfileId>> {
+        let count = self.u14()?;
+        (0..count).map(|_| self.profile()).collect()
+    }
+
+    fn identity(&mut self) -> Option<CIDeviceIdentity> {
+        let manufacturer = match self.take(3)? {
+            [0x00, a, b] => ManufacturerId::Extended(*a, *b),
+            [id, _, _] => ManufacturerId::from_bytes(&[*id]).ok()?.0,
+            _ => {
+                return None;
+            }
+        };
+        let family = U14::new(self.u14()?).ok()?;
+        let model = U14::new(self.u14()?).ok()?;
+        let version = self.take(4)?;
+
+        Some(CIDeviceIdentity { manufacturer, family, model, version: [version[0], version[1], version[2], version[3]] })
+    }
+
+    fn property_chunk(&mut self) -> Option<PropertyChunk> {
+        let request_id = self.byte()?;
+        let header_length = self.u14()? as usize;
+        let header = self.take(header_length)?.to_vec();
+        let chunk_count = self.u14()?;
+        let chunk_number = self.u14()?;
+        let data_length = self.u14()? as usize;
+        let data = self.take(data_length)?.to_vec();
+
+        Some(PropertyChunk { request_id, header, chunk_count, chunk_number, data })
+    }
+}
+
+impl CIDeviceIdentity {
+    fn as_bytes(&self) -> Result<Vec<u8>, ApresError> {
+        // The manufacturer ID is always 3 bytes here, with single byte IDs padded with zeros
+        let mut output = match self.manufacturer {
+            ManufacturerId::Extended(a, b) => vec![0x00, a, b],
+            manufacturer => vec![manufacturer.as_bytes()[0], 0x00, 0x00]
+        };
+        output.extend(get_u14_bytes(self.family.get() as usize)?.iter());
+        output.extend(get_u14_bytes(self.model.get() as usize)?.iter());
+        output.extend(self.version.iter());
+        Ok(output)
+    }
+}
+
+impl PropertyChunk {
+    /// Split property data into chunks of at most `max_chunk_size` bytes. There is always at least one chunk.
+    pub fn split(request_id: u8, header: &[u8], data: &[u8], max_chunk_size: usize) -> Vec<PropertyChunk> {
+        let chunks: Vec<&[u8]> = if data.is_empty() {
+            vec![data]
+        } else {
+            data.chunks(max_chunk_size.max(1)).collect()
+        };
+
+        let chunk_count = chunks.len() as u16;
+        chunks.into_iter()
+            .enumerate()
+            .map(|(i, chunk)| {
+                PropertyChunk {
+                    request_id,
+                    header: if i == 0 { header.to_vec() } else { Vec::new() },
+                    chunk_count,
+                    chunk_number: i as u16 + 1,
+                    data: chunk.to_vec()
+                }
+            })
+            .collect()
+    }
+
+    /// Join a request's chunks back into its header and data. The chunks must all be there, in order.
+    pub fn join(chunks: &[PropertyChunk]) -> Result<(Vec<u8>, Vec<u8>), ApresError> {
+        let first = match chunks.first() {
+            Some(first) => first,
+            None => {
+                return Err(ApresError::InvalidBytes(Vec::new()));
+            }
+        };
+
+        let mut data = Vec::new();
+        for (i, chunk) in chunks.iter().enumerate() {
+            let in_order = chunk.request_id == first.request_id
+                && chunk.chunk_count as usize == chunks.len()
+                && chunk.chunk_number as usize == i + 1;
+            if !in_order {
+                return Err(ApresError::InvalidBytes(chunk.data.clone()));
+            }
+            data.extend(chunk.data.iter());
+        }
+
+        Ok((first.header.clone(), data))
+    }
+
+    /// Whether this is the last chunk of its request
+    pub fn is_last(&self) -> bool {
+        self.chunk_number >= self.chunk_count
+    }
+
+    fn as_bytes(&self) -> Result<Vec<u8>, ApresError> {
+        let mut output = vec![self.request_id];
+        output.extend(get_u14_bytes(self.header.len())?.iter());
+        output.extend(self.header.iter());
+        output.extend(get_u14_bytes(self.chunk_count as usize)?.iter());
+        output.extend(get_u14_bytes(self.chunk_number as usize)?.iter());
+        output.extend(get_u14_bytes(self.data.len())?.iter());
+        output.extend(self.data.iter());
+        Ok(output)
+    }
+}
+
+impl CIMessageKind {
+    fn get_sub_id(&self) -> u8 {
+        match self {
+            CIMessageKind::ProfileInquiry => 0x20,
+            CIMessageKind::ProfileInquiryReply { .. } => 0x21,
+            CIMessageKind::SetProfileOn { .. } => 0x22,
+            CIMessageKind::SetProfileOff { .. } => 0x23,
+            CIMessageKind::ProfileEnabled { .. } => 0x24,
+            CIMessageKind::ProfileDisabled { .. } => 0x25,
+            CIMessageKind::PropertyExchangeCapabilities { .. } => 0x30,
+            CIMessageKind::PropertyExchangeCapabilitiesReply { .. } => 0x31,
+            CIMessageKind::GetPropertyData(_) => 0x34,
+            CIMessageKind::GetPropertyDataReply(_) => 0x35,
+            CIMessageKind::SetPropertyData(_) => 0x36,
+            CIMessageKind::SetPropertyDataReply(_) => 0x37,
+            CIMessageKind::Discovery { .. } => 0x70,
+            CIMessageKind::DiscoveryReply { .. } => 0x71,
+            CIMessageKind::InvalidateMUID { .. } => 0x7E
+        }
+    }
+
+    fn from_bytes(sub_id: u8, bytes: &[u8]) -> Option<CIMessageKind> {
+        let mut reader = Reader::new(bytes);
+        let kind = match sub_id {
+            0x20 => CIMessageKind::ProfileInquiry,
+            0x21 => {
+                CIMessageKind::ProfileInquiryReply {
+                    enabled: reader.profiles()?,
+                    disabled: reader.profiles()?
+                }
+            }
+            0x22 | 0x24 | 0x25 => {
+                let profile = reader.profile()?;
+                let channels = U14::new_masked(reader.u14().unwrap_or(0));
+                match sub_id {
+                    0x22 => CIMessageKind::SetProfileOn { profile, channels },
+                    0x24 => CIMessageKind::ProfileEnabled { profile, channels },
+                    _ => CIMessageKind::ProfileDisabled { profile, channels }
+                }
+            }
+            0x23 => CIMessageKind::SetProfileOff { profile: reader.profile()? },
+            0x30 | 0x31 => {
+                let simultaneous_requests = reader.byte()?;
+                let major_version = reader.byte_or_zero();
+                let minor_version = reader.byte_or_zero();
+                if sub_id == 0x30 {
+                    CIMessageKind::PropertyExchangeCapabilities { simultaneous_requests, major_version, minor_version }
+                } else {
+                    CIMessageKind::PropertyExchangeCapabilitiesReply { simultaneous_requests, major_version, minor_version }
+                }
+            }
+            0x34 => CIMessageKind::GetPropertyData(reader.property_chunk()?),
+            0x35 => CIMessageKind::GetPropertyDataReply(reader.property_chunk()?),
+            0x36 => CIMessageKind::SetPropertyData(reader.property_chunk()?),
+            0x37 => CIMessageKind::SetPropertyDataReply(reader.property_chunk()?),
+            0x70 | 0x71 => {
+                let identity = reader.identity()?;
+                let categories = reader.byte()?;
+                let max_sysex_size = reader.u28()?;
+                let output_path = reader.byte_or_zero();
+                if sub_id == 0x70 {
+                    CIMessageKind::Discovery { identity, categories, max_sysex_size, output_path }
+                } else {
+                    let function_block = reader.byte().unwrap_or(0x7F);
+                    CIMessageKind::DiscoveryReply { identity, categories, max_sysex_size, output_path, function_block }
+                }
+            }
+            0x7E => CIMessageKind::InvalidateMUID { target: reader.u28()? },
+            _ => {
+                return None;
+            }
+        };
+
+        Some(kind)
+    }
+
+    // Fails if a length doesn't fit in 14 bits or a byte isn't 7-bit. Property data has to be encoded
+    // (with Mcoded7, for example) before it is sent.
+    fn as_bytes(&self) -> Result<Vec<u8>, ApresError> {
+        let mut output = Vec::new();
+        match self {
+            CIMessageKind::ProfileInquiry => (),
+            CIMessageKind::ProfileInquiryReply { enabled, disabled } => {
+                for profiles in [enabled, disabled].iter() {
+                    output.extend(get_u14_bytes(profiles.len())?.iter());
+                    for profile in profiles.iter() {
+                        output.extend(profile.0.iter());
+                    }
+                }
+            }
+            CIMessageKind::SetProfileOn { profile, channels }
+            | CIMessageKind::ProfileEnabled { profile, channels }
+            | CIMessageKind::ProfileDisabled { profile, channels } => {
+                output.extend(profile.0.iter());
+                output.extend(get_u14_bytes(channels.get() as usize)?.iter());
+            }
+            CIMessageKind::SetProfileOff { profile } => {
+                output.extend(profile.0.iter());
+                output.extend([0x00, 0x00].iter());
+            }
+            CIMessageKind::PropertyExchangeCapabilities { simultaneous_requests, major_version, minor_version }
+            | CIMessageKind::PropertyExchangeCapabilitiesReply { simultaneous_requests, major_version, minor_version } => {
+                output.extend([*simultaneous_requests, *major_version, *minor_version].iter());
+            }
+            CIMessageKind::GetPropertyData(chunk)
+            | CIMessageKind::GetPropertyDataReply(chunk)
+            | CIMessageKind::SetPropertyData(chunk)
+            | CIMessageKind::SetPropertyDataReply(chunk) => {
+                output.extend(chunk.as_bytes()?);
+            }
+            CIMessageKind::Discovery { identity, categories, max_sysex_size, output_path } => {
+                output.extend(identity.as_bytes()?);
+                output.push(*categories);
+                output.extend(get_u28_bytes(*max_sysex_size)?.iter());
+                output.push(*output_path);
+            }
+            CIMessageKind::DiscoveryReply { identity, categories, max_sysex_size, output_path, function_block } => {
+                output.extend(identity.as_bytes()?);
+                output.push(*categories);
+                output.extend(get_u28_bytes(*max_sysex_size)?.iter());
+                output.push(*output_path);
+                output.push(*function_block);
+            }
+            CIMessageKind::InvalidateMUID { target } => {
+                output.extend(get_u28_bytes(*target)?.iter());
+            }
+        }
+
+        if output.iter().any(|byte| *byte > 0x7F) {
+            Err(ApresError::InvalidBytes(output))
+        } else {
+            Ok(output)
+        }
+    }
+}
+
+impl CIMessage {
+    pub fn new(source: u32, destination: u32, kind: CIMessageKind) -> CIMessage {
+        CIMessage { device_id: FUNCTION_BLOCK, version: CI_VERSION, source, destination, kind }
+    }
+
+    /// Property exchange get inquiry. The request is all in the header, so it is sent as a single chunk.
+    pub fn get_property_data(source: u32, destination: u32, request_id: u8, header: &[u8]) -> CIMessage {
+        let chunk = PropertyChunk { request_id, header: header.to_vec(), chunk_count: 1, chunk_number: 1, data: Vec::new() };
+        CIMessage::new(source, destination, CIMessageKind::GetPropertyData(chunk))
+    }
+
+    /// Property exchange set inquiries, with the data split into chunks of at most `max_chunk_size` bytes
+    pub fn set_property_data(source: u32, destination: u32, request_id: u8, header: &[u8], data: &[u8], max_chunk_size: usize) -> Vec<CIMessage> {
+        PropertyChunk::split(request_id, header, data, max_chunk_size)
+            .into_iter()
+            .map(|chunk| CIMessage::new(source, destination, CIMessageKind::SetPropertyData(chunk)))
+            .collect()
+    }
+
+    /// Replies to a property exchange get inquiry, with the data split into chunks of at most `max_chunk_size` bytes
+    pub fn get_property_data_reply(source: u32, destination: u32, request_id: u8, header: &[u8], data: &[u8], max_chunk_size: usize) -> Vec<CIMessage> {
+        PropertyChunk::split(request_id, header, data, max_chunk_size)
+            .into_iter()
+            .map(|chunk| CIMessage::new(source, destination, CIMessageKind::GetPropertyDataReply(chunk)))
+            .collect()
+    }
+
+    /// Parse a system exclusive message's data (not including the 0xF0 and 0xF7 bytes)
+    pub fn from_bytes(bytes: &[u8]) -> Result<CIMessage, ApresError> {
+        if bytes.len() < 13 || bytes[0] != NON_REAL_TIME || bytes[2] != CI_SUB_ID || bytes.iter().any(|byte| *byte > 0x7F) {
+            return Err(ApresError::InvalidBytes(bytes.to_vec()));
+        }
+
+        // Later MIDI-CI versions only add fields at the end of messages
+        match CIMessageKind::from_bytes(bytes[3], &bytes[13..]) {
+            Some(kind) => {
+                Ok(CIMessage {
+                    device_id: U7::new(bytes[1])?,
+                    version: bytes[4],
+                    source: get_u28(&bytes[5..9]),
+                    destination: get_u28(&bytes[9..13]),
+                    kind
+                })
+            }
+            None => Err(ApresError::InvalidBytes(bytes.to_vec()))
+        }
+    }
+
+    /// Get the system exclusive message's data (not including the 0xF0 and 0xF7 bytes).
+    /// Fails if a field can't be sent in 7-bit bytes.
+    pub fn as_bytes(&self) -> Result<Vec<u8>, ApresError> {
+        if self.version > 0x7F {
+            return Err(ApresError::ValueOutOfRange(self.version as u32, 0x7F));
+        }
+
+        let mut output = vec![NON_REAL_TIME, self.device_id.get(), CI_SUB_ID, self.kind.get_sub_id(), self.version];
+        output.extend(get_u28_bytes(self.source)?.iter());
+        output.extend(get_u28_bytes(self.destination)?.iter());
+        output.extend(self.kind.as_bytes()?);
+        Ok(output)
+    }
+
+    pub fn is_broadcast(&self) -> bool {
+        self.destination == BROADCAST_MUID
+    }
+}
+
+impl CIResponder {
+    pub fn new(muid: u32, identity: CIDeviceIdentity) -> CIResponder {
+        CIResponder {
+            muid: muid & BROADCAST_MUID,
+            identity,
+            categories: PROFILE_CONFIGURATION,
+            max_sysex_size: 512,
+            function_block: 0x7F,
+            profiles: Vec::new()
+        }
+    }
+
+    /// Get the replies to a message. Only discovery, and profile messages addressed to this device, are answered.
+    pub fn respond(&mut self, message: &CIMessage) -> Vec<CIMessage> {
+        if message.source == self.muid || !(message.is_broadcast() || message.destination == self.muid) {
+            return Vec::new();
+        }
+
+        let (device_id, muid) = (message.device_id, self.muid);
+        let reply = |destination: u32, kind: CIMessageKind| {
+            CIMessage { device_id, version: CI_VERSION, source: muid, destination, kind }
+        };
+
+        match &message.kind {
+            CIMessageKind::Discovery { output_path, .. } => {
+                vec![reply(message.source, CIMessageKind::DiscoveryReply {
+                    identity: self.identity,
+                    categories: self.categories,
+                    max_sysex_size: self.max_sysex_size,
+                    output_path: *output_path,
+                    function_block: self.function_block
+                })]
+            }
+            CIMessageKind::ProfileInquiry if self.categories & PROFILE_CONFIGURATION != 0 => {
+                let get_profiles = |enabled: bool| {
+                    self.profiles.iter()
+                        .filter(|(_, is_enabled)| *is_enabled == enabled)
+                        .map(|(profile, _)| *profile)
+                        .collect()
+                };
+                vec![reply(message.source, CIMessageKind::ProfileInquiryReply {
+                    enabled: get_profiles(true),
+                    disabled: get_profiles(false)
+                })]
+            }
+            CIMessageKind::SetProfileOn { profile, channels } => {
+                self.set_profile_enabled(profile, true)
+                    .map(|_| reply(BROADCAST_MUID, CIMessageKind::ProfileEnabled { profile: *profile, channels: *channels }))
+                    .into_iter()
+                    .collect()
+            }
+            CIMessageKind::SetProfileOff { profile } => {
+                self.set_profile_enabled(profile, false)
+                    .map(|_| reply(BROADCAST_MUID, CIMessageKind::ProfileDisabled { profile: *profile, channels: U14::new_masked(0) }))
+                    .into_iter()
+                    .collect()
+            }
+            _ => Vec::new()
+        }
+    }
+
+    // None if the profile isn't supported
+    fn set_profile_enabled(&mut self, profile: &ProfileId, enabled: bool) -> Option<()> {
+        let (_, is_enabled) = self.profiles.iter_mut().find(|(supported, _)| supported == profile)?;
+        *is_enabled = enabled;
+        Some(())
+    }
+}
+
+impl TryFrom<CIMessage> for MIDIEvent {
+    type Error = ApresError;
+    fn try_from(message: CIMessage) -> Result<MIDIEvent, ApresError> {
+        Ok(MIDIEvent::SystemExclusive(message.as_bytes()?))
+    }
+}
+
+impl TryFrom<MIDIEvent> for CIMessage {
+    type Error = MIDIEvent;
+    fn try_from(event: MIDIEvent) -> Result<CIMessage, MIDIEvent> {
+        match &event {
+            MIDIEvent::SystemExclusive(data) => {
+                CIMessage::from_bytes(data).map_err(|_| event)
+            }
+            _ => {
+                Err(event)
+            }
+        }
+    }
+}
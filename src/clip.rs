@@ -0,0 +1,208 @@
+use std::fs;
+
+use crate::{MIDI, ApresError, UMP, UMPProtocol, UMPEncoder, UMPDecoder, StreamMessage};
+
+const CLIP_HEADER: &[u8; 8] = b"SMF2CLIP";
+const MAX_DELTA_CLOCKSTAMP: usize = 0xFFFFF;
+
+/// MIDI Clip File: UMP with delta clockstamps, the MIDI 2.0 counterpart to a single SMF track
+#[derive(Clone, Debug, PartialEq)]
+pub struct MIDIClip {
+    pub ticks_per_quarter_note: u16,
+    /// Packets in the clip configuration header, other than the ticks per quarter note
+    pub header: Vec<UMP>,
+    /// Packets in the clip, with the tick they fall on. Kept in order.
+    pub packets: Vec<(usize, UMP)>,
+    /// Tick of the end of the clip
+    pub end: usize
+}
+
+// Delta clockstamps to wait a number of ticks. At least one is always given, since every packet needs one.
+fn get_delta_clockstamps(mut ticks: usize) -> Vec<UMP> {
+    let mut output = Vec::new();
+    while ticks > MAX_DELTA_CLOCKSTAMP {
+        output.push(UMP::DeltaClockstamp(MAX_DELTA_CLOCKSTAMP as u32));
+        ticks -= MAX_DELTA_CLOCKSTAMP;
+    }
+    output.push(UMP::DeltaClockstamp(ticks as u32));
+
+    output
+}
+
+impl MIDIClip {
+    pub fn new(ticks_per_quarter_note: u16) -> MIDIClip {
+        MIDIClip {
+            ticks_per_quarter_note,
+            header: Vec::new(),
+            packets: Vec::new(),
+            end: 0
+        }
+    }
+
+    pub fn from_path(path: &str) -> Result<MIDIClip, ApresError> {
+        match fs::read(path) {
+            Ok(bytes) => MIDIClip::from_bytes(&bytes),
+            Err(_) => Err(ApresError::PathNotFound(path.to_string()))
+        }
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), ApresError> {
+        match fs::write(path, self.as_bytes()) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(ApresError::PathNotFound(path.to_string()))
+        }
+    }
+
+    /// Parse a clip file. Delta clockstamps, no-ops and the start and end of clip markers are consumed;
+    /// anything after the end of clip is ignored.
+    pub fn from_bytes(bytes: &[u8]) -> Result<MIDIClip, ApresError> {
+        if bytes.len() < CLIP_HEADER.len() || &bytes[..CLIP_HEADER.len()] != CLIP_HEADER || bytes.len() % 4 != 0 {
+            return Err(ApresError::InvalidBytes(bytes.to_vec()));
+        }
+
+        let words: Vec<u32> = bytes[CLIP_HEADER.len()..]
+            .chunks(4)
+            .map(|chunk| u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+            .collect();
+
+        let mut ticks_per_quarter_note = None;
+        let mut clip = MIDIClip::new(0);
+        let mut started = false;
+        let mut ended = false;
+        let mut tick = 0;
+        for ump in UMP::parse_words(&words)? {
+            match ump {
+                UMP::NoOp => (),
+                UMP::DeltaClockstamp(ticks) => {
+                    tick += ticks as usize;
+                }
+                UMP::DeltaClockstampTicksPerQuarterNote(ticks) if !started => {
+                    ticks_per_quarter_note = Some(ticks);
+                }
+                UMP::Stream(StreamMessage::StartOfClip) if !started => {
+                    started = true;
+                    tick = 0;
+                }
+                UMP::Stream(StreamMessage::EndOfClip) if started => {
+                    clip.end = tick;
+                    ended = true;
+                    break;
+                }
+                ump => {
+                    if started {
+                        clip.packets.push((tick, ump));
+                    } else {
+                        clip.header.push(ump);
+                    }
+                }
+            }
+        }
+
+        match ticks_per_quarter_note {
+            Some(ticks) if ended => {
+                clip.ticks_per_quarter_note = ticks;
+                Ok(clip)
+            }
+            _ => Err(ApresError::InvalidBytes(bytes.to_vec()))
+        }
+    }
+
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut umps = vec![
+            UMP::DeltaClockstamp(0),
+            UMP::DeltaClockstampTicksPerQuarterNote(self.ticks_per_quarter_note)
+        ];
+        for ump in self.header.iter() {
+            umps.push(UMP::DeltaClockstamp(0));
+            umps.push(ump.clone());
+        }
+        umps.push(UMP::DeltaClockstamp(0));
+        umps.push(UMP::Stream(StreamMessage::StartOfClip));
+
+        let mut packets: Vec<&(usize, UMP)> = self.packets.iter().collect();
+        packets.sort_by_key(|(tick, _)| *tick);
+
+        let mut previous_tick = 0;
+        for (tick, ump) in packets {
+            umps.extend(get_delta_clockstamps(tick - previous_tick));
+            umps.push(ump.clone());
+            previous_tick = *tick;
+        }
+        umps.extend(get_delta_clockstamps(self.end.saturating_sub(previous_tick)));
+        umps.push(UMP::Stream(StreamMessage::EndOfClip));
+
+        let mut output = CLIP_HEADER.to_vec();
+        for ump in umps.iter() {
+            for word in ump.as_words() {
+                output.extend(word.to_be_bytes().iter());
+            }
+        }
+
+        output
+    }
+}
+
+impl MIDI {
+    /// Build a MIDI with a single track holding a clip's events
+    pub fn from_clip(clip: &MIDIClip) -> MIDI {
+        let mut midi = MIDI::new();
+        midi.set_format(0);
+        midi.set_ppqn(clip.ticks_per_quarter_note);
+        // Track 0 is always in bounds
+        let _ = midi.insert_clip(0, clip);
+
+        midi
+    }
+
+    /// Translate a track to a clip, sending its events on one group with the given protocol.
+    /// The clip keeps the MIDI's ticks per quarter note.
+    pub fn get_clip(&self, track: usize, group: u8, protocol: UMPProtocol) -> MIDIClip {
+        let mut clip = MIDIClip::new(self.ppqn);
+        clip.end = self.get_track_length(track);
+
+        let mut encoder = UMPEncoder::new(group, protocol);
+        for (tick, event_track, event_id) in self.get_ordered_event_ids() {
+            if event_track != track {
+                continue;
+            }
+            if let Some(event) = self.get_event(event_id) {
+                for ump in encoder.feed(event) {
+                    clip.packets.push((tick, ump));
+                }
+            }
+        }
+
+        clip
+    }
+
+    /// Translate a clip's packets to MIDI 1.0 events and insert them in a track, rescaling ticks to the
+    /// MIDI's ticks per quarter note. Packets from every group go to the same track.
+    pub fn insert_clip(&mut self, track: usize, clip: &MIDIClip) -> Result<(), ApresError> {
+        if track > 15 {
+            return Err(ApresError::TrackOutOfBounds);
+        }
+
+        self.begin_transaction("clip");
+        let result = self.insert_clip_packets(track, clip);
+        self.end_transaction();
+
+        result
+    }
+
+    fn insert_clip_packets(&mut self, track: usize, clip: &MIDIClip) -> Result<(), ApresError> {
+        let ticks_per_quarter_note = clip.ticks_per_quarter_note.max(1) as usize;
+        let mut decoder = UMPDecoder::new();
+        let packets = clip.header.iter()
+            .map(|ump| (0, ump))
+            .chain(clip.packets.iter().map(|(tick, ump)| (*tick, ump)));
+
+        for (tick, ump) in packets {
+            let tick = tick * self.ppqn as usize / ticks_per_quarter_note;
+            for (_, event) in decoder.feed(ump) {
+                self.insert_event(track, tick, event)?;
+            }
+        }
+
+        Ok(())
+    }
+}
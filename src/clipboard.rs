@@ -0,0 +1,118 @@
+use crate::{MIDI, MIDIEvent, ApresError};
+
+/// How pasted events interact with events already in the destination
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PasteMode {
+    /// Keep existing events alongside the pasted ones
+    Merge,
+    /// Remove existing events in the pasted tracks and tick range first
+    Replace
+}
+
+/// A region of a MIDI, independent of event ids.
+///
+/// Ticks are relative to the start of the region and tracks are relative to the first copied track.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Clipboard {
+    pub ppqn: u16,
+    /// Length of the region in ticks
+    pub length: usize,
+    pub tracks: Vec<Vec<(usize, MIDIEvent)>>
+}
+
+impl Clipboard {
+    pub fn is_empty(&self) -> bool {
+        self.tracks.iter().all(|track| track.is_empty())
+    }
+
+    pub fn count_events(&self) -> usize {
+        self.tracks.iter().map(|track| track.len()).sum()
+    }
+}
+
+impl MIDI {
+    /// Copy the events in `tracks` from `start_tick` up to (but not including) `end_tick`
+    pub fn copy(&self, tracks: &[usize], start_tick: usize, end_tick: usize) -> Clipboard {
+        let mut clipboard_tracks = vec![Vec::new(); tracks.len()];
+        for (tick, track, event_id) in self.get_ordered_event_ids().iter() {
+            if *tick < start_tick || *tick >= end_tick {
+                continue;
+            }
+            if let Some(index) = tracks.iter().position(|t| t == track) {
+                if let Some(event) = self.events.get(event_id) {
+                    clipboard_tracks[index].push((*tick - start_tick, event.clone()));
+                }
+            }
+        }
+
+        Clipboard {
+            ppqn: self.ppqn,
+            length: end_tick.saturating_sub(start_tick),
+            tracks: clipboard_tracks
+        }
+    }
+
+    /// Copy a region, then remove it from the MIDI. Recorded as a single 'cut' transaction.
+    pub fn cut(&mut self, tracks: &[usize], start_tick: usize, end_tick: usize) -> Clipboard {
+        let clipboard = self.copy(tracks, start_tick, end_tick);
+        self.begin_transaction("cut");
+        self.clear_range(tracks, start_tick, end_tick);
+        self.end_transaction();
+
+        clipboard
+    }
+
+    /// Paste a clipboard's first track into `track` (and the rest into the following tracks) at `tick`.
+    /// Ticks are scaled from the clipboard's ppqn to this MIDI's ppqn.
+    /// Recorded as a single 'paste' transaction. Returns the ids of the new events.
+    pub fn paste(&mut self, clipboard: &Clipboard, track: usize, tick: usize, mode: PasteMode) -> Result<Vec<u64>, ApresError> {
+        if track + clipboard.tracks.len() > 16 {
+            Err(ApresError::TrackOutOfBounds)?;
+        }
+
+        let target_ppqn = self.ppqn;
+        let scale = |relative_tick: usize| -> usize {
+            if clipboard.ppqn == target_ppqn || clipboard.ppqn == 0 {
+                relative_tick
+            } else {
+                let scaled = (relative_tick as u64 * target_ppqn as u64) + (clipboard.ppqn as u64 / 2);
+                (scaled / clipboard.ppqn as u64) as usize
+            }
+        };
+
+        self.begin_transaction("paste");
+        if mode == PasteMode::Replace {
+            let target_tracks: Vec<usize> = (track .. track + clipboard.tracks.len()).collect();
+            self.clear_range(&target_tracks, tick, tick + scale(clipboard.length));
+        }
+
+        let mut output = Vec::new();
+        for (i, events) in clipboard.tracks.iter().enumerate() {
+            for (relative_tick, event) in events.iter() {
+                match self.insert_event(track + i, tick + scale(*relative_tick), event.clone()) {
+                    Ok(event_id) => {
+                        output.push(event_id);
+                    }
+                    Err(e) => {
+                        self.end_transaction();
+                        Err(e)?;
+                    }
+                }
+            }
+        }
+        self.end_transaction();
+
+        Ok(output)
+    }
+
+    fn clear_range(&mut self, tracks: &[usize], start_tick: usize, end_tick: usize) {
+        let to_remove: Vec<u64> = self.get_ordered_event_ids().iter()
+            .filter(|(tick, track, _)| *tick >= start_tick && *tick < end_tick && tracks.contains(track))
+            .map(|(_, _, event_id)| *event_id)
+            .collect();
+
+        for event_id in to_remove.iter() {
+            self.remove_event(*event_id).ok();
+        }
+    }
+}
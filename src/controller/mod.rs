@@ -2,7 +2,10 @@ mod platform;
 pub use platform::Controller;
 
 
-use crate::{MIDIEvent, ApresError, build_pitch_wheel_change};
+use std::convert::TryFrom;
+use std::io::Write;
+
+use crate::{MIDIEvent, MIDIBytes, MIDIMessage, ApresError, Channel, U7, U14, ControllerPairer, PairedEvent, ParameterDecoder, ParameterEvent, MPEDecoder, MPEEvent, CIMessage, CIResponder, build_pitch_wheel_change, build_control_change, build_system_exclusive};
 
 type Callback<T> = fn(&mut Controller, &mut T, &MIDIEvent);
 
@@ -57,6 +60,85 @@ impl Controller {
         output
     }
 
+    /// Read the next byte, which must be a 7-bit data byte
+    pub fn get_next_u7(&mut self) -> Result<U7, ApresError> {
+        let byte = self.get_next_byte()?;
+        match U7::new(byte) {
+            Ok(value) => {
+                Ok(value)
+            }
+            Err(_) => {
+                Err(ApresError::InvalidBytes(vec![byte]))
+            }
+        }
+    }
+
+    /// Read the next event, categorized as a MIDIMessage
+    pub fn get_next_message(&mut self) -> Result<MIDIMessage, ApresError> {
+        Ok(MIDIMessage::from(self.get_next()?))
+    }
+
+    /// Read events through a ControllerPairer until it outputs one, so MSB/LSB controller pairs arrive as 14-bit values.
+    /// With MSBOnlyMode::WaitForLSB, a lone MSB is only output once the next event arrives.
+    pub fn get_next_paired(&mut self, pairer: &mut ControllerPairer) -> Result<PairedEvent, ApresError> {
+        loop {
+            if let Some(paired_event) = pairer.pop() {
+                return Ok(paired_event);
+            }
+            let event = self.get_next()?;
+            pairer.push(event);
+        }
+    }
+
+    /// Read events through a ParameterDecoder until it outputs one, so RPN/NRPN sequences arrive as parameter values
+    pub fn get_next_parameter(&mut self, decoder: &mut ParameterDecoder) -> Result<ParameterEvent, ApresError> {
+        loop {
+            if let Some(parameter_event) = decoder.pop() {
+                return Ok(parameter_event);
+            }
+            let event = self.get_next()?;
+            decoder.push(event);
+        }
+    }
+
+    /// Read events through an MPEDecoder until it outputs one, so notes on member channels arrive as per-note expression
+    pub fn get_next_mpe(&mut self, decoder: &mut MPEDecoder) -> Result<MPEEvent, ApresError> {
+        loop {
+            if let Some(mpe_event) = decoder.pop() {
+                return Ok(mpe_event);
+            }
+            let event = self.get_next()?;
+            decoder.push(event);
+        }
+    }
+
+    /// Read events, answering MIDI-CI messages through `output` (usually the device's other port) with a CIResponder,
+    /// until an event arrives that the responder doesn't answer
+    pub fn get_next_ci<W: Write>(&mut self, responder: &mut CIResponder, output: &mut W) -> Result<MIDIEvent, ApresError> {
+        loop {
+            let event = self.get_next()?;
+            // Unanswered events are handed back as they came in, not re-encoded
+            let replies = match CIMessage::try_from(event.clone()) {
+                Ok(message) => {
+                    let replies = responder.respond(&message);
+                    if replies.is_empty() {
+                        return Ok(event);
+                    }
+                    replies
+                }
+                Err(_) => {
+                    return Ok(event);
+                }
+            };
+
+            for reply in replies {
+                if output.write_all(&MIDIEvent::try_from(reply)?.as_bytes()).is_err() {
+                    return Err(ApresError::PipeBroken);
+                }
+            }
+        }
+    }
+
     pub fn get_next(&mut self) -> Result<MIDIEvent, ApresError> {
         let in_callback_mode = self.listening;
         self.force_listening();
@@ -67,278 +149,52 @@ impl Controller {
             }
 
             0x80..=0xEF => {
-                let channel: u8;
+                let channel: Channel;
                 let lead_nibble: u8 = lead_byte >> 4;
                 match lead_nibble {
                     0x8 => {
-                        channel = lead_byte & 0x0F;
-                        let note = self.get_next_byte()?;
-                        let velocity = self.get_next_byte()?;
+                        channel = Channel::from_status(lead_byte);
+                        let note = self.get_next_u7()?;
+                        let velocity = self.get_next_u7()?;
                         Ok(MIDIEvent::NoteOff(channel, note, velocity))
                     }
                     0x9 => {
-                        channel = lead_byte & 0x0F;
-                        let note = self.get_next_byte()?;
-                        let velocity = self.get_next_byte()?;
+                        channel = Channel::from_status(lead_byte);
+                        let note = self.get_next_u7()?;
+                        let velocity = self.get_next_u7()?;
                         // Convert fake NoteOff (NoteOn where velocity is 0) to real NoteOff
-                        if velocity == 0 {
+                        if velocity.get() == 0 {
                             Ok(MIDIEvent::NoteOff(channel, note, velocity))
                         } else {
                             Ok(MIDIEvent::NoteOn(channel, note, velocity))
                         }
                     }
                     0xA => {
-                        channel = lead_byte & 0x0F;
-                        let note = self.get_next_byte()?;
-                        let velocity = self.get_next_byte()?;
+                        channel = Channel::from_status(lead_byte);
+                        let note = self.get_next_u7()?;
+                        let velocity = self.get_next_u7()?;
                         Ok(MIDIEvent::AfterTouch(channel, note, velocity))
                     }
                     0xB => {
-                        let controller = self.get_next_byte()?;
-                        let value = self.get_next_byte()?;
-                        channel = lead_byte & 0x0F;
-                        match controller {
-                            0x00 => {
-                                Ok(MIDIEvent::BankSelect(channel, value))
-                            }
-                            0x20 => {
-                                Ok(MIDIEvent::BankSelectLSB(channel, value))
-                            }
-                            0x01 => {
-                                Ok(MIDIEvent::ModulationWheel(channel, value))
-                            }
-                            0x21 => {
-                                Ok(MIDIEvent::ModulationWheelLSB(channel, value))
-                            }
-                            0x02 => {
-                                Ok(MIDIEvent::BreathController(channel, value))
-							}
-                            0x22 => {
-                                Ok(MIDIEvent::BreathControllerLSB(channel, value))
-							}
-                            0x04 => {
-                                Ok(MIDIEvent::FootPedal(channel, value))
-							}
-                            0x24 => {
-                                Ok(MIDIEvent::FootPedalLSB(channel, value))
-							}
-                            0x05 => {
-                                Ok(MIDIEvent::PortamentoTime(channel, value))
-							}
-                            0x25 => {
-                                Ok(MIDIEvent::PortamentoTimeLSB(channel, value))
-							}
-                            0x06 => {
-                                Ok(MIDIEvent::DataEntry(channel, value))
-							}
-                            0x26 => {
-                                Ok(MIDIEvent::DataEntryLSB(channel, value))
-							}
-                            0x07 => {
-                                Ok(MIDIEvent::Volume(channel, value))
-							}
-                            0x27 => {
-                                Ok(MIDIEvent::VolumeLSB(channel, value))
-							}
-                            0x08 => {
-                                Ok(MIDIEvent::Balance(channel, value))
-							}
-                            0x28 => {
-                                Ok(MIDIEvent::BalanceLSB(channel, value))
-							}
-                            0x0A => {
-                                Ok(MIDIEvent::Pan(channel, value))
-							}
-                            0x2A => {
-                                Ok(MIDIEvent::PanLSB(channel, value))
-							}
-                            0x0B => {
-                                Ok(MIDIEvent::Expression(channel, value))
-							}
-                            0x2B => {
-                                Ok(MIDIEvent::ExpressionLSB(channel, value))
-							}
-                            0x0C => {
-                                Ok(MIDIEvent::EffectControl1(channel, value))
-							}
-                            0x2C => {
-                                Ok(MIDIEvent::EffectControl1LSB(channel, value))
-							}
-                            0x0D => {
-                                Ok(MIDIEvent::EffectControl2(channel, value))
-							}
-                            0x2D => {
-                                Ok(MIDIEvent::EffectControl2LSB(channel, value))
-							}
-
-                            0x10 => {
-                                Ok(MIDIEvent::GeneralPurpose1(channel, value))
-							}
-                            0x30 => {
-                                Ok(MIDIEvent::GeneralPurpose1LSB(channel, value))
-							}
-                            0x11 => {
-                                Ok(MIDIEvent::GeneralPurpose2(channel, value))
-							}
-                            0x31 => {
-                                Ok(MIDIEvent::GeneralPurpose2LSB(channel, value))
-							}
-                            0x12 => {
-                                Ok(MIDIEvent::GeneralPurpose3(channel, value))
-							}
-                            0x32 => {
-                                Ok(MIDIEvent::GeneralPurpose3LSB(channel, value))
-							}
-                            0x13 => {
-                                Ok(MIDIEvent::GeneralPurpose4(channel, value))
-							}
-                            0x33 => {
-                                Ok(MIDIEvent::GeneralPurpose4LSB(channel, value))
-							}
-                            0x40 => {
-                                Ok(MIDIEvent::HoldPedal(channel, value))
-                            }
-                            0x41 => {
-                                Ok(MIDIEvent::Portamento(channel, value))
-                            }
-                            0x42 => {
-                                Ok(MIDIEvent::Sustenuto(channel, value))
-                            }
-                            0x43 => {
-                                Ok(MIDIEvent::SoftPedal(channel, value))
-                            }
-                            0x44 => {
-                                Ok(MIDIEvent::Legato(channel, value))
-                            }
-                            0x45 => {
-                                Ok(MIDIEvent::Hold2Pedal(channel, value))
-                            }
-                            0x46 => {
-                                Ok(MIDIEvent::SoundVariation(channel, value))
-                            }
-                            0x47 => {
-                                Ok(MIDIEvent::SoundTimbre(channel, value))
-                            }
-                            0x48 => {
-                                Ok(MIDIEvent::SoundReleaseTime(channel, value))
-                            }
-                            0x49 => {
-                                Ok(MIDIEvent::SoundAttack(channel, value))
-                            }
-                            0x4A => {
-                                Ok(MIDIEvent::SoundBrightness(channel, value))
-                            }
-                            0x4B => {
-                                Ok(MIDIEvent::SoundControl1(channel, value))
-                            }
-                            0x4C => {
-                                Ok(MIDIEvent::SoundControl2(channel, value))
-                            }
-                            0x4D => {
-                                Ok(MIDIEvent::SoundControl3(channel, value))
-                            }
-                            0x4E => {
-                                Ok(MIDIEvent::SoundControl4(channel, value))
-                            }
-                            0x4F => {
-                                Ok(MIDIEvent::SoundControl5(channel, value))
-                            }
-                            0x50 => {
-                                Ok(MIDIEvent::GeneralPurpose5(channel, value))
-                            }
-                            0x51 => {
-                                Ok(MIDIEvent::GeneralPurpose6(channel, value))
-                            }
-                            0x52 => {
-                                Ok(MIDIEvent::GeneralPurpose7(channel, value))
-                            }
-                            0x53 => {
-                                Ok(MIDIEvent::GeneralPurpose8(channel, value))
-                            }
-
-                            0x5B => {
-                                Ok(MIDIEvent::EffectsLevel(channel, value))
-                            }
-
-                            0x5C => {
-                                Ok(MIDIEvent::TremuloLevel(channel, value))
-                            }
-
-                            0x5D => {
-                                Ok(MIDIEvent::ChorusLevel(channel, value))
-                            }
-                            0x5E => {
-                                Ok(MIDIEvent::CelesteLevel(channel, value))
-                            }
-
-                            0x5F => {
-                                Ok(MIDIEvent::PhaserLevel(channel, value))
-                            }
-
-                            0x60 => {
-                                Ok(MIDIEvent::DataIncrement(channel))
-                            }
-
-                            0x61 => {
-                                Ok(MIDIEvent::DataDecrement(channel))
-                            }
-                            0x62 => {
-                                Ok(MIDIEvent::NonRegisteredParameterNumberLSB(channel, value))
-                            }
-
-                            0x63 => {
-                                Ok(MIDIEvent::NonRegisteredParameterNumber(channel, value))
-                            }
-
-                            0x64 => {
-                                Ok(MIDIEvent::RegisteredParameterNumberLSB(channel, value))
-                            }
-                            0x65 => {
-                                Ok(MIDIEvent::RegisteredParameterNumber(channel, value))
-                            }
-                            0x78 => {
-                                Ok(MIDIEvent::AllSoundOff(channel))
-                            }
-                            0x79 => {
-                                Ok(MIDIEvent::AllControllersOff(channel))
-                            }
-                            0x7A => {
-                                Ok(MIDIEvent::LocalControl(channel, value))
-                            }
-                            0x7B => {
-                                Ok(MIDIEvent::AllNotesOff(channel))
-                            }
-                            0x7C => {
-                                Ok(MIDIEvent::OmniOff(channel))
-                            }
-                            0x7D => {
-                                Ok(MIDIEvent::OmniOn(channel))
-                            }
-                            0xFE => {
-                                Ok(MIDIEvent::MonophonicOperation(channel, value))
-                            }
-                            0xFF => {
-                                Ok(MIDIEvent::PolyphonicOperation(channel))
-                            }
-                            _ => {
-                                Ok(MIDIEvent::ControlChange(channel, controller, value))
-                            }
-                        }
+                        let controller = self.get_next_u7()?;
+                        let value = self.get_next_u7()?;
+                        channel = Channel::from_status(lead_byte);
+                        Ok(build_control_change(channel, controller, value))
                     }
                     0xC => {
-                        channel = lead_byte & 0x0F;
-                        let new_program = self.get_next_byte()?;
+                        channel = Channel::from_status(lead_byte);
+                        let new_program = self.get_next_u7()?;
                         Ok(MIDIEvent::ProgramChange(channel, new_program))
                     }
                     0xD => {
-                        channel = lead_byte & 0x0F;
-                        let pressure = self.get_next_byte()?;
+                        channel = Channel::from_status(lead_byte);
+                        let pressure = self.get_next_u7()?;
                         Ok(MIDIEvent::ChannelPressure(channel, pressure))
                     }
                     0xE => {
-                        channel = lead_byte & 0x0F;
-                        let least_significant_byte = self.get_next_byte()?;
-                        let most_significant_byte = self.get_next_byte()?;
+                        channel = Channel::from_status(lead_byte);
+                        let least_significant_byte = self.get_next_u7()?;
+                        let most_significant_byte = self.get_next_u7()?;
                         Ok(build_pitch_wheel_change(channel, least_significant_byte, most_significant_byte))
                     }
                     _ => {
@@ -359,48 +215,26 @@ impl Controller {
                     }
                 }
 
-                Ok(MIDIEvent::SystemExclusive(bytedump))
+                Ok(build_system_exclusive(bytedump))
             }
 
-            // Time Code
+            // MTC Quarter Frame
             0xF1 => {
-                let byte_a = self.get_next_byte()?;
-                let rate = match (byte_a >> 5) & 0x3 {
-                    0 => {
-                        24.0
-                    }
-                    1 => {
-                        25.0
-                    }
-                    2 => {
-                        29.97
-                    }
-                    3 => {
-                        30.0
-                    }
-                    _ => {
-                        30.0
-                    }
-                };
-                let hour = byte_a & 0x1F;
-                let minute = self.get_next_byte()? & 0x3F;
-                let second = self.get_next_byte()? & 0x3F;
-                let frame = self.get_next_byte()? & 0x1F;
-
-                Ok(MIDIEvent::TimeCode(rate, hour, minute, second, frame))
+                let byte = self.get_next_u7()?.get();
+                Ok(MIDIEvent::MTCQuarterFrame(byte >> 4, byte & 0x0F))
             }
 
             0xF2 => {
-                let least_significant_byte = self.get_next_byte()?;
-                let most_significant_byte = self.get_next_byte()?;
+                let least_significant_byte = self.get_next_u7()?;
+                let most_significant_byte = self.get_next_u7()?;
 
-                let beat = ((most_significant_byte as u16) << 7) + (least_significant_byte as u16);
+                let beat = U14::from_lsb_msb(least_significant_byte, most_significant_byte);
                 Ok(MIDIEvent::SongPositionPointer(beat))
             }
 
             0xF3 => {
-                let song = self.get_next_byte()?;
-                Ok(MIDIEvent::SongSelect(song & 0x7F))
+                let song = self.get_next_u7()?;
+                Ok(MIDIEvent::SongSelect(song))
             }
 
             0xF6 => {
@@ -416,7 +250,7 @@ impl Controller {
                     bytedump.push(byte);
                 }
 
-                Ok(MIDIEvent::SystemExclusive(bytedump))
+                Ok(build_system_exclusive(bytedump))
             }
 
             // Clock
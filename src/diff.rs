@@ -0,0 +1,370 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::mem::discriminant;
+
+use crate::{MIDI, MIDIEvent, U7};
+
+/// Controls what counts as a difference when comparing two MIDIs.
+///
+/// Running status and other byte-level encoding choices never show up in a diff since
+/// events are compared after being decoded.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DiffOptions {
+    /// Match events by content and position instead of by event id (default: true)
+    pub ignore_event_ids: bool,
+    /// Treat the events at each tick as unordered (default: false)
+    pub ignore_intra_tick_order: bool,
+    /// Treat NoteOn with a velocity of 0 and NoteOff with any velocity as the same event (default: true)
+    pub normalize_note_off: bool
+}
+
+impl DiffOptions {
+    pub fn new() -> DiffOptions {
+        DiffOptions {
+            ignore_event_ids: true,
+            ignore_intra_tick_order: false,
+            normalize_note_off: true
+        }
+    }
+}
+
+impl Default for DiffOptions {
+    fn default() -> DiffOptions {
+        DiffOptions::new()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum EventDiff {
+    Added {
+        tick: usize,
+        event_id: u64,
+        event: MIDIEvent
+    },
+    Removed {
+        tick: usize,
+        event_id: u64,
+        event: MIDIEvent
+    },
+    Changed {
+        old_tick: usize,
+        new_tick: usize,
+        old_id: u64,
+        new_id: u64,
+        old_event: MIDIEvent,
+        new_event: MIDIEvent
+    }
+}
+
+impl EventDiff {
+    fn get_tick(&self) -> usize {
+        match self {
+            EventDiff::Added { tick, .. } | EventDiff::Removed { tick, .. } => {
+                *tick
+            }
+            EventDiff::Changed { new_tick, .. } => {
+                *new_tick
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TrackDiff {
+    pub track: usize,
+    pub changes: Vec<EventDiff>
+}
+
+/// Structured, semantic difference between two MIDIs. Values are given as (old, new).
+#[derive(Clone, Debug, PartialEq)]
+pub struct MIDIDiff {
+    pub ppqn: Option<(u16, u16)>,
+    pub format: Option<(u16, u16)>,
+    pub tracks: Vec<TrackDiff>
+}
+
+impl MIDIDiff {
+    pub fn is_empty(&self) -> bool {
+        self.ppqn.is_none() && self.format.is_none() && self.tracks.is_empty()
+    }
+
+    /// Get a human-readable report of the differences, one change per line
+    pub fn to_report(&self) -> String {
+        let mut lines = Vec::new();
+        if let Some((old, new)) = self.ppqn {
+            lines.push(format!("ppqn: {} -> {}", old, new));
+        }
+        if let Some((old, new)) = self.format {
+            lines.push(format!("format: {} -> {}", old, new));
+        }
+
+        for track_diff in self.tracks.iter() {
+            lines.push(format!("track {}:", track_diff.track));
+            for change in track_diff.changes.iter() {
+                lines.push(match change {
+                    EventDiff::Added { tick, event, .. } => {
+                        format!("  + @{} {}", tick, event)
+                    }
+                    EventDiff::Removed { tick, event, .. } => {
+                        format!("  - @{} {}", tick, event)
+                    }
+                    EventDiff::Changed { old_tick, new_tick, old_event, new_event, .. } => {
+                        if old_tick == new_tick {
+                            format!("  ~ @{} {} -> {}", new_tick, old_event, new_event)
+                        } else {
+                            format!("  ~ @{} -> @{} {} -> {}", old_tick, new_tick, old_event, new_event)
+                        }
+                    }
+                });
+            }
+        }
+
+        lines.join("\n")
+    }
+}
+
+impl MIDI {
+    /// Compare this MIDI (old) against another (new)
+    pub fn diff(&self, other: &MIDI, options: &DiffOptions) -> MIDIDiff {
+        let ppqn = if self.ppqn != other.ppqn {
+            Some((self.ppqn, other.ppqn))
+        } else {
+            None
+        };
+        let format = if self.midi_format != other.midi_format {
+            Some((self.midi_format, other.midi_format))
+        } else {
+            None
+        };
+
+        let mut changes: BTreeMap<usize, Vec<EventDiff>> = BTreeMap::new();
+        if options.ignore_event_ids {
+            let old_tracks = self.get_diffable_tracks();
+            let new_tracks = other.get_diffable_tracks();
+            let track_numbers: BTreeSet<usize> = old_tracks.keys().chain(new_tracks.keys()).copied().collect();
+            let empty = BTreeMap::new();
+            for track in track_numbers.iter() {
+                let track_changes = diff_track(
+                    old_tracks.get(track).unwrap_or(&empty),
+                    new_tracks.get(track).unwrap_or(&empty),
+                    options
+                );
+                if !track_changes.is_empty() {
+                    changes.insert(*track, track_changes);
+                }
+            }
+        } else {
+            self.diff_by_id(other, options, &mut changes);
+        }
+
+        MIDIDiff {
+            ppqn,
+            format,
+            tracks: changes.into_iter().map(|(track, changes)| TrackDiff { track, changes }).collect()
+        }
+    }
+
+    /// Get each track's events, grouped by tick and ordered by event id within a tick
+    fn get_diffable_tracks(&self) -> BTreeMap<usize, BTreeMap<usize, Vec<(u64, MIDIEvent)>>> {
+        let mut output: BTreeMap<usize, BTreeMap<usize, Vec<(u64, MIDIEvent)>>> = BTreeMap::new();
+        for (tick, track, event_id) in self.get_ordered_event_ids().iter() {
+            if let Some(event) = self.events.get(event_id) {
+                output.entry(*track)
+                    .or_default()
+                    .entry(*tick)
+                    .or_default()
+                    .push((*event_id, event.clone()));
+            }
+        }
+
+        output
+    }
+
+    fn diff_by_id(&self, other: &MIDI, options: &DiffOptions, changes: &mut BTreeMap<usize, Vec<EventDiff>>) {
+        let event_ids: BTreeSet<u64> = self.event_positions.keys().chain(other.event_positions.keys()).copied().collect();
+        for event_id in event_ids.iter() {
+            let old = match (self.event_positions.get(event_id), self.get_event(*event_id)) {
+                (Some(position), Some(event)) => Some((*position, event)),
+                _ => None
+            };
+            let new = match (other.event_positions.get(event_id), other.get_event(*event_id)) {
+                (Some(position), Some(event)) => Some((*position, event)),
+                _ => None
+            };
+
+            match (old, new) {
+                (Some(((old_track, old_tick), old_event)), Some(((new_track, new_tick), new_event))) => {
+                    if old_track != new_track {
+                        changes.entry(old_track).or_default().push(EventDiff::Removed {
+                            tick: old_tick,
+                            event_id: *event_id,
+                            event: old_event
+                        });
+                        changes.entry(new_track).or_default().push(EventDiff::Added {
+                            tick: new_tick,
+                            event_id: *event_id,
+                            event: new_event
+                        });
+                    } else if old_tick != new_tick || get_comparable_event(&old_event, options) != get_comparable_event(&new_event, options) {
+                        changes.entry(new_track).or_default().push(EventDiff::Changed {
+                            old_tick,
+                            new_tick,
+                            old_id: *event_id,
+                            new_id: *event_id,
+                            old_event,
+                            new_event
+                        });
+                    }
+                }
+                (Some(((track, tick), event)), None) => {
+                    changes.entry(track).or_default().push(EventDiff::Removed {
+                        tick,
+                        event_id: *event_id,
+                        event
+                    });
+                }
+                (None, Some(((track, tick), event))) => {
+                    changes.entry(track).or_default().push(EventDiff::Added {
+                        tick,
+                        event_id: *event_id,
+                        event
+                    });
+                }
+                (None, None) => ()
+            }
+        }
+
+        for track_changes in changes.values_mut() {
+            track_changes.sort_by_key(|change| change.get_tick());
+        }
+    }
+}
+
+// Events are compared in this form, but reported as they are
+fn get_comparable_event(event: &MIDIEvent, options: &DiffOptions) -> MIDIEvent {
+    match event {
+        MIDIEvent::NoteOn(channel, note, velocity) if options.normalize_note_off && velocity.get() == 0 => {
+            MIDIEvent::NoteOff(*channel, *note, U7::default())
+        }
+        MIDIEvent::NoteOff(channel, note, _) if options.normalize_note_off => {
+            MIDIEvent::NoteOff(*channel, *note, U7::default())
+        }
+        _ => {
+            event.clone()
+        }
+    }
+}
+
+fn diff_track(old_ticks: &BTreeMap<usize, Vec<(u64, MIDIEvent)>>, new_ticks: &BTreeMap<usize, Vec<(u64, MIDIEvent)>>, options: &DiffOptions) -> Vec<EventDiff> {
+    let ticks: BTreeSet<usize> = old_ticks.keys().chain(new_ticks.keys()).copied().collect();
+    let empty = Vec::new();
+    let mut output = Vec::new();
+    for tick in ticks.iter() {
+        let old_events = old_ticks.get(tick).unwrap_or(&empty);
+        let new_events = new_ticks.get(tick).unwrap_or(&empty);
+
+        let old_comparable: Vec<MIDIEvent> = old_events.iter().map(|(_, event)| get_comparable_event(event, options)).collect();
+        let new_comparable: Vec<MIDIEvent> = new_events.iter().map(|(_, event)| get_comparable_event(event, options)).collect();
+        let (old_matched, new_matched) = if options.ignore_intra_tick_order {
+            match_unordered(&old_comparable, &new_comparable)
+        } else {
+            match_ordered(&old_comparable, &new_comparable)
+        };
+
+        let mut removed: Vec<usize> = (0..old_events.len()).filter(|i| !old_matched[*i]).collect();
+        let mut added: Vec<usize> = (0..new_events.len()).filter(|j| !new_matched[*j]).collect();
+
+        // Pair up unmatched events of the same kind as changes
+        let mut i = 0;
+        while i < removed.len() {
+            let old_index = removed[i];
+            let pair_index = added.iter().position(|new_index| {
+                discriminant(&old_comparable[old_index]) == discriminant(&new_comparable[*new_index])
+                    && old_comparable[old_index] != new_comparable[*new_index]
+            });
+            match pair_index {
+                Some(j) => {
+                    let (old_id, old_event) = &old_events[old_index];
+                    let (new_id, new_event) = &new_events[added.remove(j)];
+                    removed.remove(i);
+                    output.push(EventDiff::Changed {
+                        old_tick: *tick,
+                        new_tick: *tick,
+                        old_id: *old_id,
+                        new_id: *new_id,
+                        old_event: old_event.clone(),
+                        new_event: new_event.clone()
+                    });
+                }
+                None => {
+                    i += 1;
+                }
+            }
+        }
+
+        for (event_id, event) in removed.iter().map(|i| &old_events[*i]) {
+            output.push(EventDiff::Removed {
+                tick: *tick,
+                event_id: *event_id,
+                event: event.clone()
+            });
+        }
+        for (event_id, event) in added.iter().map(|j| &new_events[*j]) {
+            output.push(EventDiff::Added {
+                tick: *tick,
+                event_id: *event_id,
+                event: event.clone()
+            });
+        }
+    }
+
+    output
+}
+
+fn match_unordered(old_events: &[MIDIEvent], new_events: &[MIDIEvent]) -> (Vec<bool>, Vec<bool>) {
+    let mut old_matched = vec![false; old_events.len()];
+    let mut new_matched = vec![false; new_events.len()];
+    for (i, old_event) in old_events.iter().enumerate() {
+        for (j, new_event) in new_events.iter().enumerate() {
+            if !new_matched[j] && old_event == new_event {
+                old_matched[i] = true;
+                new_matched[j] = true;
+                break;
+            }
+        }
+    }
+
+    (old_matched, new_matched)
+}
+
+// Longest common subsequence, so only the events that break the order are reported
+fn match_ordered(old_events: &[MIDIEvent], new_events: &[MIDIEvent]) -> (Vec<bool>, Vec<bool>) {
+    let width = new_events.len() + 1;
+    let mut lengths = vec![0usize; (old_events.len() + 1) * width];
+    for i in (0..old_events.len()).rev() {
+        for j in (0..new_events.len()).rev() {
+            lengths[(i * width) + j] = if old_events[i] == new_events[j] {
+                lengths[((i + 1) * width) + j + 1] + 1
+            } else {
+                std::cmp::max(lengths[((i + 1) * width) + j], lengths[(i * width) + j + 1])
+            };
+        }
+    }
+
+    let mut old_matched = vec![false; old_events.len()];
+    let mut new_matched = vec![false; new_events.len()];
+    let (mut i, mut j) = (0, 0);
+    while i < old_events.len() && j < new_events.len() {
+        if old_events[i] == new_events[j] {
+            old_matched[i] = true;
+            new_matched[j] = true;
+            i += 1;
+            j += 1;
+        } else if lengths[((i + 1) * width) + j] >= lengths[(i * width) + j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+
+    (old_matched, new_matched)
+}
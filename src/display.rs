@@ -0,0 +1,372 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{MIDIEvent, ApresError, Channel, U7, U14, PitchBend, Pitch, Tempo, Meter, Key, Timecode, build_control_change, build_system_exclusive};
+use crate::high_resolution::get_control_change;
+
+/// Formats events in a short, stable form, eg "NoteOn ch=1 C4 vel=100", "CC ch=1 #7=90" or "Tempo 120bpm".
+/// Channels are counted from 1 and notes are named with C4 as middle C (60).
+/// Every control change, named or not, is written as "CC".
+impl fmt::Display for MIDIEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some((channel, controller, value)) = get_control_change(self) {
+            return write!(f, "CC ch={} #{}={}", channel.get() + 1, controller.get(), value.get());
+        }
+
+        match self {
+            MIDIEvent::SequenceNumber(number) => {
+                write!(f, "SequenceNumber {}", number)
+            }
+            MIDIEvent::Text(text) => {
+                write!(f, "Text {:?}", text)
+            }
+            MIDIEvent::CopyRightNotice(text) => {
+                write!(f, "CopyRightNotice {:?}", text)
+            }
+            MIDIEvent::TrackName(text) => {
+                write!(f, "TrackName {:?}", text)
+            }
+            MIDIEvent::InstrumentName(text) => {
+                write!(f, "InstrumentName {:?}", text)
+            }
+            MIDIEvent::Lyric(text) => {
+                write!(f, "Lyric {:?}", text)
+            }
+            MIDIEvent::Marker(text) => {
+                write!(f, "Marker {:?}", text)
+            }
+            MIDIEvent::CuePoint(text) => {
+                write!(f, "CuePoint {:?}", text)
+            }
+            MIDIEvent::ChannelPrefix(channel) => {
+                write!(f, "ChannelPrefix ch={}", channel.get() + 1)
+            }
+            MIDIEvent::SetTempo(tempo) => {
+                write!(f, "Tempo {}", tempo)
+            }
+            MIDIEvent::SMPTEOffset(timecode) => {
+                write!(f, "SMPTEOffset {}", timecode)
+            }
+            MIDIEvent::TimeSignature(meter) => {
+                write!(f, "TimeSignature {}", meter)?;
+                let default = Meter::new(meter.get_numerator(), meter.get_denominator()).unwrap_or_default();
+                if meter.get_clocks_per_click() != default.get_clocks_per_click()
+                || meter.get_thirty_seconds_per_quarter() != default.get_thirty_seconds_per_quarter() {
+                    write!(f, " clocks={} 32nds={}", meter.get_clocks_per_click(), meter.get_thirty_seconds_per_quarter())?;
+                }
+                Ok(())
+            }
+            MIDIEvent::KeySignature(key) => {
+                write!(f, "KeySignature {}", key)
+            }
+            MIDIEvent::SequencerSpecific(data) => {
+                write!(f, "SequencerSpecific{}", get_hex_string(data))
+            }
+            MIDIEvent::NoteOn(channel, note, velocity) => {
+                write!(f, "NoteOn ch={} {} vel={}", channel.get() + 1, Pitch::from_u7(*note), velocity.get())
+            }
+            MIDIEvent::NoteOff(channel, note, velocity) => {
+                write!(f, "NoteOff ch={} {} vel={}", channel.get() + 1, Pitch::from_u7(*note), velocity.get())
+            }
+            MIDIEvent::AfterTouch(channel, note, pressure) => {
+                write!(f, "AfterTouch ch={} {} pressure={}", channel.get() + 1, Pitch::from_u7(*note), pressure.get())
+            }
+            MIDIEvent::ProgramChange(channel, program) => {
+                write!(f, "ProgramChange ch={} program={}", channel.get() + 1, program.get())
+            }
+            MIDIEvent::ChannelPressure(channel, pressure) => {
+                write!(f, "ChannelPressure ch={} pressure={}", channel.get() + 1, pressure.get())
+            }
+            MIDIEvent::PitchWheelChange(channel, bend) => {
+                write!(f, "PitchBend ch={} {:+}", channel.get() + 1, bend.get_signed())
+            }
+            MIDIEvent::SystemExclusive(data) => {
+                write!(f, "SysEx{}", get_hex_string(data))
+            }
+            MIDIEvent::MTCQuarterFrame(piece, value) => {
+                write!(f, "MTCQuarterFrame piece={} value={}", piece & 0x07, value & 0x0F)
+            }
+            MIDIEvent::SongPositionPointer(beats) => {
+                write!(f, "SongPosition {}", beats.get())
+            }
+            MIDIEvent::SongSelect(song) => {
+                write!(f, "SongSelect {}", song.get())
+            }
+            MIDIEvent::TimeCode(timecode) => {
+                write!(f, "TimeCode {}", timecode)
+            }
+            MIDIEvent::EndOfTrack => {
+                write!(f, "EndOfTrack")
+            }
+            MIDIEvent::TuneRequest => {
+                write!(f, "TuneRequest")
+            }
+            MIDIEvent::MIDIClock => {
+                write!(f, "MIDIClock")
+            }
+            MIDIEvent::MIDIStart => {
+                write!(f, "MIDIStart")
+            }
+            MIDIEvent::MIDIContinue => {
+                write!(f, "MIDIContinue")
+            }
+            MIDIEvent::MIDIStop => {
+                write!(f, "MIDIStop")
+            }
+            MIDIEvent::ActiveSense => {
+                write!(f, "ActiveSense")
+            }
+            MIDIEvent::Reset => {
+                write!(f, "Reset")
+            }
+            // Control changes are all handled above
+            _ => {
+                write!(f, "{:?}", self)
+            }
+        }
+    }
+}
+
+/// Parses the format written by Display. Notes may also be given as numbers or with flats (eg "Eb3"),
+/// and tempos in microseconds per quarter note (eg "Tempo 500000us").
+impl FromStr for MIDIEvent {
+    type Err = ApresError;
+    fn from_str(input: &str) -> Result<MIDIEvent, ApresError> {
+        let input = input.trim();
+        let (name, rest) = match input.split_once(char::is_whitespace) {
+            Some((name, rest)) => (name, rest.trim()),
+            None => (input, "")
+        };
+        let args: Vec<&str> = rest.split_whitespace().collect();
+
+        let event = match (name, args.as_slice()) {
+            ("SequenceNumber", [number]) => {
+                MIDIEvent::SequenceNumber(parse_number(number)?)
+            }
+            ("Text", _) => {
+                MIDIEvent::Text(parse_quoted(rest)?)
+            }
+            ("CopyRightNotice", _) => {
+                MIDIEvent::CopyRightNotice(parse_quoted(rest)?)
+            }
+            ("TrackName", _) => {
+                MIDIEvent::TrackName(parse_quoted(rest)?)
+            }
+            ("InstrumentName", _) => {
+                MIDIEvent::InstrumentName(parse_quoted(rest)?)
+            }
+            ("Lyric", _) => {
+                MIDIEvent::Lyric(parse_quoted(rest)?)
+            }
+            ("Marker", _) => {
+                MIDIEvent::Marker(parse_quoted(rest)?)
+            }
+            ("CuePoint", _) => {
+                MIDIEvent::CuePoint(parse_quoted(rest)?)
+            }
+            ("ChannelPrefix", [channel]) => {
+                MIDIEvent::ChannelPrefix(parse_channel(channel)?)
+            }
+            ("Tempo", [tempo]) => {
+                MIDIEvent::SetTempo(tempo.parse::<Tempo>()?)
+            }
+            ("SMPTEOffset", _) => {
+                MIDIEvent::SMPTEOffset(rest.parse::<Timecode>()?)
+            }
+            ("TimeSignature", [meter, options @ ..]) => {
+                let mut meter = meter.parse::<Meter>()?;
+                for option in options.iter() {
+                    if let Ok(clocks) = parse_field(option, "clocks") {
+                        meter = meter.with_clocks_per_click(parse_number(clocks)?);
+                    } else {
+                        meter = meter.with_thirty_seconds_per_quarter(parse_number(parse_field(option, "32nds")?)?);
+                    }
+                }
+                MIDIEvent::TimeSignature(meter)
+            }
+            ("KeySignature", _) => {
+                MIDIEvent::KeySignature(rest.parse::<Key>()?)
+            }
+            ("SequencerSpecific", bytes) => {
+                MIDIEvent::SequencerSpecific(parse_hex(bytes)?)
+            }
+            ("NoteOn", [channel, note, velocity]) => {
+                MIDIEvent::NoteOn(parse_channel(channel)?, parse_note(note)?, parse_u7(parse_field(velocity, "vel")?)?)
+            }
+            ("NoteOff", [channel, note, velocity]) => {
+                MIDIEvent::NoteOff(parse_channel(channel)?, parse_note(note)?, parse_u7(parse_field(velocity, "vel")?)?)
+            }
+            ("AfterTouch", [channel, note, pressure]) => {
+                MIDIEvent::AfterTouch(parse_channel(channel)?, parse_note(note)?, parse_u7(parse_field(pressure, "pressure")?)?)
+            }
+            ("CC", [channel, control]) => {
+                let (controller, value) = match control.strip_prefix('#').and_then(|control| control.split_once('=')) {
+                    Some(pair) => pair,
+                    None => {
+                        return Err(ApresError::ParseError(input.to_string()));
+                    }
+                };
+                build_control_change(parse_channel(channel)?, parse_u7(controller)?, parse_u7(value)?)
+            }
+            ("ProgramChange", [channel, program]) => {
+                MIDIEvent::ProgramChange(parse_channel(channel)?, parse_u7(parse_field(program, "program")?)?)
+            }
+            ("ChannelPressure", [channel, pressure]) => {
+                MIDIEvent::ChannelPressure(parse_channel(channel)?, parse_u7(parse_field(pressure, "pressure")?)?)
+            }
+            ("PitchBend", [channel, bend]) => {
+                MIDIEvent::PitchWheelChange(parse_channel(channel)?, PitchBend::from_signed(parse_number(bend)?)?)
+            }
+            ("SysEx", bytes) => {
+                build_system_exclusive(parse_hex(bytes)?)
+            }
+            ("MTCQuarterFrame", [piece, value]) => {
+                let piece: u8 = parse_number(parse_field(piece, "piece")?)?;
+                let value: u8 = parse_number(parse_field(value, "value")?)?;
+                if piece > 0x07 || value > 0x0F {
+                    return Err(ApresError::InvalidBytes(vec![piece, value]));
+                }
+                MIDIEvent::MTCQuarterFrame(piece, value)
+            }
+            ("SongPosition", [beats]) => {
+                MIDIEvent::SongPositionPointer(U14::new(parse_number(beats)?)?)
+            }
+            ("SongSelect", [song]) => {
+                MIDIEvent::SongSelect(parse_u7(song)?)
+            }
+            ("TimeCode", _) => {
+                MIDIEvent::TimeCode(rest.parse::<Timecode>()?)
+            }
+            ("EndOfTrack", []) => {
+                MIDIEvent::EndOfTrack
+            }
+            ("TuneRequest", []) => {
+                MIDIEvent::TuneRequest
+            }
+            ("MIDIClock", []) => {
+                MIDIEvent::MIDIClock
+            }
+            ("MIDIStart", []) => {
+                MIDIEvent::MIDIStart
+            }
+            ("MIDIContinue", []) => {
+                MIDIEvent::MIDIContinue
+            }
+            ("MIDIStop", []) => {
+                MIDIEvent::MIDIStop
+            }
+            ("ActiveSense", []) => {
+                MIDIEvent::ActiveSense
+            }
+            ("Reset", []) => {
+                MIDIEvent::Reset
+            }
+            _ => {
+                return Err(ApresError::ParseError(input.to_string()));
+            }
+        };
+
+        Ok(event)
+    }
+}
+
+// Parse a note name ("C4", "Eb3") or number
+fn parse_note(name: &str) -> Result<U7, ApresError> {
+    Ok(name.parse::<Pitch>()?.get_u7())
+}
+
+// Parse a 1-based "ch=N"
+fn parse_channel(field: &str) -> Result<Channel, ApresError> {
+    let channel: u8 = parse_number(parse_field(field, "ch")?)?;
+    if channel == 0 {
+        Err(ApresError::ParseError(field.to_string()))
+    } else {
+        Channel::new(channel - 1)
+    }
+}
+
+// Get the value of a "name=value" field
+fn parse_field<'a>(field: &'a str, name: &str) -> Result<&'a str, ApresError> {
+    match field.split_once('=') {
+        Some((field_name, value)) if field_name == name => {
+            Ok(value)
+        }
+        _ => {
+            Err(ApresError::ParseError(field.to_string()))
+        }
+    }
+}
+
+fn parse_number<T: FromStr>(value: &str) -> Result<T, ApresError> {
+    match value.parse::<T>() {
+        Ok(number) => Ok(number),
+        Err(_) => Err(ApresError::ParseError(value.to_string()))
+    }
+}
+
+fn parse_u7(value: &str) -> Result<U7, ApresError> {
+    U7::new(parse_number(value)?)
+}
+
+fn get_hex_string(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!(" {:02X}", byte)).collect()
+}
+
+fn parse_hex(bytes: &[&str]) -> Result<Vec<u8>, ApresError> {
+    let mut output = Vec::new();
+    for byte in bytes.iter() {
+        match u8::from_str_radix(byte, 16) {
+            Ok(byte) => {
+                output.push(byte);
+            }
+            Err(_) => {
+                return Err(ApresError::ParseError(byte.to_string()));
+            }
+        }
+    }
+
+    Ok(output)
+}
+
+// Undo the escaping done by formatting a string with {:?}
+fn parse_quoted(quoted: &str) -> Result<String, ApresError> {
+    let inner = match quoted.strip_prefix('"').and_then(|rest| rest.strip_suffix('"')) {
+        Some(inner) => inner,
+        None => {
+            return Err(ApresError::ParseError(quoted.to_string()));
+        }
+    };
+
+    let mut output = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            output.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('n') => output.push('\n'),
+            Some('r') => output.push('\r'),
+            Some('t') => output.push('\t'),
+            Some('0') => output.push('\0'),
+            Some('\\') => output.push('\\'),
+            Some('"') => output.push('"'),
+            Some('\'') => output.push('\''),
+            Some('u') => {
+                let code: String = chars.by_ref().skip(1).take_while(|c| *c != '}').collect();
+                match u32::from_str_radix(&code, 16).ok().and_then(char::from_u32) {
+                    Some(c) => output.push(c),
+                    None => {
+                        return Err(ApresError::ParseError(quoted.to_string()));
+                    }
+                }
+            }
+            _ => {
+                return Err(ApresError::ParseError(quoted.to_string()));
+            }
+        }
+    }
+
+    Ok(output)
+}
@@ -0,0 +1,461 @@
+use std::fmt;
+
+use crate::{MIDIEvent, Channel, U7, ChannelState};
+use crate::high_resolution::get_control_change;
+
+/// Channel 10 (9 counting from 0), reserved for percussion in General MIDI
+pub const DRUM_CHANNEL: Channel = Channel::from_status(9);
+
+/// Bank select MSB that chooses GM2 rhythm (drum) sets, on any channel
+pub const GM2_RHYTHM_BANK: U7 = U7::new_masked(0x78);
+
+/// Bank select MSB that chooses GM2 melodic sounds, with the bank select LSB choosing a variation
+pub const GM2_MELODIC_BANK: U7 = U7::new_masked(0x79);
+
+/// The 16 General MIDI instrument families, each covering 8 programs
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ProgramFamily {
+    Piano,
+    ChromaticPercussion,
+    Organ,
+    Guitar,
+    Bass,
+    Strings,
+    Ensemble,
+    Brass,
+    Reed,
+    Pipe,
+    SynthLead,
+    SynthPad,
+    SynthEffects,
+    Ethnic,
+    Percussive,
+    SoundEffects
+}
+
+const PROGRAM_NAMES: [&str; 128] = [
+    "Acoustic Grand Piano", "Bright Acoustic Piano", "Electric Grand Piano", "Honky-tonk Piano",
+    "Electric Piano 1", "Electric Piano 2", "Harpsichord", "Clavi",
+    "Celesta", "Glockenspiel", "Music Box", "Vibraphone",
+    "Marimba", "Xylophone", "Tubular Bells", "Dulcimer",
+    "Drawbar Organ", "Percussive Organ", "Rock Organ", "Church Organ",
+    "Reed Organ", "Accordion", "Harmonica", "Tango Accordion",
+    "Acoustic Guitar (nylon)", "Acoustic Guitar (steel)", "Electric Guitar (jazz)", "Electric Guitar (clean)",
+    "Electric Guitar (muted)", "Overdriven Guitar", "Distortion Guitar", "Guitar Harmonics",
+    "Acoustic Bass", "Electric Bass (finger)", "Electric Bass (pick)", "Fretless Bass",
+    "Slap Bass 1", "Slap Bass 2", "Synth Bass 1", "Synth Bass 2",
+    "Violin", "Viola", "Cello", "Contrabass",
+    "Tremolo Strings", "Pizzicato Strings", "Orchestral Harp", "Timpani",
+    "String Ensemble 1", "String Ensemble 2", "SynthStrings 1", "SynthStrings 2",
+    "Choir Aahs", "Voice Oohs", "Synth Voice", "Orchestra Hit",
+    "Trumpet", "Trombone", "Tuba", "Muted Trumpet",
+    "French Horn", "Brass Section", "SynthBrass 1", "SynthBrass 2",
+    "Soprano Sax", "Alto Sax", "Tenor Sax", "Baritone Sax",
+    "Oboe", "English Horn", "Bassoon", "Clarinet",
+    "Piccolo", "Flute", "Recorder", "Pan Flute",
+    "Blown Bottle", "Shakuhachi", "Whistle", "Ocarina",
+    "Lead 1 (square)", "Lead 2 (sawtooth)", "Lead 3 (calliope)", "Lead 4 (chiff)",
+    "Lead 5 (charang)", "Lead 6 (voice)", "Lead 7 (fifths)", "Lead 8 (bass + lead)",
+    "Pad 1 (new age)", "Pad 2 (warm)", "Pad 3 (polysynth)", "Pad 4 (choir)",
+    "Pad 5 (bowed)", "Pad 6 (metallic)", "Pad 7 (halo)", "Pad 8 (sweep)",
+    "FX 1 (rain)", "FX 2 (soundtrack)", "FX 3 (crystal)", "FX 4 (atmosphere)",
+    "FX 5 (brightness)", "FX 6 (goblins)", "FX 7 (echoes)", "FX 8 (sci-fi)",
+    "Sitar", "Banjo", "Shamisen", "Koto",
+    "Kalimba", "Bag pipe", "Fiddle", "Shanai",
+    "Tinkle Bell", "Agogo", "Steel Drums", "Woodblock",
+    "Taiko Drum", "Melodic Tom", "Synth Drum", "Reverse Cymbal",
+    "Guitar Fret Noise", "Breath Noise", "Seashore", "Bird Tweet",
+    "Telephone Ring", "Helicopter", "Applause", "Gunshot"
+];
+
+// GM2 variations as (program, bank select LSB, name). Variation 0 of every program is the GM1 sound.
+const GM2_VARIATIONS: [(u8, u8, &str); 128] = [
+    (0, 1, "Acoustic Grand Piano (wide)"),
+    (0, 2, "Acoustic Grand Piano (dark)"),
+    (1, 1, "Bright Acoustic Piano (wide)"),
+    (2, 1, "Electric Grand Piano (wide)"),
+    (3, 1, "Honky-tonk Piano (wide)"),
+    (4, 1, "Detuned Electric Piano 1"),
+    (4, 2, "Electric Piano 1 (velocity mix)"),
+    (4, 3, "60's Electric Piano"),
+    (5, 1, "Detuned Electric Piano 2"),
+    (5, 2, "Electric Piano 2 (velocity mix)"),
+    (5, 3, "EP Legend"),
+    (5, 4, "EP Phase"),
+    (6, 1, "Harpsichord (octave mix)"),
+    (6, 2, "Harpsichord (wide)"),
+    (6, 3, "Harpsichord (with key off)"),
+    (7, 1, "Pulse Clavinet"),
+    (11, 1, "Vibraphone (wide)"),
+    (12, 1, "Marimba (wide)"),
+    (14, 1, "Church Bell"),
+    (14, 2, "Carillon"),
+    (16, 1, "Detuned Drawbar Organ"),
+    (16, 2, "Italian 60's Organ"),
+    (16, 3, "Drawbar Organ 2"),
+    (17, 1, "Detuned Percussive Organ"),
+    (17, 2, "Percussive Organ 2"),
+    (19, 1, "Church Organ (octave mix)"),
+    (19, 2, "Detuned Church Organ"),
+    (20, 1, "Puff Organ"),
+    (21, 1, "Accordion 2"),
+    (24, 1, "Ukulele"),
+    (24, 2, "Acoustic Guitar (nylon + key off)"),
+    (24, 3, "Acoustic Guitar (nylon 2)"),
+    (25, 1, "12-Strings Guitar"),
+    (25, 2, "Mandolin"),
+    (25, 3, "Steel Guitar with Body Sound"),
+    (26, 1, "Electric Guitar (pedal steel)"),
+    (27, 1, "Electric Guitar (detuned clean)"),
+    (27, 2, "Mid Tone Guitar"),
+    (28, 1, "Electric Guitar (funky cutting)"),
+    (28, 2, "Electric Guitar (muted velo-sw)"),
+    (28, 3, "Jazz Man"),
+    (29, 1, "Guitar Pinch"),
+    (30, 1, "Distortion Guitar (with feedback)"),
+    (30, 2, "Distorted Rhythm Guitar"),
+    (31, 1, "Guitar Feedback"),
+    (33, 1, "Finger Slap Bass"),
+    (38, 1, "Synth Bass (warm)"),
+    (38, 2, "Synth Bass 3 (resonance)"),
+    (38, 3, "Clavi Bass"),
+    (38, 4, "Hammer"),
+    (39, 1, "Synth Bass 4 (attack)"),
+    (39, 2, "Synth Bass (rubber)"),
+    (39, 3, "Attack Pulse"),
+    (40, 1, "Violin (slow attack)"),
+    (46, 1, "Yang Chin"),
+    (48, 1, "Strings and Brass"),
+    (48, 2, "60s Strings"),
+    (50, 1, "Synth Strings 3"),
+    (52, 1, "Choir Aahs 2"),
+    (53, 1, "Humming"),
+    (54, 1, "Analog Voice"),
+    (55, 1, "Bass Hit Plus"),
+    (55, 2, "6th Hit"),
+    (55, 3, "Euro Hit"),
+    (56, 1, "Dark Trumpet Soft"),
+    (57, 1, "Trombone 2"),
+    (57, 2, "Bright Trombone"),
+    (59, 1, "Muted Trumpet 2"),
+    (60, 1, "French Horn 2 (warm)"),
+    (61, 1, "Brass Section 2 (octave mix)"),
+    (62, 1, "Synth Brass 3"),
+    (62, 2, "Analog Synth Brass 1"),
+    (62, 3, "Jump Brass"),
+    (63, 1, "Synth Brass 4"),
+    (63, 2, "Analog Synth Brass 2"),
+    (80, 1, "Lead 1a (square 2)"),
+    (80, 2, "Lead 1b (sine)"),
+    (81, 1, "Lead 2a (sawtooth 2)"),
+    (81, 2, "Lead 2b (saw + pulse)"),
+    (81, 3, "Lead 2c (double sawtooth)"),
+    (81, 4, "Lead 2d (sequenced analog)"),
+    (84, 1, "Lead 5a (wire lead)"),
+    (87, 1, "Lead 8a (soft wrl)"),
+    (89, 1, "Pad 2a (sine pad)"),
+    (91, 1, "Pad 4a (itopia)"),
+    (98, 1, "FX 3a (synth mallet)"),
+    (102, 1, "FX 7a (echo bell)"),
+    (102, 2, "FX 7b (echo pan)"),
+    (104, 1, "Sitar 2 (bend)"),
+    (107, 1, "Taisho Koto"),
+    (115, 1, "Castanets"),
+    (116, 1, "Concert Bass Drum"),
+    (117, 1, "Melodic Tom 2 (power)"),
+    (118, 1, "Rhythm Box Tom"),
+    (118, 2, "Electric Drum"),
+    (120, 1, "Guitar Cutting Noise"),
+    (120, 2, "Acoustic Bass String Slap"),
+    (121, 1, "Flute Key Click"),
+    (122, 1, "Rain"),
+    (122, 2, "Thunder"),
+    (122, 3, "Wind"),
+    (122, 4, "Stream"),
+    (122, 5, "Bubble"),
+    (123, 1, "Dog"),
+    (123, 2, "Horse Gallop"),
+    (123, 3, "Bird Tweet 2"),
+    (124, 1, "Telephone Ring 2"),
+    (124, 2, "Door Creaking"),
+    (124, 3, "Door"),
+    (124, 4, "Scratch"),
+    (124, 5, "Wind Chime"),
+    (125, 1, "Car Engine"),
+    (125, 2, "Car Stop"),
+    (125, 3, "Car Pass"),
+    (125, 4, "Car Crash"),
+    (125, 5, "Siren"),
+    (125, 6, "Train"),
+    (125, 7, "Jetplane"),
+    (125, 8, "Starship"),
+    (125, 9, "Burst Noise"),
+    (126, 1, "Laughing"),
+    (126, 2, "Screaming"),
+    (126, 3, "Punch"),
+    (126, 4, "Heart Beat"),
+    (126, 5, "Footsteps"),
+    (127, 1, "Machine Gun"),
+    (127, 2, "Lasergun"),
+    (127, 3, "Explosion")
+];
+
+// GM2 rhythm sets, by program
+const DRUM_KIT_NAMES: [(u8, &str); 9] = [
+    (0, "Standard Set"),
+    (8, "Room Set"),
+    (16, "Power Set"),
+    (24, "Electronic Set"),
+    (25, "Analog Set"),
+    (32, "Jazz Set"),
+    (40, "Brush Set"),
+    (48, "Orchestra Set"),
+    (56, "SFX Set")
+];
+
+// Percussion key map from note 27 (GM2) through 87. GM1 only defines 35 to 81.
+const FIRST_PERCUSSION_NOTE: u8 = 27;
+const PERCUSSION_NAMES: [&str; 61] = [
+    "High Q", "Slap", "Scratch Push", "Scratch Pull", "Sticks", "Square Click", "Metronome Click", "Metronome Bell",
+    "Acoustic Bass Drum", "Bass Drum 1", "Side Stick", "Acoustic Snare",
+    "Hand Clap", "Electric Snare", "Low Floor Tom", "Closed Hi Hat",
+    "High Floor Tom", "Pedal Hi-Hat", "Low Tom", "Open Hi-Hat",
+    "Low-Mid Tom", "Hi-Mid Tom", "Crash Cymbal 1", "High Tom",
+    "Ride Cymbal 1", "Chinese Cymbal", "Ride Bell", "Tambourine",
+    "Splash Cymbal", "Cowbell", "Crash Cymbal 2", "Vibraslap",
+    "Ride Cymbal 2", "Hi Bongo", "Low Bongo", "Mute Hi Conga",
+    "Open Hi Conga", "Low Conga", "High Timbale", "Low Timbale",
+    "High Agogo", "Low Agogo", "Cabasa", "Maracas",
+    "Short Whistle", "Long Whistle", "Short Guiro", "Long Guiro",
+    "Claves", "Hi Wood Block", "Low Wood Block", "Mute Cuica",
+    "Open Cuica", "Mute Triangle", "Open Triangle",
+    "Shaker", "Jingle Bell", "Belltree", "Castanets", "Mute Surdo", "Open Surdo"
+];
+
+impl ProgramFamily {
+    pub fn from_program(program: U7) -> ProgramFamily {
+        match program.get() / 8 {
+            0 => ProgramFamily::Piano,
+            1 => ProgramFamily::ChromaticPercussion,
+            2 => ProgramFamily::Organ,
+            3 => ProgramFamily::Guitar,
+            4 => ProgramFamily::Bass,
+            5 => ProgramFamily::Strings,
+            6 => ProgramFamily::Ensemble,
+            7 => ProgramFamily::Brass,
+            8 => ProgramFamily::Reed,
+            9 => ProgramFamily::Pipe,
+            10 => ProgramFamily::SynthLead,
+            11 => ProgramFamily::SynthPad,
+            12 => ProgramFamily::SynthEffects,
+            13 => ProgramFamily::Ethnic,
+            14 => ProgramFamily::Percussive,
+            _ => ProgramFamily::SoundEffects
+        }
+    }
+
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            ProgramFamily::Piano => "Piano",
+            ProgramFamily::ChromaticPercussion => "Chromatic Percussion",
+            ProgramFamily::Organ => "Organ",
+            ProgramFamily::Guitar => "Guitar",
+            ProgramFamily::Bass => "Bass",
+            ProgramFamily::Strings => "Strings",
+            ProgramFamily::Ensemble => "Ensemble",
+            ProgramFamily::Brass => "Brass",
+            ProgramFamily::Reed => "Reed",
+            ProgramFamily::Pipe => "Pipe",
+            ProgramFamily::SynthLead => "Synth Lead",
+            ProgramFamily::SynthPad => "Synth Pad",
+            ProgramFamily::SynthEffects => "Synth Effects",
+            ProgramFamily::Ethnic => "Ethnic",
+            ProgramFamily::Percussive => "Percussive",
+            ProgramFamily::SoundEffects => "Sound Effects"
+        }
+    }
+}
+
+impl fmt::Display for ProgramFamily {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.get_name())
+    }
+}
+
+/// Get the General MIDI (GM1) name of a program
+pub fn get_program_name(program: U7) -> &'static str {
+    PROGRAM_NAMES[program.get() as usize]
+}
+
+/// Get the name of a GM2 melodic sound, selected with bank select MSB 0x79 and the variation as the bank select LSB.
+/// Returns None for variations that GM2 doesn't define.
+pub fn get_gm2_program_name(program: U7, variation: U7) -> Option<&'static str> {
+    if variation.get() == 0 {
+        return Some(get_program_name(program));
+    }
+
+    GM2_VARIATIONS.iter()
+        .find(|(variation_program, variation_lsb, _)| *variation_program == program.get() && *variation_lsb == variation.get())
+        .map(|(_, _, name)| *name)
+}
+
+/// Get the name of a GM2 rhythm set
+pub fn get_drum_kit_name(program: U7) -> Option<&'static str> {
+    DRUM_KIT_NAMES.iter()
+        .find(|(kit_program, _)| *kit_program == program.get())
+        .map(|(_, name)| *name)
+}
+
+/// Get the name of the percussion sound mapped to a note on a drum channel (GM2 key map, 27-87)
+pub fn get_percussion_name(note: U7) -> Option<&'static str> {
+    let index = note.get().checked_sub(FIRST_PERCUSSION_NOTE)?;
+    PERCUSSION_NAMES.get(index as usize).copied()
+}
+
+/// Get the GM/GM2 name of a controller, or None if it's undefined
+pub fn get_controller_name(controller: U7) -> Option<&'static str> {
+    let name = match controller.get() {
+        0x00 => "Bank Select",
+        0x01 => "Modulation Wheel",
+        0x02 => "Breath Controller",
+        0x04 => "Foot Controller",
+        0x05 => "Portamento Time",
+        0x06 => "Data Entry",
+        0x07 => "Channel Volume",
+        0x08 => "Balance",
+        0x0A => "Pan",
+        0x0B => "Expression",
+        0x0C => "Effect Control 1",
+        0x0D => "Effect Control 2",
+        0x10 => "General Purpose 1",
+        0x11 => "General Purpose 2",
+        0x12 => "General Purpose 3",
+        0x13 => "General Purpose 4",
+        0x20 => "Bank Select LSB",
+        0x21 => "Modulation Wheel LSB",
+        0x22 => "Breath Controller LSB",
+        0x24 => "Foot Controller LSB",
+        0x25 => "Portamento Time LSB",
+        0x26 => "Data Entry LSB",
+        0x27 => "Channel Volume LSB",
+        0x28 => "Balance LSB",
+        0x2A => "Pan LSB",
+        0x2B => "Expression LSB",
+        0x2C => "Effect Control 1 LSB",
+        0x2D => "Effect Control 2 LSB",
+        0x30 => "General Purpose 1 LSB",
+        0x31 => "General Purpose 2 LSB",
+        0x32 => "General Purpose 3 LSB",
+        0x33 => "General Purpose 4 LSB",
+        0x40 => "Sustain",
+        0x41 => "Portamento",
+        0x42 => "Sostenuto",
+        0x43 => "Soft Pedal",
+        0x44 => "Legato Footswitch",
+        0x45 => "Hold 2",
+        0x46 => "Sound Variation",
+        0x47 => "Resonance",
+        0x48 => "Release Time",
+        0x49 => "Attack Time",
+        0x4A => "Brightness",
+        0x4B => "Decay Time",
+        0x4C => "Vibrato Rate",
+        0x4D => "Vibrato Depth",
+        0x4E => "Vibrato Delay",
+        0x4F => "Sound Controller 10",
+        0x50 => "General Purpose 5",
+        0x51 => "General Purpose 6",
+        0x52 => "General Purpose 7",
+        0x53 => "General Purpose 8",
+        0x54 => "Portamento Control",
+        0x58 => "High Resolution Velocity Prefix",
+        0x5B => "Reverb Send Level",
+        0x5C => "Tremolo Depth",
+        0x5D => "Chorus Send Level",
+        0x5E => "Celeste Depth",
+        0x5F => "Phaser Depth",
+        0x60 => "Data Increment",
+        0x61 => "Data Decrement",
+        0x62 => "NRPN LSB",
+        0x63 => "NRPN MSB",
+        0x64 => "RPN LSB",
+        0x65 => "RPN MSB",
+        0x78 => "All Sound Off",
+        0x79 => "Reset All Controllers",
+        0x7A => "Local Control",
+        0x7B => "All Notes Off",
+        0x7C => "Omni Off",
+        0x7D => "Omni On",
+        0x7E => "Mono On",
+        0x7F => "Poly On",
+        _ => {
+            return None;
+        }
+    };
+
+    Some(name)
+}
+
+impl MIDIEvent {
+    /// Get the General MIDI name of the program, percussion sound or controller this event refers to.
+    /// Program changes and notes on the drum channel (10) are named as drum kits and percussion.
+    /// Bank selects aren't taken into account, see `ChannelState::get_instrument_name()` for that.
+    pub fn get_general_midi_name(&self) -> Option<&'static str> {
+        if let Some((_channel, controller, _value)) = get_control_change(self) {
+            return get_controller_name(controller);
+        }
+
+        match self {
+            MIDIEvent::ProgramChange(channel, program) if *channel == DRUM_CHANNEL => {
+                get_drum_kit_name(*program)
+            }
+            MIDIEvent::ProgramChange(_channel, program) => {
+                Some(get_program_name(*program))
+            }
+            MIDIEvent::NoteOn(channel, note, _)
+            | MIDIEvent::NoteOff(channel, note, _)
+            | MIDIEvent::AfterTouch(channel, note, _) if *channel == DRUM_CHANNEL => {
+                get_percussion_name(*note)
+            }
+            _ => {
+                None
+            }
+        }
+    }
+}
+
+impl ChannelState {
+    /// Whether the channel plays drums: channel 10 unless a GM2 melodic bank is selected, or any channel with the GM2 rhythm bank
+    pub fn is_percussion(&self) -> bool {
+        match self.bank_select {
+            Some(bank) if bank == GM2_RHYTHM_BANK => true,
+            Some(bank) if bank == GM2_MELODIC_BANK => false,
+            _ => self.channel == DRUM_CHANNEL
+        }
+    }
+
+    /// Get the GM/GM2 name of the selected instrument (or drum kit), taking bank select into account.
+    /// Undefined GM2 variations fall back to the program's main sound, as GM2 devices do.
+    pub fn get_instrument_name(&self) -> Option<&'static str> {
+        let program = self.program.unwrap_or_default();
+        if self.is_percussion() {
+            get_drum_kit_name(program)
+        } else if self.program.is_none() {
+            None
+        } else if self.bank_select == Some(GM2_MELODIC_BANK) {
+            get_gm2_program_name(program, self.bank_select_lsb.unwrap_or_default())
+                .or_else(|| Some(get_program_name(program)))
+        } else {
+            Some(get_program_name(program))
+        }
+    }
+
+    /// Get the name of the percussion sound a note plays on this channel, if it's a drum channel
+    pub fn get_percussion_name(&self, note: U7) -> Option<&'static str> {
+        if self.is_percussion() {
+            get_percussion_name(note)
+        } else {
+            None
+        }
+    }
+}
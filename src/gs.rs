@@ -0,0 +1,322 @@
+use std::convert::TryFrom;
+
+use crate::{MIDIEvent, ApresError, U7};
+
+pub const ROLAND_ID: u8 = 0x41;
+pub const GS_MODEL_ID: u8 = 0x42;
+
+const RQ1: u8 = 0x11;
+const DT1: u8 = 0x12;
+
+const RESET_ADDRESS: [u8; 3] = [0x40, 0x00, 0x7F];
+const REVERB_MACRO_ADDRESS: [u8; 3] = [0x40, 0x01, 0x30];
+const CHORUS_MACRO_ADDRESS: [u8; 3] = [0x40, 0x01, 0x38];
+
+/// Roland GS system exclusive message
+#[derive(Clone, Debug, PartialEq)]
+pub enum GSMessage {
+    /// DT1: Write data starting at an address
+    DataSet { device_id: U7, address: [u8; 3], data: Vec<u8> },
+    /// RQ1: Ask for `size` bytes starting at an address
+    DataRequest { device_id: U7, address: [u8; 3], size: u32 }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GSReverbType {
+    Room1,
+    Room2,
+    Room3,
+    Hall1,
+    Hall2,
+    Plate,
+    Delay,
+    PanningDelay
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GSChorusType {
+    Chorus1,
+    Chorus2,
+    Chorus3,
+    Chorus4,
+    FeedbackChorus,
+    Flanger,
+    ShortDelay,
+    ShortDelayFeedback
+}
+
+/// Per-part parameters, at address 0x40 0x1n 0xpp
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GSPartParameter {
+    /// Channel the part listens on, 0-15, or 0x10 for off
+    ReceiveChannel,
+    /// 0 for a normal part, 1 or 2 to use drum map 1 or 2
+    UseForRhythmPart,
+    /// Semitones, centered at 0x40
+    PitchKeyShift,
+    Level,
+    /// 0 is random, otherwise centered at 0x40
+    Pan,
+    KeyRangeLow,
+    KeyRangeHigh,
+    ChorusSendLevel,
+    ReverbSendLevel
+}
+
+/// Well known GS parameters, set with a DT1 message
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GSParameter {
+    /// GS Reset, to initialize a GS module
+    Reset,
+    ReverbMacro(GSReverbType),
+    ChorusMacro(GSChorusType),
+    /// Part parameter. Parts are counted from 0, with part 9 (part 10) being the drum part by default.
+    Part { part: u8, parameter: GSPartParameter, value: U7 }
+}
+
+/// Get the Roland checksum of a message's address and data: the value that brings their sum to a multiple of 128
+pub fn get_roland_checksum(bytes: &[u8]) -> u8 {
+    let sum: u32 = bytes.iter().map(|byte| *byte as u32).sum();
+    ((128 - (sum % 128)) % 128) as u8
+}
+
+impl GSReverbType {
+    fn from_value(value: u8) -> Option<GSReverbType> {
+        match value {
+            0 => Some(GSReverbType::Room1),
+            1 => Some(GSReverbType::Room2),
+            2 => Some(GSReverbType::Room3),
+            3 => Some(GSReverbType::Hall1),
+            4 => Some(GSReverbType::Hall2),
+            5 => Some(GSReverbType::Plate),
+            6 => Some(GSReverbType::Delay),
+            7 => Some(GSReverbType::PanningDelay),
+            _ => None
+        }
+    }
+
+    fn get_value(&self) -> u8 {
+        match self {
+            GSReverbType::Room1 => 0,
+            GSReverbType::Room2 => 1,
+            GSReverbType::Room3 => 2,
+            GSReverbType::Hall1 => 3,
+            GSReverbType::Hall2 => 4,
+            GSReverbType::Plate => 5,
+            GSReverbType::Delay => 6,
+            GSReverbType::PanningDelay => 7
+        }
+    }
+}
+
+impl GSChorusType {
+    fn from_value(value: u8) -> Option<GSChorusType> {
+        match value {
+            0 => Some(GSChorusType::Chorus1),
+            1 => Some(GSChorusType::Chorus2),
+            2 => Some(GSChorusType::Chorus3),
+            3 => Some(GSChorusType::Chorus4),
+            4 => Some(GSChorusType::FeedbackChorus),
+            5 => Some(GSChorusType::Flanger),
+            6 => Some(GSChorusType::ShortDelay),
+            7 => Some(GSChorusType::ShortDelayFeedback),
+            _ => None
+        }
+    }
+
+    fn get_value(&self) -> u8 {
+        match self {
+            GSChorusType::Chorus1 => 0,
+            GSChorusType::Chorus2 => 1,
+            GSChorusType::Chorus3 => 2,
+            GSChorusType::Chorus4 => 3,
+            GSChorusType::FeedbackChorus => 4,
+            GSChorusType::Flanger => 5,
+            GSChorusType::ShortDelay => 6,
+            GSChorusType::ShortDelayFeedback => 7
+        }
+    }
+}
+
+impl GSPartParameter {
+    fn from_address(address: u8) -> Option<GSPartParameter> {
+        match address {
+            0x02 => Some(GSPartParameter::ReceiveChannel),
+            0x15 => Some(GSPartParameter::UseForRhythmPart),
+            0x16 => Some(GSPartParameter::PitchKeyShift),
+            0x19 => Some(GSPartParameter::Level),
+            0x1C => Some(GSPartParameter::Pan),
+            0x1D => Some(GSPartParameter::KeyRangeLow),
+            0x1E => Some(GSPartParameter::KeyRangeHigh),
+            0x21 => Some(GSPartParameter::ChorusSendLevel),
+            0x22 => Some(GSPartParameter::ReverbSendLevel),
+            _ => None
+        }
+    }
+
+    fn get_address(&self) -> u8 {
+        match self {
+            GSPartParameter::ReceiveChannel => 0x02,
+            GSPartParameter::UseForRhythmPart => 0x15,
+            GSPartParameter::PitchKeyShift => 0x16,
+            GSPartParameter::Level => 0x19,
+            GSPartParameter::Pan => 0x1C,
+            GSPartParameter::KeyRangeLow => 0x1D,
+            GSPartParameter::KeyRangeHigh => 0x1E,
+            GSPartParameter::ChorusSendLevel => 0x21,
+            GSPartParameter::ReverbSendLevel => 0x22
+        }
+    }
+}
+
+// GS numbers its part blocks with the drum part (10) first: block 0 is part 10, blocks 1-9 are parts 1-9
+// and blocks 10-15 are parts 11-16
+fn get_part_block(part: u8) -> u8 {
+    match part {
+        0..=8 => part + 1,
+        9 => 0,
+        _ => part
+    }
+}
+
+fn get_block_part(block: u8) -> u8 {
+    match block {
+        0 => 9,
+        1..=9 => block - 1,
+        _ => block
+    }
+}
+
+impl GSMessage {
+    /// Build a DT1 message that sets a well known parameter
+    pub fn from_parameter(device_id: U7, parameter: GSParameter) -> Result<GSMessage, ApresError> {
+        let (address, value) = match parameter {
+            GSParameter::Reset => {
+                (RESET_ADDRESS, 0x00)
+            }
+            GSParameter::ReverbMacro(reverb_type) => {
+                (REVERB_MACRO_ADDRESS, reverb_type.get_value())
+            }
+            GSParameter::ChorusMacro(chorus_type) => {
+                (CHORUS_MACRO_ADDRESS, chorus_type.get_value())
+            }
+            GSParameter::Part { part, parameter, value } => {
+                if part > 0x0F {
+                    return Err(ApresError::ValueOutOfRange(part as u32, 0x0F));
+                }
+                ([0x40, 0x10 | get_part_block(part), parameter.get_address()], value.get())
+            }
+        };
+
+        Ok(GSMessage::DataSet { device_id, address, data: vec![value] })
+    }
+
+    /// Get the well known parameter a DT1 message sets, if any
+    pub fn get_parameter(&self) -> Option<GSParameter> {
+        let (address, value) = match self {
+            GSMessage::DataSet { address, data, .. } if data.len() == 1 => {
+                (*address, data[0])
+            }
+            _ => {
+                return None;
+            }
+        };
+
+        match address {
+            RESET_ADDRESS if value == 0x00 => {
+                Some(GSParameter::Reset)
+            }
+            REVERB_MACRO_ADDRESS => {
+                GSReverbType::from_value(value).map(GSParameter::ReverbMacro)
+            }
+            CHORUS_MACRO_ADDRESS => {
+                GSChorusType::from_value(value).map(GSParameter::ChorusMacro)
+            }
+            [0x40, block, parameter] if block & 0xF0 == 0x10 => {
+                Some(GSParameter::Part {
+                    part: get_block_part(block & 0x0F),
+                    parameter: GSPartParameter::from_address(parameter)?,
+                    value: U7::new(value).ok()?
+                })
+            }
+            _ => {
+                None
+            }
+        }
+    }
+
+    pub fn get_device_id(&self) -> U7 {
+        match self {
+            GSMessage::DataSet { device_id, .. } | GSMessage::DataRequest { device_id, .. } => *device_id
+        }
+    }
+
+    /// Parse a system exclusive message's data (not including the 0xF0 and 0xF7 bytes), checking the checksum
+    pub fn from_bytes(bytes: &[u8]) -> Result<GSMessage, ApresError> {
+        if bytes.len() < 8 || bytes[0] != ROLAND_ID || bytes[2] != GS_MODEL_ID || bytes.iter().any(|byte| *byte > 0x7F) {
+            return Err(ApresError::InvalidBytes(bytes.to_vec()));
+        }
+
+        let body = &bytes[4..bytes.len() - 1];
+        if get_roland_checksum(body) != bytes[bytes.len() - 1] {
+            return Err(ApresError::InvalidBytes(bytes.to_vec()));
+        }
+
+        let device_id = U7::new(bytes[1])?;
+        let address = [body[0], body[1], body[2]];
+        match (bytes[3], &body[3..]) {
+            (DT1, data) if !data.is_empty() => {
+                Ok(GSMessage::DataSet { device_id, address, data: data.to_vec() })
+            }
+            (RQ1, [a, b, c]) => {
+                let size = ((*a as u32) << 14) | ((*b as u32) << 7) | (*c as u32);
+                Ok(GSMessage::DataRequest { device_id, address, size })
+            }
+            _ => {
+                Err(ApresError::InvalidBytes(bytes.to_vec()))
+            }
+        }
+    }
+
+    /// Get the system exclusive message's data (not including the 0xF0 and 0xF7 bytes), with the checksum
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let (device_id, command, mut body) = match self {
+            GSMessage::DataSet { device_id, address, data } => {
+                let mut body = address.to_vec();
+                body.extend(data.iter().copied());
+                (device_id, DT1, body)
+            }
+            GSMessage::DataRequest { device_id, address, size } => {
+                let mut body = address.to_vec();
+                body.extend([(size >> 14) as u8 & 0x7F, (size >> 7) as u8 & 0x7F, *size as u8 & 0x7F].iter());
+                (device_id, RQ1, body)
+            }
+        };
+
+        body.push(get_roland_checksum(&body));
+
+        let mut output = vec![ROLAND_ID, device_id.get(), GS_MODEL_ID, command];
+        output.extend(body);
+        output
+    }
+}
+
+impl From<GSMessage> for MIDIEvent {
+    fn from(message: GSMessage) -> MIDIEvent {
+        MIDIEvent::SystemExclusive(message.as_bytes())
+    }
+}
+
+impl TryFrom<MIDIEvent> for GSMessage {
+    type Error = MIDIEvent;
+    fn try_from(event: MIDIEvent) -> Result<GSMessage, MIDIEvent> {
+        match &event {
+            MIDIEvent::SystemExclusive(data) => {
+                GSMessage::from_bytes(data).map_err(|_| event)
+            }
+            _ => {
+                Err(event)
+            }
+        }
+    }
+}
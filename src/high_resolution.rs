@@ -0,0 +1,249 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::{MIDI, MIDIEvent, MIDIBytes, ApresError, Channel, U7, U14, build_control_change};
+
+/// Highest controller number that has a paired LSB controller (controller + 0x20)
+const MAX_MSB_CONTROLLER: u8 = 0x1F;
+
+/// A controller value combined from an MSB (0x00-0x1F) and its LSB (0x20-0x3F) message
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ControlChange14 {
+    pub channel: Channel,
+    /// The MSB controller number, 0x00-0x1F
+    pub controller: U7,
+    pub value: U14
+}
+
+impl ControlChange14 {
+    pub fn new(channel: Channel, controller: U7, value: U14) -> Result<ControlChange14, ApresError> {
+        if controller.get() > MAX_MSB_CONTROLLER {
+            Err(ApresError::ValueOutOfRange(controller.get() as u32, MAX_MSB_CONTROLLER as u32))
+        } else {
+            Ok(ControlChange14 { channel, controller, value })
+        }
+    }
+
+    pub fn get_lsb_controller(&self) -> U7 {
+        U7::new_masked(self.controller.get() + 0x20)
+    }
+
+    /// Split into the MSB event followed by the LSB event
+    pub fn to_events(&self) -> (MIDIEvent, MIDIEvent) {
+        (
+            build_control_change(self.channel, self.controller, self.value.get_msb()),
+            build_control_change(self.channel, self.get_lsb_controller(), self.value.get_lsb())
+        )
+    }
+}
+
+/// How an MSB message is handled when it isn't followed by its LSB
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MSBOnlyMode {
+    /// Output the value as soon as the MSB arrives, resetting the LSB to 0, as the MIDI spec specifies.
+    /// A following LSB outputs the value again, refined.
+    ResetLSB,
+    /// Output the value as soon as the MSB arrives, keeping the last LSB received for that controller
+    KeepLSB,
+    /// Hold the MSB until the next message. If that's the matching LSB, output a single combined value,
+    /// otherwise output the MSB with an LSB of 0.
+    WaitForLSB
+}
+
+/// An event passed through a ControllerPairer
+#[derive(Clone, Debug, PartialEq)]
+pub enum PairedEvent {
+    ControlChange14(ControlChange14),
+    /// Any event that isn't part of a 14-bit controller pair
+    Event(MIDIEvent)
+}
+
+/// Combines MSB/LSB controller messages into 14-bit values, passing all other events through in order.
+#[derive(Clone, Debug)]
+pub struct ControllerPairer {
+    mode: MSBOnlyMode,
+    msb_values: HashMap<(Channel, U7), U7>,
+    lsb_values: HashMap<(Channel, U7), U7>,
+    pending: Option<(Channel, U7)>,
+    ready: VecDeque<PairedEvent>
+}
+
+impl ControllerPairer {
+    pub fn new(mode: MSBOnlyMode) -> ControllerPairer {
+        ControllerPairer {
+            mode,
+            msb_values: HashMap::new(),
+            lsb_values: HashMap::new(),
+            pending: None,
+            ready: VecDeque::new()
+        }
+    }
+
+    pub fn get_mode(&self) -> MSBOnlyMode {
+        self.mode
+    }
+
+    /// Whether an MSB is being held, waiting for its LSB
+    pub fn has_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Pass an event in. Any resulting events can be taken with `pop()`.
+    pub fn push(&mut self, event: MIDIEvent) {
+        let (channel, controller, value) = match get_control_change(&event) {
+            Some(control_change) => {
+                control_change
+            }
+            None => {
+                self.flush();
+                self.ready.push_back(PairedEvent::Event(event));
+                return;
+            }
+        };
+
+        if controller.get() <= MAX_MSB_CONTROLLER {
+            self.flush();
+            let key = (channel, controller);
+            self.msb_values.insert(key, value);
+            match self.mode {
+                MSBOnlyMode::ResetLSB => {
+                    self.lsb_values.insert(key, U7::default());
+                    self.output(key);
+                }
+                MSBOnlyMode::KeepLSB => {
+                    self.output(key);
+                }
+                MSBOnlyMode::WaitForLSB => {
+                    self.pending = Some(key);
+                }
+            }
+        } else if controller.get() <= MAX_MSB_CONTROLLER + 0x20 {
+            let key = (channel, U7::new_masked(controller.get() - 0x20));
+            if self.pending != Some(key) {
+                self.flush();
+            }
+            self.pending = None;
+            self.lsb_values.insert(key, value);
+
+            if self.msb_values.contains_key(&key) {
+                self.output(key);
+            } else {
+                // Nothing to pair an LSB with until its MSB has been seen
+                self.ready.push_back(PairedEvent::Event(event));
+            }
+        } else {
+            self.flush();
+            self.ready.push_back(PairedEvent::Event(event));
+        }
+    }
+
+    /// Take the next resulting event, if any
+    pub fn pop(&mut self) -> Option<PairedEvent> {
+        self.ready.pop_front()
+    }
+
+    /// Pass an event in and take all the resulting events
+    pub fn feed(&mut self, event: MIDIEvent) -> Vec<PairedEvent> {
+        self.push(event);
+        self.ready.drain(..).collect()
+    }
+
+    /// Stop waiting on any held MSB, outputting it with an LSB of 0
+    pub fn flush(&mut self) {
+        if let Some(key) = self.pending.take() {
+            self.lsb_values.insert(key, U7::default());
+            self.output(key);
+        }
+    }
+
+    fn output(&mut self, key: (Channel, U7)) {
+        let (channel, controller) = key;
+        let msb = *self.msb_values.get(&key).unwrap_or(&U7::default());
+        let lsb = *self.lsb_values.get(&key).unwrap_or(&U7::default());
+        self.ready.push_back(PairedEvent::ControlChange14(ControlChange14 {
+            channel,
+            controller,
+            value: U14::from_lsb_msb(lsb, msb)
+        }));
+    }
+}
+
+/// Get the (channel, controller, value) of any control change event, named or not
+pub(crate) fn get_control_change(event: &MIDIEvent) -> Option<(Channel, U7, U7)> {
+    let bytes = event.as_bytes();
+    if bytes.len() == 3 && bytes[0] & 0xF0 == 0xB0 {
+        Some((Channel::from_status(bytes[0]), U7::new_masked(bytes[1]), U7::new_masked(bytes[2])))
+    } else {
+        None
+    }
+}
+
+impl MIDI {
+    /// Get a track's events as (tick, event), with MSB/LSB controller pairs combined into 14-bit values.
+    /// A combined value is placed at the tick of its MSB.
+    pub fn get_paired_events(&self, track: usize, mode: MSBOnlyMode) -> Vec<(usize, PairedEvent)> {
+        let mut pairer = ControllerPairer::new(mode);
+        let mut output = Vec::new();
+        let mut pending_tick = 0;
+
+        for (tick, event_track, event_id) in self.get_ordered_event_ids().iter() {
+            if *event_track != track {
+                continue;
+            }
+            let event = match self.events.get(event_id) {
+                Some(event) => {
+                    event.clone()
+                }
+                None => {
+                    continue;
+                }
+            };
+
+            let was_pending = pairer.has_pending();
+            for (i, paired_event) in pairer.feed(event).into_iter().enumerate() {
+                // Anything held back is always the first event out
+                if was_pending && i == 0 {
+                    output.push((pending_tick, paired_event));
+                } else {
+                    output.push((*tick, paired_event));
+                }
+            }
+
+            if pairer.has_pending() {
+                pending_tick = *tick;
+            }
+        }
+
+        pairer.flush();
+        while let Some(paired_event) = pairer.pop() {
+            output.push((pending_tick, paired_event));
+        }
+
+        output
+    }
+
+    /// Insert a 14-bit controller value as its MSB event followed by its LSB event.
+    /// Recorded as a single 'control_change_14' transaction. Returns the ids of the (MSB, LSB) events.
+    pub fn insert_control_change_14(&mut self, track: usize, tick: usize, control_change: ControlChange14) -> Result<(u64, u64), ApresError> {
+        let (msb_event, lsb_event) = control_change.to_events();
+
+        self.begin_transaction("control_change_14");
+        let output = match self.insert_event(track, tick, msb_event) {
+            Ok(msb_id) => {
+                match self.insert_event(track, tick, lsb_event) {
+                    Ok(lsb_id) => {
+                        Ok((msb_id, lsb_id))
+                    }
+                    Err(e) => {
+                        Err(e)
+                    }
+                }
+            }
+            Err(e) => {
+                Err(e)
+            }
+        };
+        self.end_transaction();
+
+        output
+    }
+}
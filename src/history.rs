@@ -0,0 +1,309 @@
+use crate::{MIDI, MIDIEvent};
+
+/// A single, invertible change to a MIDI.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MIDIEdit {
+    InsertEvent {
+        event_id: u64,
+        track: usize,
+        tick: usize,
+        event: MIDIEvent
+    },
+    RemoveEvent {
+        event_id: u64,
+        track: usize,
+        tick: usize,
+        event: MIDIEvent
+    },
+    /// Positions are (track, tick). A position of None means the event id had no position.
+    MoveEvent {
+        event_id: u64,
+        from: Option<(usize, usize)>,
+        to: Option<(usize, usize)>
+    },
+    ReplaceEvent {
+        event_id: u64,
+        track: usize,
+        tick: usize,
+        old_event: MIDIEvent,
+        new_event: MIDIEvent
+    },
+    SetPPQN {
+        old_ppqn: u16,
+        new_ppqn: u16
+    },
+    SetFormat {
+        old_format: u16,
+        new_format: u16
+    }
+}
+
+impl MIDIEdit {
+    /// Get the edit that reverses this one
+    pub fn inverse(&self) -> MIDIEdit {
+        match self {
+            MIDIEdit::InsertEvent { event_id, track, tick, event } => {
+                MIDIEdit::RemoveEvent {
+                    event_id: *event_id,
+                    track: *track,
+                    tick: *tick,
+                    event: event.clone()
+                }
+            }
+            MIDIEdit::RemoveEvent { event_id, track, tick, event } => {
+                MIDIEdit::InsertEvent {
+                    event_id: *event_id,
+                    track: *track,
+                    tick: *tick,
+                    event: event.clone()
+                }
+            }
+            MIDIEdit::MoveEvent { event_id, from, to } => {
+                MIDIEdit::MoveEvent {
+                    event_id: *event_id,
+                    from: *to,
+                    to: *from
+                }
+            }
+            MIDIEdit::ReplaceEvent { event_id, track, tick, old_event, new_event } => {
+                MIDIEdit::ReplaceEvent {
+                    event_id: *event_id,
+                    track: *track,
+                    tick: *tick,
+                    old_event: new_event.clone(),
+                    new_event: old_event.clone()
+                }
+            }
+            MIDIEdit::SetPPQN { old_ppqn, new_ppqn } => {
+                MIDIEdit::SetPPQN {
+                    old_ppqn: *new_ppqn,
+                    new_ppqn: *old_ppqn
+                }
+            }
+            MIDIEdit::SetFormat { old_format, new_format } => {
+                MIDIEdit::SetFormat {
+                    old_format: *new_format,
+                    new_format: *old_format
+                }
+            }
+        }
+    }
+
+    /// Get the id of the event this edit affects, if any
+    pub fn get_event_id(&self) -> Option<u64> {
+        match self {
+            MIDIEdit::InsertEvent { event_id, .. }
+            | MIDIEdit::RemoveEvent { event_id, .. }
+            | MIDIEdit::MoveEvent { event_id, .. }
+            | MIDIEdit::ReplaceEvent { event_id, .. } => {
+                Some(*event_id)
+            }
+            MIDIEdit::SetPPQN { .. } | MIDIEdit::SetFormat { .. } => {
+                None
+            }
+        }
+    }
+
+    fn get_name(&self) -> &'static str {
+        match self {
+            MIDIEdit::InsertEvent { .. } => "insert_event",
+            MIDIEdit::RemoveEvent { .. } => "remove_event",
+            MIDIEdit::MoveEvent { .. } => "move_event",
+            MIDIEdit::ReplaceEvent { .. } => "replace_event",
+            MIDIEdit::SetPPQN { .. } => "set_ppqn",
+            MIDIEdit::SetFormat { .. } => "set_format"
+        }
+    }
+}
+
+/// A named group of edits that are undone and redone together
+#[derive(Clone, Debug, PartialEq)]
+pub struct Transaction {
+    pub name: String,
+    pub edits: Vec<MIDIEdit>
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct EditHistory {
+    undo_stack: Vec<Transaction>,
+    redo_stack: Vec<Transaction>,
+    open_transaction: Option<Transaction>,
+    transaction_depth: usize,
+    // Set while undoing/redoing so the applied edits aren't recorded again
+    replaying: bool
+}
+
+impl EditHistory {
+    pub fn new() -> EditHistory {
+        EditHistory::default()
+    }
+
+    fn record(&mut self, edit: MIDIEdit) {
+        if self.replaying {
+            return;
+        }
+
+        self.redo_stack.clear();
+        match self.open_transaction.as_mut() {
+            Some(transaction) => {
+                transaction.edits.push(edit);
+            }
+            None => {
+                self.undo_stack.push(Transaction {
+                    name: edit.get_name().to_string(),
+                    edits: vec![edit]
+                });
+            }
+        }
+    }
+
+    fn close_transaction(&mut self) {
+        self.transaction_depth = 0;
+        if let Some(transaction) = self.open_transaction.take() {
+            if !transaction.edits.is_empty() {
+                self.undo_stack.push(transaction);
+            }
+        }
+    }
+}
+
+impl MIDI {
+    /// Start recording edits so they can be undone. Has no effect if history is already enabled.
+    pub fn enable_history(&mut self) {
+        if self.history.is_none() {
+            self.history = Some(EditHistory::new());
+        }
+    }
+
+    /// Stop recording edits and forget any recorded history
+    pub fn disable_history(&mut self) {
+        self.history = None;
+    }
+
+    pub fn is_history_enabled(&self) -> bool {
+        self.history.is_some()
+    }
+
+    pub(crate) fn record_edit(&mut self, edit: MIDIEdit) {
+        if let Some(history) = self.history.as_mut() {
+            history.record(edit);
+        }
+    }
+
+    /// Group all edits until the matching `end_transaction()` into a single undo step.
+    /// Transactions may be nested; only the outermost one is recorded, under its name.
+    pub fn begin_transaction(&mut self, name: &str) {
+        if let Some(history) = self.history.as_mut() {
+            if history.open_transaction.is_none() {
+                history.open_transaction = Some(Transaction {
+                    name: name.to_string(),
+                    edits: Vec::new()
+                });
+            }
+            history.transaction_depth += 1;
+        }
+    }
+
+    pub fn end_transaction(&mut self) {
+        if let Some(history) = self.history.as_mut() {
+            if history.transaction_depth > 1 {
+                history.transaction_depth -= 1;
+            } else {
+                history.close_transaction();
+            }
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        match &self.history {
+            Some(history) => {
+                !history.undo_stack.is_empty() || history.open_transaction.as_ref().is_some_and(|t| !t.edits.is_empty())
+            }
+            None => {
+                false
+            }
+        }
+    }
+
+    pub fn can_redo(&self) -> bool {
+        match &self.history {
+            Some(history) => {
+                !history.redo_stack.is_empty()
+            }
+            None => {
+                false
+            }
+        }
+    }
+
+    /// Get the names of the undoable transactions, most recent last
+    pub fn get_undo_names(&self) -> Vec<String> {
+        match &self.history {
+            Some(history) => {
+                history.undo_stack.iter().map(|t| t.name.clone()).collect()
+            }
+            None => {
+                Vec::new()
+            }
+        }
+    }
+
+    /// Reverse the most recent transaction. Any open transaction is closed first.
+    /// Returns the name of the undone transaction.
+    pub fn undo(&mut self) -> Option<String> {
+        let transaction = match self.history.as_mut() {
+            Some(history) => {
+                history.close_transaction();
+                history.undo_stack.pop()?
+            }
+            None => {
+                return None;
+            }
+        };
+
+        self.replay(transaction.edits.iter().rev().map(|edit| edit.inverse()).collect());
+
+        let name = transaction.name.clone();
+        if let Some(history) = self.history.as_mut() {
+            history.redo_stack.push(transaction);
+        }
+
+        Some(name)
+    }
+
+    /// Reapply the most recently undone transaction. Returns the name of the redone transaction.
+    pub fn redo(&mut self) -> Option<String> {
+        let transaction = match self.history.as_mut() {
+            Some(history) => {
+                history.close_transaction();
+                history.redo_stack.pop()?
+            }
+            None => {
+                return None;
+            }
+        };
+
+        self.replay(transaction.edits.clone());
+
+        let name = transaction.name.clone();
+        if let Some(history) = self.history.as_mut() {
+            history.undo_stack.push(transaction);
+        }
+
+        Some(name)
+    }
+
+    fn replay(&mut self, edits: Vec<MIDIEdit>) {
+        if let Some(history) = self.history.as_mut() {
+            history.replaying = true;
+        }
+
+        for edit in edits.into_iter() {
+            self.apply_edit(edit);
+        }
+
+        if let Some(history) = self.history.as_mut() {
+            history.replaying = false;
+        }
+    }
+}
@@ -0,0 +1,251 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::ApresError;
+
+/// Letter name of a key's tonic
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Tonic {
+    C,
+    D,
+    E,
+    F,
+    G,
+    A,
+    B
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Accidental {
+    Flat,
+    Natural,
+    Sharp
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Mode {
+    Major,
+    Minor
+}
+
+/// A key, as used in KeySignature.
+///
+/// Every key can be stored in a MIDI file, but keys with more than 7 sharps or flats
+/// (eg, D# major) are stored as their enharmonic equivalent (Eb major).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Key {
+    pub tonic: Tonic,
+    pub accidental: Accidental,
+    pub mode: Mode
+}
+
+impl Tonic {
+    /// Position on the circle of fifths, relative to C
+    fn get_fifths(&self) -> i8 {
+        match self {
+            Tonic::F => -1,
+            Tonic::C => 0,
+            Tonic::G => 1,
+            Tonic::D => 2,
+            Tonic::A => 3,
+            Tonic::E => 4,
+            Tonic::B => 5
+        }
+    }
+
+    fn from_fifths(fifths: i8) -> Tonic {
+        match fifths {
+            -1 => Tonic::F,
+            0 => Tonic::C,
+            1 => Tonic::G,
+            2 => Tonic::D,
+            3 => Tonic::A,
+            4 => Tonic::E,
+            _ => Tonic::B
+        }
+    }
+}
+
+impl Key {
+    pub fn new(tonic: Tonic, accidental: Accidental, mode: Mode) -> Key {
+        Key { tonic, accidental, mode }
+    }
+
+    /// Build a key from the KeySignature bytes.
+    /// `sf` is the number of sharps (positive) or flats (negative), -7 to 7. `mi` is 0 for major, 1 for minor.
+    pub fn from_sf_mi(sf: i8, mi: u8) -> Result<Key, ApresError> {
+        let mode = match mi {
+            0 => Mode::Major,
+            1 => Mode::Minor,
+            _ => {
+                return Err(ApresError::InvalidBytes(vec![sf as u8, mi]));
+            }
+        };
+
+        if !(-7..=7).contains(&sf) {
+            Err(ApresError::InvalidBytes(vec![sf as u8, mi]))
+        } else {
+            Ok(Key::from_fifths(Key::get_mode_offset(mode) + sf, mode))
+        }
+    }
+
+    /// Get the (sf, mi) bytes used in KeySignature.
+    /// Keys that can't be written directly are written as their enharmonic equivalent.
+    pub fn get_sf_mi(&self) -> (i8, u8) {
+        let key = self.get_representable();
+        let mi = match key.mode {
+            Mode::Major => 0,
+            Mode::Minor => 1
+        };
+
+        (key.get_fifths() - Key::get_mode_offset(key.mode), mi)
+    }
+
+    /// Number of sharps (positive) or flats (negative) in the key, which may be more than 7
+    pub fn get_sharps(&self) -> i8 {
+        self.get_fifths() - Key::get_mode_offset(self.mode)
+    }
+
+    /// Whether the key can be written to a MIDI file as-is, ie has no more than 7 sharps or flats
+    pub fn is_representable(&self) -> bool {
+        (-7..=7).contains(&self.get_sharps())
+    }
+
+    /// Get the same key spelled the other way (eg, C# and Db), if it can be spelled with a single accidental
+    pub fn get_enharmonic(&self) -> Option<Key> {
+        let fifths = self.get_fifths();
+        for candidate in [fifths + 12, fifths - 12].iter() {
+            if (-8..=12).contains(candidate) {
+                return Some(Key::from_fifths(*candidate, self.mode));
+            }
+        }
+
+        None
+    }
+
+    /// Get this key if it is representable, otherwise its enharmonic equivalent
+    pub fn get_representable(&self) -> Key {
+        if self.is_representable() {
+            *self
+        } else {
+            match self.get_enharmonic() {
+                Some(key) => key,
+                None => *self
+            }
+        }
+    }
+
+    /// Get the pitch class of the tonic, 0 (C) to 11 (B)
+    pub fn get_pitch_class(&self) -> u8 {
+        ((self.get_fifths() as i32 * 7).rem_euclid(12)) as u8
+    }
+
+    // Position of the tonic on the circle of fifths relative to C, counting accidentals.
+    // Single accidentals cover -8 (Fb) to 12 (B#)
+    fn get_fifths(&self) -> i8 {
+        let accidental_offset = match self.accidental {
+            Accidental::Flat => -7,
+            Accidental::Natural => 0,
+            Accidental::Sharp => 7
+        };
+
+        self.tonic.get_fifths() + accidental_offset
+    }
+
+    fn from_fifths(fifths: i8, mode: Mode) -> Key {
+        let (tonic, accidental) = if fifths < -1 {
+            (Tonic::from_fifths(fifths + 7), Accidental::Flat)
+        } else if fifths > 5 {
+            (Tonic::from_fifths(fifths - 7), Accidental::Sharp)
+        } else {
+            (Tonic::from_fifths(fifths), Accidental::Natural)
+        };
+
+        Key { tonic, accidental, mode }
+    }
+
+    // A minor key shares its signature with the major key 3 fifths below it
+    fn get_mode_offset(mode: Mode) -> i8 {
+        match mode {
+            Mode::Major => 0,
+            Mode::Minor => 3
+        }
+    }
+}
+
+impl Default for Key {
+    fn default() -> Key {
+        Key::new(Tonic::C, Accidental::Natural, Mode::Major)
+    }
+}
+
+/// Formats as the tonic, an optional '#' or 'b', then 'm' for minor keys. eg, "C", "F#m", "Bb"
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let tonic = match self.tonic {
+            Tonic::C => "C",
+            Tonic::D => "D",
+            Tonic::E => "E",
+            Tonic::F => "F",
+            Tonic::G => "G",
+            Tonic::A => "A",
+            Tonic::B => "B"
+        };
+        let accidental = match self.accidental {
+            Accidental::Flat => "b",
+            Accidental::Natural => "",
+            Accidental::Sharp => "#"
+        };
+        let mode = match self.mode {
+            Mode::Major => "",
+            Mode::Minor => "m"
+        };
+
+        write!(f, "{}{}{}", tonic, accidental, mode)
+    }
+}
+
+/// Parses names like "C", "F#m", "Bb minor", "Eb major" or "G♯ min"
+impl FromStr for Key {
+    type Err = ApresError;
+    fn from_str(name: &str) -> Result<Key, ApresError> {
+        let mut chars = name.trim().chars();
+        let tonic = match chars.next().map(|c| c.to_ascii_uppercase()) {
+            Some('C') => Tonic::C,
+            Some('D') => Tonic::D,
+            Some('E') => Tonic::E,
+            Some('F') => Tonic::F,
+            Some('G') => Tonic::G,
+            Some('A') => Tonic::A,
+            Some('B') => Tonic::B,
+            _ => {
+                return Err(ApresError::ParseError(name.to_string()));
+            }
+        };
+
+        let mut rest = chars.as_str();
+        let accidental = if rest.starts_with('#') || rest.starts_with('♯') {
+            rest = &rest[rest.chars().next().unwrap().len_utf8()..];
+            Accidental::Sharp
+        } else if rest.starts_with('b') || rest.starts_with('♭') {
+            rest = &rest[rest.chars().next().unwrap().len_utf8()..];
+            Accidental::Flat
+        } else {
+            Accidental::Natural
+        };
+
+        let mode = match rest.trim() {
+            "" | "M" | "maj" | "major" | "Major" => Mode::Major,
+            "m" | "min" | "minor" | "Minor" => Mode::Minor,
+            _ => {
+                return Err(ApresError::ParseError(name.to_string()));
+            }
+        };
+
+        Ok(Key { tonic, accidental, mode })
+    }
+}
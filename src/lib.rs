@@ -1,13 +1,85 @@
+//! A MIDI library
+//!
+//! # Serde
+//!
+//! Enable the `serde` feature to serialize `MIDI`, `MIDIEvent` and `ApresError`.
+//!
+//! The schema, shown as JSON:
+//!
+//! * `MIDI` is an object: `{"ppqn": 120, "format": 1, "tracks": [[{"id": 1, "tick": 0, "event": ...}, ...], ...]}`.
+//!   Each track is a list of events with their absolute tick and event id, ordered by tick then id.
+//!   Events in the same tick are kept in id order, and ids are kept when deserializing.
+//! * `MIDIEvent` is externally tagged by variant name: `{"NoteOn": [0, 60, 100]}`, `{"SetTempo": 500000}`,
+//!   `"EndOfTrack"`.
+//! * `Channel` (0-15), `U7`, `U14` and `PitchBend` (0-16383, centered at 8192) are plain numbers, checked when deserializing.
+//! * `Tempo` is microseconds per quarter note.
+//! * `Meter` is `{"numerator": 6, "denominator": 8, "clocks_per_click": 36, "thirty_seconds_per_quarter": 8}`.
+//! * `Key` is `{"tonic": "E", "accidental": "Flat", "mode": "Minor"}`.
+//! * `Timecode` is `{"rate": "Fps25", "hours": 1, "minutes": 0, "seconds": 0, "frames": 0, "subframes": 0}`, checked when deserializing.
+//! * `ApresError` is externally tagged like `MIDIEvent`: `{"ValueOutOfRange": [128, 127]}`.
+
+use std::fmt;
 use std::fs::File;
 use std::io::prelude::*;
 use std::cmp::max;
 use std::collections::{HashMap, HashSet};
+use std::sync::mpsc::Sender;
 
 pub mod controller;
+pub mod channel_state;
+pub mod history;
+pub mod diff;
+pub mod clipboard;
+pub mod message;
+pub mod types;
+pub mod high_resolution;
+pub mod parameter;
+pub mod key;
+pub mod tempo;
+pub mod timecode;
+pub mod pitch;
+pub mod general_midi;
+pub mod sysex;
+pub mod gs;
+pub mod xg;
+pub mod mts;
+pub mod scala;
+pub mod mpe;
+pub mod ump;
+pub mod clip;
+pub mod ci;
+mod observer;
+mod display;
+#[cfg(feature = "serde")]
+mod serialization;
 
 use controller::Controller;
+pub use types::{Channel, U7, U14, PitchBend};
+pub use channel_state::ChannelState;
+pub use history::MIDIEdit;
+pub use diff::{DiffOptions, MIDIDiff};
+pub use clipboard::{Clipboard, PasteMode};
+pub use message::{MIDIMessage, MetaEvent, ChannelMessage, ChannelMessageKind, SystemCommon, SystemRealtime, SysEx};
+pub use high_resolution::{ControlChange14, ControllerPairer, MSBOnlyMode, PairedEvent};
+pub use parameter::{ParameterDecoder, ParameterEvent, RegisteredParameter};
+pub use key::{Key, Tonic, Accidental, Mode};
+pub use tempo::{Tempo, Meter};
+pub use timecode::{Timecode, FrameRate, QuarterFrameDecoder};
+pub use pitch::{Pitch, Tuning};
+pub use general_midi::ProgramFamily;
+pub use sysex::{ManufacturerId, UniversalSysEx, MachineControlCommand};
+pub use gs::{GSMessage, GSParameter, GSPartParameter, GSReverbType, GSChorusType};
+pub use xg::{XGMessage, XGParameter, XGPartParameter, XGEffectType};
+pub use mts::{MTSFrequency, MTSMessage, KeyboardTuning};
+pub use scala::{ScalaScale, KeyboardMapping};
+pub use mpe::{MPEZone, MPEConfiguration, MPEEvent, MPENote, MPEDecoder, MPEEncoder};
+pub use ump::{UMP, UMPProtocol, PacketStatus, MIDI2ChannelVoice, StreamMessage, FlexData, UMPEncoder, UMPDecoder};
+pub use clip::MIDIClip;
+pub use ci::{CIMessage, CIMessageKind, CIDeviceIdentity, CIResponder, ProfileId, PropertyChunk};
+use history::EditHistory;
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ApresError {
     InvalidMIDIFile(String),
     InvalidBytes(Vec<u8>),
@@ -20,12 +92,58 @@ pub enum ApresError {
     Killed,
     MissingHeader,
     BadDevice(u8),
+    ValueOutOfRange(u32, u32), // (value, maximum)
+    ParseError(String),
+    InvalidTimeSignature(u8, u8), // (numerator, denominator)
     OutOfMemory,
     UnknownError
 }
 
+impl fmt::Display for ApresError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApresError::InvalidMIDIFile(path) => {
+                write!(f, "invalid MIDI file: {}", path)
+            }
+            ApresError::InvalidBytes(bytes) => {
+                write!(f, "invalid bytes: {:02X?}", bytes)
+            }
+            ApresError::UnknownMetaEvent(bytes) => {
+                write!(f, "unknown meta event: {:02X?}", bytes)
+            }
+            ApresError::IllegibleString(bytes) => {
+                write!(f, "illegible string: {:02X?}", bytes)
+            }
+            ApresError::EventNotFound(event_id) => {
+                write!(f, "event {} not found", event_id)
+            }
+            ApresError::PathNotFound(path) => {
+                write!(f, "path not found: {}", path)
+            }
+            ApresError::BadDevice(device_id) => {
+                write!(f, "bad device: {}", device_id)
+            }
+            ApresError::ValueOutOfRange(value, maximum) => {
+                write!(f, "value {} out of range (maximum {})", value, maximum)
+            }
+            ApresError::ParseError(input) => {
+                write!(f, "couldn't parse {:?}", input)
+            }
+            ApresError::InvalidTimeSignature(numerator, denominator) => {
+                write!(f, "invalid time signature {}/{}", numerator, denominator)
+            }
+            _ => {
+                write!(f, "{:?}", self)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ApresError {}
+
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum MIDIEvent {
 	SequenceNumber(u16),
 	Text(String),
@@ -35,99 +153,102 @@ pub enum MIDIEvent {
 	Lyric(String),
 	Marker(String),
 	CuePoint(String),
-	ChannelPrefix(u8),
-    // Note: Tempo Stored in u32 but is a 3 byte value
-	SetTempo(u32),
-	SMPTEOffset(u8, u8, u8, u8, u8),
-	TimeSignature(u8, u8, u8, u8),
-	KeySignature(String),
+	ChannelPrefix(Channel),
+	SetTempo(Tempo),
+	SMPTEOffset(Timecode),
+	TimeSignature(Meter),
+	KeySignature(Key),
     SequencerSpecific(Vec<u8>),
 
-	NoteOn(u8, u8, u8),
-	NoteOff(u8, u8, u8),
-	AfterTouch(u8, u8, u8),
-
-    BankSelect(u8, u8),
-    BankSelectLSB(u8, u8),
-    ModulationWheel(u8, u8),
-    ModulationWheelLSB(u8, u8),
-    BreathController(u8, u8),
-    BreathControllerLSB(u8, u8),
-    FootPedal(u8, u8),
-    FootPedalLSB(u8, u8),
-    PortamentoTime(u8, u8),
-    PortamentoTimeLSB(u8, u8),
-    DataEntry(u8, u8),
-    DataEntryLSB(u8, u8),
-    Volume(u8, u8),
-    VolumeLSB(u8, u8),
-    Balance(u8, u8),
-    BalanceLSB(u8, u8),
-    Pan(u8, u8),
-    PanLSB(u8, u8),
-    Expression(u8, u8),
-    ExpressionLSB(u8, u8),
-    EffectControl1(u8, u8),
-    EffectControl1LSB(u8, u8),
-    EffectControl2(u8, u8),
-    EffectControl2LSB(u8, u8),
-    GeneralPurpose1(u8, u8),
-    GeneralPurpose1LSB(u8, u8),
-    GeneralPurpose2(u8, u8),
-    GeneralPurpose2LSB(u8, u8),
-    GeneralPurpose3(u8, u8),
-    GeneralPurpose3LSB(u8, u8),
-    GeneralPurpose4(u8, u8),
-    GeneralPurpose4LSB(u8, u8),
-	HoldPedal(u8, u8),
-	Portamento(u8, u8),
-	Sustenuto(u8, u8),
-	SoftPedal(u8, u8),
-	Legato(u8, u8),
-	Hold2Pedal(u8, u8),
-	SoundVariation(u8, u8),
-	SoundTimbre(u8, u8),
-	SoundReleaseTime(u8, u8),
-	SoundAttack(u8, u8),
-	SoundBrightness(u8, u8),
-	SoundControl1(u8, u8),
-	SoundControl2(u8, u8),
-	SoundControl3(u8, u8),
-	SoundControl4(u8, u8),
-	SoundControl5(u8, u8),
-    GeneralPurpose5(u8, u8),
-    GeneralPurpose6(u8, u8),
-    GeneralPurpose7(u8, u8),
-    GeneralPurpose8(u8, u8),
-	EffectsLevel(u8, u8),
-	TremuloLevel(u8, u8),
-	ChorusLevel(u8, u8),
-	CelesteLevel(u8, u8),
-	PhaserLevel(u8, u8),
-	DataIncrement(u8),
-	DataDecrement(u8),
-    RegisteredParameterNumber(u8, u8),
-    RegisteredParameterNumberLSB(u8, u8),
-    NonRegisteredParameterNumber(u8, u8),
-    NonRegisteredParameterNumberLSB(u8, u8),
-	AllControllersOff(u8),
-	LocalControl(u8, u8),
-	AllNotesOff(u8),
-	AllSoundOff(u8),
-	OmniOff(u8),
-	OmniOn(u8),
-	MonophonicOperation(u8, u8),
-	PolyphonicOperation(u8),
-	ControlChange(u8, u8, u8),
-
-	ProgramChange(u8, u8),
-	ChannelPressure(u8, u8),
-	PitchWheelChange(u8, f64),
+	NoteOn(Channel, U7, U7),
+	NoteOff(Channel, U7, U7),
+	AfterTouch(Channel, U7, U7),
+
+    BankSelect(Channel, U7),
+    BankSelectLSB(Channel, U7),
+    ModulationWheel(Channel, U7),
+    ModulationWheelLSB(Channel, U7),
+    BreathController(Channel, U7),
+    BreathControllerLSB(Channel, U7),
+    FootPedal(Channel, U7),
+    FootPedalLSB(Channel, U7),
+    PortamentoTime(Channel, U7),
+    PortamentoTimeLSB(Channel, U7),
+    DataEntry(Channel, U7),
+    DataEntryLSB(Channel, U7),
+    Volume(Channel, U7),
+    VolumeLSB(Channel, U7),
+    Balance(Channel, U7),
+    BalanceLSB(Channel, U7),
+    Pan(Channel, U7),
+    PanLSB(Channel, U7),
+    Expression(Channel, U7),
+    ExpressionLSB(Channel, U7),
+    EffectControl1(Channel, U7),
+    EffectControl1LSB(Channel, U7),
+    EffectControl2(Channel, U7),
+    EffectControl2LSB(Channel, U7),
+    GeneralPurpose1(Channel, U7),
+    GeneralPurpose1LSB(Channel, U7),
+    GeneralPurpose2(Channel, U7),
+    GeneralPurpose2LSB(Channel, U7),
+    GeneralPurpose3(Channel, U7),
+    GeneralPurpose3LSB(Channel, U7),
+    GeneralPurpose4(Channel, U7),
+    GeneralPurpose4LSB(Channel, U7),
+	HoldPedal(Channel, U7),
+	Portamento(Channel, U7),
+	Sustenuto(Channel, U7),
+	SoftPedal(Channel, U7),
+	Legato(Channel, U7),
+	Hold2Pedal(Channel, U7),
+	SoundVariation(Channel, U7),
+	SoundTimbre(Channel, U7),
+	SoundReleaseTime(Channel, U7),
+	SoundAttack(Channel, U7),
+	SoundBrightness(Channel, U7),
+	SoundControl1(Channel, U7),
+	SoundControl2(Channel, U7),
+	SoundControl3(Channel, U7),
+	SoundControl4(Channel, U7),
+	SoundControl5(Channel, U7),
+    GeneralPurpose5(Channel, U7),
+    GeneralPurpose6(Channel, U7),
+    GeneralPurpose7(Channel, U7),
+    GeneralPurpose8(Channel, U7),
+	EffectsLevel(Channel, U7),
+	TremuloLevel(Channel, U7),
+	ChorusLevel(Channel, U7),
+	CelesteLevel(Channel, U7),
+	PhaserLevel(Channel, U7),
+	DataIncrement(Channel),
+	DataDecrement(Channel),
+    RegisteredParameterNumber(Channel, U7),
+    RegisteredParameterNumberLSB(Channel, U7),
+    NonRegisteredParameterNumber(Channel, U7),
+    NonRegisteredParameterNumberLSB(Channel, U7),
+	AllControllersOff(Channel),
+	/// On (true) or off (false)
+	LocalControl(Channel, bool),
+	AllNotesOff(Channel),
+	AllSoundOff(Channel),
+	OmniOff(Channel),
+	OmniOn(Channel),
+	/// Mono mode, with the number of channels to use. 0 uses as many channels as the receiver has voices.
+	MonophonicOperation(Channel, U7),
+	PolyphonicOperation(Channel),
+	ControlChange(Channel, U7, U7),
+
+	ProgramChange(Channel, U7),
+	ChannelPressure(Channel, U7),
+	PitchWheelChange(Channel, PitchBend),
 	SystemExclusive(Vec<u8>),
+	/// Piece number (0-7) and its 4-bit value. See `Timecode::to_quarter_frames()` and `QuarterFrameDecoder`.
 	MTCQuarterFrame(u8, u8),
-	SongPositionPointer(u16),
-	SongSelect(u8),
-    TimeCode(f32, u8, u8, u8, u8),
+	SongPositionPointer(U14),
+	SongSelect(U7),
+    /// MTC full frame message, sent as a universal real time system exclusive message
+    TimeCode(Timecode),
 
 	EndOfTrack,
     TuneRequest,
@@ -237,30 +358,38 @@ impl MIDIBytes for MIDIEvent {
             }
 
             MIDIEvent::ChannelPrefix(channel) => {
-                vec![0xFF, 0x20, 0x01, *channel]
+                vec![0xFF, 0x20, 0x01, channel.get()]
             }
 
-            MIDIEvent::SetTempo(us_per_quarter_note) => {
+            MIDIEvent::SetTempo(tempo) => {
+                let us_per_quarter_note = tempo.get();
                 vec![
                     0xFF, 0x51, 0x03,
-                    ((*us_per_quarter_note / 256u32.pow(2)) % 256) as u8,
-                    ((*us_per_quarter_note / 256u32.pow(1)) % 256) as u8,
-                    (*us_per_quarter_note % 256) as u8,
+                    ((us_per_quarter_note / 256u32.pow(2)) % 256) as u8,
+                    ((us_per_quarter_note / 256u32.pow(1)) % 256) as u8,
+                    (us_per_quarter_note % 256) as u8,
                 ]
             }
 
-            //TODO: Figure out what ff/fr are, u16 for now
-            MIDIEvent::SMPTEOffset(hour, minute, second, ff, fr) => {
-                vec![0xFF, 0x54, 05, *hour, *minute, *second, *ff, *fr]
+            MIDIEvent::SMPTEOffset(timecode) => {
+                let mut output = vec![0xFF, 0x54, 05];
+                output.extend(timecode.as_bytes().iter());
+                output
             }
 
-            MIDIEvent::TimeSignature(numerator, denominator, clocks_per_metronome, thirtysecondths_per_quarter) => {
-                vec![0xFF, 0x58, 04, *numerator, *denominator, *clocks_per_metronome, *thirtysecondths_per_quarter]
+            MIDIEvent::TimeSignature(meter) => {
+                vec![
+                    0xFF, 0x58, 04,
+                    meter.get_numerator(),
+                    meter.get_denominator_exponent(),
+                    meter.get_clocks_per_click(),
+                    meter.get_thirty_seconds_per_quarter()
+                ]
             }
 
-            MIDIEvent::KeySignature(string) => {
-                let (mi, sf) = get_mi_sf(string);
-                vec![0xFF, 0x59, 0x02, sf, mi]
+            MIDIEvent::KeySignature(key) => {
+                let (sf, mi) = key.get_sf_mi();
+                vec![0xFF, 0x59, 0x02, sf as u8, mi]
             }
 
             MIDIEvent::SequencerSpecific(data) => {
@@ -272,395 +401,393 @@ impl MIDIBytes for MIDIEvent {
 
             MIDIEvent::NoteOn(channel, note, velocity) => {
                 vec![
-                    0x90 | *channel,
-                    *note,
-                    *velocity
+                    0x90 | channel.get(),
+                    note.get(),
+                    velocity.get()
                 ]
             }
 
             MIDIEvent::NoteOff(channel, note, velocity) => {
                 vec![
-                    0x80 | *channel,
-                    *note,
-                    *velocity
+                    0x80 | channel.get(),
+                    note.get(),
+                    velocity.get()
                 ]
             }
 
             MIDIEvent::AfterTouch(channel, note, pressure) => {
                 vec![
-                    0xA0 | *channel,
-                     *note,
-                     *pressure
+                    0xA0 | channel.get(),
+                     note.get(),
+                     pressure.get()
                 ]
             }
 
             MIDIEvent::BankSelect(channel, value) => {
-                vec![ 0xB0 | *channel, 0x00, *value ]
+                vec![ 0xB0 | channel.get(), 0x00, value.get() ]
             }
             MIDIEvent::BankSelectLSB(channel, value) => {
-                vec![ 0xB0 | *channel, 0x20, *value ]
+                vec![ 0xB0 | channel.get(), 0x20, value.get() ]
             }
 
             MIDIEvent::ModulationWheel(channel, value) => {
-                vec![ 0xB0 | *channel, 0x01, *value ]
+                vec![ 0xB0 | channel.get(), 0x01, value.get() ]
             }
             MIDIEvent::ModulationWheelLSB(channel, value) => {
-                vec![ 0xB0 | *channel, 0x21, *value ]
+                vec![ 0xB0 | channel.get(), 0x21, value.get() ]
             }
 
             MIDIEvent::BreathController(channel, value) => {
-                vec![ 0xB0 | *channel, 0x02, *value ]
+                vec![ 0xB0 | channel.get(), 0x02, value.get() ]
             }
             MIDIEvent::BreathControllerLSB(channel, value) => {
-                vec![ 0xB0 | *channel, 0x22, *value ]
+                vec![ 0xB0 | channel.get(), 0x22, value.get() ]
             }
 
             MIDIEvent::FootPedal(channel, value) => {
-                vec![ 0xB0 | *channel, 0x04, *value ]
+                vec![ 0xB0 | channel.get(), 0x04, value.get() ]
             }
             MIDIEvent::FootPedalLSB(channel, value) => {
-                vec![ 0xB0 | *channel, 0x24, *value ]
+                vec![ 0xB0 | channel.get(), 0x24, value.get() ]
             }
 
             MIDIEvent::PortamentoTime(channel, value) => {
-                vec![ 0xB0 | *channel, 0x05, *value ]
+                vec![ 0xB0 | channel.get(), 0x05, value.get() ]
             }
             MIDIEvent::PortamentoTimeLSB(channel, value) => {
-                vec![ 0xB0 | *channel, 0x25, *value ]
+                vec![ 0xB0 | channel.get(), 0x25, value.get() ]
             }
 
             MIDIEvent::DataEntry(channel, value) => {
-                vec![ 0xB0 | *channel, 0x06, *value ]
+                vec![ 0xB0 | channel.get(), 0x06, value.get() ]
             }
             MIDIEvent::DataEntryLSB(channel, value) => {
-                vec![ 0xB0 | *channel, 0x26, *value ]
+                vec![ 0xB0 | channel.get(), 0x26, value.get() ]
             }
 
             MIDIEvent::Volume(channel, value) => {
-                vec![ 0xB0 | *channel, 0x07, *value ]
+                vec![ 0xB0 | channel.get(), 0x07, value.get() ]
             }
             MIDIEvent::VolumeLSB(channel, value) => {
-                vec![ 0xB0 | *channel, 0x27, *value ]
+                vec![ 0xB0 | channel.get(), 0x27, value.get() ]
             }
 
             MIDIEvent::Balance(channel, value) => {
-                vec![ 0xB0 | *channel, 0x08, *value ]
+                vec![ 0xB0 | channel.get(), 0x08, value.get() ]
             }
             MIDIEvent::BalanceLSB(channel, value) => {
-                vec![ 0xB0 | *channel, 0x28, *value ]
+                vec![ 0xB0 | channel.get(), 0x28, value.get() ]
             }
 
             MIDIEvent::Pan(channel, value) => {
-                vec![ 0xB0 | *channel, 0x0A, *value ]
+                vec![ 0xB0 | channel.get(), 0x0A, value.get() ]
             }
             MIDIEvent::PanLSB(channel, value) => {
-                vec![ 0xB0 | *channel, 0x2A, *value ]
+                vec![ 0xB0 | channel.get(), 0x2A, value.get() ]
             }
 
             MIDIEvent::Expression(channel, value) => {
-                vec![ 0xB0 | *channel, 0x0B, *value ]
+                vec![ 0xB0 | channel.get(), 0x0B, value.get() ]
             }
             MIDIEvent::ExpressionLSB(channel, value) => {
-                vec![ 0xB0 | *channel, 0x2B, *value ]
+                vec![ 0xB0 | channel.get(), 0x2B, value.get() ]
             }
 
             MIDIEvent::EffectControl1(channel, value) => {
-                vec![ 0xB0 | *channel, 0x0C, *value ]
+                vec![ 0xB0 | channel.get(), 0x0C, value.get() ]
             }
             MIDIEvent::EffectControl1LSB(channel, value) => {
-                vec![ 0xB0 | *channel, 0x2C, *value ]
+                vec![ 0xB0 | channel.get(), 0x2C, value.get() ]
             }
             MIDIEvent::EffectControl2(channel, value) => {
-                vec![ 0xB0 | *channel, 0x0D, *value ]
+                vec![ 0xB0 | channel.get(), 0x0D, value.get() ]
             }
             MIDIEvent::EffectControl2LSB(channel, value) => {
-                vec![ 0xB0 | *channel, 0x2D, *value ]
+                vec![ 0xB0 | channel.get(), 0x2D, value.get() ]
             }
 
             MIDIEvent::GeneralPurpose1(channel, value) => {
-                vec![ 0xB0 | *channel, 0x10, *value ]
+                vec![ 0xB0 | channel.get(), 0x10, value.get() ]
             }
             MIDIEvent::GeneralPurpose1LSB(channel, value) => {
-                vec![ 0xB0 | *channel, 0x30, *value ]
+                vec![ 0xB0 | channel.get(), 0x30, value.get() ]
             }
             MIDIEvent::GeneralPurpose2(channel, value) => {
-                vec![ 0xB0 | *channel, 0x11, *value ]
+                vec![ 0xB0 | channel.get(), 0x11, value.get() ]
             }
             MIDIEvent::GeneralPurpose2LSB(channel, value) => {
-                vec![ 0xB0 | *channel, 0x31, *value ]
+                vec![ 0xB0 | channel.get(), 0x31, value.get() ]
             }
             MIDIEvent::GeneralPurpose3(channel, value) => {
-                vec![ 0xB0 | *channel, 0x12, *value ]
+                vec![ 0xB0 | channel.get(), 0x12, value.get() ]
             }
             MIDIEvent::GeneralPurpose3LSB(channel, value) => {
-                vec![ 0xB0 | *channel, 0x32, *value ]
+                vec![ 0xB0 | channel.get(), 0x32, value.get() ]
             }
             MIDIEvent::GeneralPurpose4(channel, value) => {
-                vec![ 0xB0 | *channel, 0x13, *value ]
+                vec![ 0xB0 | channel.get(), 0x13, value.get() ]
             }
             MIDIEvent::GeneralPurpose4LSB(channel, value) => {
-                vec![ 0xB0 | *channel, 0x33, *value ]
+                vec![ 0xB0 | channel.get(), 0x33, value.get() ]
             }
             MIDIEvent::GeneralPurpose5(channel, value) => {
-                vec![ 0xB0 | *channel, 0x50, *value]
+                vec![ 0xB0 | channel.get(), 0x50, value.get()]
             }
             MIDIEvent::GeneralPurpose6(channel, value) => {
-                vec![ 0xB0 | *channel, 0x51, *value]
+                vec![ 0xB0 | channel.get(), 0x51, value.get()]
             }
             MIDIEvent::GeneralPurpose7(channel, value) => {
-                vec![ 0xB0 | *channel, 0x52, *value]
+                vec![ 0xB0 | channel.get(), 0x52, value.get()]
             }
             MIDIEvent::GeneralPurpose8(channel, value) => {
-                vec![ 0xB0 | *channel, 0x53, *value]
+                vec![ 0xB0 | channel.get(), 0x53, value.get()]
             }
 
             MIDIEvent::HoldPedal(channel, value) => {
                 vec![
-                    0xB0 | *channel,
+                    0xB0 | channel.get(),
                     0x40,
-                    *value
+                    value.get()
                 ]
             }
 
             MIDIEvent::Portamento(channel, value) => {
                 vec![
-                    0xB0 | *channel,
+                    0xB0 | channel.get(),
                     0x41,
-                    *value
+                    value.get()
                 ]
             }
 
             MIDIEvent::Sustenuto(channel, value) => {
                 vec![
-                    0xB0 | *channel,
+                    0xB0 | channel.get(),
                     0x42,
-                    *value
+                    value.get()
                 ]
             }
 
             MIDIEvent::SoftPedal(channel, value) => {
                 vec![
-                    0xB0 | *channel,
+                    0xB0 | channel.get(),
                     0x43,
-                    *value
+                    value.get()
                 ]
             }
 
             MIDIEvent::Legato(channel, value) => {
                 vec![
-                    0xB0 | *channel,
+                    0xB0 | channel.get(),
                     0x44,
-                    *value
+                    value.get()
                 ]
             }
 
             MIDIEvent::Hold2Pedal(channel, value) => {
                 vec![
-                    0xB0 | *channel,
+                    0xB0 | channel.get(),
                     0x45,
-                    *value
+                    value.get()
                 ]
             }
 
             MIDIEvent::SoundVariation(channel, value) => {
                 vec![
-                    0xB0 | *channel,
+                    0xB0 | channel.get(),
                     0x46,
-                    *value
+                    value.get()
                 ]
             }
 
             MIDIEvent::SoundTimbre(channel, value) => {
                 vec![
-                    0xB0 | *channel,
+                    0xB0 | channel.get(),
                     0x47,
-                    *value
+                    value.get()
                 ]
             }
 
             MIDIEvent::SoundReleaseTime(channel, value) => {
                 vec![
-                    0xB0 | *channel,
+                    0xB0 | channel.get(),
                     0x48,
-                    *value
+                    value.get()
                 ]
             }
 
             MIDIEvent::SoundAttack(channel, value) => {
                 vec![
-                    0xB0 | *channel,
+                    0xB0 | channel.get(),
                     0x49,
-                    *value
+                    value.get()
                 ]
             }
 
             MIDIEvent::SoundBrightness(channel, value) => {
                 vec![
-                    0xB0 | *channel,
+                    0xB0 | channel.get(),
                     0x4A,
-                    *value
+                    value.get()
                 ]
             }
 
             MIDIEvent::SoundControl1(channel, value) => {
-                vec![ 0xB0 | *channel, 0x4B, *value ]
+                vec![ 0xB0 | channel.get(), 0x4B, value.get() ]
             }
             MIDIEvent::SoundControl2(channel, value) => {
-                vec![ 0xB0 | *channel, 0x4C, *value ]
+                vec![ 0xB0 | channel.get(), 0x4C, value.get() ]
             }
             MIDIEvent::SoundControl3(channel, value) => {
-                vec![ 0xB0 | *channel, 0x4D, *value ]
+                vec![ 0xB0 | channel.get(), 0x4D, value.get() ]
             }
             MIDIEvent::SoundControl4(channel, value) => {
-                vec![ 0xB0 | *channel, 0x4E, *value ]
+                vec![ 0xB0 | channel.get(), 0x4E, value.get() ]
             }
             MIDIEvent::SoundControl5(channel, value) => {
-                vec![ 0xB0 | *channel, 0x4F, *value ]
+                vec![ 0xB0 | channel.get(), 0x4F, value.get() ]
             }
 
             MIDIEvent::EffectsLevel(channel, value) => {
                 vec![
-                    0xB0 | *channel,
+                    0xB0 | channel.get(),
                     0x5B,
-                    *value
+                    value.get()
                 ]
             }
 
             MIDIEvent::TremuloLevel(channel, value) => {
                 vec![
-                    0xB0 | *channel,
+                    0xB0 | channel.get(),
                     0x5C,
-                    *value
+                    value.get()
                 ]
             }
 
             MIDIEvent::ChorusLevel(channel, value) => {
                 vec![
-                    0xB0 | *channel,
+                    0xB0 | channel.get(),
                     0x5D,
-                    *value
+                    value.get()
                 ]
             }
 
             MIDIEvent::CelesteLevel(channel, value) => {
                 vec![
-                    0xB0 | *channel,
+                    0xB0 | channel.get(),
                     0x5E,
-                    *value
+                    value.get()
                 ]
             }
 
             MIDIEvent::PhaserLevel(channel, value) => {
                 vec![
-                    0xB0 | *channel,
+                    0xB0 | channel.get(),
                     0x5F,
-                    *value
+                    value.get()
                 ]
             }
 
             MIDIEvent::DataIncrement(channel) => {
-                vec![0xB0 | *channel, 0x60, 0x00]
+                vec![0xB0 | channel.get(), 0x60, 0x00]
             }
 
             MIDIEvent::DataDecrement(channel) => {
-                vec![0xB0 | *channel, 0x61, 0x00]
+                vec![0xB0 | channel.get(), 0x61, 0x00]
             }
 
             MIDIEvent::NonRegisteredParameterNumber(channel, value) => {
-                vec![ 0xB0 | *channel, 0x63, *value ]
+                vec![ 0xB0 | channel.get(), 0x63, value.get() ]
             }
             MIDIEvent::NonRegisteredParameterNumberLSB(channel, value) => {
-                vec![ 0xB0 | *channel, 0x62, *value ]
+                vec![ 0xB0 | channel.get(), 0x62, value.get() ]
             }
             MIDIEvent::RegisteredParameterNumber(channel, value) => {
-                vec![ 0xB0 | *channel, 0x65, *value ]
+                vec![ 0xB0 | channel.get(), 0x65, value.get() ]
             }
             MIDIEvent::RegisteredParameterNumberLSB(channel, value) => {
-                vec![ 0xB0 | *channel, 0x64, *value ]
+                vec![ 0xB0 | channel.get(), 0x64, value.get() ]
             }
 
 
             MIDIEvent::AllControllersOff(channel) => {
                 vec![
-                    0xB0 | *channel,
+                    0xB0 | channel.get(),
                     0x79, 0x00
                 ]
             }
 
-            MIDIEvent::LocalControl(channel, value) => {
-                vec![ 0xB0 | *channel, 0x7A, *value ]
+            MIDIEvent::LocalControl(channel, on) => {
+                vec![ 0xB0 | channel.get(), 0x7A, if *on { 0x7F } else { 0x00 } ]
             }
 
             MIDIEvent::AllNotesOff(channel) => {
                 vec![
-                    0xB0 | *channel,
+                    0xB0 | channel.get(),
                     0x7B, 0x00
                 ]
             }
 
             MIDIEvent::AllSoundOff(channel) => {
                 vec![
-                    0xB0 | *channel,
+                    0xB0 | channel.get(),
                     0x78, 0x00
                 ]
             }
 
             MIDIEvent::OmniOff(channel) => {
                 vec![
-                    0xB0 | *channel,
+                    0xB0 | channel.get(),
                     0x7C, 0x00
                 ]
             }
 
             MIDIEvent::OmniOn(channel) => {
                 vec![
-                    0xB0 | *channel,
+                    0xB0 | channel.get(),
                     0x7D, 0x00
                 ]
             }
 
             MIDIEvent::MonophonicOperation(channel, value) => {
                 vec![
-                    0xB0 | *channel, 0xFE, *value
+                    0xB0 | channel.get(), 0x7E, value.get()
                 ]
             }
 
             MIDIEvent::PolyphonicOperation(channel) => {
                 vec![
-                    0xB0 | *channel, 0xFF, 0
+                    0xB0 | channel.get(), 0x7F, 0
                 ]
             }
 
             MIDIEvent::ControlChange(channel, controller, value) => {
                 vec![
-                    0xB0 | *channel,
-                    *controller,
-                    *value
+                    0xB0 | channel.get(),
+                    controller.get(),
+                    value.get()
                 ]
             }
 
             MIDIEvent::ProgramChange(channel, program) => {
                 vec![
-                    0xC0 | *channel,
-                    *program
+                    0xC0 | channel.get(),
+                    program.get()
                 ]
             }
 
             MIDIEvent::ChannelPressure(channel, pressure) => {
                 vec![
-                    0xD0 | *channel,
-                    *pressure
+                    0xD0 | channel.get(),
+                    pressure.get()
                 ]
             }
 
             MIDIEvent::PitchWheelChange(channel, value) => {
-                let unsigned_value = get_pitchwheel_value(*value);
-                let lsb: u8 = (unsigned_value & 0x007F) as u8;
-                let msb: u8 = ((unsigned_value >> 8) & 0x007F) as u8;
+                let unsigned_value = value.get_u14();
                 vec![
-                    0xE0 | *channel,
-                    lsb,
-                    msb
+                    0xE0 | channel.get(),
+                    unsigned_value.get_lsb().get(),
+                    unsigned_value.get_msb().get()
                 ]
             }
 
@@ -671,46 +798,33 @@ impl MIDIBytes for MIDIEvent {
                 output
             }
 
-            MIDIEvent::MTCQuarterFrame(message_type, values) => {
-                let mut b = 0;
-                b |= *message_type;
-                b <<= 3;
-                b |= *values;
-
-                vec![ 0xF1, b ]
+            MIDIEvent::MTCQuarterFrame(piece, value) => {
+                vec![ 0xF1, ((piece & 0x07) << 4) | (value & 0x0F) ]
             }
 
-            MIDIEvent::TimeCode(rate, hour, minute, second, frame) => {
-                let coded_rate = {
-                    if *rate == 24.0 {
-                        0
-                    } else if *rate == 25.0 {
-                        1
-                    } else if *rate == 27.97 {
-                        2
-                    } else if *rate == 30.0 {
-                        3
-                    } else {
-                        3
-                    }
-                };
-
-                let first_byte: u8 = (coded_rate << 5) + hour;
-                vec![0xF1, first_byte, *minute, *second, *frame]
+            MIDIEvent::TimeCode(timecode) => {
+                vec![
+                    0xF0, 0x7F, 0x7F, 0x01, 0x01,
+                    timecode.get_hours_byte(),
+                    timecode.minutes,
+                    timecode.seconds,
+                    timecode.frames,
+                    0xF7
+                ]
             }
 
             MIDIEvent::SongPositionPointer(beat) => {
                 vec![
                     0xF2,
-                    (*beat & 0x7F) as u8,
-                    ((*beat >> 8) & 0x7F) as u8
+                    beat.get_lsb().get(),
+                    beat.get_msb().get()
                 ]
             }
 
             MIDIEvent::SongSelect(song) => {
                 vec![
                     0xF3,
-                    *song & 0x7F
+                    song.get()
                 ]
             }
 
@@ -752,22 +866,22 @@ impl MIDIBytes for MIDIEvent {
             }
 
             0x80..=0xEF => {
-                let channel: u8;
+                let channel: Channel;
                 let leadnibble: u8 = leadbyte >> 4;
                 match leadnibble {
                     0x8 => {
-                        channel = leadbyte & 0x0F;
-                        let note = bytes.remove(0);
-                        let velocity = bytes.remove(0);
+                        channel = Channel::from_status(leadbyte);
+                        let note = dequeue_u7(bytes)?;
+                        let velocity = dequeue_u7(bytes)?;
                         let event = MIDIEvent::NoteOff(channel, note, velocity);
                         output = Ok(event);
                     }
                     0x9 => {
-                        channel = leadbyte & 0x0F;
-                        let note = bytes.remove(0);
-                        let velocity = bytes.remove(0);
+                        channel = Channel::from_status(leadbyte);
+                        let note = dequeue_u7(bytes)?;
+                        let velocity = dequeue_u7(bytes)?;
                         // Convert fake NoteOff (NoteOn where velocity is 0) to real NoteOff
-                        let event = if velocity == 0 {
+                        let event = if velocity.get() == 0 {
                             MIDIEvent::NoteOff(channel, note, velocity)
                         } else {
                             MIDIEvent::NoteOn(channel, note, velocity)
@@ -776,260 +890,34 @@ impl MIDIBytes for MIDIEvent {
                         output = Ok(event);
                     }
                     0xA => {
-                        channel = leadbyte & 0x0F;
-                        let note = bytes.remove(0);
-                        let velocity = bytes.remove(0);
+                        channel = Channel::from_status(leadbyte);
+                        let note = dequeue_u7(bytes)?;
+                        let velocity = dequeue_u7(bytes)?;
                         let event = MIDIEvent::AfterTouch(channel, note, velocity);
                         output = Ok(event);
                     }
                     0xB => {
-                        channel = leadbyte & 0x0F;
-                        let controller = bytes.remove(0);
-                        let value = bytes.remove(0);
-                        output = match controller {
-                            0x00 => {
-                                Ok(MIDIEvent::BankSelect(channel, value))
-                            }
-                            0x20 => {
-                                Ok(MIDIEvent::BankSelectLSB(channel, value))
-                            }
-                            0x01 => {
-                                Ok(MIDIEvent::ModulationWheel(channel, value))
-                            }
-                            0x21 => {
-                                Ok(MIDIEvent::ModulationWheelLSB(channel, value))
-                            }
-                            0x02 => {
-                                Ok(MIDIEvent::BreathController(channel, value))
-                            }
-                            0x22 => {
-                                Ok(MIDIEvent::BreathControllerLSB(channel, value))
-                            }
-                            0x04 => {
-                                Ok(MIDIEvent::FootPedal(channel, value))
-                            }
-                            0x24 => {
-                                Ok(MIDIEvent::FootPedalLSB(channel, value))
-                            }
-                            0x05 => {
-                                Ok(MIDIEvent::PortamentoTime(channel, value))
-                            }
-                            0x25 => {
-                                Ok(MIDIEvent::PortamentoTimeLSB(channel, value))
-                            }
-                            0x06 => {
-                                Ok(MIDIEvent::DataEntry(channel, value))
-                            }
-                            0x26 => {
-                                Ok(MIDIEvent::DataEntryLSB(channel, value))
-                            }
-                            0x07 => {
-                                Ok(MIDIEvent::Volume(channel, value))
-                            }
-                            0x27 => {
-                                Ok(MIDIEvent::VolumeLSB(channel, value))
-                            }
-                            0x08 => {
-                                Ok(MIDIEvent::Balance(channel, value))
-                            }
-                            0x28 => {
-                                Ok(MIDIEvent::BalanceLSB(channel, value))
-                            }
-                            0x0A => {
-                                Ok(MIDIEvent::Pan(channel, value))
-                            }
-                            0x2A => {
-                                Ok(MIDIEvent::PanLSB(channel, value))
-                            }
-                            0x0B => {
-                                Ok(MIDIEvent::Expression(channel, value))
-                            }
-                            0x2B => {
-                                Ok(MIDIEvent::ExpressionLSB(channel, value))
-                            }
-                            0x0C => {
-                                Ok(MIDIEvent::EffectControl1(channel, value))
-                            }
-                            0x2C => {
-                                Ok(MIDIEvent::EffectControl1LSB(channel, value))
-                            }
-                            0x0D => {
-                                Ok(MIDIEvent::EffectControl2(channel, value))
-                            }
-                            0x2D => {
-                                Ok(MIDIEvent::EffectControl2LSB(channel, value))
-                            }
-
-                            0x10 => {
-                                Ok(MIDIEvent::GeneralPurpose1(channel, value))
-                            }
-                            0x30 => {
-                                Ok(MIDIEvent::GeneralPurpose1LSB(channel, value))
-                            }
-                            0x11 => {
-                                Ok(MIDIEvent::GeneralPurpose2(channel, value))
-                            }
-                            0x31 => {
-                                Ok(MIDIEvent::GeneralPurpose2LSB(channel, value))
-                            }
-                            0x12 => {
-                                Ok(MIDIEvent::GeneralPurpose3(channel, value))
-                            }
-                            0x32 => {
-                                Ok(MIDIEvent::GeneralPurpose3LSB(channel, value))
-                            }
-                            0x13 => {
-                                Ok(MIDIEvent::GeneralPurpose4(channel, value))
-                            }
-                            0x33 => {
-                                Ok(MIDIEvent::GeneralPurpose4LSB(channel, value))
-                            }
-                            0x40 => {
-                                Ok(MIDIEvent::HoldPedal(channel, value))
-                            }
-                            0x41 => {
-                                Ok(MIDIEvent::Portamento(channel, value))
-                            }
-                            0x42 => {
-                                Ok(MIDIEvent::Sustenuto(channel, value))
-                            }
-                            0x43 => {
-                                Ok(MIDIEvent::SoftPedal(channel, value))
-                            }
-                            0x44 => {
-                                Ok(MIDIEvent::Legato(channel, value))
-                            }
-                            0x45 => {
-                                Ok(MIDIEvent::Hold2Pedal(channel, value))
-                            }
-                            0x46 => {
-                                Ok(MIDIEvent::SoundVariation(channel, value))
-                            }
-                            0x47 => {
-                                Ok(MIDIEvent::SoundTimbre(channel, value))
-                            }
-                            0x48 => {
-                                Ok(MIDIEvent::SoundReleaseTime(channel, value))
-                            }
-                            0x49 => {
-                                Ok(MIDIEvent::SoundAttack(channel, value))
-                            }
-                            0x4A => {
-                                Ok(MIDIEvent::SoundBrightness(channel, value))
-                            }
-                            0x4B => {
-                                Ok(MIDIEvent::SoundControl1(channel, value))
-                            }
-                            0x4C => {
-                                Ok(MIDIEvent::SoundControl2(channel, value))
-                            }
-                            0x4D => {
-                                Ok(MIDIEvent::SoundControl3(channel, value))
-                            }
-                            0x4E => {
-                                Ok(MIDIEvent::SoundControl4(channel, value))
-                            }
-                            0x4F => {
-                                Ok(MIDIEvent::SoundControl5(channel, value))
-                            }
-                            0x50 => {
-                                Ok(MIDIEvent::GeneralPurpose5(channel, value))
-                            }
-                            0x51 => {
-                                Ok(MIDIEvent::GeneralPurpose6(channel, value))
-                            }
-                            0x52 => {
-                                Ok(MIDIEvent::GeneralPurpose7(channel, value))
-                            }
-                            0x53 => {
-                                Ok(MIDIEvent::GeneralPurpose8(channel, value))
-                            }
-
-                            0x5B => {
-                                Ok(MIDIEvent::EffectsLevel(channel, value))
-                            }
-
-                            0x5C => {
-                                Ok(MIDIEvent::TremuloLevel(channel, value))
-                            }
-
-                            0x5D => {
-                                Ok(MIDIEvent::ChorusLevel(channel, value))
-                            }
-                            0x5E => {
-                                Ok(MIDIEvent::CelesteLevel(channel, value))
-                            }
-
-                            0x5F => {
-                                Ok(MIDIEvent::PhaserLevel(channel, value))
-                            }
-
-                            0x60 => {
-                                Ok(MIDIEvent::DataIncrement(channel))
-                            }
-
-                            0x61 => {
-                                Ok(MIDIEvent::DataDecrement(channel))
-                            }
-                            0x62 => {
-                                Ok(MIDIEvent::NonRegisteredParameterNumberLSB(channel, value))
-                            }
-
-                            0x63 => {
-                                Ok(MIDIEvent::NonRegisteredParameterNumber(channel, value))
-                            }
-
-                            0x64 => {
-                                Ok(MIDIEvent::RegisteredParameterNumberLSB(channel, value))
-                            }
-                            0x65 => {
-                                Ok(MIDIEvent::RegisteredParameterNumber(channel, value))
-                            }
-                            0x78 => {
-                                Ok(MIDIEvent::AllSoundOff(channel))
-                            }
-                            0x79 => {
-                                Ok(MIDIEvent::AllControllersOff(channel))
-                            }
-                            0x7A => {
-                                Ok(MIDIEvent::LocalControl(channel, value))
-                            }
-                            0x7B => {
-                                Ok(MIDIEvent::AllNotesOff(channel))
-                            }
-                            0x7C => {
-                                Ok(MIDIEvent::OmniOff(channel))
-                            }
-                            0x7D => {
-                                Ok(MIDIEvent::OmniOn(channel))
-                            }
-                            0xFE => {
-                                Ok(MIDIEvent::MonophonicOperation(channel, value))
-                            }
-                            0xFF => {
-                                Ok(MIDIEvent::PolyphonicOperation(channel))
-                            }
-                            _ => {
-                                Ok(MIDIEvent::ControlChange(channel, controller, value))
-                            }
-                        }
+                        channel = Channel::from_status(leadbyte);
+                        let controller = dequeue_u7(bytes)?;
+                        let value = dequeue_u7(bytes)?;
+                        output = Ok(build_control_change(channel, controller, value));
                     }
                     0xC => {
-                        channel = leadbyte & 0x0F;
-                        let new_program = bytes.remove(0);
+                        channel = Channel::from_status(leadbyte);
+                        let new_program = dequeue_u7(bytes)?;
                         let event = MIDIEvent::ProgramChange(channel, new_program);
                         output = Ok(event);
                     }
                     0xD => {
-                        channel = leadbyte & 0x0F;
-                        let pressure = bytes.remove(0);
+                        channel = Channel::from_status(leadbyte);
+                        let pressure = dequeue_u7(bytes)?;
                         let event = MIDIEvent::ChannelPressure(channel, pressure);
                         output = Ok(event);
                     }
                     0xE => {
-                        channel = leadbyte & 0x0F;
-                        let least_significant_byte = bytes.remove(0);
-                        let most_significant_byte = bytes.remove(0);
+                        channel = Channel::from_status(leadbyte);
+                        let least_significant_byte = dequeue_u7(bytes)?;
+                        let most_significant_byte = dequeue_u7(bytes)?;
                         let event = build_pitch_wheel_change(channel, least_significant_byte, most_significant_byte);
                         output = Ok(event);
                     }
@@ -1050,23 +938,28 @@ impl MIDIBytes for MIDIEvent {
                     }
                 }
 
-                let event = MIDIEvent::SystemExclusive(bytedump);
-                output = Ok(event);
+                output = Ok(build_system_exclusive(bytedump));
+            }
+
+            0xF1 => {
+                // MTC Quarter Frame
+                let byte = dequeue_u7(bytes)?.get();
+                output = Ok(MIDIEvent::MTCQuarterFrame(byte >> 4, byte & 0x0F));
             }
 
             0xF2 => {
                 // Song Position Pointer
-                let least_significant_byte = bytes.remove(0);
-                let most_significant_byte = bytes.remove(0);
+                let least_significant_byte = dequeue_u7(bytes)?;
+                let most_significant_byte = dequeue_u7(bytes)?;
 
-                let beat = ((most_significant_byte as u16) << 8) + (least_significant_byte as u16);
+                let beat = U14::from_lsb_msb(least_significant_byte, most_significant_byte);
                 let event = MIDIEvent::SongPositionPointer(beat);
                 output = Ok(event);
             }
 
             0xF3 => {
-                let song = bytes.remove(0);
-                let event = MIDIEvent::SongSelect(song & 0x7F);
+                let song = dequeue_u7(bytes)?;
+                let event = MIDIEvent::SongSelect(song);
                 output = Ok(event);
             }
 
@@ -1074,8 +967,15 @@ impl MIDIBytes for MIDIEvent {
                 let meta_byte = bytes.remove(0); // Meta Type
                 varlength = get_variable_length_number(bytes);
                 if meta_byte == 0x51 {
-                    let event = MIDIEvent::SetTempo(dequeue_n(bytes, varlength as usize));
-                    output = Ok(event);
+                    let microseconds = dequeue_n(bytes, varlength as usize);
+                    match Tempo::new(microseconds) {
+                        Ok(tempo) => {
+                            output = Ok(MIDIEvent::SetTempo(tempo));
+                        }
+                        Err(_e) => {
+                            output = Err(ApresError::UnknownMetaEvent(microseconds.to_be_bytes().to_vec()));
+                        }
+                    }
                 } else {
                     let mut bytedump = Vec::new();
                     for _ in 0..varlength {
@@ -1164,24 +1064,49 @@ impl MIDIBytes for MIDIEvent {
                             }
                         }
                         0x20 => {
-                            let event = MIDIEvent::ChannelPrefix(bytedump[0]);
-                            output = Ok(event);
+                            match Channel::new(bytedump[0]) {
+                                Ok(channel) => {
+                                    output = Ok(MIDIEvent::ChannelPrefix(channel));
+                                }
+                                Err(_e) => {
+                                    output = Err(ApresError::UnknownMetaEvent(bytedump.clone()));
+                                }
+                            }
                         }
                         0x2F => {
                             output = Ok(MIDIEvent::EndOfTrack);
                         }
                         0x51 => { }
                         0x54 => {
-                            let event = MIDIEvent::SMPTEOffset(bytedump[0], bytedump[1], bytedump[2], bytedump[3], bytedump[4]);
-                            output = Ok(event);
+                            // An out of range offset only skips this event, not the whole file
+                            match Timecode::from_bytes([bytedump[0], bytedump[1], bytedump[2], bytedump[3], bytedump[4]]) {
+                                Ok(timecode) => {
+                                    output = Ok(MIDIEvent::SMPTEOffset(timecode));
+                                }
+                                Err(_e) => {
+                                    output = Err(ApresError::UnknownMetaEvent(bytedump.clone()));
+                                }
+                            }
                         }
                         0x58 => {
-                            let event = MIDIEvent::TimeSignature(bytedump[0], bytedump[1], bytedump[2], bytedump[3]);
-                            output = Ok(event);
+                            match Meter::from_raw(bytedump[0], bytedump[1], bytedump[2], bytedump[3]) {
+                                Ok(meter) => {
+                                    output = Ok(MIDIEvent::TimeSignature(meter));
+                                }
+                                Err(_e) => {
+                                    output = Err(ApresError::UnknownMetaEvent(bytedump.clone()));
+                                }
+                            }
                         }
                         0x59 => {
-                            let event = build_key_signature(bytedump[1], bytedump[0]);
-                            output = Ok(event);
+                            match Key::from_sf_mi(bytedump[0] as i8, bytedump[1]) {
+                                Ok(key) => {
+                                    output = Ok(MIDIEvent::KeySignature(key));
+                                }
+                                Err(_e) => {
+                                    output = Err(ApresError::UnknownMetaEvent(bytedump.clone()));
+                                }
+                            }
                         }
                         0x7F => {
                             let event = MIDIEvent::SequencerSpecific(bytedump[3..].to_vec());
@@ -1195,7 +1120,7 @@ impl MIDIBytes for MIDIEvent {
                 }
             }
 
-            0xF1 | 0xF6 | 0xF8 | 0xFA | 0xFB | 0xFC | 0xFE | 0xF7 => {
+            0xF6 | 0xF8 | 0xFA | 0xFB | 0xFC | 0xFE | 0xF7 => {
                 // Do Nothing. These are system-realtime and shouldn't be in a file.
             }
 
@@ -1232,16 +1157,18 @@ impl MIDIBytes for MIDIEvent {
 /// ```
 /// Creating a song
 /// ```
-/// use apres::MIDI;
+/// use apres::{MIDI, Channel, U7};
 /// use apres::MIDIEvent::{NoteOff, NoteOn};
 /// // Create an empty MIDI file.
 /// let mut midi = MIDI::new();
+/// let channel = Channel::new(0).unwrap();
+/// let note = U7::new(64).unwrap();
 ///
 /// // Press midi note 64 (Middle E) on the first track (0) at the first position (0 ticks)
-/// midi.insert_event(0, 0, NoteOn(64, 100, 100));
+/// midi.insert_event(0, 0, NoteOn(channel, note, U7::new(100).unwrap()));
 ///
 /// // Release midi note 64 (Middle E) on the first track (0) one beat later (120 ticks)
-/// midi.push_event(0, 120, NoteOn(64, 100, 100));
+/// midi.push_event(0, 120, NoteOff(channel, note, U7::new(100).unwrap()));
 ///
 /// // Save it to a file
 /// midi.save("beep.mid");
@@ -1253,6 +1180,8 @@ pub struct MIDI {
     events: HashMap<u64, MIDIEvent>,
     event_id_gen: u64,
     event_positions: HashMap<u64, (usize, usize)>,
+    history: Option<EditHistory>, // Only used once enable_history() is called
+    observers: Vec<Sender<MIDIEdit>>,
 
     _active_byte: u8 // Only used when reading in a .mid
 }
@@ -1267,6 +1196,8 @@ impl MIDI {
             midi_format: 1,
             events: HashMap::new(),
             event_positions: HashMap::new(),
+            history: None,
+            observers: Vec::new(),
             _active_byte: 0x90
         }
     }
@@ -1518,6 +1449,17 @@ impl MIDI {
         output
     }
 
+    /// Get every event id as (tick, track, event id), ordered by tick, then track, then id
+    fn get_ordered_event_ids(&self) -> Vec<(usize, usize, u64)> {
+        let mut output = Vec::new();
+        for (eid, (track, tick)) in self.event_positions.iter() {
+            output.push((*tick, *track, *eid));
+        }
+        output.sort();
+
+        output
+    }
+
     pub fn count_tracks(&self) -> usize {
         let mut used_tracks = HashSet::new();
         for (_, (current_track, __)) in self.event_positions.iter() {
@@ -1544,7 +1486,8 @@ impl MIDI {
 
     /// Set Pulses Per Quarter Note
     pub fn set_ppqn(&mut self, new_ppqn: u16) {
-        self.ppqn = new_ppqn;
+        let old_ppqn = self.ppqn;
+        self.apply_edit(MIDIEdit::SetPPQN { old_ppqn, new_ppqn });
     }
 
     /// Get Pulses Per Quarter Note
@@ -1553,22 +1496,63 @@ impl MIDI {
     }
 
     pub fn set_format(&mut self, new_format: u16) {
-        self.midi_format = new_format;
+        let old_format = self.midi_format;
+        self.apply_edit(MIDIEdit::SetFormat { old_format, new_format });
     }
 
     pub fn get_format(&self) -> u16 {
         self.midi_format
     }
 
+    /// Make a change to the MIDI, recording it in the edit history if enabled and notifying subscribers.
+    /// Every mutation of events, positions, ppqn and format goes through here.
+    fn apply_edit(&mut self, edit: MIDIEdit) {
+        match &edit {
+            MIDIEdit::InsertEvent { event_id, track, tick, event } => {
+                self.events.insert(*event_id, event.clone());
+                self.event_positions.insert(*event_id, (*track, *tick));
+            }
+            MIDIEdit::RemoveEvent { event_id, .. } => {
+                self.events.remove(event_id);
+                self.event_positions.remove(event_id);
+            }
+            MIDIEdit::MoveEvent { event_id, to, .. } => {
+                match to {
+                    Some(position) => {
+                        self.event_positions.insert(*event_id, *position);
+                    }
+                    None => {
+                        self.event_positions.remove(event_id);
+                    }
+                }
+            }
+            MIDIEdit::ReplaceEvent { event_id, new_event, .. } => {
+                self.events.insert(*event_id, new_event.clone());
+            }
+            MIDIEdit::SetPPQN { new_ppqn, .. } => {
+                self.ppqn = *new_ppqn;
+            }
+            MIDIEdit::SetFormat { new_format, .. } => {
+                self.midi_format = *new_format;
+            }
+        }
+
+        self.notify_observers(&edit);
+        self.record_edit(edit);
+    }
+
     /// Change the track or position of an event, given it id in the MIDI
     pub fn move_event(&mut self, new_track: usize, new_tick: usize, event_id: u64) {
-        self.event_positions.entry(event_id)
-            .and_modify(|pair| { *pair = (new_track, new_tick); })
-            .or_insert((new_track, new_tick));
+        let from = self.event_positions.get(&event_id).copied();
+        self.apply_edit(MIDIEdit::MoveEvent {
+            event_id,
+            from,
+            to: Some((new_track, new_tick))
+        });
     }
 
     /// Insert an event into the track
-    pub fn insert_event(&mut self, track: usize, tick: usize, event: MIDIEvent) -> Result<u64, ApresError> {
+    pub fn insert_event<T: Into<MIDIEvent>>(&mut self, track: usize, tick: usize, event: T) -> Result<u64, ApresError> {
         let result;
         if track > 15 {
             result = Err(ApresError::TrackOutOfBounds);
@@ -1576,9 +1560,12 @@ impl MIDI {
             let new_event_id = self.event_id_gen;
             self.event_id_gen += 1;
 
-            self.events.insert(new_event_id, event);
-
-            self.move_event(track, tick, new_event_id);
+            self.apply_edit(MIDIEdit::InsertEvent {
+                event_id: new_event_id,
+                track,
+                tick,
+                event: event.into()
+            });
 
             result = Ok(new_event_id);
         }
@@ -1587,18 +1574,21 @@ impl MIDI {
     }
 
     /// Insert an event after the latest event in the track
-    pub fn push_event(&mut self, track: usize, wait: usize, event: MIDIEvent) -> Result<u64, ApresError> {
+    pub fn push_event<T: Into<MIDIEvent>>(&mut self, track: usize, wait: usize, event: T) -> Result<u64, ApresError> {
         let result;
         if track > 15 {
             result = Err(ApresError::TrackOutOfBounds);
         } else {
             let new_event_id = self.event_id_gen;
-
-            self.events.insert(new_event_id, event);
             self.event_id_gen += 1;
 
             let last_tick_in_track = self.get_track_length(track) - 1;
-            self.move_event(track, last_tick_in_track + wait, new_event_id);
+            self.apply_edit(MIDIEdit::InsertEvent {
+                event_id: new_event_id,
+                track,
+                tick: last_tick_in_track + wait,
+                event: event.into()
+            });
 
             result = Ok(new_event_id);
         }
@@ -1606,6 +1596,36 @@ impl MIDI {
         result
     }
 
+    /// Remove an event from the MIDI, returning it
+    pub fn remove_event(&mut self, event_id: u64) -> Result<MIDIEvent, ApresError> {
+        let event = match self.events.get(&event_id) {
+            Some(event) => {
+                event.clone()
+            }
+            None => {
+                Err(ApresError::EventNotFound(event_id))?
+            }
+        };
+
+        let (track, tick) = match self.event_positions.get(&event_id) {
+            Some(position) => {
+                *position
+            }
+            None => {
+                Err(ApresError::EventNotFound(event_id))?
+            }
+        };
+
+        self.apply_edit(MIDIEdit::RemoveEvent {
+            event_id,
+            track,
+            tick,
+            event: event.clone()
+        });
+
+        Ok(event)
+    }
+
     pub fn get_event(&self, event_id: u64) -> Option<MIDIEvent> {
         match self.events.get(&event_id) {
             Some(event) => {
@@ -1617,13 +1637,28 @@ impl MIDI {
         }
     }
 
-    pub fn replace_event(&mut self, event_id: u64, new_midi_event: MIDIEvent) -> Result<(), ApresError> {
-        if self.events.contains_key(&event_id) {
-            self.events.entry(event_id)
-                .and_modify(|e| *e = new_midi_event);
-            Ok(())
-        } else {
-            Err(ApresError::EventNotFound(event_id))
+    /// Get an event as a categorized MIDIMessage, given its id
+    pub fn get_message(&self, event_id: u64) -> Option<MIDIMessage> {
+        self.events.get(&event_id).map(|event| MIDIMessage::from(event.clone()))
+    }
+
+    pub fn replace_event<T: Into<MIDIEvent>>(&mut self, event_id: u64, new_midi_event: T) -> Result<(), ApresError> {
+        match (self.events.get(&event_id), self.event_positions.get(&event_id)) {
+            (Some(old_event), Some((track, tick))) => {
+                let old_event = old_event.clone();
+                let (track, tick) = (*track, *tick);
+                self.apply_edit(MIDIEdit::ReplaceEvent {
+                    event_id,
+                    track,
+                    tick,
+                    old_event,
+                    new_event: new_midi_event.into()
+                });
+                Ok(())
+            }
+            _ => {
+                Err(ApresError::EventNotFound(event_id))
+            }
         }
     }
 }
@@ -1642,13 +1677,25 @@ pub fn listen<T>(channel: u8, device_id: u8, context: &mut T, callback: fn(&mut
 fn dequeue_n(bytes: &mut Vec<u8>, n: usize) -> u32 {
     let mut tn: u32 = 0;
     for _ in 0..n {
-        tn *= 256;
         let x = bytes.remove(0);
-        tn += x as u32;
+        // Saturate rather than overflow on lengths over 4 bytes
+        tn = tn.saturating_mul(256).saturating_add(x as u32);
     }
     tn
 }
 
+fn dequeue_u7(bytes: &mut Vec<u8>) -> Result<U7, ApresError> {
+    let byte = bytes.remove(0);
+    match U7::new(byte) {
+        Ok(value) => {
+            Ok(value)
+        }
+        Err(_) => {
+            Err(ApresError::InvalidBytes(vec![byte]))
+        }
+    }
+}
+
 fn get_variable_length_number(bytes: &mut Vec<u8>) -> u64 {
     let mut n = 0u64;
 
@@ -1686,27 +1733,11 @@ fn to_variable_length_bytes(number: usize) -> Vec<u8> {
 
 // input a number between (-1, 1), get an unsigned value with 0x2000 as midpoint
 pub fn get_pitchwheel_value(n: f64) -> u16 {
-    if n < 0_f64 {
-        ((1_f64 + n) * (0x2000 as f64)) as u16
-    } else if n > 0_f64 {
-        (n * (0x1FFF as f64)) as u16 + 0x2000
-    } else {
-        0x2000
-    }
-}
-
-fn build_key_signature(mi: u8, sf: u8) -> MIDIEvent {
-    let chord_name = get_chord_name_from_mi_sf(mi, sf);
-
-    MIDIEvent::KeySignature(chord_name.to_string())
-}
-
-fn build_pitch_wheel_change(channel: u8, lsb: u8, msb: u8) -> MIDIEvent {
-    let unsigned_value: f64 = (((msb as u16) << 8) + (lsb as u16)) as f64;
-    let new_value: f64 = ((unsigned_value * 2_f64) as f64 / 0x3FFF as f64) - 1_f64;
-    MIDIEvent::PitchWheelChange(channel, new_value)
+    PitchBend::from_normalized(n).get()
 }
 
+// Flats come back as 8 | n, not as they're written to the file. Kept as it was for compatibility.
+#[deprecated(note = "parse a Key and use Key::get_sf_mi")]
 pub fn get_mi_sf(chord_name: &str) -> (u8, u8) {
     match chord_name {
         "A" => (0, 3),
@@ -1737,21 +1768,259 @@ pub fn get_mi_sf(chord_name: &str) -> (u8, u8) {
     }
 }
 
-fn get_chord_name_from_mi_sf(mi: u8, sf: u8) -> String {
-    let map = vec![
-        vec![
-            "Cb", "Gb", "Db", "Ab",
-            "Eb", "Bb", "F",
-            "C", "G", "D", "A",
-            "E", "B", "F#", "C#",
-        ],
-        vec![
-            "Abm", "Ebm", "Bbm", "Fm",
-            "Cm", "Gm", "Dm",
-            "Am", "Em", "Bm", "F#m",
-            "C#m", "G#m", "D#m", "A#m",
-        ]
-    ];
-
-    map[mi as usize][((sf as i8) + 7) as usize].to_string()
+fn build_control_change(channel: Channel, controller: U7, value: U7) -> MIDIEvent {
+    match controller.get() {
+        0x00 => {
+            MIDIEvent::BankSelect(channel, value)
+        }
+        0x20 => {
+            MIDIEvent::BankSelectLSB(channel, value)
+        }
+        0x01 => {
+            MIDIEvent::ModulationWheel(channel, value)
+        }
+        0x21 => {
+            MIDIEvent::ModulationWheelLSB(channel, value)
+        }
+        0x02 => {
+            MIDIEvent::BreathController(channel, value)
+        }
+        0x22 => {
+            MIDIEvent::BreathControllerLSB(channel, value)
+        }
+        0x04 => {
+            MIDIEvent::FootPedal(channel, value)
+        }
+        0x24 => {
+            MIDIEvent::FootPedalLSB(channel, value)
+        }
+        0x05 => {
+            MIDIEvent::PortamentoTime(channel, value)
+        }
+        0x25 => {
+            MIDIEvent::PortamentoTimeLSB(channel, value)
+        }
+        0x06 => {
+            MIDIEvent::DataEntry(channel, value)
+        }
+        0x26 => {
+            MIDIEvent::DataEntryLSB(channel, value)
+        }
+        0x07 => {
+            MIDIEvent::Volume(channel, value)
+        }
+        0x27 => {
+            MIDIEvent::VolumeLSB(channel, value)
+        }
+        0x08 => {
+            MIDIEvent::Balance(channel, value)
+        }
+        0x28 => {
+            MIDIEvent::BalanceLSB(channel, value)
+        }
+        0x0A => {
+            MIDIEvent::Pan(channel, value)
+        }
+        0x2A => {
+            MIDIEvent::PanLSB(channel, value)
+        }
+        0x0B => {
+            MIDIEvent::Expression(channel, value)
+        }
+        0x2B => {
+            MIDIEvent::ExpressionLSB(channel, value)
+        }
+        0x0C => {
+            MIDIEvent::EffectControl1(channel, value)
+        }
+        0x2C => {
+            MIDIEvent::EffectControl1LSB(channel, value)
+        }
+        0x0D => {
+            MIDIEvent::EffectControl2(channel, value)
+        }
+        0x2D => {
+            MIDIEvent::EffectControl2LSB(channel, value)
+        }
+
+        0x10 => {
+            MIDIEvent::GeneralPurpose1(channel, value)
+        }
+        0x30 => {
+            MIDIEvent::GeneralPurpose1LSB(channel, value)
+        }
+        0x11 => {
+            MIDIEvent::GeneralPurpose2(channel, value)
+        }
+        0x31 => {
+            MIDIEvent::GeneralPurpose2LSB(channel, value)
+        }
+        0x12 => {
+            MIDIEvent::GeneralPurpose3(channel, value)
+        }
+        0x32 => {
+            MIDIEvent::GeneralPurpose3LSB(channel, value)
+        }
+        0x13 => {
+            MIDIEvent::GeneralPurpose4(channel, value)
+        }
+        0x33 => {
+            MIDIEvent::GeneralPurpose4LSB(channel, value)
+        }
+        0x40 => {
+            MIDIEvent::HoldPedal(channel, value)
+        }
+        0x41 => {
+            MIDIEvent::Portamento(channel, value)
+        }
+        0x42 => {
+            MIDIEvent::Sustenuto(channel, value)
+        }
+        0x43 => {
+            MIDIEvent::SoftPedal(channel, value)
+        }
+        0x44 => {
+            MIDIEvent::Legato(channel, value)
+        }
+        0x45 => {
+            MIDIEvent::Hold2Pedal(channel, value)
+        }
+        0x46 => {
+            MIDIEvent::SoundVariation(channel, value)
+        }
+        0x47 => {
+            MIDIEvent::SoundTimbre(channel, value)
+        }
+        0x48 => {
+            MIDIEvent::SoundReleaseTime(channel, value)
+        }
+        0x49 => {
+            MIDIEvent::SoundAttack(channel, value)
+        }
+        0x4A => {
+            MIDIEvent::SoundBrightness(channel, value)
+        }
+        0x4B => {
+            MIDIEvent::SoundControl1(channel, value)
+        }
+        0x4C => {
+            MIDIEvent::SoundControl2(channel, value)
+        }
+        0x4D => {
+            MIDIEvent::SoundControl3(channel, value)
+        }
+        0x4E => {
+            MIDIEvent::SoundControl4(channel, value)
+        }
+        0x4F => {
+            MIDIEvent::SoundControl5(channel, value)
+        }
+        0x50 => {
+            MIDIEvent::GeneralPurpose5(channel, value)
+        }
+        0x51 => {
+            MIDIEvent::GeneralPurpose6(channel, value)
+        }
+        0x52 => {
+            MIDIEvent::GeneralPurpose7(channel, value)
+        }
+        0x53 => {
+            MIDIEvent::GeneralPurpose8(channel, value)
+        }
+
+        0x5B => {
+            MIDIEvent::EffectsLevel(channel, value)
+        }
+
+        0x5C => {
+            MIDIEvent::TremuloLevel(channel, value)
+        }
+
+        0x5D => {
+            MIDIEvent::ChorusLevel(channel, value)
+        }
+        0x5E => {
+            MIDIEvent::CelesteLevel(channel, value)
+        }
+
+        0x5F => {
+            MIDIEvent::PhaserLevel(channel, value)
+        }
+
+        0x60 => {
+            MIDIEvent::DataIncrement(channel)
+        }
+
+        0x61 => {
+            MIDIEvent::DataDecrement(channel)
+        }
+        0x62 => {
+            MIDIEvent::NonRegisteredParameterNumberLSB(channel, value)
+        }
+
+        0x63 => {
+            MIDIEvent::NonRegisteredParameterNumber(channel, value)
+        }
+
+        0x64 => {
+            MIDIEvent::RegisteredParameterNumberLSB(channel, value)
+        }
+        0x65 => {
+            MIDIEvent::RegisteredParameterNumber(channel, value)
+        }
+        0x78 => {
+            MIDIEvent::AllSoundOff(channel)
+        }
+        0x79 => {
+            MIDIEvent::AllControllersOff(channel)
+        }
+        0x7A => {
+            MIDIEvent::LocalControl(channel, value.get() >= 0x40)
+        }
+        0x7B => {
+            MIDIEvent::AllNotesOff(channel)
+        }
+        0x7C => {
+            MIDIEvent::OmniOff(channel)
+        }
+        0x7D => {
+            MIDIEvent::OmniOn(channel)
+        }
+        0x7E => {
+            MIDIEvent::MonophonicOperation(channel, value)
+        }
+        0x7F => {
+            MIDIEvent::PolyphonicOperation(channel)
+        }
+        _ => {
+            MIDIEvent::ControlChange(channel, controller, value)
+        }
+    }
 }
+
+// Recognize the system exclusive messages that have their own MIDIEvent. TimeCode has no device id,
+// so only full frame messages sent to all devices become one.
+fn build_system_exclusive(data: Vec<u8>) -> MIDIEvent {
+    match data.as_slice() {
+        [0x7F, 0x7F, 0x01, 0x01, hours_byte, minutes, seconds, frames] => {
+            match Timecode::from_bytes([*hours_byte, *minutes, *seconds, *frames, 0]) {
+                Ok(timecode) => {
+                    MIDIEvent::TimeCode(timecode)
+                }
+                Err(_) => {
+                    MIDIEvent::SystemExclusive(data)
+                }
+            }
+        }
+        _ => {
+            MIDIEvent::SystemExclusive(data)
+        }
+    }
+}
+
+fn build_pitch_wheel_change(channel: Channel, lsb: U7, msb: U7) -> MIDIEvent {
+    MIDIEvent::PitchWheelChange(channel, PitchBend::from_u14(U14::from_lsb_msb(lsb, msb)))
+}
+
+#[cfg(test)]
+mod tests;
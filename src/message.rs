@@ -0,0 +1,354 @@
+use std::convert::TryFrom;
+
+use crate::{MIDIEvent, MIDIBytes, Channel, U7, U14, PitchBend, Key, Tempo, Meter, Timecode, build_control_change, build_system_exclusive};
+
+/// Layered view of a MIDIEvent, split by the kind of message it is.
+///
+/// Converting a MIDIEvent into a MIDIMessage and back gives the same MIDIEvent, with one exception:
+/// a generic `MIDIEvent::ControlChange` on a controller number that has its own variant
+/// (eg, 0x07 -> `MIDIEvent::Volume`) comes back as that named variant. Both encode to the same bytes.
+///
+/// `MIDIEvent::TimeCode` is sent as a universal real time system exclusive message, so it's categorized as SysEx,
+/// and a `MIDIEvent::SystemExclusive` holding a valid MTC full frame comes back as `MIDIEvent::TimeCode`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MIDIMessage {
+    Meta(MetaEvent),
+    Channel(ChannelMessage),
+    SystemCommon(SystemCommon),
+    SystemRealtime(SystemRealtime),
+    SysEx(SysEx)
+}
+
+/// Events that only exist in files
+#[derive(Clone, Debug, PartialEq)]
+pub enum MetaEvent {
+    SequenceNumber(u16),
+    Text(String),
+    CopyRightNotice(String),
+    TrackName(String),
+    InstrumentName(String),
+    Lyric(String),
+    Marker(String),
+    CuePoint(String),
+    ChannelPrefix(Channel),
+    SetTempo(Tempo),
+    SMPTEOffset(Timecode),
+    TimeSignature(Meter),
+    KeySignature(Key),
+    SequencerSpecific(Vec<u8>),
+    EndOfTrack
+}
+
+/// Channel voice message. Controllers are addressed by number
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChannelMessage {
+    pub channel: Channel,
+    pub kind: ChannelMessageKind
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ChannelMessageKind {
+    NoteOff { note: U7, velocity: U7 },
+    NoteOn { note: U7, velocity: U7 },
+    AfterTouch { note: U7, pressure: U7 },
+    ControlChange { controller: U7, value: U7 },
+    ProgramChange { program: U7 },
+    ChannelPressure { pressure: U7 },
+    PitchWheelChange { value: PitchBend }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SystemCommon {
+    MTCQuarterFrame(u8, u8),
+    SongPositionPointer(U14),
+    SongSelect(U7),
+    TuneRequest
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SystemRealtime {
+    MIDIClock,
+    MIDIStart,
+    MIDIContinue,
+    MIDIStop,
+    ActiveSense,
+    Reset
+}
+
+/// System Exclusive message data, not including the 0xF0 and 0xF7 bytes
+#[derive(Clone, Debug, PartialEq)]
+pub struct SysEx(pub Vec<u8>);
+
+impl ChannelMessage {
+    pub fn new(channel: Channel, kind: ChannelMessageKind) -> ChannelMessage {
+        ChannelMessage { channel, kind }
+    }
+}
+
+impl From<MetaEvent> for MIDIEvent {
+    fn from(meta: MetaEvent) -> MIDIEvent {
+        match meta {
+            MetaEvent::SequenceNumber(sequence) => MIDIEvent::SequenceNumber(sequence),
+            MetaEvent::Text(text) => MIDIEvent::Text(text),
+            MetaEvent::CopyRightNotice(text) => MIDIEvent::CopyRightNotice(text),
+            MetaEvent::TrackName(text) => MIDIEvent::TrackName(text),
+            MetaEvent::InstrumentName(text) => MIDIEvent::InstrumentName(text),
+            MetaEvent::Lyric(text) => MIDIEvent::Lyric(text),
+            MetaEvent::Marker(text) => MIDIEvent::Marker(text),
+            MetaEvent::CuePoint(text) => MIDIEvent::CuePoint(text),
+            MetaEvent::ChannelPrefix(channel) => MIDIEvent::ChannelPrefix(channel),
+            MetaEvent::SetTempo(tempo) => MIDIEvent::SetTempo(tempo),
+            MetaEvent::SMPTEOffset(timecode) => MIDIEvent::SMPTEOffset(timecode),
+            MetaEvent::TimeSignature(meter) => MIDIEvent::TimeSignature(meter),
+            MetaEvent::KeySignature(key) => MIDIEvent::KeySignature(key),
+            MetaEvent::SequencerSpecific(data) => MIDIEvent::SequencerSpecific(data),
+            MetaEvent::EndOfTrack => MIDIEvent::EndOfTrack
+        }
+    }
+}
+
+impl TryFrom<MIDIEvent> for MetaEvent {
+    type Error = MIDIEvent;
+    fn try_from(event: MIDIEvent) -> Result<MetaEvent, MIDIEvent> {
+        match MIDIMessage::from(event.clone()) {
+            MIDIMessage::Meta(meta) => Ok(meta),
+            _ => Err(event)
+        }
+    }
+}
+
+impl From<ChannelMessage> for MIDIEvent {
+    fn from(message: ChannelMessage) -> MIDIEvent {
+        let channel = message.channel;
+        match message.kind {
+            ChannelMessageKind::NoteOff { note, velocity } => MIDIEvent::NoteOff(channel, note, velocity),
+            ChannelMessageKind::NoteOn { note, velocity } => MIDIEvent::NoteOn(channel, note, velocity),
+            ChannelMessageKind::AfterTouch { note, pressure } => MIDIEvent::AfterTouch(channel, note, pressure),
+            ChannelMessageKind::ControlChange { controller, value } => build_control_change(channel, controller, value),
+            ChannelMessageKind::ProgramChange { program } => MIDIEvent::ProgramChange(channel, program),
+            ChannelMessageKind::ChannelPressure { pressure } => MIDIEvent::ChannelPressure(channel, pressure),
+            ChannelMessageKind::PitchWheelChange { value } => MIDIEvent::PitchWheelChange(channel, value)
+        }
+    }
+}
+
+impl TryFrom<MIDIEvent> for ChannelMessage {
+    type Error = MIDIEvent;
+    fn try_from(event: MIDIEvent) -> Result<ChannelMessage, MIDIEvent> {
+        match MIDIMessage::from(event.clone()) {
+            MIDIMessage::Channel(channel_message) => Ok(channel_message),
+            _ => Err(event)
+        }
+    }
+}
+
+impl From<SystemCommon> for MIDIEvent {
+    fn from(message: SystemCommon) -> MIDIEvent {
+        match message {
+            SystemCommon::MTCQuarterFrame(piece, value) => MIDIEvent::MTCQuarterFrame(piece, value),
+            SystemCommon::SongPositionPointer(beat) => MIDIEvent::SongPositionPointer(beat),
+            SystemCommon::SongSelect(song) => MIDIEvent::SongSelect(song),
+            SystemCommon::TuneRequest => MIDIEvent::TuneRequest
+        }
+    }
+}
+
+impl TryFrom<MIDIEvent> for SystemCommon {
+    type Error = MIDIEvent;
+    fn try_from(event: MIDIEvent) -> Result<SystemCommon, MIDIEvent> {
+        match MIDIMessage::from(event.clone()) {
+            MIDIMessage::SystemCommon(system_common) => Ok(system_common),
+            _ => Err(event)
+        }
+    }
+}
+
+impl From<SystemRealtime> for MIDIEvent {
+    fn from(message: SystemRealtime) -> MIDIEvent {
+        match message {
+            SystemRealtime::MIDIClock => MIDIEvent::MIDIClock,
+            SystemRealtime::MIDIStart => MIDIEvent::MIDIStart,
+            SystemRealtime::MIDIContinue => MIDIEvent::MIDIContinue,
+            SystemRealtime::MIDIStop => MIDIEvent::MIDIStop,
+            SystemRealtime::ActiveSense => MIDIEvent::ActiveSense,
+            SystemRealtime::Reset => MIDIEvent::Reset
+        }
+    }
+}
+
+impl TryFrom<MIDIEvent> for SystemRealtime {
+    type Error = MIDIEvent;
+    fn try_from(event: MIDIEvent) -> Result<SystemRealtime, MIDIEvent> {
+        match MIDIMessage::from(event.clone()) {
+            MIDIMessage::SystemRealtime(system_realtime) => Ok(system_realtime),
+            _ => Err(event)
+        }
+    }
+}
+
+impl From<SysEx> for MIDIEvent {
+    fn from(message: SysEx) -> MIDIEvent {
+        build_system_exclusive(message.0)
+    }
+}
+
+impl TryFrom<MIDIEvent> for SysEx {
+    type Error = MIDIEvent;
+    fn try_from(event: MIDIEvent) -> Result<SysEx, MIDIEvent> {
+        match MIDIMessage::from(event.clone()) {
+            MIDIMessage::SysEx(sysex) => Ok(sysex),
+            _ => Err(event)
+        }
+    }
+}
+
+impl From<MIDIMessage> for MIDIEvent {
+    fn from(message: MIDIMessage) -> MIDIEvent {
+        match message {
+            MIDIMessage::Meta(meta) => meta.into(),
+            MIDIMessage::Channel(channel_message) => channel_message.into(),
+            MIDIMessage::SystemCommon(system_common) => system_common.into(),
+            MIDIMessage::SystemRealtime(system_realtime) => system_realtime.into(),
+            MIDIMessage::SysEx(sysex) => sysex.into()
+        }
+    }
+}
+
+impl From<MIDIEvent> for MIDIMessage {
+    fn from(event: MIDIEvent) -> MIDIMessage {
+        match event {
+            MIDIEvent::SequenceNumber(sequence) => MIDIMessage::Meta(MetaEvent::SequenceNumber(sequence)),
+            MIDIEvent::Text(text) => MIDIMessage::Meta(MetaEvent::Text(text)),
+            MIDIEvent::CopyRightNotice(text) => MIDIMessage::Meta(MetaEvent::CopyRightNotice(text)),
+            MIDIEvent::TrackName(text) => MIDIMessage::Meta(MetaEvent::TrackName(text)),
+            MIDIEvent::InstrumentName(text) => MIDIMessage::Meta(MetaEvent::InstrumentName(text)),
+            MIDIEvent::Lyric(text) => MIDIMessage::Meta(MetaEvent::Lyric(text)),
+            MIDIEvent::Marker(text) => MIDIMessage::Meta(MetaEvent::Marker(text)),
+            MIDIEvent::CuePoint(text) => MIDIMessage::Meta(MetaEvent::CuePoint(text)),
+            MIDIEvent::ChannelPrefix(channel) => MIDIMessage::Meta(MetaEvent::ChannelPrefix(channel)),
+            MIDIEvent::SetTempo(tempo) => MIDIMessage::Meta(MetaEvent::SetTempo(tempo)),
+            MIDIEvent::SMPTEOffset(timecode) => MIDIMessage::Meta(MetaEvent::SMPTEOffset(timecode)),
+            MIDIEvent::TimeSignature(meter) => MIDIMessage::Meta(MetaEvent::TimeSignature(meter)),
+            MIDIEvent::KeySignature(key) => MIDIMessage::Meta(MetaEvent::KeySignature(key)),
+            MIDIEvent::SequencerSpecific(data) => MIDIMessage::Meta(MetaEvent::SequencerSpecific(data)),
+            MIDIEvent::EndOfTrack => MIDIMessage::Meta(MetaEvent::EndOfTrack),
+
+            MIDIEvent::NoteOff(channel, note, velocity) => {
+                MIDIMessage::Channel(ChannelMessage::new(channel, ChannelMessageKind::NoteOff { note, velocity }))
+            }
+            MIDIEvent::NoteOn(channel, note, velocity) => {
+                MIDIMessage::Channel(ChannelMessage::new(channel, ChannelMessageKind::NoteOn { note, velocity }))
+            }
+            MIDIEvent::AfterTouch(channel, note, pressure) => {
+                MIDIMessage::Channel(ChannelMessage::new(channel, ChannelMessageKind::AfterTouch { note, pressure }))
+            }
+            MIDIEvent::ProgramChange(channel, program) => {
+                MIDIMessage::Channel(ChannelMessage::new(channel, ChannelMessageKind::ProgramChange { program }))
+            }
+            MIDIEvent::ChannelPressure(channel, pressure) => {
+                MIDIMessage::Channel(ChannelMessage::new(channel, ChannelMessageKind::ChannelPressure { pressure }))
+            }
+            MIDIEvent::PitchWheelChange(channel, value) => {
+                MIDIMessage::Channel(ChannelMessage::new(channel, ChannelMessageKind::PitchWheelChange { value }))
+            }
+            MIDIEvent::BankSelect(..)
+            | MIDIEvent::BankSelectLSB(..)
+            | MIDIEvent::ModulationWheel(..)
+            | MIDIEvent::ModulationWheelLSB(..)
+            | MIDIEvent::BreathController(..)
+            | MIDIEvent::BreathControllerLSB(..)
+            | MIDIEvent::FootPedal(..)
+            | MIDIEvent::FootPedalLSB(..)
+            | MIDIEvent::PortamentoTime(..)
+            | MIDIEvent::PortamentoTimeLSB(..)
+            | MIDIEvent::DataEntry(..)
+            | MIDIEvent::DataEntryLSB(..)
+            | MIDIEvent::Volume(..)
+            | MIDIEvent::VolumeLSB(..)
+            | MIDIEvent::Balance(..)
+            | MIDIEvent::BalanceLSB(..)
+            | MIDIEvent::Pan(..)
+            | MIDIEvent::PanLSB(..)
+            | MIDIEvent::Expression(..)
+            | MIDIEvent::ExpressionLSB(..)
+            | MIDIEvent::EffectControl1(..)
+            | MIDIEvent::EffectControl1LSB(..)
+            | MIDIEvent::EffectControl2(..)
+            | MIDIEvent::EffectControl2LSB(..)
+            | MIDIEvent::GeneralPurpose1(..)
+            | MIDIEvent::GeneralPurpose1LSB(..)
+            | MIDIEvent::GeneralPurpose2(..)
+            | MIDIEvent::GeneralPurpose2LSB(..)
+            | MIDIEvent::GeneralPurpose3(..)
+            | MIDIEvent::GeneralPurpose3LSB(..)
+            | MIDIEvent::GeneralPurpose4(..)
+            | MIDIEvent::GeneralPurpose4LSB(..)
+            | MIDIEvent::HoldPedal(..)
+            | MIDIEvent::Portamento(..)
+            | MIDIEvent::Sustenuto(..)
+            | MIDIEvent::SoftPedal(..)
+            | MIDIEvent::Legato(..)
+            | MIDIEvent::Hold2Pedal(..)
+            | MIDIEvent::SoundVariation(..)
+            | MIDIEvent::SoundTimbre(..)
+            | MIDIEvent::SoundReleaseTime(..)
+            | MIDIEvent::SoundAttack(..)
+            | MIDIEvent::SoundBrightness(..)
+            | MIDIEvent::SoundControl1(..)
+            | MIDIEvent::SoundControl2(..)
+            | MIDIEvent::SoundControl3(..)
+            | MIDIEvent::SoundControl4(..)
+            | MIDIEvent::SoundControl5(..)
+            | MIDIEvent::GeneralPurpose5(..)
+            | MIDIEvent::GeneralPurpose6(..)
+            | MIDIEvent::GeneralPurpose7(..)
+            | MIDIEvent::GeneralPurpose8(..)
+            | MIDIEvent::EffectsLevel(..)
+            | MIDIEvent::TremuloLevel(..)
+            | MIDIEvent::ChorusLevel(..)
+            | MIDIEvent::CelesteLevel(..)
+            | MIDIEvent::PhaserLevel(..)
+            | MIDIEvent::DataIncrement(..)
+            | MIDIEvent::DataDecrement(..)
+            | MIDIEvent::RegisteredParameterNumber(..)
+            | MIDIEvent::RegisteredParameterNumberLSB(..)
+            | MIDIEvent::NonRegisteredParameterNumber(..)
+            | MIDIEvent::NonRegisteredParameterNumberLSB(..)
+            | MIDIEvent::AllControllersOff(..)
+            | MIDIEvent::LocalControl(..)
+            | MIDIEvent::AllNotesOff(..)
+            | MIDIEvent::AllSoundOff(..)
+            | MIDIEvent::OmniOff(..)
+            | MIDIEvent::OmniOn(..)
+            | MIDIEvent::MonophonicOperation(..)
+            | MIDIEvent::PolyphonicOperation(..)
+            | MIDIEvent::ControlChange(..) => {
+                // Named controllers are addressed by number, as they're encoded
+                let bytes = event.as_bytes();
+                let kind = ChannelMessageKind::ControlChange {
+                    controller: U7::new_masked(bytes[1]),
+                    value: U7::new_masked(bytes[2])
+                };
+                MIDIMessage::Channel(ChannelMessage::new(Channel::from_status(bytes[0]), kind))
+            }
+
+            MIDIEvent::MTCQuarterFrame(piece, value) => MIDIMessage::SystemCommon(SystemCommon::MTCQuarterFrame(piece, value)),
+            MIDIEvent::SongPositionPointer(beat) => MIDIMessage::SystemCommon(SystemCommon::SongPositionPointer(beat)),
+            MIDIEvent::SongSelect(song) => MIDIMessage::SystemCommon(SystemCommon::SongSelect(song)),
+            MIDIEvent::TuneRequest => MIDIMessage::SystemCommon(SystemCommon::TuneRequest),
+
+            MIDIEvent::MIDIClock => MIDIMessage::SystemRealtime(SystemRealtime::MIDIClock),
+            MIDIEvent::MIDIStart => MIDIMessage::SystemRealtime(SystemRealtime::MIDIStart),
+            MIDIEvent::MIDIContinue => MIDIMessage::SystemRealtime(SystemRealtime::MIDIContinue),
+            MIDIEvent::MIDIStop => MIDIMessage::SystemRealtime(SystemRealtime::MIDIStop),
+            MIDIEvent::ActiveSense => MIDIMessage::SystemRealtime(SystemRealtime::ActiveSense),
+            MIDIEvent::Reset => MIDIMessage::SystemRealtime(SystemRealtime::Reset),
+
+            MIDIEvent::SystemExclusive(data) => MIDIMessage::SysEx(SysEx(data)),
+            MIDIEvent::TimeCode(_) => {
+                let bytes = event.as_bytes();
+                MIDIMessage::SysEx(SysEx(bytes[1..bytes.len() - 1].to_vec()))
+            }
+        }
+    }
+}
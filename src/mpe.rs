@@ -0,0 +1,671 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::{MIDI, MIDIEvent, ApresError, Channel, U7, PitchBend, MSBOnlyMode, ParameterDecoder, ParameterEvent, RegisteredParameter, build_control_change};
+use crate::high_resolution::get_control_change;
+
+/// The controller MPE uses for timbre, usually the third dimension of a note's expression
+pub const TIMBRE_CONTROLLER: U7 = U7::new_masked(74);
+
+const LOWER_MANAGER: Channel = Channel::from_status(0x00);
+const UPPER_MANAGER: Channel = Channel::from_status(0x0F);
+
+/// An MPE zone: a manager channel for zone-wide messages, and member channels that each carry one note's expression
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MPEZone {
+    manager: Channel,
+    member_count: u8,
+    /// Bend range of the member channels in semitones. Defaults to 48.
+    pub pitch_bend_range: f64,
+    /// Bend range of the manager channel in semitones. Defaults to 2.
+    pub manager_pitch_bend_range: f64
+}
+
+/// The zones of an MPE device. The lower zone is managed on channel 0 and the upper on channel 15.
+/// Together they can't have more than 14 member channels.
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub struct MPEConfiguration {
+    pub lower: Option<MPEZone>,
+    pub upper: Option<MPEZone>
+}
+
+/// An event passed through an MPEDecoder. Each note on a member channel gets an id, shared by all the events
+/// in its expression stream.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MPEEvent {
+    /// A note started, with its channel's expression at the time. Pitch bend is in semitones.
+    NoteOn { id: u64, channel: Channel, note: U7, velocity: U7, pitch_bend: f64, pressure: U7, timbre: U7 },
+    NoteOff { id: u64, channel: Channel, note: U7, velocity: U7 },
+    /// The note's pitch bend changed, in semitones
+    PitchBend { id: u64, semitones: f64 },
+    Pressure { id: u64, pressure: U7 },
+    Timbre { id: u64, timbre: U7 },
+    /// A registered or non-registered parameter was set. MPE configuration and pitch bend
+    /// sensitivity are also applied to the decoder's zones.
+    Parameter(ParameterEvent),
+    /// Any other event, including everything on manager channels and channels outside the zones
+    Event(MIDIEvent)
+}
+
+/// A note with its expression, as extracted from or inserted into a MIDI. Expression values are
+/// (tick, value) with absolute ticks, the first being the note's initial value.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MPENote {
+    pub channel: Channel,
+    pub note: U7,
+    pub velocity: U7,
+    pub release_velocity: U7,
+    pub start: usize,
+    pub end: usize,
+    /// Pitch bend in semitones
+    pub pitch_bend: Vec<(usize, f64)>,
+    pub pressure: Vec<(usize, U7)>,
+    pub timbre: Vec<(usize, U7)>
+}
+
+// The latest expression received on a member channel
+#[derive(Clone, Copy, Debug)]
+struct ChannelExpression {
+    pitch_bend: PitchBend,
+    pressure: U7,
+    timbre: U7
+}
+
+impl Default for ChannelExpression {
+    fn default() -> ChannelExpression {
+        ChannelExpression {
+            pitch_bend: PitchBend::CENTER,
+            pressure: U7::default(),
+            timbre: U7::new_masked(0x40)
+        }
+    }
+}
+
+/// Decodes a stream of events into per-note expression, tracking zone configuration as it goes.
+#[derive(Clone, Debug)]
+pub struct MPEDecoder {
+    configuration: MPEConfiguration,
+    parameters: ParameterDecoder,
+    expression: HashMap<Channel, ChannelExpression>,
+    /// (id, channel, note) of each sounding note, oldest first
+    active: Vec<(u64, Channel, U7)>,
+    next_id: u64,
+    ready: VecDeque<MPEEvent>
+}
+
+// A member channel in an MPEEncoder
+#[derive(Clone, Debug)]
+struct MemberChannel {
+    channel: Channel,
+    notes: Vec<u64>,
+    last_used: u64
+}
+
+/// Allocates notes to a zone's member channels and generates the events to play them with expression
+#[derive(Clone, Debug)]
+pub struct MPEEncoder {
+    zone: MPEZone,
+    members: Vec<MemberChannel>,
+    /// id -> (channel, note)
+    notes: HashMap<u64, (Channel, U7)>,
+    next_id: u64,
+    clock: u64
+}
+
+impl MPEZone {
+    /// The lower zone, managed on channel 0 with member channels from 1 up
+    pub fn lower(member_count: u8) -> Result<MPEZone, ApresError> {
+        MPEZone::new(LOWER_MANAGER, member_count)
+    }
+
+    /// The upper zone, managed on channel 15 with member channels from 14 down
+    pub fn upper(member_count: u8) -> Result<MPEZone, ApresError> {
+        MPEZone::new(UPPER_MANAGER, member_count)
+    }
+
+    fn new(manager: Channel, member_count: u8) -> Result<MPEZone, ApresError> {
+        if member_count == 0 || member_count > 15 {
+            return Err(ApresError::ValueOutOfRange(member_count as u32, 15));
+        }
+
+        Ok(MPEZone {
+            manager,
+            member_count,
+            pitch_bend_range: 48_f64,
+            manager_pitch_bend_range: 2_f64
+        })
+    }
+
+    pub fn get_manager(&self) -> Channel {
+        self.manager
+    }
+
+    pub fn is_lower(&self) -> bool {
+        self.manager == LOWER_MANAGER
+    }
+
+    pub fn get_member_count(&self) -> u8 {
+        self.member_count
+    }
+
+    /// Get the member channels, in order from the manager channel outwards
+    pub fn get_member_channels(&self) -> Vec<Channel> {
+        (1..=self.member_count)
+            .map(|i| {
+                if self.is_lower() {
+                    Channel::from_status(i)
+                } else {
+                    Channel::from_status(0x0F - i)
+                }
+            })
+            .collect()
+    }
+
+    pub fn is_member(&self, channel: Channel) -> bool {
+        if self.is_lower() {
+            channel.get() >= 1 && channel.get() <= self.member_count
+        } else {
+            channel.get() <= 14 && channel.get() >= 15 - self.member_count
+        }
+    }
+
+    /// Whether the channel is the zone's manager or one of its members
+    pub fn contains(&self, channel: Channel) -> bool {
+        channel == self.manager || self.is_member(channel)
+    }
+
+    /// Get the events that set the zone up on a receiver: the MPE configuration message, then the
+    /// pitch bend ranges if they aren't the defaults
+    pub fn get_configuration_events(&self) -> Vec<MIDIEvent> {
+        let mut output = ParameterEvent::mpe_configuration(self.manager, U7::new_masked(self.member_count)).to_events();
+        if self.manager_pitch_bend_range != 2_f64 {
+            output.extend(get_bend_range_parameter(self.manager, self.manager_pitch_bend_range).to_events());
+        }
+        if self.pitch_bend_range != 48_f64 {
+            // Setting one member channel's range sets all of them
+            output.extend(get_bend_range_parameter(self.get_member_channels()[0], self.pitch_bend_range).to_events());
+        }
+
+        output
+    }
+}
+
+fn get_bend_range_parameter(channel: Channel, range: f64) -> ParameterEvent {
+    let range = range.clamp(0_f64, U7::MAX as f64);
+    let semitones = range.trunc();
+    let cents = ((range - semitones) * 100_f64).round().min(99_f64);
+    ParameterEvent::pitch_bend_sensitivity(channel, U7::new_masked(semitones as u8), U7::new_masked(cents as u8))
+}
+
+impl MPEConfiguration {
+    /// Apply an MPE configuration message, as a receiver would. Setting a zone resets its bend ranges, and
+    /// shrinks the other zone if they would overlap. A member count of 0 removes the zone.
+    /// Messages on channels other than 0 and 15 are ignored.
+    pub fn apply(&mut self, manager: Channel, member_count: u8) {
+        let member_count = member_count.min(15);
+        let (zone, other) = match manager.get() {
+            0x00 => (&mut self.lower, &mut self.upper),
+            0x0F => (&mut self.upper, &mut self.lower),
+            _ => {
+                return;
+            }
+        };
+
+        *zone = MPEZone::new(manager, member_count).ok();
+
+        let remaining = 14_u8.saturating_sub(member_count);
+        match other {
+            Some(other_zone) if other_zone.member_count > remaining => {
+                if remaining == 0 {
+                    *other = None;
+                } else {
+                    other_zone.member_count = remaining;
+                }
+            }
+            _ => ()
+        }
+    }
+
+    /// Get the zone a channel is the manager or a member of
+    pub fn get_zone(&self, channel: Channel) -> Option<&MPEZone> {
+        self.lower.iter().chain(self.upper.iter()).find(|zone| zone.contains(channel))
+    }
+
+    fn get_zone_mut(&mut self, channel: Channel) -> Option<&mut MPEZone> {
+        self.lower.iter_mut().chain(self.upper.iter_mut()).find(|zone| zone.contains(channel))
+    }
+
+    pub fn is_member(&self, channel: Channel) -> bool {
+        matches!(self.get_zone(channel), Some(zone) if zone.is_member(channel))
+    }
+}
+
+impl MPEDecoder {
+    /// `configuration` is the zones to start with, which MPE configuration messages will change
+    pub fn new(configuration: MPEConfiguration) -> MPEDecoder {
+        MPEDecoder {
+            configuration,
+            parameters: ParameterDecoder::new(MSBOnlyMode::ResetLSB),
+            expression: HashMap::new(),
+            active: Vec::new(),
+            next_id: 0,
+            ready: VecDeque::new()
+        }
+    }
+
+    pub fn get_configuration(&self) -> &MPEConfiguration {
+        &self.configuration
+    }
+
+    /// Get the (id, channel, note) of every sounding note, oldest first
+    pub fn get_active_notes(&self) -> Vec<(u64, Channel, U7)> {
+        self.active.clone()
+    }
+
+    /// Pass an event in. Any resulting events can be taken with `pop()`.
+    pub fn push(&mut self, event: MIDIEvent) {
+        self.parameters.push(event);
+        while let Some(parameter_event) = self.parameters.pop() {
+            match parameter_event {
+                ParameterEvent::Event(event) => {
+                    self.push_event(event);
+                }
+                parameter_event => {
+                    self.apply_parameter(&parameter_event);
+                    self.ready.push_back(MPEEvent::Parameter(parameter_event));
+                }
+            }
+        }
+    }
+
+    /// Take the next resulting event, if any
+    pub fn pop(&mut self) -> Option<MPEEvent> {
+        self.ready.pop_front()
+    }
+
+    /// Pass an event in and take all the resulting events
+    pub fn feed(&mut self, event: MIDIEvent) -> Vec<MPEEvent> {
+        self.push(event);
+        self.ready.drain(..).collect()
+    }
+
+    fn apply_parameter(&mut self, parameter_event: &ParameterEvent) {
+        if let ParameterEvent::RPN { channel, param, value } = parameter_event {
+            match param {
+                RegisteredParameter::MPEConfiguration => {
+                    self.configuration.apply(*channel, value.get_msb().get());
+                }
+                RegisteredParameter::PitchBendSensitivity => {
+                    let range = value.get_msb().get() as f64 + (value.get_lsb().get() as f64 / 100_f64);
+                    if let Some(zone) = self.configuration.get_zone_mut(*channel) {
+                        if zone.manager == *channel {
+                            zone.manager_pitch_bend_range = range;
+                        } else {
+                            zone.pitch_bend_range = range;
+                        }
+                    }
+                }
+                _ => ()
+            }
+        }
+    }
+
+    fn push_event(&mut self, event: MIDIEvent) {
+        let channel = match get_channel_voice_channel(&event) {
+            Some(channel) if self.configuration.is_member(channel) => channel,
+            _ => {
+                self.ready.push_back(MPEEvent::Event(event));
+                return;
+            }
+        };
+
+        let range = self.configuration.get_zone(channel).map(|zone| zone.pitch_bend_range).unwrap_or(48_f64);
+        let mut expression = self.expression.get(&channel).copied().unwrap_or_default();
+
+        match event {
+            MIDIEvent::NoteOn(_, note, velocity) if velocity.get() > 0 => {
+                let id = self.next_id;
+                self.next_id += 1;
+                self.active.push((id, channel, note));
+                self.ready.push_back(MPEEvent::NoteOn {
+                    id,
+                    channel,
+                    note,
+                    velocity,
+                    pitch_bend: expression.pitch_bend.get_semitones(range),
+                    pressure: expression.pressure,
+                    timbre: expression.timbre
+                });
+            }
+            MIDIEvent::NoteOn(_, note, velocity) | MIDIEvent::NoteOff(_, note, velocity) => {
+                match self.active.iter().position(|(_, active_channel, active_note)| *active_channel == channel && *active_note == note) {
+                    Some(position) => {
+                        let (id, _, _) = self.active.remove(position);
+                        self.ready.push_back(MPEEvent::NoteOff { id, channel, note, velocity });
+                    }
+                    None => {
+                        self.ready.push_back(MPEEvent::Event(event));
+                    }
+                }
+            }
+            MIDIEvent::PitchWheelChange(_, pitch_bend) => {
+                expression.pitch_bend = pitch_bend;
+                let semitones = pitch_bend.get_semitones(range);
+                for id in self.get_channel_note_ids(channel) {
+                    self.ready.push_back(MPEEvent::PitchBend { id, semitones });
+                }
+            }
+            MIDIEvent::ChannelPressure(_, pressure) => {
+                expression.pressure = pressure;
+                for id in self.get_channel_note_ids(channel) {
+                    self.ready.push_back(MPEEvent::Pressure { id, pressure });
+                }
+            }
+            MIDIEvent::AfterTouch(_, note, pressure) => {
+                match self.active.iter().find(|(_, active_channel, active_note)| *active_channel == channel && *active_note == note) {
+                    Some((id, _, _)) => {
+                        self.ready.push_back(MPEEvent::Pressure { id: *id, pressure });
+                    }
+                    None => {
+                        self.ready.push_back(MPEEvent::Event(event));
+                    }
+                }
+            }
+            _ => {
+                match get_control_change(&event) {
+                    Some((_, controller, timbre)) if controller == TIMBRE_CONTROLLER => {
+                        expression.timbre = timbre;
+                        for id in self.get_channel_note_ids(channel) {
+                            self.ready.push_back(MPEEvent::Timbre { id, timbre });
+                        }
+                    }
+                    _ => {
+                        self.ready.push_back(MPEEvent::Event(event));
+                    }
+                }
+            }
+        }
+
+        self.expression.insert(channel, expression);
+    }
+
+    fn get_channel_note_ids(&self, channel: Channel) -> Vec<u64> {
+        self.active.iter()
+            .filter(|(_, active_channel, _)| *active_channel == channel)
+            .map(|(id, _, _)| *id)
+            .collect()
+    }
+}
+
+fn get_channel_voice_channel(event: &MIDIEvent) -> Option<Channel> {
+    match event {
+        MIDIEvent::NoteOn(channel, _, _)
+        | MIDIEvent::NoteOff(channel, _, _)
+        | MIDIEvent::AfterTouch(channel, _, _)
+        | MIDIEvent::ChannelPressure(channel, _)
+        | MIDIEvent::ProgramChange(channel, _)
+        | MIDIEvent::PitchWheelChange(channel, _) => Some(*channel),
+        _ => get_control_change(event).map(|(channel, _, _)| channel)
+    }
+}
+
+impl MPEEncoder {
+    pub fn new(zone: MPEZone) -> MPEEncoder {
+        MPEEncoder {
+            members: zone.get_member_channels()
+                .into_iter()
+                .map(|channel| MemberChannel { channel, notes: Vec::new(), last_used: 0 })
+                .collect(),
+            zone,
+            notes: HashMap::new(),
+            next_id: 0,
+            clock: 0
+        }
+    }
+
+    pub fn get_zone(&self) -> &MPEZone {
+        &self.zone
+    }
+
+    /// Get the member channel a note was given
+    pub fn get_channel(&self, id: u64) -> Option<Channel> {
+        self.notes.get(&id).map(|(channel, _)| *channel)
+    }
+
+    /// Start a note with its initial expression (pitch bend in semitones), returning its id and the events
+    /// to send: the expression, then the note on. Notes go to the free member channel that was released
+    /// longest ago. If every member channel is busy, the one with the fewest notes is shared.
+    pub fn note_on(&mut self, note: U7, velocity: U7, pitch_bend: f64, pressure: U7, timbre: U7) -> (u64, Vec<MIDIEvent>) {
+        self.clock += 1;
+        let index = self.members.iter().enumerate()
+            .min_by_key(|(_, member)| (member.notes.len(), member.last_used))
+            .map(|(i, _)| i)
+            .unwrap_or(0);
+
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let member = &mut self.members[index];
+        member.notes.push(id);
+        member.last_used = self.clock;
+        self.notes.insert(id, (member.channel, note));
+
+        let channel = member.channel;
+        let events = vec![
+            MIDIEvent::PitchWheelChange(channel, PitchBend::from_semitones(pitch_bend, self.zone.pitch_bend_range)),
+            MIDIEvent::ChannelPressure(channel, pressure),
+            build_timbre(channel, timbre),
+            MIDIEvent::NoteOn(channel, note, velocity)
+        ];
+
+        (id, events)
+    }
+
+    /// Release a note, freeing its channel
+    pub fn note_off(&mut self, id: u64, velocity: U7) -> Result<MIDIEvent, ApresError> {
+        let (channel, note) = match self.notes.remove(&id) {
+            Some(entry) => entry,
+            None => {
+                return Err(ApresError::EventNotFound(id));
+            }
+        };
+
+        self.clock += 1;
+        if let Some(member) = self.members.iter_mut().find(|member| member.channel == channel) {
+            member.notes.retain(|note_id| *note_id != id);
+            member.last_used = self.clock;
+        }
+
+        Ok(MIDIEvent::NoteOff(channel, note, velocity))
+    }
+
+    /// Bend a note, in semitones
+    pub fn pitch_bend(&self, id: u64, semitones: f64) -> Result<MIDIEvent, ApresError> {
+        let channel = self.get_note_channel(id)?;
+        Ok(MIDIEvent::PitchWheelChange(channel, PitchBend::from_semitones(semitones, self.zone.pitch_bend_range)))
+    }
+
+    pub fn pressure(&self, id: u64, pressure: U7) -> Result<MIDIEvent, ApresError> {
+        Ok(MIDIEvent::ChannelPressure(self.get_note_channel(id)?, pressure))
+    }
+
+    pub fn timbre(&self, id: u64, timbre: U7) -> Result<MIDIEvent, ApresError> {
+        Ok(build_timbre(self.get_note_channel(id)?, timbre))
+    }
+
+    fn get_note_channel(&self, id: u64) -> Result<Channel, ApresError> {
+        match self.get_channel(id) {
+            Some(channel) => Ok(channel),
+            None => Err(ApresError::EventNotFound(id))
+        }
+    }
+}
+
+fn build_timbre(channel: Channel, timbre: U7) -> MIDIEvent {
+    build_control_change(channel, TIMBRE_CONTROLLER, timbre)
+}
+
+#[derive(Clone, Copy, Debug)]
+enum NoteAction {
+    Off,
+    On,
+    PitchBend(f64),
+    Pressure(U7),
+    Timbre(U7)
+}
+
+impl MIDI {
+    /// Get the notes played on MPE member channels, across all tracks, with their expression.
+    /// `configuration` is the zones to start with. MPE configuration messages in the MIDI change them.
+    /// Notes still sounding at the end of the MIDI end at the last event.
+    pub fn get_mpe_notes(&self, configuration: MPEConfiguration) -> Vec<MPENote> {
+        let mut decoder = MPEDecoder::new(configuration);
+        let mut notes: Vec<MPENote> = Vec::new();
+        let mut indices: HashMap<u64, usize> = HashMap::new();
+        let mut last_tick = 0;
+
+        for (tick, _track, event_id) in self.get_ordered_event_ids().into_iter() {
+            let event = match self.events.get(&event_id) {
+                Some(event) => event.clone(),
+                None => {
+                    continue;
+                }
+            };
+            last_tick = tick;
+
+            for mpe_event in decoder.feed(event).into_iter() {
+                match mpe_event {
+                    MPEEvent::NoteOn { id, channel, note, velocity, pitch_bend, pressure, timbre } => {
+                        indices.insert(id, notes.len());
+                        notes.push(MPENote {
+                            channel,
+                            note,
+                            velocity,
+                            release_velocity: U7::default(),
+                            start: tick,
+                            end: tick,
+                            pitch_bend: vec![(tick, pitch_bend)],
+                            pressure: vec![(tick, pressure)],
+                            timbre: vec![(tick, timbre)]
+                        });
+                    }
+                    MPEEvent::NoteOff { id, velocity, .. } => {
+                        if let Some(index) = indices.remove(&id) {
+                            notes[index].end = tick;
+                            notes[index].release_velocity = velocity;
+                        }
+                    }
+                    MPEEvent::PitchBend { id, semitones } => {
+                        if let Some(index) = indices.get(&id) {
+                            notes[*index].pitch_bend.push((tick, semitones));
+                        }
+                    }
+                    MPEEvent::Pressure { id, pressure } => {
+                        if let Some(index) = indices.get(&id) {
+                            notes[*index].pressure.push((tick, pressure));
+                        }
+                    }
+                    MPEEvent::Timbre { id, timbre } => {
+                        if let Some(index) = indices.get(&id) {
+                            notes[*index].timbre.push((tick, timbre));
+                        }
+                    }
+                    MPEEvent::Parameter(_) | MPEEvent::Event(_) => ()
+                }
+            }
+        }
+
+        for index in indices.values() {
+            notes[*index].end = last_tick;
+        }
+
+        notes
+    }
+
+    /// Insert notes into a track, allocated to the zone's member channels by an MPEEncoder. Each note's
+    /// channel is ignored. Expression at or before a note's start sets its initial value, and expression after
+    /// its end is dropped. The zone's configuration events aren't inserted (see `MPEZone::get_configuration_events()`).
+    /// Recorded as a single 'mpe' transaction.
+    pub fn insert_mpe_notes(&mut self, track: usize, zone: &MPEZone, notes: &[MPENote]) -> Result<(), ApresError> {
+        if track > 15 {
+            return Err(ApresError::TrackOutOfBounds);
+        }
+
+        // (tick, order, note index, action). At the same tick, releases go first, then new notes, then
+        // expression, except that a note with no length is released after it starts.
+        let mut actions: Vec<(usize, u8, usize, NoteAction)> = Vec::new();
+        for (i, note) in notes.iter().enumerate() {
+            let end = note.end.max(note.start);
+            actions.push((note.start, 1, i, NoteAction::On));
+            actions.push((end, if end == note.start { 3 } else { 0 }, i, NoteAction::Off));
+            for (tick, semitones) in note.pitch_bend.iter() {
+                if *tick > note.start && *tick <= end {
+                    actions.push((*tick, 2, i, NoteAction::PitchBend(*semitones)));
+                }
+            }
+            for (tick, pressure) in note.pressure.iter() {
+                if *tick > note.start && *tick <= end {
+                    actions.push((*tick, 2, i, NoteAction::Pressure(*pressure)));
+                }
+            }
+            for (tick, timbre) in note.timbre.iter() {
+                if *tick > note.start && *tick <= end {
+                    actions.push((*tick, 2, i, NoteAction::Timbre(*timbre)));
+                }
+            }
+        }
+
+        // Sort is stable, so a note's expression at the same tick keeps its order
+        actions.sort_by_key(|(tick, order, index, _)| (*tick, *order, *index));
+
+        self.begin_transaction("mpe");
+        let result = self.insert_note_actions(track, zone, notes, &actions);
+        self.end_transaction();
+
+        result
+    }
+
+    fn insert_note_actions(&mut self, track: usize, zone: &MPEZone, notes: &[MPENote], actions: &[(usize, u8, usize, NoteAction)]) -> Result<(), ApresError> {
+        let mut encoder = MPEEncoder::new(*zone);
+        let mut ids: HashMap<usize, u64> = HashMap::new();
+
+        for (tick, _, index, action) in actions.iter() {
+            let note = &notes[*index];
+            let events = match action {
+                NoteAction::On => {
+                    let (id, events) = encoder.note_on(
+                        note.note,
+                        note.velocity,
+                        get_initial_value(&note.pitch_bend, note.start).unwrap_or(0_f64),
+                        get_initial_value(&note.pressure, note.start).unwrap_or_default(),
+                        get_initial_value(&note.timbre, note.start).unwrap_or(U7::new_masked(0x40))
+                    );
+                    ids.insert(*index, id);
+                    events
+                }
+                NoteAction::Off => {
+                    vec![encoder.note_off(ids[index], note.release_velocity)?]
+                }
+                NoteAction::PitchBend(semitones) => {
+                    vec![encoder.pitch_bend(ids[index], *semitones)?]
+                }
+                NoteAction::Pressure(pressure) => {
+                    vec![encoder.pressure(ids[index], *pressure)?]
+                }
+                NoteAction::Timbre(timbre) => {
+                    vec![encoder.timbre(ids[index], *timbre)?]
+                }
+            };
+
+            for event in events.into_iter() {
+                self.insert_event(track, *tick, event)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// The last value at or before the start of a note
+fn get_initial_value<T: Copy>(values: &[(usize, T)], start: usize) -> Option<T> {
+    values.iter().filter(|(tick, _)| *tick <= start).last().map(|(_, value)| *value)
+}
@@ -0,0 +1,592 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+use crate::{MIDI, MIDIEvent, MIDIBytes, ApresError, Channel, U7, U14, PitchBend, Pitch, Tuning, build_control_change};
+use crate::high_resolution::get_control_change;
+
+const NON_REAL_TIME: u8 = 0x7E;
+const REAL_TIME: u8 = 0x7F;
+const MIDI_TUNING: u8 = 0x08;
+
+const BULK_DUMP_REQUEST: u8 = 0x00;
+const BULK_DUMP: u8 = 0x01;
+const SINGLE_NOTE_TUNING_CHANGE: u8 = 0x02;
+const SINGLE_NOTE_TUNING_CHANGE_BANK: u8 = 0x07;
+const SCALE_OCTAVE_TUNING: u8 = 0x08;
+const SCALE_OCTAVE_TUNING_2_BYTE: u8 = 0x09;
+
+const NAME_LENGTH: usize = 16;
+
+/// A frequency in MIDI Tuning Standard format: a note number and a fraction of a semitone
+/// above it, in units of 100/16384 cents. Note numbers are relative to A4 (69) at 440Hz.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct MTSFrequency {
+    pub semitone: U7,
+    pub fraction: U14
+}
+
+/// MIDI Tuning Standard message
+#[derive(Clone, Debug, PartialEq)]
+pub enum MTSMessage {
+    BulkDumpRequest { device_id: U7, program: U7 },
+    /// A whole tuning program: a name of up to 16 ASCII characters and a frequency for every note
+    BulkDump { device_id: U7, program: U7, name: String, frequencies: Vec<MTSFrequency> },
+    /// Retune notes in a tuning program in real time
+    SingleNoteTuningChange { device_id: U7, program: U7, changes: Vec<(U7, MTSFrequency)> },
+    /// Retune notes in a tuning program in a given bank
+    SingleNoteTuningChangeBank { device_id: U7, realtime: bool, bank: U7, program: U7, changes: Vec<(U7, MTSFrequency)> },
+    /// Offset each pitch class (C to B) from equal temperament, in cents, on a set of channels.
+    /// `channels` has bit n set to address channel n. Offsets are -64 to 63 cents,
+    /// or -100 to 100 cents in steps of 100/8192 cents when `high_resolution` is set.
+    ScaleOctaveTuning { device_id: U7, realtime: bool, high_resolution: bool, channels: u16, offsets: [f64; 12] }
+}
+
+/// A frequency for every note, or None for notes left unmapped
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyboardTuning {
+    frequencies: [Option<f64>; 128]
+}
+
+impl MTSFrequency {
+    /// Reserved value, meaning the note's tuning should be left unchanged
+    pub const NO_CHANGE: MTSFrequency = MTSFrequency {
+        semitone: U7::new_masked(0x7F),
+        fraction: U14::new_masked(0x3FFF)
+    };
+
+    /// Build from a fractional note number, eg 60.5 for a quarter tone above middle C
+    pub fn from_note_number(note: f64) -> Result<MTSFrequency, ApresError> {
+        let units = (note * 16384_f64).round();
+        if !units.is_finite() || units < 0_f64 || units >= 0x1FFFFF as f64 {
+            return Err(ApresError::ValueOutOfRange(note.max(0_f64).round() as u32, U7::MAX as u32));
+        }
+
+        let units = units as u32;
+        Ok(MTSFrequency {
+            semitone: U7::new_masked((units >> 14) as u8),
+            fraction: U14::new_masked((units & 0x3FFF) as u16)
+        })
+    }
+
+    pub fn from_frequency(frequency: f64) -> Result<MTSFrequency, ApresError> {
+        MTSFrequency::from_note_number(69_f64 + (12_f64 * (frequency / 440_f64).log2()))
+    }
+
+    pub fn from_bytes(bytes: [u8; 3]) -> Result<MTSFrequency, ApresError> {
+        Ok(MTSFrequency {
+            semitone: U7::new(bytes[0])?,
+            fraction: U14::from_lsb_msb(U7::new(bytes[2])?, U7::new(bytes[1])?)
+        })
+    }
+
+    pub fn as_bytes(&self) -> [u8; 3] {
+        [self.semitone.get(), self.fraction.get_msb().get(), self.fraction.get_lsb().get()]
+    }
+
+    /// Get the fractional note number
+    pub fn get_note_number(&self) -> f64 {
+        self.semitone.get() as f64 + (self.fraction.get() as f64 / 16384_f64)
+    }
+
+    pub fn get_frequency(&self) -> f64 {
+        440_f64 * 2_f64.powf((self.get_note_number() - 69_f64) / 12_f64)
+    }
+
+    pub fn is_no_change(&self) -> bool {
+        *self == MTSFrequency::NO_CHANGE
+    }
+}
+
+impl MTSMessage {
+    /// Parse a system exclusive message's data (not including the 0xF0 and 0xF7 bytes)
+    pub fn from_bytes(bytes: &[u8]) -> Result<MTSMessage, ApresError> {
+        if bytes.len() < 5 || (bytes[0] != NON_REAL_TIME && bytes[0] != REAL_TIME) || bytes[2] != MIDI_TUNING || bytes.iter().any(|byte| *byte > 0x7F) {
+            return Err(ApresError::InvalidBytes(bytes.to_vec()));
+        }
+
+        let device_id = U7::new(bytes[1])?;
+        let realtime = bytes[0] == REAL_TIME;
+        let message = match (bytes[0], bytes[3], &bytes[4..]) {
+            (NON_REAL_TIME, BULK_DUMP_REQUEST, [program]) => {
+                MTSMessage::BulkDumpRequest { device_id, program: U7::new(*program)? }
+            }
+            (NON_REAL_TIME, BULK_DUMP, [program, data @ .., checksum]) if data.len() == NAME_LENGTH + (128 * 3) => {
+                if get_mts_checksum(&bytes[..bytes.len() - 1]) != *checksum {
+                    return Err(ApresError::InvalidBytes(bytes.to_vec()));
+                }
+
+                let name = match std::str::from_utf8(&data[..NAME_LENGTH]) {
+                    Ok(name) => name.trim_end().to_string(),
+                    Err(_) => {
+                        return Err(ApresError::IllegibleString(data[..NAME_LENGTH].to_vec()));
+                    }
+                };
+
+                let mut frequencies = Vec::new();
+                for chunk in data[NAME_LENGTH..].chunks(3) {
+                    frequencies.push(MTSFrequency::from_bytes([chunk[0], chunk[1], chunk[2]])?);
+                }
+
+                MTSMessage::BulkDump { device_id, program: U7::new(*program)?, name, frequencies }
+            }
+            (REAL_TIME, SINGLE_NOTE_TUNING_CHANGE, [program, count, data @ ..]) => {
+                MTSMessage::SingleNoteTuningChange {
+                    device_id,
+                    program: U7::new(*program)?,
+                    changes: get_note_changes(*count, data).ok_or_else(|| ApresError::InvalidBytes(bytes.to_vec()))?
+                }
+            }
+            (_, SINGLE_NOTE_TUNING_CHANGE_BANK, [bank, program, count, data @ ..]) => {
+                MTSMessage::SingleNoteTuningChangeBank {
+                    device_id,
+                    realtime,
+                    bank: U7::new(*bank)?,
+                    program: U7::new(*program)?,
+                    changes: get_note_changes(*count, data).ok_or_else(|| ApresError::InvalidBytes(bytes.to_vec()))?
+                }
+            }
+            (_, SCALE_OCTAVE_TUNING, [ff, gg, hh, data @ ..]) if data.len() == 12 => {
+                let mut offsets = [0_f64; 12];
+                for (offset, byte) in offsets.iter_mut().zip(data.iter()) {
+                    *offset = *byte as f64 - 64_f64;
+                }
+
+                MTSMessage::ScaleOctaveTuning {
+                    device_id,
+                    realtime,
+                    high_resolution: false,
+                    channels: get_channel_mask([*ff, *gg, *hh]),
+                    offsets
+                }
+            }
+            (_, SCALE_OCTAVE_TUNING_2_BYTE, [ff, gg, hh, data @ ..]) if data.len() == 24 => {
+                let mut offsets = [0_f64; 12];
+                for (offset, pair) in offsets.iter_mut().zip(data.chunks(2)) {
+                    let value = U14::from_lsb_msb(U7::new(pair[1])?, U7::new(pair[0])?);
+                    *offset = (value.get() as f64 - 8192_f64) * 100_f64 / 8192_f64;
+                }
+
+                MTSMessage::ScaleOctaveTuning {
+                    device_id,
+                    realtime,
+                    high_resolution: true,
+                    channels: get_channel_mask([*ff, *gg, *hh]),
+                    offsets
+                }
+            }
+            _ => {
+                return Err(ApresError::InvalidBytes(bytes.to_vec()));
+            }
+        };
+
+        Ok(message)
+    }
+
+    /// Get the system exclusive message's data (not including the 0xF0 and 0xF7 bytes).
+    /// Names are padded or cut to 16 characters, and at most 127 note changes are sent.
+    pub fn as_bytes(&self) -> Vec<u8> {
+        match self {
+            MTSMessage::BulkDumpRequest { device_id, program } => {
+                vec![NON_REAL_TIME, device_id.get(), MIDI_TUNING, BULK_DUMP_REQUEST, program.get()]
+            }
+            MTSMessage::BulkDump { device_id, program, name, frequencies } => {
+                let mut output = vec![NON_REAL_TIME, device_id.get(), MIDI_TUNING, BULK_DUMP, program.get()];
+                let mut name_bytes: Vec<u8> = name.chars()
+                    .map(|c| if c.is_ascii() && !c.is_ascii_control() { c as u8 } else { b'?' })
+                    .take(NAME_LENGTH)
+                    .collect();
+                name_bytes.resize(NAME_LENGTH, b' ');
+                output.extend(name_bytes);
+
+                for i in 0..128 {
+                    let frequency = frequencies.get(i).unwrap_or(&MTSFrequency::NO_CHANGE);
+                    output.extend(frequency.as_bytes().iter());
+                }
+
+                output.push(get_mts_checksum(&output));
+                output
+            }
+            MTSMessage::SingleNoteTuningChange { device_id, program, changes } => {
+                let mut output = vec![REAL_TIME, device_id.get(), MIDI_TUNING, SINGLE_NOTE_TUNING_CHANGE, program.get()];
+                output.extend(get_note_change_bytes(changes));
+                output
+            }
+            MTSMessage::SingleNoteTuningChangeBank { device_id, realtime, bank, program, changes } => {
+                let universal_id = if *realtime { REAL_TIME } else { NON_REAL_TIME };
+                let mut output = vec![universal_id, device_id.get(), MIDI_TUNING, SINGLE_NOTE_TUNING_CHANGE_BANK, bank.get(), program.get()];
+                output.extend(get_note_change_bytes(changes));
+                output
+            }
+            MTSMessage::ScaleOctaveTuning { device_id, realtime, high_resolution, channels, offsets } => {
+                let universal_id = if *realtime { REAL_TIME } else { NON_REAL_TIME };
+                let sub_id = if *high_resolution { SCALE_OCTAVE_TUNING_2_BYTE } else { SCALE_OCTAVE_TUNING };
+                let mut output = vec![
+                    universal_id, device_id.get(), MIDI_TUNING, sub_id,
+                    ((channels >> 14) & 0x03) as u8,
+                    ((channels >> 7) & 0x7F) as u8,
+                    (channels & 0x7F) as u8
+                ];
+
+                for offset in offsets.iter() {
+                    if *high_resolution {
+                        let value = U14::new_masked(((offset * 8192_f64 / 100_f64).round() + 8192_f64).clamp(0_f64, U14::MAX as f64) as u16);
+                        output.push(value.get_msb().get());
+                        output.push(value.get_lsb().get());
+                    } else {
+                        output.push((offset.round().clamp(-64_f64, 63_f64) + 64_f64) as u8);
+                    }
+                }
+
+                output
+            }
+        }
+    }
+
+    pub fn get_device_id(&self) -> U7 {
+        match self {
+            MTSMessage::BulkDumpRequest { device_id, .. }
+            | MTSMessage::BulkDump { device_id, .. }
+            | MTSMessage::SingleNoteTuningChange { device_id, .. }
+            | MTSMessage::SingleNoteTuningChangeBank { device_id, .. }
+            | MTSMessage::ScaleOctaveTuning { device_id, .. } => *device_id
+        }
+    }
+}
+
+// Bulk dumps are checksummed with the XOR of every byte after 0xF0
+fn get_mts_checksum(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0, |checksum, byte| checksum ^ byte) & 0x7F
+}
+
+fn get_channel_mask(bytes: [u8; 3]) -> u16 {
+    ((bytes[0] as u16 & 0x03) << 14) | ((bytes[1] as u16) << 7) | bytes[2] as u16
+}
+
+fn get_note_changes(count: u8, data: &[u8]) -> Option<Vec<(U7, MTSFrequency)>> {
+    if data.len() != count as usize * 4 {
+        return None;
+    }
+
+    let mut changes = Vec::new();
+    for chunk in data.chunks(4) {
+        changes.push((U7::new(chunk[0]).ok()?, MTSFrequency::from_bytes([chunk[1], chunk[2], chunk[3]]).ok()?));
+    }
+
+    Some(changes)
+}
+
+fn get_note_change_bytes(changes: &[(U7, MTSFrequency)]) -> Vec<u8> {
+    let count = changes.len().min(U7::MAX as usize);
+    let mut output = vec![count as u8];
+    for (note, frequency) in changes.iter().take(count) {
+        output.push(note.get());
+        output.extend(frequency.as_bytes().iter());
+    }
+
+    output
+}
+
+impl From<MTSMessage> for MIDIEvent {
+    fn from(message: MTSMessage) -> MIDIEvent {
+        MIDIEvent::SystemExclusive(message.as_bytes())
+    }
+}
+
+impl TryFrom<MIDIEvent> for MTSMessage {
+    type Error = MIDIEvent;
+    fn try_from(event: MIDIEvent) -> Result<MTSMessage, MIDIEvent> {
+        match &event {
+            MIDIEvent::SystemExclusive(data) => {
+                MTSMessage::from_bytes(data).map_err(|_| event)
+            }
+            _ => {
+                Err(event)
+            }
+        }
+    }
+}
+
+impl KeyboardTuning {
+    /// A tuning with every note unmapped
+    pub fn new() -> KeyboardTuning {
+        KeyboardTuning {
+            frequencies: [None; 128]
+        }
+    }
+
+    /// Map every note to its frequency in a 12 note tuning
+    pub fn from_tuning(tuning: &Tuning) -> KeyboardTuning {
+        let mut output = KeyboardTuning::new();
+        for note in 0..128 {
+            output.frequencies[note] = Some(Pitch::from_u7(U7::new_masked(note as u8)).get_frequency(tuning));
+        }
+
+        output
+    }
+
+    pub fn get_frequency(&self, note: U7) -> Option<f64> {
+        self.frequencies[note.get() as usize]
+    }
+
+    pub fn set_frequency(&mut self, note: U7, frequency: Option<f64>) {
+        self.frequencies[note.get() as usize] = frequency;
+    }
+
+    /// Get every note's frequency in MTS format, with unmapped notes as `MTSFrequency::NO_CHANGE`
+    pub fn get_mts_frequencies(&self) -> Result<Vec<MTSFrequency>, ApresError> {
+        let mut output = Vec::new();
+        for frequency in self.frequencies.iter() {
+            output.push(match frequency {
+                Some(frequency) => MTSFrequency::from_frequency(*frequency)?,
+                None => MTSFrequency::NO_CHANGE
+            });
+        }
+
+        Ok(output)
+    }
+
+    /// Build a bulk dump of the whole tuning
+    pub fn as_bulk_dump(&self, device_id: U7, program: U7, name: &str) -> Result<MTSMessage, ApresError> {
+        Ok(MTSMessage::BulkDump {
+            device_id,
+            program,
+            name: name.to_string(),
+            frequencies: self.get_mts_frequencies()?
+        })
+    }
+
+    /// Build real time single note tuning changes for every mapped note. Only 127 notes fit in one
+    /// message, so a fully mapped keyboard needs two.
+    pub fn as_single_note_tuning_changes(&self, device_id: U7, program: U7) -> Result<Vec<MTSMessage>, ApresError> {
+        let mut changes = Vec::new();
+        for (note, frequency) in self.get_mts_frequencies()?.iter().enumerate() {
+            if !frequency.is_no_change() {
+                changes.push((U7::new_masked(note as u8), *frequency));
+            }
+        }
+
+        let mut output = Vec::new();
+        for chunk in changes.chunks(U7::MAX as usize) {
+            output.push(MTSMessage::SingleNoteTuningChange { device_id, program, changes: chunk.to_vec() });
+        }
+
+        Ok(output)
+    }
+
+    /// Update the tuning with an MTS message, as a receiver would. Scale/octave tuning offsets are
+    /// relative to equal temperament, and requests are ignored.
+    pub fn apply_mts(&mut self, message: &MTSMessage) {
+        match message {
+            MTSMessage::BulkDump { frequencies, .. } => {
+                for (note, frequency) in frequencies.iter().enumerate().take(128) {
+                    if !frequency.is_no_change() {
+                        self.frequencies[note] = Some(frequency.get_frequency());
+                    }
+                }
+            }
+            MTSMessage::SingleNoteTuningChange { changes, .. } | MTSMessage::SingleNoteTuningChangeBank { changes, .. } => {
+                for (note, frequency) in changes.iter() {
+                    if !frequency.is_no_change() {
+                        self.set_frequency(*note, Some(frequency.get_frequency()));
+                    }
+                }
+            }
+            MTSMessage::ScaleOctaveTuning { offsets, .. } => {
+                let tuning = Tuning::default().with_offsets(*offsets);
+                for note in 0..128 {
+                    self.frequencies[note] = Some(Pitch::from_u7(U7::new_masked(note as u8)).get_frequency(&tuning));
+                }
+            }
+            MTSMessage::BulkDumpRequest { .. } => ()
+        }
+    }
+}
+
+/// Equal temperament, A4 = 440Hz
+impl Default for KeyboardTuning {
+    fn default() -> KeyboardTuning {
+        KeyboardTuning::from_tuning(&Tuning::default())
+    }
+}
+
+// A channel used to play retuned notes, and the bend it was last set to
+struct RetuneChannel {
+    channel: Channel,
+    bend: Option<PitchBend>,
+    sounding: Vec<U7>,
+    last_used: usize
+}
+
+fn get_event_channel(event: &MIDIEvent) -> Option<Channel> {
+    match event.as_bytes().first() {
+        Some(status) if (0x80..0xF0).contains(status) => Some(Channel::from_status(*status)),
+        _ => None
+    }
+}
+
+// The same channel-wide event on another channel
+fn get_rechanneled_event(event: &MIDIEvent, channel: Channel) -> Option<MIDIEvent> {
+    match event {
+        MIDIEvent::ProgramChange(_, program) => Some(MIDIEvent::ProgramChange(channel, *program)),
+        MIDIEvent::ChannelPressure(_, pressure) => Some(MIDIEvent::ChannelPressure(channel, *pressure)),
+        _ => get_control_change(event).map(|(_, controller, value)| build_control_change(channel, controller, value))
+    }
+}
+
+impl MIDI {
+    /// Retune the notes on `channels` with pitch bend, for synths without MTS. Each note is played as the
+    /// nearest note to its frequency in `tuning`, spread across `channels` so that notes needing different
+    /// bends sound on different channels. Unmapped notes keep their equal tempered pitch.
+    ///
+    /// The channels are treated as one instrument: program changes, control changes and channel pressure on
+    /// any of them are copied to the rest, and existing pitch bends on them are removed. Receivers should be
+    /// set to a bend range of `bend_range` semitones (see `ParameterEvent::pitch_bend_sensitivity()`).
+    /// If more notes need distinct bends at once than there are channels, the least recently used channel is reused.
+    /// Recorded as a single 'retune' transaction.
+    pub fn retune_with_pitch_bend(&mut self, tuning: &KeyboardTuning, channels: &[Channel], bend_range: U7) -> Result<(), ApresError> {
+        self.begin_transaction("retune");
+        let result = self.retune_events(tuning, channels, bend_range);
+        self.end_transaction();
+
+        result
+    }
+
+    fn retune_events(&mut self, tuning: &KeyboardTuning, channels: &[Channel], bend_range: U7) -> Result<(), ApresError> {
+        let mut states: Vec<RetuneChannel> = Vec::new();
+        for channel in channels.iter() {
+            if !states.iter().any(|state| state.channel == *channel) {
+                states.push(RetuneChannel { channel: *channel, bend: None, sounding: Vec::new(), last_used: 0 });
+            }
+        }
+
+        // (source channel, source note) -> (index of the channel state, note played), oldest first
+        let mut active: HashMap<(Channel, U7), Vec<(usize, U7)>> = HashMap::new();
+
+        // (tick, track, event id, event, what it becomes), in order. Applied once every event is planned.
+        let mut plan = Vec::new();
+
+        for (step, (tick, track, event_id)) in self.get_ordered_event_ids().into_iter().enumerate() {
+            let event = match self.events.get(&event_id) {
+                Some(event) => event.clone(),
+                None => {
+                    continue;
+                }
+            };
+
+            let channel = match get_event_channel(&event) {
+                Some(channel) if states.iter().any(|state| state.channel == channel) => channel,
+                _ => {
+                    plan.push((tick, track, event_id, event.clone(), vec![event]));
+                    continue;
+                }
+            };
+
+            let new_events = match event.clone() {
+                MIDIEvent::NoteOn(_, note, velocity) if velocity.get() > 0 => {
+                    let target = match tuning.get_frequency(note) {
+                        Some(frequency) => 69_f64 + (12_f64 * (frequency / 440_f64).log2()),
+                        None => note.get() as f64
+                    };
+                    let played = U7::new_masked(target.round().clamp(0_f64, U7::MAX as f64) as u8);
+                    let bend = PitchBend::from_semitones(target - played.get() as f64, bend_range.get() as f64);
+
+                    let index = states.iter()
+                        .position(|state| state.bend == Some(bend) && !state.sounding.contains(&played))
+                        .or_else(|| {
+                            states.iter().enumerate()
+                                .filter(|(_, state)| state.sounding.is_empty())
+                                .min_by_key(|(_, state)| state.last_used)
+                                .map(|(i, _)| i)
+                        })
+                        .or_else(|| {
+                            states.iter().enumerate()
+                                .min_by_key(|(_, state)| state.last_used)
+                                .map(|(i, _)| i)
+                        })
+                        .unwrap_or(0);
+
+                    let state = &mut states[index];
+                    let mut new_events = Vec::new();
+                    if state.bend != Some(bend) {
+                        new_events.push(MIDIEvent::PitchWheelChange(state.channel, bend));
+                        state.bend = Some(bend);
+                    }
+                    new_events.push(MIDIEvent::NoteOn(state.channel, played, velocity));
+
+                    state.sounding.push(played);
+                    state.last_used = step + 1;
+                    active.entry((channel, note)).or_default().push((index, played));
+                    new_events
+                }
+                MIDIEvent::NoteOn(_, note, _) | MIDIEvent::NoteOff(_, note, _) => {
+                    let (index, played) = match active.get_mut(&(channel, note)) {
+                        Some(notes) if !notes.is_empty() => notes.remove(0),
+                        _ => {
+                            plan.push((tick, track, event_id, event.clone(), vec![event]));
+                            continue;
+                        }
+                    };
+
+                    let state = &mut states[index];
+                    if let Some(position) = state.sounding.iter().position(|sounding| *sounding == played) {
+                        state.sounding.remove(position);
+                    }
+
+                    match event {
+                        MIDIEvent::NoteOff(_, _, velocity) => vec![MIDIEvent::NoteOff(state.channel, played, velocity)],
+                        _ => vec![MIDIEvent::NoteOn(state.channel, played, U7::new_masked(0))]
+                    }
+                }
+                MIDIEvent::AfterTouch(_, note, pressure) => {
+                    match active.get(&(channel, note)).and_then(|notes| notes.first()) {
+                        Some((index, played)) => vec![MIDIEvent::AfterTouch(states[*index].channel, *played, pressure)],
+                        None => vec![event.clone()]
+                    }
+                }
+                MIDIEvent::PitchWheelChange(_, _) => Vec::new(),
+                _ => {
+                    let mut new_events = vec![event.clone()];
+                    for state in states.iter() {
+                        if state.channel == channel {
+                            continue;
+                        }
+                        if let Some(copy) = get_rechanneled_event(&event, state.channel) {
+                            new_events.push(copy);
+                        }
+                    }
+                    new_events
+                }
+            };
+            plan.push((tick, track, event_id, event, new_events));
+        }
+
+        self.apply_retune_plan(plan)
+    }
+
+    // Events that become one event are replaced in place. Once an event becomes several, it and the rest of
+    // its tick are removed and inserted again, since order within a tick follows insertion.
+    fn apply_retune_plan(&mut self, plan: Vec<(usize, usize, u64, MIDIEvent, Vec<MIDIEvent>)>) -> Result<(), ApresError> {
+        let mut rebuilding = None;
+        for (tick, track, event_id, event, new_events) in plan {
+            if rebuilding != Some((tick, track)) {
+                rebuilding = None;
+            }
+            if new_events.len() > 1 {
+                rebuilding = Some((tick, track));
+            }
+
+            if rebuilding.is_some() {
+                self.remove_event(event_id)?;
+                for new_event in new_events {
+                    self.insert_event(track, tick, new_event)?;
+                }
+            } else if new_events.is_empty() {
+                self.remove_event(event_id)?;
+            } else if new_events[0] != event {
+                self.replace_event(event_id, new_events[0].clone())?;
+            }
+        }
+
+        Ok(())
+    }
+}
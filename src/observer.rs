@@ -0,0 +1,26 @@
+use std::sync::mpsc::{channel, Receiver};
+
+use crate::{MIDI, MIDIEdit};
+
+impl MIDI {
+    /// Get a receiver of every change made to this MIDI from now on.
+    ///
+    /// Changes are reported as the same `MIDIEdit` records kept by the edit history,
+    /// including the edits applied by `undo()` and `redo()`.
+    /// Dropping the receiver unsubscribes it.
+    pub fn subscribe(&mut self) -> Receiver<MIDIEdit> {
+        let (sender, receiver) = channel();
+        self.observers.push(sender);
+        receiver
+    }
+
+    /// Get the number of receivers still subscribed. A dropped receiver is only forgotten at the next change.
+    pub fn count_subscribers(&self) -> usize {
+        self.observers.len()
+    }
+
+    pub(crate) fn notify_observers(&mut self, edit: &MIDIEdit) {
+        // Sending fails once the receiver has been dropped, so forget that observer
+        self.observers.retain(|sender| sender.send(edit.clone()).is_ok());
+    }
+}
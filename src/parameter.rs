@@ -0,0 +1,379 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::{MIDI, MIDIEvent, ApresError, Channel, U7, U14, MSBOnlyMode};
+use crate::high_resolution::get_control_change;
+
+const NULL_PARAMETER: U7 = U7::new_masked(0x7F);
+
+/// Registered parameter numbers, with the well-known ones named
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RegisteredParameter {
+    /// MSB is semitones, LSB is cents
+    PitchBendSensitivity,
+    /// 0x2000 is A440, in steps of 100/8192 cents
+    FineTuning,
+    /// MSB is semitones, 0x40 is A440. LSB is unused.
+    CoarseTuning,
+    TuningProgramSelect,
+    TuningBankSelect,
+    ModulationDepthRange,
+    /// MSB is the number of member channels of an MPE zone
+    MPEConfiguration,
+    /// Deselects the current parameter so stray data entry messages are ignored
+    Null,
+    Other(U14)
+}
+
+impl RegisteredParameter {
+    pub fn from_number(number: U14) -> RegisteredParameter {
+        match number.get() {
+            0x0000 => RegisteredParameter::PitchBendSensitivity,
+            0x0001 => RegisteredParameter::FineTuning,
+            0x0002 => RegisteredParameter::CoarseTuning,
+            0x0003 => RegisteredParameter::TuningProgramSelect,
+            0x0004 => RegisteredParameter::TuningBankSelect,
+            0x0005 => RegisteredParameter::ModulationDepthRange,
+            0x0006 => RegisteredParameter::MPEConfiguration,
+            0x3FFF => RegisteredParameter::Null,
+            _ => RegisteredParameter::Other(number)
+        }
+    }
+
+    pub fn get_number(&self) -> U14 {
+        match self {
+            RegisteredParameter::PitchBendSensitivity => U14::new_masked(0x0000),
+            RegisteredParameter::FineTuning => U14::new_masked(0x0001),
+            RegisteredParameter::CoarseTuning => U14::new_masked(0x0002),
+            RegisteredParameter::TuningProgramSelect => U14::new_masked(0x0003),
+            RegisteredParameter::TuningBankSelect => U14::new_masked(0x0004),
+            RegisteredParameter::ModulationDepthRange => U14::new_masked(0x0005),
+            RegisteredParameter::MPEConfiguration => U14::new_masked(0x0006),
+            RegisteredParameter::Null => U14::new_masked(0x3FFF),
+            RegisteredParameter::Other(number) => *number
+        }
+    }
+}
+
+/// An event passed through a ParameterDecoder
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParameterEvent {
+    /// A registered parameter was set to a value
+    RPN {
+        channel: Channel,
+        param: RegisteredParameter,
+        value: U14
+    },
+    /// A non-registered parameter was set to a value
+    NRPN {
+        channel: Channel,
+        param: U14,
+        value: U14
+    },
+    /// Any event that isn't part of a parameter number sequence
+    Event(MIDIEvent)
+}
+
+impl ParameterEvent {
+    /// Pitch bend range in semitones and cents, in either direction
+    pub fn pitch_bend_sensitivity(channel: Channel, semitones: U7, cents: U7) -> ParameterEvent {
+        ParameterEvent::RPN {
+            channel,
+            param: RegisteredParameter::PitchBendSensitivity,
+            value: U14::from_lsb_msb(cents, semitones)
+        }
+    }
+
+    /// Fine tuning in cents, -100 to just under 100. Values outside the range are clamped.
+    pub fn fine_tuning(channel: Channel, cents: f64) -> ParameterEvent {
+        let offset = (cents.clamp(-100_f64, 100_f64) * 0x2000 as f64 / 100_f64).round() as i32;
+        ParameterEvent::RPN {
+            channel,
+            param: RegisteredParameter::FineTuning,
+            value: U14::new_masked((offset + 0x2000).clamp(0, U14::MAX as i32) as u16)
+        }
+    }
+
+    /// Coarse tuning in semitones, -64 to 63. Values outside the range are clamped.
+    pub fn coarse_tuning(channel: Channel, semitones: i8) -> ParameterEvent {
+        let msb = (semitones.clamp(-0x40, 0x3F) + 0x40) as u8;
+        ParameterEvent::RPN {
+            channel,
+            param: RegisteredParameter::CoarseTuning,
+            value: U14::from_lsb_msb(U7::default(), U7::new_masked(msb))
+        }
+    }
+
+    /// Configure an MPE zone. `channel` is the zone's manager channel (0 for the lower zone, 15 for the upper).
+    pub fn mpe_configuration(channel: Channel, member_channels: U7) -> ParameterEvent {
+        ParameterEvent::RPN {
+            channel,
+            param: RegisteredParameter::MPEConfiguration,
+            value: U14::from_lsb_msb(U7::default(), member_channels)
+        }
+    }
+
+    /// Get the sequence of events that sets the parameter: parameter number, data entry MSB and LSB,
+    /// then the null parameter so later data entry messages don't change it.
+    pub fn to_events(&self) -> Vec<MIDIEvent> {
+        match self {
+            ParameterEvent::RPN { channel, param, value } => {
+                let number = param.get_number();
+                vec![
+                    MIDIEvent::RegisteredParameterNumber(*channel, number.get_msb()),
+                    MIDIEvent::RegisteredParameterNumberLSB(*channel, number.get_lsb()),
+                    MIDIEvent::DataEntry(*channel, value.get_msb()),
+                    MIDIEvent::DataEntryLSB(*channel, value.get_lsb()),
+                    MIDIEvent::RegisteredParameterNumber(*channel, NULL_PARAMETER),
+                    MIDIEvent::RegisteredParameterNumberLSB(*channel, NULL_PARAMETER)
+                ]
+            }
+            ParameterEvent::NRPN { channel, param, value } => {
+                vec![
+                    MIDIEvent::NonRegisteredParameterNumber(*channel, param.get_msb()),
+                    MIDIEvent::NonRegisteredParameterNumberLSB(*channel, param.get_lsb()),
+                    MIDIEvent::DataEntry(*channel, value.get_msb()),
+                    MIDIEvent::DataEntryLSB(*channel, value.get_lsb()),
+                    MIDIEvent::RegisteredParameterNumber(*channel, NULL_PARAMETER),
+                    MIDIEvent::RegisteredParameterNumberLSB(*channel, NULL_PARAMETER)
+                ]
+            }
+            ParameterEvent::Event(event) => {
+                vec![event.clone()]
+            }
+        }
+    }
+}
+
+// (channel, is_registered, parameter number)
+type ParameterKey = (Channel, bool, U14);
+
+/// Decodes parameter number selection and data entry messages into RPN/NRPN values,
+/// passing all other events through in order.
+/// Data entry while the null parameter is selected is passed through untouched.
+#[derive(Clone, Debug)]
+pub struct ParameterDecoder {
+    mode: MSBOnlyMode,
+    /// Selected parameter of each channel as (is_registered, MSB, LSB)
+    selected: HashMap<Channel, (bool, U7, U7)>,
+    values: HashMap<ParameterKey, U14>,
+    pending: Option<ParameterKey>,
+    ready: VecDeque<ParameterEvent>
+}
+
+impl ParameterDecoder {
+    /// `mode` decides how a data entry MSB that isn't followed by a data entry LSB is handled
+    pub fn new(mode: MSBOnlyMode) -> ParameterDecoder {
+        ParameterDecoder {
+            mode,
+            selected: HashMap::new(),
+            values: HashMap::new(),
+            pending: None,
+            ready: VecDeque::new()
+        }
+    }
+
+    /// Whether a data entry MSB is being held, waiting for its LSB
+    pub fn has_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Get the last value set for a parameter
+    pub fn get_value(&self, channel: Channel, is_registered: bool, param: U14) -> Option<U14> {
+        self.values.get(&(channel, is_registered, param)).copied()
+    }
+
+    /// Pass an event in. Any resulting events can be taken with `pop()`.
+    pub fn push(&mut self, event: MIDIEvent) {
+        let (channel, controller, value) = match get_control_change(&event) {
+            Some(control_change) => {
+                control_change
+            }
+            None => {
+                self.flush();
+                self.ready.push_back(ParameterEvent::Event(event));
+                return;
+            }
+        };
+
+        match controller.get() {
+            0x62..=0x65 => {
+                self.flush();
+                let is_registered = controller.get() >= 0x64;
+                let (was_registered, msb, lsb) = self.get_selected(channel);
+                let (msb, lsb) = if was_registered == is_registered {
+                    (msb, lsb)
+                } else {
+                    (NULL_PARAMETER, NULL_PARAMETER)
+                };
+
+                let selected = if controller.get() & 1 == 1 {
+                    (is_registered, value, lsb)
+                } else {
+                    (is_registered, msb, value)
+                };
+                self.selected.insert(channel, selected);
+            }
+            0x06 | 0x26 | 0x60 | 0x61 => {
+                let key = match self.get_selected_key(channel) {
+                    Some(key) => {
+                        key
+                    }
+                    None => {
+                        self.flush();
+                        self.ready.push_back(ParameterEvent::Event(event));
+                        return;
+                    }
+                };
+
+                if controller.get() != 0x26 || self.pending != Some(key) {
+                    self.flush();
+                }
+                self.pending = None;
+
+                let current = self.values.get(&key).copied().unwrap_or_default();
+                let new_value = match controller.get() {
+                    0x06 => {
+                        if self.mode == MSBOnlyMode::KeepLSB {
+                            U14::from_lsb_msb(current.get_lsb(), value)
+                        } else {
+                            U14::from_lsb_msb(U7::default(), value)
+                        }
+                    }
+                    0x26 => {
+                        U14::from_lsb_msb(value, current.get_msb())
+                    }
+                    0x60 => {
+                        U14::new_masked(std::cmp::min(current.get() + 1, U14::MAX))
+                    }
+                    _ => {
+                        U14::new_masked(current.get().saturating_sub(1))
+                    }
+                };
+                self.values.insert(key, new_value);
+
+                if controller.get() == 0x06 && self.mode == MSBOnlyMode::WaitForLSB {
+                    self.pending = Some(key);
+                } else {
+                    self.output(key);
+                }
+            }
+            _ => {
+                self.flush();
+                self.ready.push_back(ParameterEvent::Event(event));
+            }
+        }
+    }
+
+    /// Take the next resulting event, if any
+    pub fn pop(&mut self) -> Option<ParameterEvent> {
+        self.ready.pop_front()
+    }
+
+    /// Pass an event in and take all the resulting events
+    pub fn feed(&mut self, event: MIDIEvent) -> Vec<ParameterEvent> {
+        self.push(event);
+        self.ready.drain(..).collect()
+    }
+
+    /// Stop waiting on any held data entry MSB, outputting its value with an LSB of 0
+    pub fn flush(&mut self) {
+        if let Some(key) = self.pending.take() {
+            self.output(key);
+        }
+    }
+
+    fn get_selected(&self, channel: Channel) -> (bool, U7, U7) {
+        *self.selected.get(&channel).unwrap_or(&(true, NULL_PARAMETER, NULL_PARAMETER))
+    }
+
+    fn get_selected_key(&self, channel: Channel) -> Option<ParameterKey> {
+        let (is_registered, msb, lsb) = self.get_selected(channel);
+        if msb == NULL_PARAMETER && lsb == NULL_PARAMETER {
+            None
+        } else {
+            Some((channel, is_registered, U14::from_lsb_msb(lsb, msb)))
+        }
+    }
+
+    fn output(&mut self, key: ParameterKey) {
+        let (channel, is_registered, param) = key;
+        let value = self.values.get(&key).copied().unwrap_or_default();
+        let parameter_event = if is_registered {
+            ParameterEvent::RPN {
+                channel,
+                param: RegisteredParameter::from_number(param),
+                value
+            }
+        } else {
+            ParameterEvent::NRPN { channel, param, value }
+        };
+        self.ready.push_back(parameter_event);
+    }
+}
+
+impl MIDI {
+    /// Get a track's events as (tick, event), with parameter number sequences decoded into RPN/NRPN values.
+    /// A value held waiting for its data entry LSB is placed at the tick of its MSB.
+    pub fn get_parameter_events(&self, track: usize, mode: MSBOnlyMode) -> Vec<(usize, ParameterEvent)> {
+        let mut decoder = ParameterDecoder::new(mode);
+        let mut output = Vec::new();
+        let mut pending_tick = 0;
+
+        for (tick, event_track, event_id) in self.get_ordered_event_ids().iter() {
+            if *event_track != track {
+                continue;
+            }
+            let event = match self.events.get(event_id) {
+                Some(event) => {
+                    event.clone()
+                }
+                None => {
+                    continue;
+                }
+            };
+
+            let was_pending = decoder.has_pending();
+            for (i, parameter_event) in decoder.feed(event).into_iter().enumerate() {
+                // Anything held back is always the first event out
+                if was_pending && i == 0 {
+                    output.push((pending_tick, parameter_event));
+                } else {
+                    output.push((*tick, parameter_event));
+                }
+            }
+
+            if decoder.has_pending() {
+                pending_tick = *tick;
+            }
+        }
+
+        decoder.flush();
+        while let Some(parameter_event) = decoder.pop() {
+            output.push((pending_tick, parameter_event));
+        }
+
+        output
+    }
+
+    /// Insert the full event sequence that sets a parameter, ending with the null parameter.
+    /// Recorded as a single 'parameter' transaction. Returns the ids of the new events.
+    pub fn insert_parameter(&mut self, track: usize, tick: usize, parameter_event: &ParameterEvent) -> Result<Vec<u64>, ApresError> {
+        let mut output = Vec::new();
+
+        self.begin_transaction("parameter");
+        for event in parameter_event.to_events().into_iter() {
+            match self.insert_event(track, tick, event) {
+                Ok(event_id) => {
+                    output.push(event_id);
+                }
+                Err(e) => {
+                    self.end_transaction();
+                    Err(e)?;
+                }
+            }
+        }
+        self.end_transaction();
+
+        Ok(output)
+    }
+}
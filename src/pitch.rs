@@ -0,0 +1,297 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{ApresError, U7, Key, Tonic, Mode};
+
+/// A MIDI note number, 0-127, with conversions to note names and frequencies
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(try_from = "u8", into = "u8"))]
+pub struct Pitch(U7);
+
+/// Maps pitches to frequencies: A4 (69) at the reference frequency, with each pitch class
+/// optionally offset from 12-tone equal temperament
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tuning {
+    /// Frequency of A4 in Hz
+    pub reference_frequency: f64,
+    /// Offset of each pitch class (C to B) from equal temperament, in cents
+    pub offsets: [f64; 12]
+}
+
+const LETTERS: [Tonic; 7] = [Tonic::C, Tonic::D, Tonic::E, Tonic::F, Tonic::G, Tonic::A, Tonic::B];
+
+// Order that sharps are added to key signatures. Flats are added in the reverse order
+const SHARP_ORDER: [Tonic; 7] = [Tonic::F, Tonic::C, Tonic::G, Tonic::D, Tonic::A, Tonic::E, Tonic::B];
+
+impl Pitch {
+    pub const MIDDLE_C: Pitch = Pitch(U7::new_masked(60));
+    pub const A4: Pitch = Pitch(U7::new_masked(69));
+
+    pub fn new(note: u8) -> Result<Pitch, ApresError> {
+        Ok(Pitch(U7::new(note)?))
+    }
+
+    pub fn from_u7(note: U7) -> Pitch {
+        Pitch(note)
+    }
+
+    pub fn get(&self) -> u8 {
+        self.0.get()
+    }
+
+    pub fn get_u7(&self) -> U7 {
+        self.0
+    }
+
+    /// Pitch class, 0 (C) to 11 (B)
+    pub fn get_pitch_class(&self) -> u8 {
+        self.get() % 12
+    }
+
+    /// Octave number, given the octave that middle C (60) is in. Usually 4, sometimes 3 or 5.
+    pub fn get_octave(&self, middle_c_octave: i8) -> i16 {
+        (self.get() / 12) as i16 - 5 + middle_c_octave as i16
+    }
+
+    /// Parse a note name such as "C4", "Eb3", "F#-1" or "Bbb2", given the octave that middle C is in.
+    /// Accidentals are any of '#', 'b', '♯', '♭' or 'x' (double sharp), and may be repeated.
+    pub fn from_name(name: &str, middle_c_octave: i8) -> Result<Pitch, ApresError> {
+        let mut chars = name.trim().chars();
+        let tonic = match chars.next().map(|c| c.to_ascii_uppercase()) {
+            Some('C') => Tonic::C,
+            Some('D') => Tonic::D,
+            Some('E') => Tonic::E,
+            Some('F') => Tonic::F,
+            Some('G') => Tonic::G,
+            Some('A') => Tonic::A,
+            Some('B') => Tonic::B,
+            _ => {
+                return Err(ApresError::ParseError(name.to_string()));
+            }
+        };
+
+        let mut rest = chars.as_str();
+        let mut alter: i32 = 0;
+        while let Some(c) = rest.chars().next() {
+            let step = match c {
+                '#' | '♯' => 1,
+                'b' | '♭' => -1,
+                'x' => 2,
+                _ => {
+                    break;
+                }
+            };
+            alter = match alter.checked_add(step) {
+                Some(alter) => alter,
+                None => {
+                    return Err(ApresError::ParseError(name.to_string()));
+                }
+            };
+            rest = &rest[c.len_utf8()..];
+        }
+
+        let octave = match rest.parse::<i32>() {
+            Ok(octave) => octave,
+            Err(_) => {
+                return Err(ApresError::ParseError(name.to_string()));
+            }
+        };
+
+        // Big octaves or long runs of accidentals would overflow, they're out of range anyway
+        let note = octave.checked_add(5 - middle_c_octave as i32)
+            .and_then(|octave| octave.checked_mul(12))
+            .and_then(|note| note.checked_add(get_natural_pitch_class(tonic) as i32))
+            .and_then(|note| note.checked_add(alter));
+
+        match note {
+            Some(note) if (0..=U7::MAX as i32).contains(&note) => Pitch::new(note as u8),
+            _ => Err(ApresError::ParseError(name.to_string()))
+        }
+    }
+
+    /// Get the letter and alteration (in semitones, -2 to 2) used to write this pitch in the given key.
+    /// Notes in the key are spelled as in its scale, as is the raised 7th in minor keys.
+    /// Other notes are spelled with sharps in sharp keys (and C major / A minor) and with flats in flat keys.
+    pub fn get_spelling(&self, key: &Key) -> (Tonic, i8) {
+        let pitch_class = self.get_pitch_class() as i8;
+        let key = key.get_representable();
+        let sharps = key.get_sharps();
+
+        for tonic in LETTERS.iter() {
+            let alter = get_key_alter(*tonic, sharps);
+            if (get_natural_pitch_class(*tonic) as i8 + alter).rem_euclid(12) == pitch_class {
+                return (*tonic, alter);
+            }
+        }
+
+        if key.mode == Mode::Minor && (key.get_pitch_class() as i8 + 11) % 12 == pitch_class {
+            let index = LETTERS.iter().position(|tonic| *tonic == key.tonic).unwrap_or(0);
+            let leading_tone = LETTERS[(index + 6) % 7];
+            let alter = (pitch_class - get_natural_pitch_class(leading_tone) as i8 + 6).rem_euclid(12) - 6;
+            return (leading_tone, alter);
+        }
+
+        for tonic in LETTERS.iter() {
+            let alter = if sharps < 0 { -1 } else { 1 };
+            if (get_natural_pitch_class(*tonic) as i8 + alter).rem_euclid(12) == pitch_class {
+                return (*tonic, alter);
+            }
+        }
+
+        // Unreachable, every pitch class is a natural or a sharp/flat of one
+        (Tonic::C, 0)
+    }
+
+    /// Get the name of the pitch as it would be written in the given key, eg "Bb3" in F major or "A#3" in B major.
+    /// The octave number belongs to the letter, so B#3 is the same pitch as C4.
+    pub fn get_name(&self, key: &Key, middle_c_octave: i8) -> String {
+        let (tonic, alter) = self.get_spelling(key);
+        let natural = self.get() as i16 - alter as i16;
+        let octave = natural.div_euclid(12) - 5 + middle_c_octave as i16;
+        let accidental = match alter {
+            -2 => "bb",
+            -1 => "b",
+            1 => "#",
+            2 => "x",
+            _ => ""
+        };
+
+        format!("{}{}{}", get_tonic_name(tonic), accidental, octave)
+    }
+
+    pub fn get_frequency(&self, tuning: &Tuning) -> f64 {
+        let offset = tuning.offsets[self.get_pitch_class() as usize] / 100_f64;
+        tuning.reference_frequency * 2_f64.powf((self.get() as f64 - 69_f64 + offset) / 12_f64)
+    }
+
+    /// Get the nearest pitch to a frequency, and how far the frequency is from it, in cents
+    pub fn from_frequency(frequency: f64, tuning: &Tuning) -> Result<(Pitch, f64), ApresError> {
+        let note = 69_f64 + (12_f64 * (frequency / tuning.reference_frequency).log2());
+        if !note.is_finite() || note < -0.5 || note >= U7::MAX as f64 + 0.5 {
+            return Err(ApresError::ValueOutOfRange(note.max(0_f64).round() as u32, U7::MAX as u32));
+        }
+
+        // Offsets may move a neighbouring pitch closer than the equal tempered one
+        let mut best: Option<(Pitch, f64)> = None;
+        let rounded = note.round() as i16;
+        for candidate in (rounded - 1)..=(rounded + 1) {
+            if let Ok(pitch) = Pitch::new(candidate.clamp(0, U7::MAX as i16) as u8) {
+                let cents = 1200_f64 * (frequency / pitch.get_frequency(tuning)).log2();
+                match best {
+                    Some((_, best_cents)) if best_cents.abs() <= cents.abs() => {}
+                    _ => {
+                        best = Some((pitch, cents));
+                    }
+                }
+            }
+        }
+
+        match best {
+            Some(best) => Ok(best),
+            None => Err(ApresError::ValueOutOfRange(note.round() as u32, U7::MAX as u32))
+        }
+    }
+}
+
+impl Tuning {
+    /// Equal temperament, with A4 at the given frequency
+    pub fn new(reference_frequency: f64) -> Tuning {
+        Tuning {
+            reference_frequency,
+            offsets: [0_f64; 12]
+        }
+    }
+
+    /// Set each pitch class's offset from equal temperament, in cents
+    pub fn with_offsets(mut self, offsets: [f64; 12]) -> Tuning {
+        self.offsets = offsets;
+        self
+    }
+}
+
+/// Equal temperament, A4 = 440Hz
+impl Default for Tuning {
+    fn default() -> Tuning {
+        Tuning::new(440_f64)
+    }
+}
+
+/// Formats with sharps and middle C as C4, eg "C#4"
+impl fmt::Display for Pitch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.get_name(&Key::default(), 4))
+    }
+}
+
+/// Parses a note name with middle C as C4 (see `Pitch::from_name()`), or a note number
+impl FromStr for Pitch {
+    type Err = ApresError;
+    fn from_str(name: &str) -> Result<Pitch, ApresError> {
+        match name.trim().parse::<u8>() {
+            Ok(note) => Pitch::new(note),
+            Err(_) => Pitch::from_name(name, 4)
+        }
+    }
+}
+
+impl TryFrom<u8> for Pitch {
+    type Error = ApresError;
+    fn try_from(note: u8) -> Result<Pitch, ApresError> {
+        Pitch::new(note)
+    }
+}
+
+impl From<U7> for Pitch {
+    fn from(note: U7) -> Pitch {
+        Pitch(note)
+    }
+}
+
+impl From<Pitch> for U7 {
+    fn from(pitch: Pitch) -> U7 {
+        pitch.0
+    }
+}
+
+impl From<Pitch> for u8 {
+    fn from(pitch: Pitch) -> u8 {
+        pitch.get()
+    }
+}
+
+fn get_natural_pitch_class(tonic: Tonic) -> u8 {
+    match tonic {
+        Tonic::C => 0,
+        Tonic::D => 2,
+        Tonic::E => 4,
+        Tonic::F => 5,
+        Tonic::G => 7,
+        Tonic::A => 9,
+        Tonic::B => 11
+    }
+}
+
+fn get_tonic_name(tonic: Tonic) -> &'static str {
+    match tonic {
+        Tonic::C => "C",
+        Tonic::D => "D",
+        Tonic::E => "E",
+        Tonic::F => "F",
+        Tonic::G => "G",
+        Tonic::A => "A",
+        Tonic::B => "B"
+    }
+}
+
+// Alteration of a letter in a key signature with the given number of sharps (or flats if negative)
+fn get_key_alter(tonic: Tonic, sharps: i8) -> i8 {
+    let position = SHARP_ORDER.iter().position(|letter| *letter == tonic).unwrap_or(0) as i8;
+    if sharps > position {
+        1
+    } else if -sharps > 6 - position {
+        -1
+    } else {
+        0
+    }
+}
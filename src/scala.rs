@@ -0,0 +1,250 @@
+use std::fs;
+use std::str::FromStr;
+
+use crate::{ApresError, U7, KeyboardTuning};
+
+/// A scale from a Scala (.scl) file
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScalaScale {
+    pub description: String,
+    /// Each degree above the root, in cents. The last is the period the scale repeats at, usually 1200.
+    pub pitches: Vec<f64>
+}
+
+/// A Scala keyboard mapping (.kbm) file: which notes play which scale degrees, and at what frequency
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyboardMapping {
+    /// Lowest note to retune
+    pub first_note: U7,
+    /// Highest note to retune
+    pub last_note: U7,
+    /// Note that plays the root of the scale
+    pub middle_note: U7,
+    pub reference_note: U7,
+    /// Frequency of the reference note, in Hz
+    pub reference_frequency: f64,
+    /// Scale degree the mapping repeats at. 0 uses the scale's period.
+    pub octave_degree: usize,
+    /// Scale degree of each key from the middle note up, repeating. None leaves a key unmapped.
+    /// If empty, every key plays the next degree.
+    pub mapping: Vec<Option<usize>>
+}
+
+// Scala's comment lines start with '!'
+fn get_lines(content: &str) -> impl Iterator<Item = &str> {
+    content.lines().filter(|line| !line.starts_with('!'))
+}
+
+// Values may be followed by text, which is ignored
+fn get_first_token(line: &str) -> &str {
+    line.split_whitespace().next().unwrap_or("")
+}
+
+fn parse_value<T: FromStr>(line: Option<&str>) -> Result<T, ApresError> {
+    let line = line.unwrap_or("");
+    match get_first_token(line).parse::<T>() {
+        Ok(value) => Ok(value),
+        Err(_) => Err(ApresError::ParseError(line.to_string()))
+    }
+}
+
+fn parse_note(line: Option<&str>) -> Result<U7, ApresError> {
+    U7::new(parse_value::<u8>(line)?)
+}
+
+// Pitches with a '.' are in cents, anything else is a ratio or a whole number
+fn parse_pitch(line: &str) -> Result<f64, ApresError> {
+    let token = get_first_token(line);
+    let cents = if token.contains('.') {
+        token.parse::<f64>().ok()
+    } else {
+        let (numerator, denominator) = match token.split_once('/') {
+            Some((numerator, denominator)) => (numerator.parse::<u64>().ok(), denominator.parse::<u64>().ok()),
+            None => (token.parse::<u64>().ok(), Some(1))
+        };
+
+        match (numerator, denominator) {
+            (Some(numerator), Some(denominator)) if numerator > 0 && denominator > 0 => {
+                Some(1200_f64 * (numerator as f64 / denominator as f64).log2())
+            }
+            _ => None
+        }
+    };
+
+    match cents {
+        Some(cents) if cents.is_finite() => Ok(cents),
+        _ => Err(ApresError::ParseError(line.to_string()))
+    }
+}
+
+fn read_file(path: &str) -> Result<String, ApresError> {
+    match fs::read(path) {
+        Ok(bytes) => Ok(String::from_utf8_lossy(&bytes).to_string()),
+        Err(_) => Err(ApresError::PathNotFound(path.to_string()))
+    }
+}
+
+impl ScalaScale {
+    pub fn from_path(path: &str) -> Result<ScalaScale, ApresError> {
+        read_file(path)?.parse()
+    }
+
+    pub fn len(&self) -> usize {
+        self.pitches.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pitches.is_empty()
+    }
+
+    /// Get a scale degree's distance from the root in cents. Degrees outside the scale repeat at its period.
+    pub fn get_degree_cents(&self, degree: i32) -> f64 {
+        let size = self.pitches.len() as i32;
+        if size == 0 {
+            return 0_f64;
+        }
+
+        let period = self.pitches[self.pitches.len() - 1];
+        let step = degree.rem_euclid(size);
+        let cents = if step == 0 { 0_f64 } else { self.pitches[step as usize - 1] };
+
+        (degree.div_euclid(size) as f64 * period) + cents
+    }
+
+    /// Get the frequency of every note with the scale laid out by a keyboard mapping
+    pub fn get_tuning(&self, mapping: &KeyboardMapping) -> Result<KeyboardTuning, ApresError> {
+        let reference_cents = match mapping.get_note_cents(self, mapping.reference_note) {
+            Some(cents) => cents,
+            None => {
+                return Err(ApresError::ParseError(format!("reference note {} is unmapped", mapping.reference_note.get())));
+            }
+        };
+
+        let mut tuning = KeyboardTuning::new();
+        for note in mapping.first_note.get()..=mapping.last_note.get() {
+            let note = U7::new_masked(note);
+            if let Some(cents) = mapping.get_note_cents(self, note) {
+                let frequency = mapping.reference_frequency * 2_f64.powf((cents - reference_cents) / 1200_f64);
+                tuning.set_frequency(note, Some(frequency));
+            }
+        }
+
+        Ok(tuning)
+    }
+}
+
+impl FromStr for ScalaScale {
+    type Err = ApresError;
+    fn from_str(content: &str) -> Result<ScalaScale, ApresError> {
+        let mut lines = get_lines(content);
+        let description = lines.next().unwrap_or("").trim().to_string();
+
+        let mut lines = lines.filter(|line| !line.trim().is_empty());
+        let count = parse_value::<usize>(lines.next())?;
+        if count == 0 {
+            return Err(ApresError::ParseError(content.to_string()));
+        }
+
+        let mut pitches = Vec::new();
+        for _ in 0..count {
+            match lines.next() {
+                Some(line) => {
+                    pitches.push(parse_pitch(line)?);
+                }
+                None => {
+                    return Err(ApresError::ParseError(content.to_string()));
+                }
+            }
+        }
+
+        Ok(ScalaScale { description, pitches })
+    }
+}
+
+impl KeyboardMapping {
+    pub fn from_path(path: &str) -> Result<KeyboardMapping, ApresError> {
+        read_file(path)?.parse()
+    }
+
+    /// Get the cents above the scale's root a note plays, relative to the middle note
+    fn get_note_cents(&self, scale: &ScalaScale, note: U7) -> Option<f64> {
+        let offset = note.get() as i32 - self.middle_note.get() as i32;
+        if self.mapping.is_empty() {
+            return Some(scale.get_degree_cents(offset));
+        }
+
+        let size = self.mapping.len() as i32;
+        let degree = self.mapping[offset.rem_euclid(size) as usize]?;
+        let octave_degree = if self.octave_degree == 0 { scale.len() } else { self.octave_degree };
+
+        Some((offset.div_euclid(size) as f64 * scale.get_degree_cents(octave_degree as i32)) + scale.get_degree_cents(degree as i32))
+    }
+}
+
+impl FromStr for KeyboardMapping {
+    type Err = ApresError;
+    fn from_str(content: &str) -> Result<KeyboardMapping, ApresError> {
+        let mut lines = get_lines(content).filter(|line| !line.trim().is_empty());
+
+        // Unlisted entries are filled in below, so a huge size would be allocated.
+        // A pattern longer than the keyboard has nothing to map anyway.
+        let size = parse_value::<usize>(lines.next())?;
+        if size > U7::MAX as usize + 1 {
+            return Err(ApresError::ParseError(size.to_string()));
+        }
+        let first_note = parse_note(lines.next())?;
+        let last_note = parse_note(lines.next())?;
+        let middle_note = parse_note(lines.next())?;
+        let reference_note = parse_note(lines.next())?;
+        let reference_frequency = parse_value::<f64>(lines.next())?;
+        if !reference_frequency.is_finite() || reference_frequency <= 0_f64 {
+            return Err(ApresError::ParseError(reference_frequency.to_string()));
+        }
+        let octave_degree = parse_value::<usize>(lines.next())?;
+
+        // Missing entries at the end are unmapped
+        let mut mapping = Vec::new();
+        for _ in 0..size {
+            match lines.next().map(get_first_token) {
+                Some("x") | None => {
+                    mapping.push(None);
+                }
+                Some(token) => {
+                    match token.parse::<usize>() {
+                        Ok(degree) => {
+                            mapping.push(Some(degree));
+                        }
+                        Err(_) => {
+                            return Err(ApresError::ParseError(token.to_string()));
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(KeyboardMapping {
+            first_note,
+            last_note,
+            middle_note,
+            reference_note,
+            reference_frequency,
+            octave_degree,
+            mapping
+        })
+    }
+}
+
+/// Every note plays the next degree, with the root on middle C at 261.63Hz (its frequency with A4 = 440Hz)
+impl Default for KeyboardMapping {
+    fn default() -> KeyboardMapping {
+        KeyboardMapping {
+            first_note: U7::new_masked(0),
+            last_note: U7::new_masked(U7::MAX),
+            middle_note: U7::new_masked(60),
+            reference_note: U7::new_masked(60),
+            reference_frequency: 440_f64 * 2_f64.powf(-9_f64 / 12_f64),
+            octave_degree: 0,
+            mapping: Vec::new()
+        }
+    }
+}
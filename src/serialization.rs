@@ -0,0 +1,140 @@
+//! Serde support, enabled with the `serde` feature. The schema is documented in the crate docs.
+use std::collections::HashSet;
+
+use serde::{Serialize, Deserialize, Serializer, Deserializer};
+use serde::de::Error;
+
+use crate::{MIDI, MIDIEvent, Meter, Timecode, FrameRate, ApresError};
+use crate::history::MIDIEdit;
+
+#[derive(Serialize, Deserialize)]
+struct MeterFields {
+    numerator: u8,
+    denominator: u8,
+    clocks_per_click: u8,
+    thirty_seconds_per_quarter: u8
+}
+
+// Timecode's fields, checked with Timecode::is_valid() before they become a Timecode
+#[derive(Deserialize)]
+pub(crate) struct TimecodeFields {
+    rate: FrameRate,
+    hours: u8,
+    minutes: u8,
+    seconds: u8,
+    frames: u8,
+    subframes: u8
+}
+
+impl std::convert::TryFrom<TimecodeFields> for Timecode {
+    type Error = ApresError;
+    fn try_from(fields: TimecodeFields) -> Result<Timecode, ApresError> {
+        let timecode = Timecode {
+            rate: fields.rate,
+            hours: fields.hours,
+            minutes: fields.minutes,
+            seconds: fields.seconds,
+            frames: fields.frames,
+            subframes: fields.subframes
+        };
+
+        if timecode.is_valid() {
+            Ok(timecode)
+        } else {
+            Err(ApresError::InvalidBytes(timecode.as_bytes().to_vec()))
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct TrackEvent {
+    id: u64,
+    tick: usize,
+    event: MIDIEvent
+}
+
+#[derive(Serialize, Deserialize)]
+struct MIDIDocument {
+    ppqn: u16,
+    format: u16,
+    tracks: Vec<Vec<TrackEvent>>
+}
+
+impl Serialize for Meter {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        MeterFields {
+            numerator: self.get_numerator(),
+            denominator: self.get_denominator(),
+            clocks_per_click: self.get_clocks_per_click(),
+            thirty_seconds_per_quarter: self.get_thirty_seconds_per_quarter()
+        }.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Meter {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Meter, D::Error> {
+        let fields = MeterFields::deserialize(deserializer)?;
+        match Meter::new(fields.numerator, fields.denominator) {
+            Ok(meter) => {
+                Ok(meter
+                    .with_clocks_per_click(fields.clocks_per_click)
+                    .with_thirty_seconds_per_quarter(fields.thirty_seconds_per_quarter))
+            }
+            Err(e) => {
+                Err(D::Error::custom(e))
+            }
+        }
+    }
+}
+
+impl Serialize for MIDI {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut tracks: Vec<Vec<TrackEvent>> = Vec::new();
+        for (tick, track, event_id) in self.get_ordered_event_ids() {
+            while tracks.len() <= track {
+                tracks.push(Vec::new());
+            }
+            if let Some(event) = self.events.get(&event_id) {
+                tracks[track].push(TrackEvent {
+                    id: event_id,
+                    tick,
+                    event: event.clone()
+                });
+            }
+        }
+
+        MIDIDocument {
+            ppqn: self.ppqn,
+            format: self.midi_format,
+            tracks
+        }.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for MIDI {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<MIDI, D::Error> {
+        let document = MIDIDocument::deserialize(deserializer)?;
+        if document.tracks.len() > 16 {
+            return Err(D::Error::custom(crate::ApresError::TrackOutOfBounds));
+        }
+
+        let mut midi = MIDI::new();
+        midi.ppqn = document.ppqn;
+        midi.midi_format = document.format;
+
+        let mut used_ids = HashSet::new();
+        for (track, events) in document.tracks.into_iter().enumerate() {
+            for TrackEvent { id, tick, event } in events {
+                // 0 is reserved for 'no event'
+                if id == 0 || !used_ids.insert(id) {
+                    return Err(D::Error::custom(format!("duplicate or invalid event id {}", id)));
+                }
+
+                midi.event_id_gen = std::cmp::max(midi.event_id_gen, id + 1);
+                midi.apply_edit(MIDIEdit::InsertEvent { event_id: id, track, tick, event });
+            }
+        }
+
+        Ok(midi)
+    }
+}
@@ -0,0 +1,336 @@
+use std::convert::TryFrom;
+
+use crate::{MIDIEvent, ApresError, U7, U14, Timecode, SysEx, build_system_exclusive};
+
+/// Device ID that addresses every device
+pub const ALL_DEVICES: U7 = U7::new_masked(0x7F);
+
+const NON_REAL_TIME: u8 = 0x7E;
+const REAL_TIME: u8 = 0x7F;
+
+/// The ID at the start of a system exclusive message
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ManufacturerId {
+    /// Single byte ID, 0x01-0x7C
+    Short(u8),
+    /// Three byte ID, written as 0x00 followed by these two bytes
+    Extended(u8, u8),
+    /// 0x7D, for research and in-house use
+    NonCommercial,
+    /// 0x7E
+    UniversalNonRealTime,
+    /// 0x7F
+    UniversalRealTime
+}
+
+/// MIDI Machine Control command, sent in a universal real time message
+#[derive(Clone, Debug, PartialEq)]
+pub enum MachineControlCommand {
+    Stop,
+    Play,
+    DeferredPlay,
+    FastForward,
+    Rewind,
+    /// Punch in
+    RecordStrobe,
+    /// Punch out
+    RecordExit,
+    RecordPause,
+    Pause,
+    Eject,
+    Chase,
+    Reset,
+    /// Move to a position
+    Locate(Timecode),
+    /// Any other command, as its bytes
+    Other(Vec<u8>)
+}
+
+/// Typed Universal System Exclusive messages. Every message is addressed to a device ID (0x7F for all devices).
+#[derive(Clone, Debug, PartialEq)]
+pub enum UniversalSysEx {
+    GMSystemOn { device_id: U7 },
+    GMSystemOff { device_id: U7 },
+    GM2SystemOn { device_id: U7 },
+    IdentityRequest { device_id: U7 },
+    IdentityReply {
+        device_id: U7,
+        manufacturer: ManufacturerId,
+        family: U14,
+        member: U14,
+        /// Software revision, as 4 bytes
+        version: [u8; 4]
+    },
+    /// Volume, 0 to 0x3FFF
+    MasterVolume { device_id: U7, volume: U14 },
+    /// Balance, 0 (left) to 0x3FFF (right), centered at 0x2000
+    MasterBalance { device_id: U7, balance: U14 },
+    /// Fine tuning, -100 (0) to +100 (0x3FFF) cents, centered at 0x2000
+    MasterFineTuning { device_id: U7, value: U14 },
+    /// Coarse tuning in semitones, -64 to 63
+    MasterCoarseTuning { device_id: U7, semitones: i8 },
+    /// MTC Full Frame. The subframes are not sent.
+    TimeCode { device_id: U7, timecode: Timecode },
+    MachineControl { device_id: U7, command: MachineControlCommand }
+}
+
+impl ManufacturerId {
+    /// Read the ID from the start of a system exclusive message's data.
+    /// Returns the ID and the number of bytes it took up (1 or 3).
+    pub fn from_bytes(bytes: &[u8]) -> Result<(ManufacturerId, usize), ApresError> {
+        match bytes {
+            [0x00, first, second, ..] => {
+                Ok((ManufacturerId::Extended(*first, *second), 3))
+            }
+            [0x7D, ..] => {
+                Ok((ManufacturerId::NonCommercial, 1))
+            }
+            [NON_REAL_TIME, ..] => {
+                Ok((ManufacturerId::UniversalNonRealTime, 1))
+            }
+            [REAL_TIME, ..] => {
+                Ok((ManufacturerId::UniversalRealTime, 1))
+            }
+            [id, ..] if *id > 0x00 && *id < 0x7D => {
+                Ok((ManufacturerId::Short(*id), 1))
+            }
+            _ => {
+                Err(ApresError::InvalidBytes(bytes.to_vec()))
+            }
+        }
+    }
+
+    pub fn as_bytes(&self) -> Vec<u8> {
+        match self {
+            ManufacturerId::Short(id) => vec![*id],
+            ManufacturerId::Extended(first, second) => vec![0x00, *first, *second],
+            ManufacturerId::NonCommercial => vec![0x7D],
+            ManufacturerId::UniversalNonRealTime => vec![NON_REAL_TIME],
+            ManufacturerId::UniversalRealTime => vec![REAL_TIME]
+        }
+    }
+}
+
+impl MachineControlCommand {
+    fn from_bytes(bytes: &[u8]) -> Result<MachineControlCommand, ApresError> {
+        let command = match bytes {
+            [0x01] => MachineControlCommand::Stop,
+            [0x02] => MachineControlCommand::Play,
+            [0x03] => MachineControlCommand::DeferredPlay,
+            [0x04] => MachineControlCommand::FastForward,
+            [0x05] => MachineControlCommand::Rewind,
+            [0x06] => MachineControlCommand::RecordStrobe,
+            [0x07] => MachineControlCommand::RecordExit,
+            [0x08] => MachineControlCommand::RecordPause,
+            [0x09] => MachineControlCommand::Pause,
+            [0x0A] => MachineControlCommand::Eject,
+            [0x0B] => MachineControlCommand::Chase,
+            [0x0D] => MachineControlCommand::Reset,
+            [0x44, 0x06, 0x01, hours_byte, minutes, seconds, frames, subframes] => {
+                MachineControlCommand::Locate(Timecode::from_bytes([*hours_byte, *minutes, *seconds, *frames, *subframes])?)
+            }
+            [] => {
+                return Err(ApresError::InvalidBytes(vec![]));
+            }
+            _ => MachineControlCommand::Other(bytes.to_vec())
+        };
+
+        Ok(command)
+    }
+
+    fn as_bytes(&self) -> Vec<u8> {
+        match self {
+            MachineControlCommand::Stop => vec![0x01],
+            MachineControlCommand::Play => vec![0x02],
+            MachineControlCommand::DeferredPlay => vec![0x03],
+            MachineControlCommand::FastForward => vec![0x04],
+            MachineControlCommand::Rewind => vec![0x05],
+            MachineControlCommand::RecordStrobe => vec![0x06],
+            MachineControlCommand::RecordExit => vec![0x07],
+            MachineControlCommand::RecordPause => vec![0x08],
+            MachineControlCommand::Pause => vec![0x09],
+            MachineControlCommand::Eject => vec![0x0A],
+            MachineControlCommand::Chase => vec![0x0B],
+            MachineControlCommand::Reset => vec![0x0D],
+            MachineControlCommand::Locate(timecode) => {
+                let mut output = vec![0x44, 0x06, 0x01];
+                output.extend(timecode.as_bytes().iter().copied());
+                output
+            }
+            MachineControlCommand::Other(bytes) => bytes.clone()
+        }
+    }
+}
+
+impl UniversalSysEx {
+    /// Parse a system exclusive message's data (not including the 0xF0 and 0xF7 bytes)
+    pub fn from_bytes(bytes: &[u8]) -> Result<UniversalSysEx, ApresError> {
+        if bytes.len() < 4 || bytes.iter().any(|byte| *byte > 0x7F) {
+            return Err(ApresError::InvalidBytes(bytes.to_vec()));
+        }
+
+        let device_id = U7::new(bytes[1])?;
+        let message = match (bytes[0], bytes[2], bytes[3], &bytes[4..]) {
+            (NON_REAL_TIME, 0x09, 0x01, []) => {
+                UniversalSysEx::GMSystemOn { device_id }
+            }
+            (NON_REAL_TIME, 0x09, 0x02, []) => {
+                UniversalSysEx::GMSystemOff { device_id }
+            }
+            (NON_REAL_TIME, 0x09, 0x03, []) => {
+                UniversalSysEx::GM2SystemOn { device_id }
+            }
+            (NON_REAL_TIME, 0x06, 0x01, []) => {
+                UniversalSysEx::IdentityRequest { device_id }
+            }
+            (NON_REAL_TIME, 0x06, 0x02, data) => {
+                let (manufacturer, offset) = ManufacturerId::from_bytes(data)?;
+                match &data[offset..] {
+                    [family_lsb, family_msb, member_lsb, member_msb, a, b, c, d] => {
+                        UniversalSysEx::IdentityReply {
+                            device_id,
+                            manufacturer,
+                            family: U14::from_lsb_msb(U7::new(*family_lsb)?, U7::new(*family_msb)?),
+                            member: U14::from_lsb_msb(U7::new(*member_lsb)?, U7::new(*member_msb)?),
+                            version: [*a, *b, *c, *d]
+                        }
+                    }
+                    _ => {
+                        return Err(ApresError::InvalidBytes(bytes.to_vec()));
+                    }
+                }
+            }
+            (REAL_TIME, 0x04, sub_id, [lsb, msb]) => {
+                let value = U14::from_lsb_msb(U7::new(*lsb)?, U7::new(*msb)?);
+                match sub_id {
+                    0x01 => UniversalSysEx::MasterVolume { device_id, volume: value },
+                    0x02 => UniversalSysEx::MasterBalance { device_id, balance: value },
+                    0x03 => UniversalSysEx::MasterFineTuning { device_id, value },
+                    0x04 => UniversalSysEx::MasterCoarseTuning { device_id, semitones: *msb as i8 - 0x40 },
+                    _ => {
+                        return Err(ApresError::InvalidBytes(bytes.to_vec()));
+                    }
+                }
+            }
+            (REAL_TIME, 0x01, 0x01, [hours_byte, minutes, seconds, frames]) => {
+                UniversalSysEx::TimeCode {
+                    device_id,
+                    timecode: Timecode::from_bytes([*hours_byte, *minutes, *seconds, *frames, 0])?
+                }
+            }
+            (REAL_TIME, 0x06, _, _) => {
+                UniversalSysEx::MachineControl {
+                    device_id,
+                    command: MachineControlCommand::from_bytes(&bytes[3..])?
+                }
+            }
+            _ => {
+                return Err(ApresError::InvalidBytes(bytes.to_vec()));
+            }
+        };
+
+        Ok(message)
+    }
+
+    /// Get the system exclusive message's data (not including the 0xF0 and 0xF7 bytes)
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let (device_id, mut output) = match self {
+            UniversalSysEx::GMSystemOn { device_id } => {
+                (device_id, vec![NON_REAL_TIME, 0x09, 0x01])
+            }
+            UniversalSysEx::GMSystemOff { device_id } => {
+                (device_id, vec![NON_REAL_TIME, 0x09, 0x02])
+            }
+            UniversalSysEx::GM2SystemOn { device_id } => {
+                (device_id, vec![NON_REAL_TIME, 0x09, 0x03])
+            }
+            UniversalSysEx::IdentityRequest { device_id } => {
+                (device_id, vec![NON_REAL_TIME, 0x06, 0x01])
+            }
+            UniversalSysEx::IdentityReply { device_id, manufacturer, family, member, version } => {
+                let mut output = vec![NON_REAL_TIME, 0x06, 0x02];
+                output.extend(manufacturer.as_bytes());
+                output.extend([
+                    family.get_lsb().get(), family.get_msb().get(),
+                    member.get_lsb().get(), member.get_msb().get()
+                ].iter());
+                output.extend(version.iter().map(|byte| byte & 0x7F));
+                (device_id, output)
+            }
+            UniversalSysEx::MasterVolume { device_id, volume } => {
+                (device_id, vec![REAL_TIME, 0x04, 0x01, volume.get_lsb().get(), volume.get_msb().get()])
+            }
+            UniversalSysEx::MasterBalance { device_id, balance } => {
+                (device_id, vec![REAL_TIME, 0x04, 0x02, balance.get_lsb().get(), balance.get_msb().get()])
+            }
+            UniversalSysEx::MasterFineTuning { device_id, value } => {
+                (device_id, vec![REAL_TIME, 0x04, 0x03, value.get_lsb().get(), value.get_msb().get()])
+            }
+            UniversalSysEx::MasterCoarseTuning { device_id, semitones } => {
+                let msb = ((*semitones).clamp(-0x40, 0x3F) + 0x40) as u8;
+                (device_id, vec![REAL_TIME, 0x04, 0x04, 0x00, msb])
+            }
+            UniversalSysEx::TimeCode { device_id, timecode } => {
+                let bytes = timecode.as_bytes();
+                (device_id, vec![REAL_TIME, 0x01, 0x01, bytes[0], bytes[1], bytes[2], bytes[3]])
+            }
+            UniversalSysEx::MachineControl { device_id, command } => {
+                let mut output = vec![REAL_TIME, 0x06];
+                output.extend(command.as_bytes());
+                (device_id, output)
+            }
+        };
+
+        // Device ID goes after the universal ID
+        output.insert(1, device_id.get());
+        output
+    }
+
+    pub fn get_device_id(&self) -> U7 {
+        match self {
+            UniversalSysEx::GMSystemOn { device_id }
+            | UniversalSysEx::GMSystemOff { device_id }
+            | UniversalSysEx::GM2SystemOn { device_id }
+            | UniversalSysEx::IdentityRequest { device_id }
+            | UniversalSysEx::IdentityReply { device_id, .. }
+            | UniversalSysEx::MasterVolume { device_id, .. }
+            | UniversalSysEx::MasterBalance { device_id, .. }
+            | UniversalSysEx::MasterFineTuning { device_id, .. }
+            | UniversalSysEx::MasterCoarseTuning { device_id, .. }
+            | UniversalSysEx::TimeCode { device_id, .. }
+            | UniversalSysEx::MachineControl { device_id, .. } => *device_id
+        }
+    }
+}
+
+impl SysEx {
+    /// Get the manufacturer ID at the start of the message, if there is one
+    pub fn get_manufacturer_id(&self) -> Option<ManufacturerId> {
+        ManufacturerId::from_bytes(&self.0).ok().map(|(id, _)| id)
+    }
+}
+
+/// Builds a `MIDIEvent::SystemExclusive`, or a `MIDIEvent::TimeCode` for MTC full frame messages sent to all devices.
+/// Note that `MIDIEvent::TimeCode` is always sent to all devices.
+impl From<UniversalSysEx> for MIDIEvent {
+    fn from(message: UniversalSysEx) -> MIDIEvent {
+        build_system_exclusive(message.as_bytes())
+    }
+}
+
+impl TryFrom<MIDIEvent> for UniversalSysEx {
+    type Error = MIDIEvent;
+    fn try_from(event: MIDIEvent) -> Result<UniversalSysEx, MIDIEvent> {
+        match &event {
+            MIDIEvent::SystemExclusive(data) => {
+                UniversalSysEx::from_bytes(data).map_err(|_| event)
+            }
+            MIDIEvent::TimeCode(timecode) => {
+                Ok(UniversalSysEx::TimeCode { device_id: ALL_DEVICES, timecode: *timecode })
+            }
+            _ => {
+                Err(event)
+            }
+        }
+    }
+}
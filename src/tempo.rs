@@ -0,0 +1,234 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::ApresError;
+
+/// Tempo as stored in SetTempo: microseconds per quarter note, limited to 24 bits
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(try_from = "u32", into = "u32"))]
+pub struct Tempo(u32);
+
+/// A time signature, eg 6/8.
+///
+/// The denominator is stored in MIDI as a power of two, so only 1, 2, 4, 8 ... 128 are allowed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Meter {
+    numerator: u8,
+    denominator_exponent: u8,
+    clocks_per_click: u8,
+    thirty_seconds_per_quarter: u8
+}
+
+impl Tempo {
+    pub const MAX: u32 = 0x00FF_FFFF;
+
+    /// A tempo of 0 would be infinitely fast, so it's out of range too
+    pub fn new(microseconds_per_quarter: u32) -> Result<Tempo, ApresError> {
+        if microseconds_per_quarter == 0 || microseconds_per_quarter > Tempo::MAX {
+            Err(ApresError::ValueOutOfRange(microseconds_per_quarter, Tempo::MAX))
+        } else {
+            Ok(Tempo(microseconds_per_quarter))
+        }
+    }
+
+    /// Build from beats (quarter notes) per minute, rounded to the nearest microsecond.
+    /// The slowest possible tempo is about 3.58 BPM.
+    pub fn from_bpm(bpm: f64) -> Result<Tempo, ApresError> {
+        let microseconds = (60_000_000_f64 / bpm).round();
+        if bpm.is_nan() || bpm <= 0_f64 || microseconds < 1_f64 || microseconds > Tempo::MAX as f64 {
+            Err(ApresError::ValueOutOfRange(microseconds.clamp(0_f64, u32::MAX as f64) as u32, Tempo::MAX))
+        } else {
+            Ok(Tempo(microseconds as u32))
+        }
+    }
+
+    /// Get microseconds per quarter note
+    pub fn get(&self) -> u32 {
+        self.0
+    }
+
+    pub fn get_bpm(&self) -> f64 {
+        60_000_000_f64 / self.0 as f64
+    }
+}
+
+/// 120 BPM, the tempo assumed when a MIDI doesn't set one
+impl Default for Tempo {
+    fn default() -> Tempo {
+        Tempo(500_000)
+    }
+}
+
+impl Meter {
+    /// Build a time signature from its numerator and (power of two) denominator.
+    /// The metronome clicks on every beat, or every dotted beat in compound meters (6/8, 9/8, 12/16...).
+    pub fn new(numerator: u8, denominator: u8) -> Result<Meter, ApresError> {
+        if numerator == 0 || !denominator.is_power_of_two() {
+            return Err(ApresError::InvalidTimeSignature(numerator, denominator));
+        }
+
+        let clocks_per_beat = std::cmp::max(1, 96 / denominator as u32);
+        let clocks_per_click = if numerator > 3 && numerator % 3 == 0 && denominator >= 8 {
+            clocks_per_beat * 3
+        } else {
+            clocks_per_beat
+        };
+
+        Ok(Meter {
+            numerator,
+            denominator_exponent: denominator.trailing_zeros() as u8,
+            clocks_per_click: clocks_per_click as u8,
+            thirty_seconds_per_quarter: 8
+        })
+    }
+
+    /// Build from the bytes used in TimeSignature, where the denominator is given as a power of two
+    pub fn from_raw(numerator: u8, denominator_exponent: u8, clocks_per_click: u8, thirty_seconds_per_quarter: u8) -> Result<Meter, ApresError> {
+        if numerator == 0 || denominator_exponent > 7 {
+            Err(ApresError::InvalidBytes(vec![numerator, denominator_exponent, clocks_per_click, thirty_seconds_per_quarter]))
+        } else {
+            Ok(Meter {
+                numerator,
+                denominator_exponent,
+                clocks_per_click,
+                thirty_seconds_per_quarter
+            })
+        }
+    }
+
+    /// Set the number of MIDI clocks (24 per quarter note) between metronome clicks
+    pub fn with_clocks_per_click(mut self, clocks_per_click: u8) -> Meter {
+        self.clocks_per_click = clocks_per_click;
+        self
+    }
+
+    /// Set the number of notated 32nd notes in a MIDI quarter note (24 MIDI clocks). Normally 8.
+    pub fn with_thirty_seconds_per_quarter(mut self, thirty_seconds_per_quarter: u8) -> Meter {
+        self.thirty_seconds_per_quarter = thirty_seconds_per_quarter;
+        self
+    }
+
+    pub fn get_numerator(&self) -> u8 {
+        self.numerator
+    }
+
+    pub fn get_denominator(&self) -> u8 {
+        1 << self.denominator_exponent
+    }
+
+    pub fn get_denominator_exponent(&self) -> u8 {
+        self.denominator_exponent
+    }
+
+    pub fn get_clocks_per_click(&self) -> u8 {
+        self.clocks_per_click
+    }
+
+    pub fn get_thirty_seconds_per_quarter(&self) -> u8 {
+        self.thirty_seconds_per_quarter
+    }
+
+    /// Get the length of a bar in ticks, given the pulses per quarter note
+    pub fn get_bar_length(&self, ppqn: u16) -> usize {
+        (ppqn as usize * 4 * self.numerator as usize) >> self.denominator_exponent
+    }
+}
+
+/// Formats as beats per minute, eg "120bpm", with as few decimals as needed to get the same tempo back
+impl fmt::Display for Tempo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bpm = self.get_bpm();
+        for precision in 0 .. 6 {
+            let rounded = format!("{:.*}", precision, bpm);
+            if let Ok(value) = rounded.parse::<f64>() {
+                if Tempo::from_bpm(value).ok() == Some(*self) {
+                    return write!(f, "{}bpm", rounded);
+                }
+            }
+        }
+
+        write!(f, "{}bpm", bpm)
+    }
+}
+
+/// Parses beats per minute, eg "120bpm" or "97.5bpm", or microseconds per quarter note, eg "500000us"
+impl FromStr for Tempo {
+    type Err = ApresError;
+    fn from_str(name: &str) -> Result<Tempo, ApresError> {
+        let name = name.trim();
+        if let Some(bpm) = name.strip_suffix("bpm") {
+            match bpm.trim().parse::<f64>() {
+                Ok(bpm) => {
+                    Tempo::from_bpm(bpm)
+                }
+                Err(_) => {
+                    Err(ApresError::ParseError(name.to_string()))
+                }
+            }
+        } else if let Some(microseconds) = name.strip_suffix("us") {
+            match microseconds.trim().parse::<u32>() {
+                Ok(microseconds) => {
+                    Tempo::new(microseconds)
+                }
+                Err(_) => {
+                    Err(ApresError::ParseError(name.to_string()))
+                }
+            }
+        } else {
+            Err(ApresError::ParseError(name.to_string()))
+        }
+    }
+}
+
+impl TryFrom<u32> for Tempo {
+    type Error = ApresError;
+    fn try_from(microseconds_per_quarter: u32) -> Result<Tempo, ApresError> {
+        Tempo::new(microseconds_per_quarter)
+    }
+}
+
+impl From<Tempo> for u32 {
+    fn from(tempo: Tempo) -> u32 {
+        tempo.0
+    }
+}
+
+/// 4/4
+impl Default for Meter {
+    fn default() -> Meter {
+        Meter {
+            numerator: 4,
+            denominator_exponent: 2,
+            clocks_per_click: 24,
+            thirty_seconds_per_quarter: 8
+        }
+    }
+}
+
+/// Formats as "numerator/denominator", eg "6/8"
+impl fmt::Display for Meter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.get_numerator(), self.get_denominator())
+    }
+}
+
+/// Parses "numerator/denominator", eg "6/8", using the default metronome clicks
+impl FromStr for Meter {
+    type Err = ApresError;
+    fn from_str(name: &str) -> Result<Meter, ApresError> {
+        let parts: Vec<&str> = name.split('/').collect();
+        if parts.len() != 2 {
+            return Err(ApresError::ParseError(name.to_string()));
+        }
+
+        match (parts[0].trim().parse::<u8>(), parts[1].trim().parse::<u8>()) {
+            (Ok(numerator), Ok(denominator)) => {
+                Meter::new(numerator, denominator)
+            }
+            _ => {
+                Err(ApresError::ParseError(name.to_string()))
+            }
+        }
+    }
+}
@@ -1,6 +1,35 @@
 #[cfg(test)]
 use super::*;
 use super::MIDIEvent::*;
+use std::convert::TryFrom;
+
+fn ch(channel: u8) -> Channel {
+    Channel::new(channel).unwrap()
+}
+
+fn u7(value: u8) -> U7 {
+    U7::new(value).unwrap()
+}
+
+fn u14(value: u16) -> U14 {
+    U14::new(value).unwrap()
+}
+
+// Load a file with a single track holding the given event bytes
+fn load_track(events: &[u8]) -> Result<MIDI, ApresError> {
+    let mut bytes = vec![0x4D, 0x54, 0x68, 0x64, 0x00, 0x00, 0x00, 0x06, 0x00, 0x01, 0x00, 0x01, 0x00, 0x60, 0x4D, 0x54, 0x72, 0x6B];
+    bytes.extend((events.len() as u32).to_be_bytes().iter());
+    bytes.extend(events.iter());
+    MIDI::from_bytes(bytes)
+}
+
+fn get_track_events(midi: &MIDI, track: usize) -> Vec<MIDIEvent> {
+    midi.get_tracks()[track].iter().map(|(_, event_id)| midi.get_event(*event_id).unwrap()).collect()
+}
+
+fn get_chord_name_from_mi_sf(mi: u8, sf: u8) -> String {
+    Key::from_sf_mi(sf as i8, mi).unwrap().to_string()
+}
 
 #[test]
 fn test_initialize_load() {
@@ -73,8 +102,8 @@ fn test_initialize_load() {
 #[test]
 fn test_add_event() {
     let mut midi = MIDI::new();
-    let on_event = midi.push_event(0, 0, NoteOn(0, 64, 100));
-    let off_event = midi.push_event(0, 119, NoteOff(0, 64, 0));
+    let on_event = midi.push_event(0, 0, NoteOn(ch(0), u7(64), u7(100)));
+    let off_event = midi.push_event(0, 119, NoteOff(ch(0), u7(64), u7(0)));
 
     assert_eq!(on_event.ok().unwrap(), 1);
     assert_eq!(off_event.ok().unwrap(), 2);
@@ -241,8 +270,8 @@ fn test_end_of_track_event() {
 
 #[test]
 fn test_channel_prefix_event() {
-    for i in std::u8::MIN .. std::u8::MAX {
-        let mut event = ChannelPrefix(i as u8);
+    for i in 0 ..= Channel::MAX {
+        let event = ChannelPrefix(ch(i));
         assert_eq!(
             event.as_bytes().as_slice(),
             [0xFF, 0x20, 0x01, i]
@@ -253,13 +282,16 @@ fn test_channel_prefix_event() {
 #[test]
 fn test_set_tempo_event() {
     let test_cases_bpm = vec![
-        (120, 500000),
-        (280, 214285),
-        (1, 0x00FFFFFF),// Minimum bpm is 3.576278762788
-        (60_000_000, 1)
+        (120.0, 500000),
+        (280.0, 214286),
+        (97.5, 615385),
+        (3.6, 16666667), // Close to the minimum bpm of 3.576278762788
+        (60_000_000.0, 1)
     ];
     for (bpm, expected_uspqn) in test_cases_bpm.iter() {
-        let mut event = SetTempo(*expected_uspqn);
+        let tempo = Tempo::from_bpm(*bpm).ok().unwrap();
+        assert_eq!(tempo.get(), *expected_uspqn);
+        let event = SetTempo(tempo);
         assert_eq!(
             event.as_bytes().as_slice(),
             [
@@ -270,33 +302,78 @@ fn test_set_tempo_event() {
             ]
         );
     }
+
+    assert!(Tempo::from_bpm(3.5).is_err());
+    assert!(Tempo::from_bpm(0.0).is_err());
+    assert!(Tempo::from_bpm(f64::INFINITY).is_err());
+    assert!(Tempo::new(0x01000000).is_err());
+    assert!(Tempo::new(0).is_err());
+    assert_eq!(Tempo::new(0x00FFFFFF).ok().unwrap().get_bpm(), 60_000_000.0 / 16777215.0);
+    assert_eq!(Tempo::default().get_bpm(), 120.0);
 }
 
 #[test]
 fn test_smpte_offset_event() {
-    let event = SMPTEOffset(1,2,3,4,5);
+    let timecode = Timecode::from_bytes([0x21, 0x02, 0x03, 0x04, 0x05]).ok().unwrap();
+    assert_eq!(timecode.rate, FrameRate::Fps25);
+    assert_eq!(timecode.hours, 1);
+    let event = SMPTEOffset(timecode);
     assert_eq!(
         event.as_bytes().as_slice(),
-        [0xFF, 0x54, 0x05, 0x01, 0x02, 0x03, 0x04, 0x05]
+        [0xFF, 0x54, 0x05, 0x21, 0x02, 0x03, 0x04, 0x05]
     );
+    assert_eq!(MIDIEvent::from_bytes(&mut event.as_bytes(), 0).ok(), Some(event));
+
+    // 25 frames don't fit in a second at 25fps
+    assert!(Timecode::from_bytes([0x21, 0x02, 0x03, 0x19, 0x00]).is_err());
 }
 
 #[test]
 fn test_time_signature_event() {
-    let event = TimeSignature(4, 4, 32, 3);
+    let event = TimeSignature(Meter::from_raw(4, 4, 32, 3).ok().unwrap());
     assert_eq!(
         event.as_bytes().as_slice(),
         [0xFF, 0x58, 0x04, 0x04, 0x04, 0x20, 0x03]
     );
+
+    let test_cases = vec![
+        ("4/4", [0x04, 0x02, 0x18, 0x08]),
+        ("3/4", [0x03, 0x02, 0x18, 0x08]),
+        ("6/8", [0x06, 0x03, 0x24, 0x08]),
+        ("7/8", [0x07, 0x03, 0x0C, 0x08]),
+        ("2/2", [0x02, 0x01, 0x30, 0x08])
+    ];
+    for (name, bytes) in test_cases.iter() {
+        let meter: Meter = name.parse().ok().unwrap();
+        assert_eq!(meter.to_string(), *name);
+        let event = TimeSignature(meter);
+        assert_eq!(&event.as_bytes()[3..], bytes);
+        assert_eq!(MIDIEvent::from_bytes(&mut event.as_bytes(), 0).ok(), Some(event));
+    }
+
+    assert_eq!(Meter::new(6, 8).ok().unwrap().get_bar_length(120), 360);
+    assert!(Meter::new(3, 6).is_err());
+    assert!(Meter::new(0, 4).is_err());
+    assert!("3".parse::<Meter>().is_err());
+    assert!(MIDIEvent::from_bytes(&mut vec![0xFF, 0x58, 0x04, 0x04, 0x08, 0x18, 0x08], 0).is_err());
 }
 
 #[test]
 fn test_key_signature_event() {
-    let event = KeySignature("A".to_string());
+    let event = KeySignature(Key::new(Tonic::A, Accidental::Natural, Mode::Major));
     assert_eq!(
         event.as_bytes().as_slice(),
         [0xFF, 0x59, 0x02, 0x03, 0x00]
     );
+
+    let event = KeySignature("Ebm".parse().ok().unwrap());
+    assert_eq!(
+        event.as_bytes().as_slice(),
+        [0xFF, 0x59, 0x02, 0xFA, 0x01]
+    );
+    assert_eq!(MIDIEvent::from_bytes(&mut event.as_bytes(), 0).ok(), Some(event));
+
+    assert!(MIDIEvent::from_bytes(&mut vec![0xFF, 0x59, 0x02, 0x08, 0x00], 0).is_err());
 }
 
 #[test]
@@ -310,7 +387,7 @@ fn test_sequence_specific_event() {
 
 #[test]
 fn test_note_on_event() {
-    let event = NoteOn(14, 23, 33);
+    let event = NoteOn(ch(14), u7(23), u7(33));
     assert_eq!(
         event.as_bytes().as_slice(),
         [0x9E, 0x17, 0x21]
@@ -319,7 +396,7 @@ fn test_note_on_event() {
 
 #[test]
 fn test_note_off_event() {
-    let event = NoteOff(14, 23, 33);
+    let event = NoteOff(ch(14), u7(23), u7(33));
     assert_eq!(
         event.as_bytes().as_slice(),
         [0x8E, 0x17, 0x21]
@@ -328,7 +405,7 @@ fn test_note_off_event() {
 
 #[test]
 fn test_aftertouch_event() {
-    let event = AfterTouch(14, 23, 33);
+    let event = AfterTouch(ch(14), u7(23), u7(33));
     assert_eq!(
         event.as_bytes().as_slice(),
         [0xAE, 0x17, 0x21]
@@ -337,7 +414,7 @@ fn test_aftertouch_event() {
 
 #[test]
 fn test_program_change_event() {
-    let event = ProgramChange(14, 23);
+    let event = ProgramChange(ch(14), u7(23));
     assert_eq!(
         event.as_bytes().as_slice(),
         [0xCE, 0x17]
@@ -346,7 +423,7 @@ fn test_program_change_event() {
 
 #[test]
 fn test_channel_pressure_event() {
-    let event = ChannelPressure(14, 23);
+    let event = ChannelPressure(ch(14), u7(23));
     assert_eq!(
         event.as_bytes().as_slice(),
         [0xDE, 0x17]
@@ -355,28 +432,45 @@ fn test_channel_pressure_event() {
 
 #[test]
 fn test_pitchwheel_change_event() {
-    let test_cases: Vec<(f64, (u8, u8))> = vec![
-        (-1.0, (0, 0)),
-        (-0.5, (0x10, 0x00)),
-        (0.0, (0x20, 0x00)),
-        (0.5, (0x2F, 0x7F)),
-        (1.0, (0x3F, 0x7F))
+    let test_cases: Vec<(f64, u16, (u8, u8))> = vec![
+        (-1.0, 0x0000, (0x00, 0x00)),
+        (-0.5, 0x1000, (0x20, 0x00)),
+        (0.0, 0x2000, (0x40, 0x00)),
+        (0.5, 0x3000, (0x60, 0x00)),
+        (1.0, 0x3FFF, (0x7F, 0x7F))
     ];
-    for (input_value, (msb, lsb)) in test_cases.iter() {
-        let event = PitchWheelChange(14, *input_value);
+    for (input_value, unsigned_value, (msb, lsb)) in test_cases.iter() {
+        let bend = PitchBend::from_normalized(*input_value);
+        assert_eq!(bend.get(), *unsigned_value);
+        assert!((bend.get_normalized() - *input_value).abs() < 0.001);
+
+        let event = PitchWheelChange(ch(14), bend);
+        assert_eq!(
+            event.as_bytes().as_slice(),
+            [0xEE, *lsb, *msb]
+        );
+    }
+
+    // Every 14-bit value survives a read/write round trip
+    for unsigned_value in 0 ..= U14::MAX {
+        let bytes = PitchWheelChange(ch(0), PitchBend::new(unsigned_value).unwrap()).as_bytes();
+        let event = MIDIEvent::from_bytes(&mut bytes.clone(), 0).ok().unwrap();
+        assert_eq!(event.as_bytes(), bytes);
         match event {
-            PitchWheelChange(_, v) => {
-                assert_eq!(v, *input_value);
-                assert_eq!(
-                    event.as_bytes().as_slice(),
-                    [0xEE, *lsb, *msb]
-                );
+            PitchWheelChange(_, bend) => {
+                assert_eq!(bend.get(), unsigned_value);
+                assert_eq!(PitchBend::from_normalized(bend.get_normalized()), bend);
             }
             _ => {
                 assert!(false);
             }
         }
     }
+
+    assert_eq!(PitchBend::from_signed(-0x2000).ok(), Some(PitchBend::MIN));
+    assert!(PitchBend::from_signed(0x2000).is_err());
+    assert!((PitchBend::from_semitones(1.0, 2.0).get_semitones(2.0) - 1.0).abs() < 0.001);
+    assert_eq!(PitchBend::from_semitones(-12.0, 2.0), PitchBend::MIN);
 }
 
 #[test]
@@ -392,154 +486,172 @@ fn test_system_exclusive_event() {
 #[test]
 fn test_control_change_events() {
     let mut event: MIDIEvent;
-    let channel = 1;
-    let value = 25;
+    let channel = ch(1);
+    let value = u7(25);
 
-    event = ControlChange(0x0E, 0x17, 0x21);
+    event = ControlChange(ch(0x0E), u7(0x17), u7(0x21));
     assert_eq!( event.as_bytes().as_slice(), [0xBE, 0x17, 0x21]);
     event = BankSelect(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x00, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x00, value.get()]);
     event = BankSelectLSB(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x20, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x20, value.get()]);
     event = ModulationWheel(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x01, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x01, value.get()]);
     event = ModulationWheelLSB(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x21, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x21, value.get()]);
     event = BreathController(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x02, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x02, value.get()]);
     event = BreathControllerLSB(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x22, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x22, value.get()]);
     event = FootPedal(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x04, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x04, value.get()]);
     event = FootPedalLSB(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x24, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x24, value.get()]);
     event = PortamentoTime(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x05, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x05, value.get()]);
     event = PortamentoTimeLSB(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x25, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x25, value.get()]);
     event = DataEntry(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x06, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x06, value.get()]);
     event = DataEntryLSB(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x26, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x26, value.get()]);
     event = Volume(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x07, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x07, value.get()]);
     event = VolumeLSB(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x27, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x27, value.get()]);
     event = Balance(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x08, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x08, value.get()]);
     event = BalanceLSB(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x28, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x28, value.get()]);
     event = Pan(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x0A, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x0A, value.get()]);
     event = PanLSB(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x2A, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x2A, value.get()]);
     event = Expression(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x0B, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x0B, value.get()]);
     event = ExpressionLSB(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x2B, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x2B, value.get()]);
     event = EffectControl1(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x0C, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x0C, value.get()]);
     event = EffectControl1LSB(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x2C, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x2C, value.get()]);
     event = EffectControl2(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x0D, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x0D, value.get()]);
     event = EffectControl2LSB(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x2D, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x2D, value.get()]);
     event = GeneralPurpose1(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x10, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x10, value.get()]);
     event = GeneralPurpose1LSB(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x30, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x30, value.get()]);
     event = GeneralPurpose2(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x11, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x11, value.get()]);
     event = GeneralPurpose2LSB(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x31, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x31, value.get()]);
     event = GeneralPurpose3(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x12, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x12, value.get()]);
     event = GeneralPurpose3LSB(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x32, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x32, value.get()]);
     event = GeneralPurpose4(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x13, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x13, value.get()]);
     event = GeneralPurpose4LSB(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x33, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x33, value.get()]);
     event = HoldPedal(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x40, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x40, value.get()]);
     event = Portamento(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x41, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x41, value.get()]);
     event = Sustenuto(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x42, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x42, value.get()]);
     event = SoftPedal(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x43, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x43, value.get()]);
     event = Legato(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x44, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x44, value.get()]);
     event = Hold2Pedal(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x45, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x45, value.get()]);
     event = SoundVariation(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x46, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x46, value.get()]);
     event = SoundTimbre(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x47, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x47, value.get()]);
     event = SoundReleaseTime(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x48, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x48, value.get()]);
     event = SoundAttack(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x49, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x49, value.get()]);
     event = SoundBrightness(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x4A, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x4A, value.get()]);
     event = SoundControl1(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x4B, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x4B, value.get()]);
     event = SoundControl2(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x4C, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x4C, value.get()]);
     event = SoundControl3(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x4D, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x4D, value.get()]);
     event = SoundControl4(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x4E, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x4E, value.get()]);
     event = SoundControl5(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x4F, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x4F, value.get()]);
     event = GeneralPurpose5(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x50, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x50, value.get()]);
     event = GeneralPurpose6(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x51, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x51, value.get()]);
     event = GeneralPurpose7(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x52, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x52, value.get()]);
     event = GeneralPurpose8(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x53, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x53, value.get()]);
     event = EffectsLevel(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x5B, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x5B, value.get()]);
     event = TremuloLevel(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x5C, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x5C, value.get()]);
     event = ChorusLevel(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x5D, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x5D, value.get()]);
     event = CelesteLevel(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x5E, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x5E, value.get()]);
     event = PhaserLevel(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x5F, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x5F, value.get()]);
     event = RegisteredParameterNumber(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x65, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x65, value.get()]);
     event = RegisteredParameterNumberLSB(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x64, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x64, value.get()]);
     event = NonRegisteredParameterNumber(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x63, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x63, value.get()]);
     event = NonRegisteredParameterNumberLSB(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x62, value]);
-    event = LocalControl(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x7A, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x62, value.get()]);
+    event = LocalControl(channel, true);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x7A, 0x7F]);
+    event = LocalControl(channel, false);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x7A, 0x00]);
     event = MonophonicOperation(channel, value);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0xFE, value]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x7E, value.get()]);
 
     event = DataIncrement(channel);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x60, 0]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x60, 0]);
     event = DataDecrement(channel);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x61, 0]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x61, 0]);
     event = PolyphonicOperation(channel);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0xFF, 0]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x7F, 0]);
     event = AllSoundOff(channel);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x78, 0]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x78, 0]);
     event = AllControllersOff(channel);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x79, 0]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x79, 0]);
     event = AllNotesOff(channel);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x7B, 0]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x7B, 0]);
     event = OmniOff(channel);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x7C, 0]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x7C, 0]);
     event = OmniOn(channel);
-    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel, 0x7D, 0]);
+    assert_eq!(event.as_bytes().as_slice(), [0xB0 | channel.get(), 0x7D, 0]);
+
+    // Channel mode messages are parsed back into their own variants
+    let channel_mode_events = vec![
+        AllSoundOff(channel),
+        AllControllersOff(channel),
+        LocalControl(channel, true),
+        LocalControl(channel, false),
+        AllNotesOff(channel),
+        OmniOff(channel),
+        OmniOn(channel),
+        MonophonicOperation(channel, u7(4)),
+        PolyphonicOperation(channel)
+    ];
+    for event in channel_mode_events.iter() {
+        assert_eq!(MIDIEvent::from_bytes(&mut event.as_bytes(), 0).ok().as_ref(), Some(event));
+    }
 }
 
 #[test]
@@ -554,3 +666,1430 @@ fn test_chords() {
     );
 }
 
+#[test]
+#[allow(deprecated)]
+fn test_get_mi_sf() {
+    assert_eq!(get_mi_sf("A"), (0, 3));
+    assert_eq!(get_mi_sf("Bb"), (0, 10));
+    assert_eq!(get_mi_sf("D#"), (0, 11));
+    assert_eq!(get_mi_sf("Cm"), (1, 11));
+    assert_eq!(get_mi_sf("F#m"), (1, 3));
+    assert_eq!(get_mi_sf("H"), (0, 0));
+}
+
+
+#[test]
+fn test_channel_state() {
+    let mut midi = MIDI::new();
+    midi.insert_event(0, 0, BankSelect(ch(1), u7(2))).ok();
+    midi.insert_event(0, 0, ProgramChange(ch(1), u7(24))).ok();
+    midi.insert_event(0, 0, Volume(ch(1), u7(90))).ok();
+    midi.insert_event(0, 10, RegisteredParameterNumber(ch(1), u7(0))).ok();
+    midi.insert_event(0, 10, RegisteredParameterNumberLSB(ch(1), u7(0))).ok();
+    midi.insert_event(0, 10, DataEntry(ch(1), u7(12))).ok();
+    midi.insert_event(1, 20, NoteOn(ch(1), u7(64), u7(100))).ok();
+    midi.insert_event(1, 20, NoteOn(ch(1), u7(67), u7(100))).ok();
+    midi.insert_event(1, 40, NoteOff(ch(1), u7(64), u7(0))).ok();
+    midi.insert_event(1, 40, NoteOn(ch(2), u7(60), u7(80))).ok();
+
+    let state = midi.get_channel_state(ch(1), 40);
+    assert_eq!(state.bank_select, Some(u7(2)));
+    assert_eq!(state.program, Some(u7(24)));
+    assert_eq!(state.controllers.get(&u7(0x07)), Some(&u7(90)));
+    assert_eq!(state.registered_parameters.get(&(u7(0), u7(0))), Some(&(u7(12), None)));
+    assert_eq!(state.active_notes.len(), 2);
+
+    let state = midi.get_channel_state(ch(1), 41);
+    assert_eq!(state.active_notes.len(), 1);
+    assert_eq!(
+        state.to_events(),
+        vec![
+            BankSelect(ch(1), u7(2)),
+            ProgramChange(ch(1), u7(24)),
+            Volume(ch(1), u7(90)),
+            RegisteredParameterNumber(ch(1), u7(0)),
+            RegisteredParameterNumberLSB(ch(1), u7(0)),
+            DataEntry(ch(1), u7(12)),
+            RegisteredParameterNumber(ch(1), u7(0)),
+            RegisteredParameterNumberLSB(ch(1), u7(0)),
+            NoteOn(ch(1), u7(67), u7(100))
+        ]
+    );
+
+    let states = midi.get_channel_states(41);
+    assert_eq!(states.len(), 16);
+    assert_eq!(states[2].to_events(), vec![NoteOn(ch(2), u7(60), u7(80))]);
+    assert_eq!(states[0].to_events(), vec![]);
+}
+
+#[test]
+fn test_undo_redo() {
+    let mut midi = MIDI::new();
+    let untracked = midi.insert_event(0, 0, ProgramChange(ch(0), u7(1))).ok().unwrap();
+    assert!(!midi.can_undo());
+
+    midi.enable_history();
+    let on_event = midi.insert_event(0, 0, NoteOn(ch(0), u7(64), u7(100))).ok().unwrap();
+    midi.begin_transaction("lengthen");
+    let off_event = midi.insert_event(0, 60, NoteOff(ch(0), u7(64), u7(0))).ok().unwrap();
+    midi.move_event(0, 120, off_event);
+    midi.end_transaction();
+    midi.replace_event(untracked, ProgramChange(ch(0), u7(2))).ok();
+    midi.remove_event(on_event).ok();
+    midi.set_ppqn(240);
+
+    assert_eq!(
+        midi.get_undo_names(),
+        vec!["insert_event", "lengthen", "replace_event", "remove_event", "set_ppqn"]
+    );
+
+    assert_eq!(midi.undo(), Some("set_ppqn".to_string()));
+    assert_eq!(midi.get_ppqn(), 120);
+    assert_eq!(midi.undo(), Some("remove_event".to_string()));
+    assert_eq!(midi.get_event(on_event), Some(NoteOn(ch(0), u7(64), u7(100))));
+    assert_eq!(midi.get_event_position(on_event), Some(&(0, 0)));
+    assert_eq!(midi.undo(), Some("replace_event".to_string()));
+    assert_eq!(midi.get_event(untracked), Some(ProgramChange(ch(0), u7(1))));
+    assert_eq!(midi.undo(), Some("lengthen".to_string()));
+    assert_eq!(midi.get_event(off_event), None);
+    assert_eq!(midi.get_event_position(off_event), None);
+
+    assert_eq!(midi.redo(), Some("lengthen".to_string()));
+    assert_eq!(midi.get_event_position(off_event), Some(&(0, 120)));
+    assert!(midi.can_redo());
+
+    // A new edit discards the redo stack
+    midi.move_event(1, 0, off_event);
+    assert!(!midi.can_redo());
+    assert_eq!(midi.redo(), None);
+
+    midi.disable_history();
+    assert_eq!(midi.undo(), None);
+}
+
+#[test]
+fn test_subscribe() {
+    let mut midi = MIDI::new();
+    let receiver = midi.subscribe();
+    let event_id = midi.insert_event(0, 10, NoteOn(ch(0), u7(64), u7(100))).ok().unwrap();
+    midi.move_event(1, 20, event_id);
+    midi.replace_event(event_id, NoteOn(ch(0), u7(65), u7(100))).ok();
+    midi.set_format(0);
+
+    let changes: Vec<MIDIEdit> = receiver.try_iter().collect();
+    assert_eq!(
+        changes,
+        vec![
+            MIDIEdit::InsertEvent { event_id, track: 0, tick: 10, event: NoteOn(ch(0), u7(64), u7(100)) },
+            MIDIEdit::MoveEvent { event_id, from: Some((0, 10)), to: Some((1, 20)) },
+            MIDIEdit::ReplaceEvent { event_id, track: 1, tick: 20, old_event: NoteOn(ch(0), u7(64), u7(100)), new_event: NoteOn(ch(0), u7(65), u7(100)) },
+            MIDIEdit::SetFormat { old_format: 1, new_format: 0 }
+        ]
+    );
+
+    // Undone edits are reported as their inverse
+    midi.enable_history();
+    midi.remove_event(event_id).ok();
+    midi.undo();
+    let changes: Vec<MIDIEdit> = receiver.try_iter().collect();
+    assert_eq!(changes.len(), 2);
+    assert_eq!(changes[1], changes[0].inverse());
+    assert_eq!(changes[1].get_event_id(), Some(event_id));
+
+    drop(receiver);
+    midi.set_ppqn(96);
+    assert_eq!(midi.count_subscribers(), 0);
+}
+
+#[test]
+fn test_diff() {
+    use crate::diff::EventDiff;
+    let mut old_midi = MIDI::new();
+    old_midi.insert_event(0, 0, NoteOn(ch(0), u7(64), u7(100))).ok();
+    old_midi.insert_event(0, 0, NoteOn(ch(0), u7(67), u7(100))).ok();
+    old_midi.insert_event(0, 120, NoteOn(ch(0), u7(64), u7(0))).ok();
+    old_midi.insert_event(1, 0, ProgramChange(ch(1), u7(4))).ok();
+
+    let mut new_midi = MIDI::new();
+    new_midi.insert_event(0, 0, NoteOn(ch(0), u7(67), u7(100))).ok();
+    new_midi.insert_event(0, 0, NoteOn(ch(0), u7(64), u7(100))).ok();
+    new_midi.insert_event(0, 120, NoteOff(ch(0), u7(64), u7(64))).ok();
+    new_midi.insert_event(1, 0, ProgramChange(ch(1), u7(5))).ok();
+    new_midi.set_ppqn(240);
+
+    let mut options = DiffOptions::new();
+    options.ignore_intra_tick_order = true;
+    let diff = old_midi.diff(&new_midi, &options);
+    assert_eq!(diff.ppqn, Some((120, 240)));
+    assert_eq!(diff.tracks.len(), 1);
+    assert_eq!(diff.tracks[0].track, 1);
+    assert_eq!(
+        diff.to_report(),
+        "ppqn: 120 -> 240\ntrack 1:\n  ~ @0 ProgramChange ch=2 program=4 -> ProgramChange ch=2 program=5"
+    );
+
+    // Respecting the order within a tick, the swapped notes show up
+    let diff = old_midi.diff(&new_midi, &DiffOptions::new());
+    assert_eq!(diff.tracks[0].track, 0);
+    assert_eq!(diff.tracks[0].changes.len(), 2);
+
+    options.normalize_note_off = false;
+    let diff = old_midi.diff(&new_midi, &options);
+    assert_eq!(diff.tracks[0].changes.len(), 2);
+
+    // By id, every event in the second midi lines up with one in the first
+    options.ignore_event_ids = false;
+    options.normalize_note_off = true;
+    let diff = old_midi.diff(&new_midi, &options);
+    assert_eq!(diff.tracks[0].changes.len(), 2);
+    assert!(old_midi.diff(&old_midi, &options).is_empty());
+
+    // Note offs are only normalized to compare them. They're reported as they are.
+    let mut moved_midi = MIDI::new();
+    moved_midi.insert_event(0, 0, NoteOn(ch(0), u7(64), u7(100))).ok();
+    moved_midi.insert_event(0, 0, NoteOn(ch(0), u7(67), u7(100))).ok();
+    moved_midi.insert_event(0, 100, NoteOff(ch(0), u7(64), u7(64))).ok();
+    let diff = old_midi.diff(&moved_midi, &options);
+    assert_eq!(
+        diff.tracks[0].changes,
+        vec![EventDiff::Changed {
+            old_tick: 120,
+            new_tick: 100,
+            old_id: 3,
+            new_id: 3,
+            old_event: NoteOn(ch(0), u7(64), u7(0)),
+            new_event: NoteOff(ch(0), u7(64), u7(64))
+        }]
+    );
+    let diff = old_midi.diff(&moved_midi, &DiffOptions::new());
+    assert_eq!(
+        diff.tracks[0].changes,
+        vec![
+            EventDiff::Added { tick: 100, event_id: 3, event: NoteOff(ch(0), u7(64), u7(64)) },
+            EventDiff::Removed { tick: 120, event_id: 3, event: NoteOn(ch(0), u7(64), u7(0)) }
+        ]
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde() {
+    let event = NoteOn(ch(0), u7(60), u7(100));
+    assert_eq!(serde_json::to_string(&event).unwrap(), "{\"NoteOn\":[0,60,100]}");
+    assert_eq!(serde_json::to_string(&EndOfTrack).unwrap(), "\"EndOfTrack\"");
+    assert_eq!(serde_json::to_string(&SetTempo(Tempo::default())).unwrap(), "{\"SetTempo\":500000}");
+    assert_eq!(
+        serde_json::to_string(&TimeSignature(Meter::new(6, 8).unwrap())).unwrap(),
+        "{\"TimeSignature\":{\"numerator\":6,\"denominator\":8,\"clocks_per_click\":36,\"thirty_seconds_per_quarter\":8}}"
+    );
+
+    // Values are range checked
+    assert!(serde_json::from_str::<MIDIEvent>("{\"NoteOn\":[16,60,100]}").is_err());
+    assert!(serde_json::from_str::<MIDIEvent>("{\"NoteOn\":[0,128,100]}").is_err());
+    assert!(serde_json::from_str::<MIDIEvent>("{\"SetTempo\":16777216}").is_err());
+    assert!(serde_json::from_str::<MIDIEvent>("{\"TimeSignature\":{\"numerator\":6,\"denominator\":7,\"clocks_per_click\":36,\"thirty_seconds_per_quarter\":8}}").is_err());
+
+    let mut midi = MIDI::new();
+    midi.set_ppqn(240);
+    let first = midi.insert_event(0, 0, TrackName("Piano".to_string())).ok().unwrap();
+    midi.insert_event(1, 120, KeySignature("Ebm".parse::<Key>().unwrap())).ok();
+    midi.insert_event(1, 0, PitchWheelChange(ch(2), PitchBend::MIN)).ok();
+    midi.insert_event(1, 0, SMPTEOffset(Timecode::new(FrameRate::Fps25, 1, 0, 0, 0).unwrap())).ok();
+    midi.remove_event(first).ok();
+
+    let json = serde_json::to_string(&midi).unwrap();
+    let copy: MIDI = serde_json::from_str(&json).unwrap();
+    assert_eq!(copy.get_ppqn(), 240);
+    assert_eq!(copy.get_tracks(), midi.get_tracks());
+    assert!(midi.diff(&copy, &DiffOptions::new()).is_empty());
+    assert_eq!(serde_json::to_string(&copy).unwrap(), json);
+
+    // New events don't reuse deserialized ids
+    let mut copy = copy;
+    assert_eq!(copy.insert_event(0, 0, EndOfTrack).ok(), Some(5));
+
+    let error = ApresError::ValueOutOfRange(128, 127);
+    assert_eq!(serde_json::to_string(&error).unwrap(), "{\"ValueOutOfRange\":[128,127]}");
+}
+
+#[test]
+fn test_copy_paste() {
+    let mut source = MIDI::new();
+    source.insert_event(0, 100, NoteOn(ch(0), u7(64), u7(100))).ok();
+    source.insert_event(0, 160, NoteOff(ch(0), u7(64), u7(0))).ok();
+    source.insert_event(1, 120, ProgramChange(ch(1), u7(3))).ok();
+    source.insert_event(2, 120, ProgramChange(ch(2), u7(3))).ok();
+    source.insert_event(0, 240, NoteOn(ch(0), u7(65), u7(100))).ok();
+
+    let clipboard = source.copy(&[0, 1], 100, 240);
+    assert_eq!(clipboard.length, 140);
+    assert_eq!(
+        clipboard.tracks,
+        vec![
+            vec![(0, NoteOn(ch(0), u7(64), u7(100))), (60, NoteOff(ch(0), u7(64), u7(0)))],
+            vec![(20, ProgramChange(ch(1), u7(3)))]
+        ]
+    );
+
+    let mut target = MIDI::new();
+    target.set_ppqn(240);
+    target.insert_event(3, 500, NoteOn(ch(0), u7(60), u7(100))).ok();
+    target.insert_event(3, 900, NoteOn(ch(0), u7(62), u7(100))).ok();
+    target.enable_history();
+
+    let new_ids = target.paste(&clipboard, 2, 400, PasteMode::Replace).ok().unwrap();
+    assert_eq!(new_ids.len(), 3);
+    assert_eq!(target.count_events(), 4);
+    assert_eq!(target.get_event_position(new_ids[1]), Some(&(2, 520)));
+    assert_eq!(target.get_event_position(new_ids[2]), Some(&(3, 440)));
+
+    // The whole paste is a single undo step
+    assert_eq!(target.undo(), Some("paste".to_string()));
+    assert_eq!(target.count_events(), 2);
+
+    target.paste(&clipboard, 2, 400, PasteMode::Merge).ok();
+    assert_eq!(target.count_events(), 5);
+
+    assert!(target.paste(&clipboard, 15, 0, PasteMode::Merge).is_err());
+
+    let cut = source.cut(&[2], 0, 1000);
+    assert_eq!(cut.count_events(), 1);
+    assert_eq!(source.count_events(), 4);
+}
+
+#[test]
+fn test_message_categories() {
+    let events = vec![
+        TrackName("Piano".to_string()),
+        SetTempo(Tempo::new(500000).ok().unwrap()),
+        NoteOn(ch(3), u7(64), u7(100)),
+        Volume(ch(3), u7(90)),
+        ControlChange(ch(3), u7(0x0E), u7(12)),
+        PitchWheelChange(ch(3), PitchBend::from_normalized(0.5)),
+        SongPositionPointer(u14(12)),
+        MIDIClock,
+        SystemExclusive(vec![0x7E, 0x7F, 0x09, 0x01]),
+        TimeCode(Timecode::new(FrameRate::Fps25, 1, 2, 3, 4).unwrap())
+    ];
+    for event in events.iter() {
+        let message = MIDIMessage::from(event.clone());
+        assert_eq!(MIDIEvent::from(message), *event);
+    }
+
+    assert_eq!(
+        MIDIMessage::from(Volume(ch(3), u7(90))),
+        MIDIMessage::Channel(ChannelMessage::new(ch(3), ChannelMessageKind::ControlChange { controller: u7(0x07), value: u7(90) }))
+    );
+    assert_eq!(MIDIEvent::from(ChannelMessage::new(ch(3), ChannelMessageKind::ControlChange { controller: u7(0x07), value: u7(90) })), Volume(ch(3), u7(90)));
+    assert_eq!(MIDIMessage::from(MIDIStop), MIDIMessage::SystemRealtime(SystemRealtime::MIDIStop));
+    let timecode = TimeCode(Timecode::new(FrameRate::Fps25, 1, 2, 3, 4).unwrap());
+    assert_eq!(MIDIMessage::from(timecode.clone()), MIDIMessage::SysEx(SysEx(vec![0x7F, 0x7F, 0x01, 0x01, 0x21, 0x02, 0x03, 0x04])));
+    assert_eq!(SystemCommon::try_from(timecode.clone()), Err(timecode));
+    assert_eq!(ChannelMessage::try_from(EndOfTrack), Err(EndOfTrack));
+    assert_eq!(MetaEvent::try_from(Lyric("la".to_string())), Ok(MetaEvent::Lyric("la".to_string())));
+
+    let mut midi = MIDI::new();
+    let event_id = midi.insert_event(0, 0, ChannelMessage::new(ch(1), ChannelMessageKind::ProgramChange { program: u7(3) })).ok().unwrap();
+    assert_eq!(midi.get_event(event_id), Some(ProgramChange(ch(1), u7(3))));
+    midi.replace_event(event_id, MetaEvent::Marker("A".to_string())).ok();
+    assert_eq!(midi.get_message(event_id), Some(MIDIMessage::Meta(MetaEvent::Marker("A".to_string()))));
+}
+
+#[test]
+fn test_data_types() {
+    assert!(Channel::new(16).is_err());
+    assert!(U7::new(128).is_err());
+    assert!(U14::new(0x4000).is_err());
+    assert_eq!(U7::try_from(127).ok(), Some(u7(127)));
+
+    let value = u14(0x2345);
+    assert_eq!(U14::from_lsb_msb(value.get_lsb(), value.get_msb()), value);
+
+    let event = SongPositionPointer(value);
+    assert_eq!(MIDIEvent::from_bytes(&mut event.as_bytes(), 0).ok(), Some(event));
+
+    // Out-of-range data bytes are rejected rather than silently truncated
+    assert!(MIDIEvent::from_bytes(&mut vec![0x90, 0x40, 0x80], 0).is_err());
+}
+
+#[test]
+fn test_controller_pairing() {
+    let mut midi = MIDI::new();
+    let control_change = ControlChange14::new(ch(2), u7(0x07), u14(0x1234)).ok().unwrap();
+    midi.insert_control_change_14(0, 0, control_change).ok();
+    midi.insert_event(0, 10, ModulationWheel(ch(2), u7(0x40))).ok();
+    midi.insert_event(0, 20, NoteOn(ch(2), u7(64), u7(100))).ok();
+    midi.insert_event(0, 30, ModulationWheelLSB(ch(2), u7(0x05))).ok();
+    assert!(ControlChange14::new(ch(0), u7(0x20), u14(0)).is_err());
+
+    let (msb_event, lsb_event) = control_change.to_events();
+    assert_eq!(msb_event, Volume(ch(2), u7(0x24)));
+    assert_eq!(lsb_event, VolumeLSB(ch(2), u7(0x34)));
+
+    let modulation = |value: u16| {
+        PairedEvent::ControlChange14(ControlChange14::new(ch(2), u7(0x01), u14(value)).ok().unwrap())
+    };
+
+    let coarse_volume = ControlChange14::new(ch(2), u7(0x07), u14(0x1200)).ok().unwrap();
+    let paired = midi.get_paired_events(0, MSBOnlyMode::ResetLSB);
+    assert_eq!(paired, vec![
+        (0, PairedEvent::ControlChange14(coarse_volume)),
+        (0, PairedEvent::ControlChange14(control_change)),
+        (10, modulation(0x2000)),
+        (20, PairedEvent::Event(NoteOn(ch(2), u7(64), u7(100)))),
+        (30, modulation(0x2005))
+    ]);
+
+    let paired = midi.get_paired_events(0, MSBOnlyMode::WaitForLSB);
+    assert_eq!(paired, vec![
+        (0, PairedEvent::ControlChange14(control_change)),
+        (10, modulation(0x2000)),
+        (20, PairedEvent::Event(NoteOn(ch(2), u7(64), u7(100)))),
+        (30, modulation(0x2005))
+    ]);
+
+    let mut pairer = ControllerPairer::new(MSBOnlyMode::KeepLSB);
+    assert_eq!(pairer.feed(ModulationWheelLSB(ch(0), u7(3))), vec![PairedEvent::Event(ModulationWheelLSB(ch(0), u7(3)))]);
+    let expected = PairedEvent::ControlChange14(ControlChange14::new(ch(0), u7(0x01), u14(0x0103)).ok().unwrap());
+    assert_eq!(pairer.feed(ModulationWheel(ch(0), u7(2))), vec![expected]);
+}
+
+#[test]
+fn test_parameter_numbers() {
+    let bend_range = ParameterEvent::pitch_bend_sensitivity(ch(0), u7(12), u7(50));
+    assert_eq!(bend_range.to_events(), vec![
+        RegisteredParameterNumber(ch(0), u7(0)),
+        RegisteredParameterNumberLSB(ch(0), u7(0)),
+        DataEntry(ch(0), u7(12)),
+        DataEntryLSB(ch(0), u7(50)),
+        RegisteredParameterNumber(ch(0), u7(0x7F)),
+        RegisteredParameterNumberLSB(ch(0), u7(0x7F))
+    ]);
+
+    let mut midi = MIDI::new();
+    midi.insert_parameter(0, 0, &bend_range).ok();
+    midi.insert_event(0, 10, DataEntry(ch(0), u7(5))).ok();
+    midi.insert_event(0, 20, NonRegisteredParameterNumber(ch(1), u7(1))).ok();
+    midi.insert_event(0, 20, NonRegisteredParameterNumberLSB(ch(1), u7(8))).ok();
+    midi.insert_event(0, 20, DataEntry(ch(1), u7(64))).ok();
+    midi.insert_event(0, 30, DataIncrement(ch(1))).ok();
+
+    let decoded = midi.get_parameter_events(0, MSBOnlyMode::WaitForLSB);
+    assert_eq!(decoded, vec![
+        (0, bend_range.clone()),
+        // Data entry after nulling is left alone
+        (10, ParameterEvent::Event(DataEntry(ch(0), u7(5)))),
+        (20, ParameterEvent::NRPN { channel: ch(1), param: u14(0x88), value: u14(0x2000) }),
+        (30, ParameterEvent::NRPN { channel: ch(1), param: u14(0x88), value: u14(0x2001) })
+    ]);
+
+    let mut decoder = ParameterDecoder::new(MSBOnlyMode::ResetLSB);
+    for event in ParameterEvent::mpe_configuration(ch(0), u7(15)).to_events() {
+        decoder.push(event);
+    }
+    assert_eq!(decoder.pop(), Some(ParameterEvent::mpe_configuration(ch(0), u7(15))));
+    assert_eq!(decoder.pop(), Some(ParameterEvent::mpe_configuration(ch(0), u7(15))));
+    assert_eq!(decoder.pop(), None);
+
+    assert_eq!(RegisteredParameter::from_number(u14(6)), RegisteredParameter::MPEConfiguration);
+    assert_eq!(RegisteredParameter::from_number(u14(0x3FFF)).get_number(), u14(0x3FFF));
+    match ParameterEvent::fine_tuning(ch(0), -50.0) {
+        ParameterEvent::RPN { value, .. } => {
+            assert_eq!(value, u14(0x1000));
+        }
+        _ => {
+            assert!(false);
+        }
+    }
+}
+
+#[test]
+fn test_timecode() {
+    let timecode = Timecode::new(FrameRate::Fps25, 1, 0, 0, 0).ok().unwrap();
+    assert_eq!(timecode.get_frame_count(), 90000);
+    assert_eq!(timecode.to_seconds(), 3600.0);
+
+    // Drop frame skips frames 0 and 1 at the start of most minutes
+    assert!(Timecode::new(FrameRate::Fps2997DropFrame, 0, 1, 0, 0).is_err());
+    assert!(Timecode::new(FrameRate::Fps2997DropFrame, 0, 10, 0, 0).is_ok());
+    let timecode = Timecode::new(FrameRate::Fps2997DropFrame, 0, 1, 0, 2).ok().unwrap();
+    assert_eq!(timecode.get_frame_count(), 1800);
+    assert_eq!(Timecode::from_frame_count(FrameRate::Fps2997DropFrame, 1799).frames, 29);
+    for frame_count in [0, 1799, 1800, 17981, 17982, 107892, 2589407].iter() {
+        let timecode = Timecode::from_frame_count(FrameRate::Fps2997DropFrame, *frame_count);
+        assert!(timecode.is_valid());
+        assert_eq!(timecode.get_frame_count(), *frame_count);
+    }
+    // An hour of drop frame timecode is an hour of real time, to within a frame
+    let hour = Timecode::new(FrameRate::Fps2997DropFrame, 1, 0, 0, 0).ok().unwrap();
+    assert!((hour.to_seconds() - 3600.0).abs() < 0.04);
+
+    let timecode = Timecode::from_seconds(FrameRate::Fps24, 61.5);
+    assert_eq!((timecode.minutes, timecode.seconds, timecode.frames), (1, 1, 12));
+
+    // Full frame and quarter frame MTC
+    let timecode = Timecode::new(FrameRate::Fps30, 23, 59, 58, 29).ok().unwrap();
+    let event = TimeCode(timecode);
+    assert_eq!(
+        event.as_bytes().as_slice(),
+        [0xF0, 0x7F, 0x7F, 0x01, 0x01, 0x77, 59, 58, 29, 0xF7]
+    );
+    assert_eq!(MIDIEvent::from_bytes(&mut event.as_bytes(), 0).ok(), Some(event));
+
+    let quarter_frames = timecode.to_quarter_frames();
+    assert_eq!(quarter_frames[1].as_bytes(), vec![0xF1, 0x11]);
+    let mut decoder = QuarterFrameDecoder::new();
+    let mut output = None;
+    for event in quarter_frames.iter() {
+        match MIDIEvent::from_bytes(&mut event.as_bytes(), 0).ok() {
+            Some(MTCQuarterFrame(piece, value)) => {
+                output = decoder.push(piece, value);
+            }
+            _ => {
+                assert!(false);
+            }
+        }
+    }
+    assert_eq!(output, Some(timecode));
+}
+
+#[test]
+fn test_display() {
+    let test_cases = vec![
+        (NoteOn(ch(0), u7(60), u7(100)), "NoteOn ch=1 C4 vel=100"),
+        (NoteOff(ch(15), u7(0), u7(0)), "NoteOff ch=16 C-1 vel=0"),
+        (AfterTouch(ch(2), u7(70), u7(30)), "AfterTouch ch=3 A#4 pressure=30"),
+        (Volume(ch(0), u7(90)), "CC ch=1 #7=90"),
+        (ControlChange(ch(0), u7(3), u7(1)), "CC ch=1 #3=1"),
+        (AllNotesOff(ch(1)), "CC ch=2 #123=0"),
+        (LocalControl(ch(1), true), "CC ch=2 #122=127"),
+        (ProgramChange(ch(9), u7(5)), "ProgramChange ch=10 program=5"),
+        (ChannelPressure(ch(0), u7(40)), "ChannelPressure ch=1 pressure=40"),
+        (PitchWheelChange(ch(0), PitchBend::from_signed(-8192).unwrap()), "PitchBend ch=1 -8192"),
+        (PitchWheelChange(ch(0), PitchBend::CENTER), "PitchBend ch=1 +0"),
+        (SetTempo(Tempo::default()), "Tempo 120bpm"),
+        (SetTempo(Tempo::from_bpm(97.5).unwrap()), "Tempo 97.5bpm"),
+        (TimeSignature(Meter::new(6, 8).unwrap()), "TimeSignature 6/8"),
+        (TimeSignature(Meter::from_raw(4, 2, 48, 8).unwrap()), "TimeSignature 4/4 clocks=48 32nds=8"),
+        (KeySignature("Ebm".parse::<Key>().unwrap()), "KeySignature Ebm"),
+        (TrackName("Piano \"1\"".to_string()), "TrackName \"Piano \\\"1\\\"\""),
+        (SMPTEOffset(Timecode::new(FrameRate::Fps2997DropFrame, 1, 2, 3, 4).unwrap()), "SMPTEOffset 01:02:03;04 29.97fps"),
+        (TimeCode(Timecode::new(FrameRate::Fps25, 0, 0, 10, 0).unwrap()), "TimeCode 00:00:10:00 25fps"),
+        (SystemExclusive(vec![0x7E, 0x7F, 0x09, 0x01]), "SysEx 7E 7F 09 01"),
+        (MTCQuarterFrame(3, 5), "MTCQuarterFrame piece=3 value=5"),
+        (SongPositionPointer(u14(1000)), "SongPosition 1000"),
+        (EndOfTrack, "EndOfTrack")
+    ];
+
+    for (event, text) in test_cases.iter() {
+        assert_eq!(event.to_string(), *text);
+        assert_eq!(text.parse::<MIDIEvent>().ok(), Some(event.clone()));
+    }
+
+    assert_eq!("NoteOn ch=1 Eb3 vel=1".parse::<MIDIEvent>().ok(), Some(NoteOn(ch(0), u7(51), u7(1))));
+    assert_eq!("NoteOn ch=1 60 vel=1".parse::<MIDIEvent>().ok(), Some(NoteOn(ch(0), u7(60), u7(1))));
+    assert_eq!("Tempo 500000us".parse::<MIDIEvent>().ok(), Some(SetTempo(Tempo::default())));
+    assert!("NoteOn ch=0 C4 vel=100".parse::<MIDIEvent>().is_err());
+    assert!("NoteOn ch=1 C4".parse::<MIDIEvent>().is_err());
+    assert!("CC ch=1 #128=0".parse::<MIDIEvent>().is_err());
+    assert!("Unknown".parse::<MIDIEvent>().is_err());
+}
+
+#[test]
+fn test_pitch() {
+    let middle_c = Pitch::new(60).unwrap();
+    assert_eq!(middle_c, Pitch::MIDDLE_C);
+    assert_eq!(middle_c.to_string(), "C4");
+    assert_eq!(middle_c.get_name(&Key::default(), 3), "C3");
+    assert_eq!(Pitch::new(0).unwrap().to_string(), "C-1");
+    assert_eq!(Pitch::new(127).unwrap().to_string(), "G9");
+
+    let test_cases = vec![
+        ("C4", 60),
+        ("c4", 60),
+        ("Eb3", 51),
+        ("D#3", 51),
+        ("B#3", 60),
+        ("Cb4", 59),
+        ("Fx4", 67),
+        ("Bbb3", 57),
+        ("A♭4", 68),
+        ("C-1", 0),
+        ("72", 72)
+    ];
+    for (name, note) in test_cases.iter() {
+        assert_eq!(name.parse::<Pitch>().ok(), Some(Pitch::new(*note).unwrap()));
+    }
+    assert_eq!(Pitch::from_name("C5", 5).ok(), Some(middle_c));
+    assert!("H4".parse::<Pitch>().is_err());
+    assert!("G#9".parse::<Pitch>().is_err());
+    assert!("C4#".parse::<Pitch>().is_err());
+
+    // Spelling follows the key
+    let key_cases = vec![
+        ("F", 70, "Bb4"),
+        ("B", 70, "A#4"),
+        ("C#", 72, "B#4"),
+        ("Gb", 71, "Cb5"),
+        ("C", 70, "A#4"),
+        ("F", 66, "Gb4"),
+        ("Dm", 73, "C#5"),
+        ("A#m", 69, "Gx4"),
+        ("D#", 63, "Eb4")
+    ];
+    for (key, note, name) in key_cases.iter() {
+        let key = key.parse::<Key>().unwrap();
+        assert_eq!(Pitch::new(*note).unwrap().get_name(&key, 4), *name);
+    }
+
+    let tuning = Tuning::default();
+    assert!((Pitch::A4.get_frequency(&tuning) - 440_f64).abs() < 0.0001);
+    assert!((middle_c.get_frequency(&tuning) - 261.6256).abs() < 0.001);
+    assert!((Pitch::A4.get_frequency(&Tuning::new(415_f64)) - 415_f64).abs() < 0.0001);
+
+    let (pitch, cents) = Pitch::from_frequency(445_f64, &tuning).ok().unwrap();
+    assert_eq!(pitch, Pitch::A4);
+    assert!((cents - 19.56).abs() < 0.01);
+    assert!(Pitch::from_frequency(0_f64, &tuning).is_err());
+    assert!(Pitch::from_frequency(20000_f64, &tuning).is_err());
+
+    let mut offsets = [0_f64; 12];
+    offsets[9] = -50_f64;
+    let tuning = Tuning::new(440_f64).with_offsets(offsets);
+    let (pitch, cents) = Pitch::from_frequency(440_f64 * 2_f64.powf(-0.5 / 12_f64), &tuning).ok().unwrap();
+    assert_eq!(pitch, Pitch::A4);
+    assert!(cents.abs() < 0.0001);
+
+    let event = NoteOn(ch(0), "Eb3".parse::<Pitch>().unwrap().into(), u7(100));
+    assert_eq!(event, NoteOn(ch(0), u7(51), u7(100)));
+}
+
+#[test]
+fn test_general_midi_names() {
+    use general_midi::*;
+
+    assert_eq!(get_program_name(u7(0)), "Acoustic Grand Piano");
+    assert_eq!(get_program_name(u7(24)), "Acoustic Guitar (nylon)");
+    assert_eq!(get_program_name(u7(127)), "Gunshot");
+    assert_eq!(ProgramFamily::from_program(u7(24)), ProgramFamily::Guitar);
+    assert_eq!(ProgramFamily::from_program(u7(127)).to_string(), "Sound Effects");
+    assert_eq!(get_gm2_program_name(u7(25), u7(2)), Some("Mandolin"));
+    assert_eq!(get_gm2_program_name(u7(25), u7(0)), Some("Acoustic Guitar (steel)"));
+    assert_eq!(get_gm2_program_name(u7(25), u7(9)), None);
+    assert_eq!(get_drum_kit_name(u7(40)), Some("Brush Set"));
+    assert_eq!(get_drum_kit_name(u7(1)), None);
+    assert_eq!(get_percussion_name(u7(26)), None);
+    assert_eq!(get_percussion_name(u7(27)), Some("High Q"));
+    assert_eq!(get_percussion_name(u7(36)), Some("Bass Drum 1"));
+    assert_eq!(get_percussion_name(u7(81)), Some("Open Triangle"));
+    assert_eq!(get_percussion_name(u7(87)), Some("Open Surdo"));
+    assert_eq!(get_percussion_name(u7(88)), None);
+    assert_eq!(get_controller_name(u7(7)), Some("Channel Volume"));
+    assert_eq!(get_controller_name(u7(3)), None);
+
+    assert_eq!(ProgramChange(ch(0), u7(24)).get_general_midi_name(), Some("Acoustic Guitar (nylon)"));
+    assert_eq!(ProgramChange(DRUM_CHANNEL, u7(0)).get_general_midi_name(), Some("Standard Set"));
+    assert_eq!(NoteOn(DRUM_CHANNEL, u7(38), u7(100)).get_general_midi_name(), Some("Acoustic Snare"));
+    assert_eq!(NoteOn(ch(0), u7(38), u7(100)).get_general_midi_name(), None);
+    assert_eq!(BankSelect(ch(0), u7(0x79)).get_general_midi_name(), Some("Bank Select"));
+    assert_eq!(HoldPedal(ch(0), u7(0x7F)).get_general_midi_name(), Some("Sustain"));
+
+    let mut state = ChannelState::new(ch(0));
+    assert_eq!(state.get_instrument_name(), None);
+    state.apply(&ProgramChange(ch(0), u7(25)));
+    assert_eq!(state.get_instrument_name(), Some("Acoustic Guitar (steel)"));
+    state.apply(&BankSelect(ch(0), GM2_MELODIC_BANK));
+    state.apply(&BankSelectLSB(ch(0), u7(1)));
+    assert_eq!(state.get_instrument_name(), Some("12-Strings Guitar"));
+    state.apply(&BankSelectLSB(ch(0), u7(9)));
+    assert_eq!(state.get_instrument_name(), Some("Acoustic Guitar (steel)"));
+    assert_eq!(state.get_percussion_name(u7(38)), None);
+    state.apply(&BankSelect(ch(0), GM2_RHYTHM_BANK));
+    state.apply(&ProgramChange(ch(0), u7(32)));
+    assert_eq!(state.get_instrument_name(), Some("Jazz Set"));
+    assert_eq!(state.get_percussion_name(u7(38)), Some("Acoustic Snare"));
+
+    let drums = ChannelState::new(DRUM_CHANNEL);
+    assert!(drums.is_percussion());
+    assert_eq!(drums.get_instrument_name(), Some("Standard Set"));
+}
+
+#[test]
+fn test_universal_sysex() {
+    let test_cases = vec![
+        (UniversalSysEx::GMSystemOn { device_id: sysex::ALL_DEVICES }, vec![0x7E, 0x7F, 0x09, 0x01]),
+        (UniversalSysEx::GMSystemOff { device_id: u7(0x10) }, vec![0x7E, 0x10, 0x09, 0x02]),
+        (UniversalSysEx::GM2SystemOn { device_id: u7(0x7F) }, vec![0x7E, 0x7F, 0x09, 0x03]),
+        (UniversalSysEx::IdentityRequest { device_id: u7(0x7F) }, vec![0x7E, 0x7F, 0x06, 0x01]),
+        (
+            UniversalSysEx::IdentityReply {
+                device_id: u7(0x01),
+                manufacturer: ManufacturerId::Short(0x43),
+                family: u14(0x0141),
+                member: u14(0x0002),
+                version: [0x00, 0x01, 0x02, 0x03]
+            },
+            vec![0x7E, 0x01, 0x06, 0x02, 0x43, 0x41, 0x02, 0x02, 0x00, 0x00, 0x01, 0x02, 0x03]
+        ),
+        (
+            UniversalSysEx::IdentityReply {
+                device_id: u7(0x01),
+                manufacturer: ManufacturerId::Extended(0x20, 0x29),
+                family: u14(0),
+                member: u14(0),
+                version: [0; 4]
+            },
+            vec![0x7E, 0x01, 0x06, 0x02, 0x00, 0x20, 0x29, 0, 0, 0, 0, 0, 0, 0, 0]
+        ),
+        (UniversalSysEx::MasterVolume { device_id: u7(0x7F), volume: u14(0x3FFF) }, vec![0x7F, 0x7F, 0x04, 0x01, 0x7F, 0x7F]),
+        (UniversalSysEx::MasterBalance { device_id: u7(0x7F), balance: u14(0x2000) }, vec![0x7F, 0x7F, 0x04, 0x02, 0x00, 0x40]),
+        (UniversalSysEx::MasterFineTuning { device_id: u7(0x7F), value: u14(0x2001) }, vec![0x7F, 0x7F, 0x04, 0x03, 0x01, 0x40]),
+        (UniversalSysEx::MasterCoarseTuning { device_id: u7(0x7F), semitones: -12 }, vec![0x7F, 0x7F, 0x04, 0x04, 0x00, 0x34]),
+        (UniversalSysEx::MachineControl { device_id: u7(0x7F), command: MachineControlCommand::Play }, vec![0x7F, 0x7F, 0x06, 0x02]),
+        (
+            UniversalSysEx::MachineControl {
+                device_id: u7(0x7F),
+                command: MachineControlCommand::Locate(Timecode::new(FrameRate::Fps30, 1, 0, 0, 0).unwrap())
+            },
+            vec![0x7F, 0x7F, 0x06, 0x44, 0x06, 0x01, 0x61, 0x00, 0x00, 0x00, 0x00]
+        )
+    ];
+
+    for (message, bytes) in test_cases.iter() {
+        assert_eq!(message.as_bytes(), *bytes);
+        assert_eq!(UniversalSysEx::from_bytes(bytes).ok(), Some(message.clone()));
+
+        let event: MIDIEvent = message.clone().into();
+        assert_eq!(event, SystemExclusive(bytes.clone()));
+        assert_eq!(UniversalSysEx::try_from(event).ok(), Some(message.clone()));
+    }
+
+    // Full frame timecode becomes a TimeCode event
+    let timecode = Timecode::new(FrameRate::Fps25, 1, 2, 3, 4).unwrap();
+    let event: MIDIEvent = UniversalSysEx::TimeCode { device_id: u7(0x7F), timecode }.into();
+    assert_eq!(event, TimeCode(timecode));
+    assert_eq!(UniversalSysEx::try_from(event).ok(), Some(UniversalSysEx::TimeCode { device_id: u7(0x7F), timecode }));
+
+    // Sent to a single device, it stays system exclusive so the device id isn't lost
+    let message = UniversalSysEx::TimeCode { device_id: u7(0x10), timecode };
+    let event: MIDIEvent = message.clone().into();
+    assert_eq!(event, SystemExclusive(message.as_bytes()));
+    assert_eq!(MIDIEvent::from_bytes(&mut event.as_bytes(), 0).ok(), Some(event.clone()));
+    assert_eq!(UniversalSysEx::try_from(event).ok(), Some(message));
+
+    assert!(UniversalSysEx::from_bytes(&[0x43, 0x10, 0x4C, 0x00]).is_err());
+    assert!(UniversalSysEx::from_bytes(&[0x7E, 0x7F, 0x09]).is_err());
+    assert!(UniversalSysEx::try_from(SystemExclusive(vec![0x41, 0x10, 0x42, 0x12])).is_err());
+
+    assert_eq!(ManufacturerId::from_bytes(&[0x41, 0x10]).ok(), Some((ManufacturerId::Short(0x41), 1)));
+    assert_eq!(ManufacturerId::from_bytes(&[0x00, 0x20, 0x29, 0x01]).ok(), Some((ManufacturerId::Extended(0x20, 0x29), 3)));
+    assert!(ManufacturerId::from_bytes(&[0x00, 0x20]).is_err());
+    assert_eq!(SysEx(vec![0x7D, 0x01]).get_manufacturer_id(), Some(ManufacturerId::NonCommercial));
+}
+
+#[test]
+fn test_gs() {
+    let test_cases = [
+        (GSParameter::Reset, vec![0x41, 0x10, 0x42, 0x12, 0x40, 0x00, 0x7F, 0x00, 0x41]),
+        (GSParameter::ReverbMacro(GSReverbType::Hall2), vec![0x41, 0x10, 0x42, 0x12, 0x40, 0x01, 0x30, 0x04, 0x0B]),
+        (GSParameter::ChorusMacro(GSChorusType::Chorus3), vec![0x41, 0x10, 0x42, 0x12, 0x40, 0x01, 0x38, 0x02, 0x05]),
+        (
+            GSParameter::Part { part: 9, parameter: GSPartParameter::Level, value: u7(100) },
+            vec![0x41, 0x10, 0x42, 0x12, 0x40, 0x10, 0x19, 0x64, 0x33]
+        ),
+        (
+            GSParameter::Part { part: 0, parameter: GSPartParameter::Pan, value: u7(0x40) },
+            vec![0x41, 0x10, 0x42, 0x12, 0x40, 0x11, 0x1C, 0x40, 0x53]
+        )
+    ];
+
+    for (parameter, bytes) in test_cases.iter() {
+        let message = GSMessage::from_parameter(u7(0x10), *parameter).unwrap();
+        assert_eq!(message.as_bytes(), *bytes);
+        assert_eq!(message.get_parameter(), Some(*parameter));
+
+        let event: MIDIEvent = message.clone().into();
+        assert_eq!(GSMessage::try_from(event).ok(), Some(message));
+    }
+
+    let request = GSMessage::DataRequest { device_id: u7(0x10), address: [0x40, 0x01, 0x30], size: 1 };
+    assert_eq!(request.as_bytes(), vec![0x41, 0x10, 0x42, 0x11, 0x40, 0x01, 0x30, 0x00, 0x00, 0x01, 0x0E]);
+    assert_eq!(GSMessage::from_bytes(&request.as_bytes()).ok(), Some(request));
+
+    // Bad checksum
+    assert!(GSMessage::from_bytes(&[0x41, 0x10, 0x42, 0x12, 0x40, 0x00, 0x7F, 0x00, 0x40]).is_err());
+    assert!(GSMessage::from_parameter(u7(0x10), GSParameter::Part { part: 16, parameter: GSPartParameter::Level, value: u7(0) }).is_err());
+}
+
+#[test]
+fn test_xg() {
+    let test_cases = [
+        (XGParameter::SystemOn, vec![0x43, 0x10, 0x4C, 0x00, 0x00, 0x7E, 0x00]),
+        (XGParameter::ReverbType(XGEffectType::HALL1), vec![0x43, 0x10, 0x4C, 0x02, 0x01, 0x00, 0x01, 0x00]),
+        (XGParameter::ChorusType(XGEffectType::FLANGER1), vec![0x43, 0x10, 0x4C, 0x02, 0x01, 0x20, 0x43, 0x00]),
+        (
+            XGParameter::Part { part: 9, parameter: XGPartParameter::ReverbSendLevel, value: u7(0x28) },
+            vec![0x43, 0x10, 0x4C, 0x08, 0x09, 0x13, 0x28]
+        )
+    ];
+
+    for (parameter, bytes) in test_cases.iter() {
+        let message = XGMessage::from_parameter(0, *parameter).unwrap();
+        assert_eq!(message.as_bytes(), *bytes);
+        assert_eq!(message.get_parameter(), Some(*parameter));
+
+        let event: MIDIEvent = message.clone().into();
+        assert_eq!(XGMessage::try_from(event).ok(), Some(message));
+    }
+
+    let request = XGMessage::ParameterRequest { device_number: 1, address: [0x02, 0x01, 0x00] };
+    assert_eq!(request.as_bytes(), vec![0x43, 0x31, 0x4C, 0x02, 0x01, 0x00]);
+    assert_eq!(XGMessage::from_bytes(&request.as_bytes()).ok(), Some(request));
+
+    assert!(XGMessage::from_bytes(&[0x41, 0x10, 0x42, 0x12, 0x40, 0x00, 0x7F, 0x00, 0x41]).is_err());
+    assert!(XGMessage::try_from(SystemExclusive(vec![0x7E, 0x7F, 0x09, 0x01])).is_err());
+}
+
+#[test]
+fn test_mts() {
+    let frequency = MTSFrequency::from_note_number(60.5).unwrap();
+    assert_eq!(frequency.as_bytes(), [60, 0x40, 0x00]);
+    assert_eq!(MTSFrequency::from_frequency(440_f64).unwrap().as_bytes(), [69, 0x00, 0x00]);
+    assert!(MTSFrequency::from_note_number(-1_f64).is_err());
+    assert!(MTSFrequency::from_note_number(128_f64).is_err());
+
+    let test_cases = [
+        (MTSMessage::BulkDumpRequest { device_id: u7(0x7F), program: u7(3) }, vec![0x7E, 0x7F, 0x08, 0x00, 0x03]),
+        (
+            MTSMessage::SingleNoteTuningChange {
+                device_id: u7(0x7F),
+                program: u7(0),
+                changes: vec![(u7(69), MTSFrequency::from_note_number(69_f64).unwrap()), (u7(60), frequency)]
+            },
+            vec![0x7F, 0x7F, 0x08, 0x02, 0x00, 0x02, 0x45, 0x45, 0x00, 0x00, 0x3C, 0x3C, 0x40, 0x00]
+        ),
+        (
+            MTSMessage::SingleNoteTuningChangeBank {
+                device_id: u7(0x10),
+                realtime: false,
+                bank: u7(1),
+                program: u7(2),
+                changes: vec![(u7(60), frequency)]
+            },
+            vec![0x7E, 0x10, 0x08, 0x07, 0x01, 0x02, 0x01, 0x3C, 0x3C, 0x40, 0x00]
+        ),
+        (
+            MTSMessage::ScaleOctaveTuning {
+                device_id: u7(0x7F),
+                realtime: true,
+                high_resolution: false,
+                channels: 0xFFFF,
+                offsets: [0_f64, -14_f64, 4_f64, 16_f64, -14_f64, -2_f64, -10_f64, 2_f64, 14_f64, -16_f64, 18_f64, -12_f64]
+            },
+            vec![0x7F, 0x7F, 0x08, 0x08, 0x03, 0x7F, 0x7F, 0x40, 0x32, 0x44, 0x50, 0x32, 0x3E, 0x36, 0x42, 0x4E, 0x30, 0x52, 0x34]
+        ),
+        (
+            MTSMessage::ScaleOctaveTuning {
+                device_id: u7(0x7F),
+                realtime: false,
+                high_resolution: true,
+                channels: 0x0001,
+                offsets: [-100_f64, 0_f64, 50_f64, 0_f64, 0_f64, 0_f64, 0_f64, 0_f64, 0_f64, 0_f64, 0_f64, 0_f64]
+            },
+            vec![
+                0x7E, 0x7F, 0x08, 0x09, 0x00, 0x00, 0x01,
+                0x00, 0x00, 0x40, 0x00, 0x60, 0x00, 0x40, 0x00, 0x40, 0x00, 0x40, 0x00,
+                0x40, 0x00, 0x40, 0x00, 0x40, 0x00, 0x40, 0x00, 0x40, 0x00, 0x40, 0x00
+            ]
+        )
+    ];
+
+    for (message, bytes) in test_cases.iter() {
+        assert_eq!(message.as_bytes(), *bytes);
+        assert_eq!(MTSMessage::from_bytes(bytes).ok(), Some(message.clone()));
+
+        let event: MIDIEvent = message.clone().into();
+        assert_eq!(MTSMessage::try_from(event).ok(), Some(message.clone()));
+    }
+
+    let dump = KeyboardTuning::default().as_bulk_dump(u7(0x7F), u7(0), "Equal").unwrap();
+    let mut bytes = dump.as_bytes();
+    assert_eq!(bytes.len(), 406);
+    assert_eq!(MTSMessage::from_bytes(&bytes).ok(), Some(dump.clone()));
+    bytes[200] ^= 0x01;
+    assert!(MTSMessage::from_bytes(&bytes).is_err());
+
+    let mut tuning = KeyboardTuning::new();
+    tuning.apply_mts(&dump);
+    assert!((tuning.get_frequency(u7(69)).unwrap() - 440_f64).abs() < 0.001);
+    assert_eq!(KeyboardTuning::default().as_single_note_tuning_changes(u7(0x7F), u7(0)).unwrap().len(), 2);
+}
+
+#[test]
+fn test_scala() {
+    // 12 tone equal temperament matches the default tuning
+    let mut scl = String::from("! 12edo.scl\n!\n12 tone equal temperament\n 12\n!\n");
+    for i in 1..=12 {
+        scl.push_str(&format!(" {}.0\n", i * 100));
+    }
+    let scale: ScalaScale = scl.parse().unwrap();
+    assert_eq!(scale.description, "12 tone equal temperament");
+    let equal = scale.get_tuning(&KeyboardMapping::default()).unwrap();
+    let default_tuning = KeyboardTuning::default();
+    for note in 0..128 {
+        let difference = equal.get_frequency(u7(note)).unwrap() - default_tuning.get_frequency(u7(note)).unwrap();
+        assert!(difference.abs() < 0.000001);
+    }
+
+    // A just major triad on the white keys C, E and G
+    let scale: ScalaScale = "Just triad\n3\n5/4\n3/2 fifth\n2\n".parse().unwrap();
+    assert_eq!(scale.len(), 3);
+    assert!((scale.get_degree_cents(-1) - -498.045).abs() < 0.001);
+    let mapping: KeyboardMapping = "! triad.kbm\n12\n0\n127\n60\n60\n261.625565\n3\n0\nx\nx\nx\n1\nx\nx\n2\nx\nx\nx\nx\n".parse().unwrap();
+    let just = scale.get_tuning(&mapping).unwrap();
+    assert!((just.get_frequency(u7(64)).unwrap() - 261.625565 * 1.25).abs() < 0.0001);
+    assert!((just.get_frequency(u7(67)).unwrap() - 261.625565 * 1.5).abs() < 0.0001);
+    assert!((just.get_frequency(u7(72)).unwrap() - 261.625565 * 2_f64).abs() < 0.0001);
+    assert!((just.get_frequency(u7(48)).unwrap() - 261.625565 / 2_f64).abs() < 0.0001);
+    assert_eq!(just.get_frequency(u7(61)), None);
+
+    assert!("Bad\n2\n5/4\n".parse::<ScalaScale>().is_err());
+    assert!("Bad\n1\n-3/2\n".parse::<ScalaScale>().is_err());
+    assert!("0\n0\n127\n60\n200\n440\n0\n".parse::<KeyboardMapping>().is_err());
+}
+
+#[test]
+fn test_retune_with_pitch_bend() {
+    let scale: ScalaScale = "Just triad\n3\n5/4\n3/2 fifth\n2\n".parse().unwrap();
+    let mapping: KeyboardMapping = "12\n0\n127\n60\n60\n261.625565\n3\n0\nx\nx\nx\n1\nx\nx\n2\nx\nx\nx\nx\n".parse().unwrap();
+    let just = scale.get_tuning(&mapping).unwrap();
+
+    // Retuned notes needing different bends go to different channels
+    let mut midi = MIDI::new();
+    midi.insert_event(0, 0, NoteOn(ch(0), u7(60), u7(100))).unwrap();
+    midi.insert_event(0, 0, NoteOn(ch(0), u7(64), u7(100))).unwrap();
+    midi.insert_event(0, 0, ProgramChange(ch(0), u7(5))).unwrap();
+    midi.insert_event(0, 10, NoteOff(ch(0), u7(60), u7(0))).unwrap();
+    midi.insert_event(0, 10, NoteOn(ch(0), u7(64), u7(0))).unwrap();
+    midi.insert_event(0, 10, NoteOn(ch(9), u7(36), u7(0))).unwrap();
+    midi.retune_with_pitch_bend(&just, &[ch(0), ch(1)], u7(2)).unwrap();
+
+    let mut events = Vec::new();
+    let mut tick = 0;
+    for (wait, event_id) in midi.get_tracks()[0].iter() {
+        tick += wait;
+        events.push((tick, midi.get_event(*event_id).unwrap()));
+    }
+
+    let flat = PitchBend::from_semitones(12_f64 * 1.25_f64.log2() - 4_f64, 2_f64);
+    assert_eq!(
+        events,
+        vec![
+            (0, PitchWheelChange(ch(0), PitchBend::CENTER)),
+            (0, NoteOn(ch(0), u7(60), u7(100))),
+            (0, PitchWheelChange(ch(1), flat)),
+            (0, NoteOn(ch(1), u7(64), u7(100))),
+            (0, ProgramChange(ch(0), u7(5))),
+            (0, ProgramChange(ch(1), u7(5))),
+            (10, NoteOff(ch(0), u7(60), u7(0))),
+            (10, NoteOn(ch(1), u7(64), u7(0))),
+            (10, NoteOn(ch(9), u7(36), u7(0)))
+        ]
+    );
+}
+
+#[test]
+fn test_mpe_configuration() {
+    let mut configuration = MPEConfiguration::default();
+    configuration.apply(ch(0), 7);
+    configuration.apply(ch(15), 10);
+    assert_eq!(configuration.lower.unwrap().get_member_count(), 4);
+    assert_eq!(configuration.upper.unwrap().get_member_channels()[..3], [ch(14), ch(13), ch(12)]);
+    assert!(configuration.is_member(ch(5)));
+    assert!(!configuration.is_member(ch(0)));
+    configuration.apply(ch(0), 15);
+    assert_eq!(configuration.upper, None);
+    configuration.apply(ch(0), 0);
+    assert_eq!(configuration, MPEConfiguration::default());
+    assert!(MPEZone::lower(0).is_err());
+}
+
+#[test]
+fn test_mpe_decoder() {
+    let mut decoder = MPEDecoder::new(MPEConfiguration::default());
+    for event in ParameterEvent::mpe_configuration(ch(0), u7(15)).to_events() {
+        decoder.push(event);
+    }
+    for event in ParameterEvent::pitch_bend_sensitivity(ch(1), u7(24), u7(0)).to_events() {
+        decoder.push(event);
+    }
+    while decoder.pop().is_some() {}
+    assert_eq!(decoder.get_configuration().lower.unwrap().pitch_bend_range, 24_f64);
+
+    assert!(decoder.feed(PitchWheelChange(ch(1), PitchBend::from_semitones(12_f64, 24_f64))).is_empty());
+    assert!(decoder.feed(ChannelPressure(ch(1), u7(10))).is_empty());
+    assert!(decoder.feed(build_control_change(ch(1), u7(74), u7(80))).is_empty());
+    match decoder.feed(NoteOn(ch(1), u7(60), u7(100))).as_slice() {
+        [MPEEvent::NoteOn { id: 0, channel, note, velocity, pitch_bend, pressure, timbre }] => {
+            assert_eq!((*channel, *note, *velocity, *pressure, *timbre), (ch(1), u7(60), u7(100), u7(10), u7(80)));
+            assert!((pitch_bend - 12_f64).abs() < 0.01);
+        }
+        events => panic!("{:?}", events)
+    }
+    assert_eq!(
+        decoder.feed(NoteOn(ch(2), u7(64), u7(90))),
+        vec![MPEEvent::NoteOn { id: 1, channel: ch(2), note: u7(64), velocity: u7(90), pitch_bend: 0_f64, pressure: u7(0), timbre: u7(64) }]
+    );
+    assert_eq!(decoder.feed(ChannelPressure(ch(2), u7(50))), vec![MPEEvent::Pressure { id: 1, pressure: u7(50) }]);
+    assert_eq!(decoder.feed(NoteOn(ch(0), u7(30), u7(90))), vec![MPEEvent::Event(NoteOn(ch(0), u7(30), u7(90)))]);
+    assert_eq!(decoder.feed(NoteOn(ch(1), u7(60), u7(0))), vec![MPEEvent::NoteOff { id: 0, channel: ch(1), note: u7(60), velocity: u7(0) }]);
+    assert_eq!(decoder.get_active_notes(), vec![(1, ch(2), u7(64))]);
+}
+
+#[test]
+fn test_mpe_encoder() {
+    // Notes go to the channel released longest ago, and share once every channel is busy
+    let mut encoder = MPEEncoder::new(MPEZone::lower(2).unwrap());
+    let (first, events) = encoder.note_on(u7(60), u7(100), 0.5, u7(0), u7(64));
+    assert_eq!(
+        events,
+        vec![
+            PitchWheelChange(ch(1), PitchBend::from_semitones(0.5, 48_f64)),
+            ChannelPressure(ch(1), u7(0)),
+            build_control_change(ch(1), u7(74), u7(64)),
+            NoteOn(ch(1), u7(60), u7(100))
+        ]
+    );
+    let (second, _) = encoder.note_on(u7(62), u7(100), 0_f64, u7(0), u7(64));
+    assert_eq!(encoder.get_channel(second), Some(ch(2)));
+    assert_eq!(encoder.note_off(first, u7(0)).unwrap(), NoteOff(ch(1), u7(60), u7(0)));
+    let (third, _) = encoder.note_on(u7(64), u7(100), 0_f64, u7(0), u7(64));
+    assert_eq!(encoder.get_channel(third), Some(ch(1)));
+    let (fourth, _) = encoder.note_on(u7(65), u7(100), 0_f64, u7(0), u7(64));
+    assert_eq!(encoder.get_channel(fourth), Some(ch(2)));
+    assert_eq!(encoder.pressure(third, u7(70)).unwrap(), ChannelPressure(ch(1), u7(70)));
+    assert!(encoder.note_off(first, u7(0)).is_err());
+}
+
+#[test]
+fn test_mpe_notes() {
+    // Notes written with an encoder read back the same
+    let zone = MPEZone::lower(15).unwrap();
+    let notes = vec![
+        MPENote {
+            channel: ch(1),
+            note: u7(60),
+            velocity: u7(100),
+            release_velocity: u7(0),
+            start: 0,
+            end: 96,
+            pitch_bend: vec![(0, 0_f64), (48, 12_f64)],
+            pressure: vec![(0, u7(20)), (24, u7(60))],
+            timbre: vec![(0, u7(64))]
+        },
+        MPENote {
+            channel: ch(2),
+            note: u7(64),
+            velocity: u7(90),
+            release_velocity: u7(30),
+            start: 48,
+            end: 144,
+            pitch_bend: vec![(48, -1_f64)],
+            pressure: vec![(48, u7(0))],
+            timbre: vec![(48, u7(100)), (96, u7(30))]
+        }
+    ];
+
+    let mut midi = MIDI::new();
+    for event in zone.get_configuration_events() {
+        midi.insert_event(0, 0, event).unwrap();
+    }
+    midi.insert_mpe_notes(0, &zone, &notes).unwrap();
+
+    let extracted = midi.get_mpe_notes(MPEConfiguration::default());
+    assert_eq!(extracted.len(), 2);
+    for (extracted, note) in extracted.iter().zip(notes.iter()) {
+        assert_eq!(
+            (extracted.channel, extracted.note, extracted.velocity, extracted.release_velocity, extracted.start, extracted.end),
+            (note.channel, note.note, note.velocity, note.release_velocity, note.start, note.end)
+        );
+        assert_eq!(extracted.pressure, note.pressure);
+        assert_eq!(extracted.timbre, note.timbre);
+        assert_eq!(extracted.pitch_bend.len(), note.pitch_bend.len());
+        for ((tick, semitones), (expected_tick, expected)) in extracted.pitch_bend.iter().zip(note.pitch_bend.iter()) {
+            assert_eq!(tick, expected_tick);
+            assert!((semitones - expected).abs() < 0.01);
+        }
+    }
+}
+
+#[test]
+fn test_ump_packets() {
+    use crate::ump::scale_up;
+    assert_eq!(scale_up(0, 7, 32), 0);
+    assert_eq!(scale_up(64, 7, 32), 0x8000_0000);
+    assert_eq!(scale_up(127, 7, 32), 0xFFFF_FFFF);
+    assert_eq!(scale_up(127, 7, 16), 0xFFFF);
+    assert_eq!(scale_up(0x3FFF, 14, 32), 0xFFFF_FFFF);
+
+    let note_on = UMP::from_event(2, &NoteOn(ch(3), u7(60), u7(100)));
+    assert_eq!(note_on[0].as_words(), vec![0x2293_3C64]);
+    assert_eq!(UMP::from_words(&[0x2293_3C64, 0]).unwrap(), (note_on[0].clone(), 1));
+    assert_eq!(UMP::from_words(&[0x1FF8_0000]).unwrap().0, UMP::SystemRealtime { group: 15, message: SystemRealtime::try_from(MIDIClock).unwrap() });
+    let song_position = UMP::SystemCommon { group: 2, message: SystemCommon::try_from(SongPositionPointer(u14(0x1234))).unwrap() };
+    assert_eq!(song_position.as_words(), vec![0x12F2_3424]);
+    assert_eq!(UMP::from_words(&song_position.as_words()).unwrap().0, song_position);
+    assert!(UMP::from_words(&[0x4093_3C00]).is_err());
+
+    let sysex = SystemExclusive((0..14).collect());
+    let packets = UMP::from_event(0, &sysex);
+    assert_eq!(packets.len(), 3);
+    assert_eq!(packets[0].as_words(), vec![0x3016_0001, 0x0203_0405]);
+    let words: Vec<u32> = packets.iter().flat_map(|ump| ump.as_words()).collect();
+    assert_eq!(UMP::parse_words(&words).unwrap(), packets);
+    let mut decoder = UMPDecoder::new();
+    let decoded: Vec<(u8, MIDIEvent)> = packets.iter().flat_map(|ump| decoder.feed(ump)).collect();
+    assert_eq!(decoded, vec![(0, sysex)]);
+}
+
+#[test]
+fn test_ump_translation() {
+    use crate::ump::scale_up;
+    let mut encoder = UMPEncoder::new(1, UMPProtocol::MIDI2);
+    let mut encoded = Vec::new();
+    for event in ParameterEvent::pitch_bend_sensitivity(ch(0), u7(12), u7(0)).to_events() {
+        encoded.extend(encoder.feed(event));
+    }
+    // Sent again once the data entry LSB arrives
+    assert_eq!(encoded.len(), 2);
+    assert_eq!(encoded[0], encoded[1]);
+    encoded.pop();
+    encoded.extend(encoder.feed(BankSelect(ch(0), u7(1))));
+    encoded.extend(encoder.feed(BankSelectLSB(ch(0), u7(2))));
+    encoded.extend(encoder.feed(ProgramChange(ch(0), u7(5))));
+    encoded.extend(encoder.feed(NoteOn(ch(0), u7(60), u7(127))));
+    encoded.extend(encoder.feed(NoteOn(ch(0), u7(60), u7(0))));
+    encoded.extend(encoder.feed(PitchWheelChange(ch(0), PitchBend::CENTER)));
+    assert_eq!(encoded, vec![
+        UMP::MIDI2ChannelVoice { group: 1, channel: ch(0), message: MIDI2ChannelVoice::RegisteredController { bank: u7(0), index: u7(0), value: scale_up(12 << 7, 14, 32) } },
+        UMP::MIDI2ChannelVoice { group: 1, channel: ch(0), message: MIDI2ChannelVoice::ProgramChange { program: u7(5), bank: Some(u14(0x82)) } },
+        UMP::MIDI2ChannelVoice { group: 1, channel: ch(0), message: MIDI2ChannelVoice::NoteOn { note: u7(60), velocity: 0xFFFF, attribute_type: 0, attribute: 0 } },
+        UMP::MIDI2ChannelVoice { group: 1, channel: ch(0), message: MIDI2ChannelVoice::NoteOff { note: u7(60), velocity: 0, attribute_type: 0, attribute: 0 } },
+        UMP::MIDI2ChannelVoice { group: 1, channel: ch(0), message: MIDI2ChannelVoice::PitchBend { value: crate::ump::PITCH_BEND_CENTER } }
+    ]);
+    assert_eq!(encoded[1].as_words(), vec![0x41C0_0001, 0x0500_0102]);
+    for ump in encoded.iter() {
+        assert_eq!(UMP::from_words(&ump.as_words()).unwrap().0, *ump);
+    }
+
+    let mut decoder = UMPDecoder::new();
+    let decoded: Vec<MIDIEvent> = encoded.iter().flat_map(|ump| decoder.feed(ump)).map(|(_, event)| event).collect();
+    assert_eq!(decoded[..4], [
+        RegisteredParameterNumber(ch(0), u7(0)),
+        RegisteredParameterNumberLSB(ch(0), u7(0)),
+        DataEntry(ch(0), u7(12)),
+        DataEntryLSB(ch(0), u7(0))
+    ]);
+    assert_eq!(decoded[4..], [
+        BankSelect(ch(0), u7(1)),
+        BankSelectLSB(ch(0), u7(2)),
+        ProgramChange(ch(0), u7(5)),
+        NoteOn(ch(0), u7(60), u7(127)),
+        NoteOff(ch(0), u7(60), u7(0)),
+        PitchWheelChange(ch(0), PitchBend::CENTER)
+    ]);
+
+    let names = StreamMessage::endpoint_name("A Very Long Endpoint Name");
+    assert_eq!(names.len(), 2);
+    for message in names.iter().chain([StreamMessage::StartOfClip].iter()) {
+        let ump = UMP::Stream(message.clone());
+        assert_eq!(UMP::from_words(&ump.as_words()).unwrap().0, ump);
+    }
+    let ump = UMP::SysEx8 { group: 0, status: PacketStatus::Complete, stream_id: 7, data: vec![0xFF, 0x00, 0x80] };
+    assert_eq!(UMP::from_words(&ump.as_words()).unwrap().0, ump);
+
+    // Key signatures carry the tonic's letter instead of a mode
+    let ump = UMP::from_event(0, &KeySignature("Ebm".parse().unwrap())).remove(0);
+    assert_eq!(ump, UMP::FlexData { group: 0, channel: None, message: FlexData::KeySignature { sharps: -6, tonic: Some(Tonic::E) } });
+    assert_eq!(ump.as_words()[1], 0xA500_0000);
+    assert_eq!(UMP::from_words(&ump.as_words()).unwrap().0, ump);
+    let mut decoder = UMPDecoder::new();
+    let message = FlexData::KeySignature { sharps: 0, tonic: Some(Tonic::D) };
+    assert!(decoder.feed(&UMP::FlexData { group: 0, channel: None, message }).is_empty());
+}
+
+#[test]
+fn test_clip() {
+    let mut midi = MIDI::new();
+    midi.set_ppqn(96);
+    midi.insert_event(0, 0, TrackName("A Clip With A Long Name".to_string())).unwrap();
+    midi.insert_event(0, 0, SetTempo(Tempo::from_bpm(90_f64).unwrap())).unwrap();
+    midi.insert_event(0, 0, TimeSignature(Meter::new(6, 8).unwrap())).unwrap();
+    midi.insert_event(0, 0, KeySignature("Ebm".parse().unwrap())).unwrap();
+    midi.insert_event(0, 0, ProgramChange(ch(2), u7(40))).unwrap();
+    midi.insert_event(0, 96, NoteOn(ch(2), u7(64), u7(90))).unwrap();
+    midi.insert_event(0, 120, PitchWheelChange(ch(2), PitchBend::MAX)).unwrap();
+    midi.insert_event(0, 192, NoteOff(ch(2), u7(64), u7(0))).unwrap();
+    midi.insert_event(0, 200, SystemExclusive(vec![0x7E, 0x7F, 0x09, 0x01])).unwrap();
+    midi.insert_event(1, 0, NoteOn(ch(5), u7(1), u7(1))).unwrap();
+
+    for protocol in [UMPProtocol::MIDI1, UMPProtocol::MIDI2].iter() {
+        let clip = midi.get_clip(0, 3, *protocol);
+        assert_eq!(clip.ticks_per_quarter_note, 96);
+        assert_eq!(clip.end, 201);
+        assert!(clip.packets.iter().all(|(_, ump)| ump.get_group() == Some(3)));
+
+        let bytes = clip.as_bytes();
+        assert_eq!(&bytes[..8], b"SMF2CLIP");
+        assert_eq!(MIDIClip::from_bytes(&bytes).unwrap(), clip);
+        assert!(MIDIClip::from_bytes(&bytes[..bytes.len() - 16]).is_err());
+
+        let copy = MIDI::from_clip(&clip);
+        assert_eq!(copy.get_ppqn(), 96);
+        let events: Vec<(usize, MIDIEvent)> = copy.get_tracks()[0].iter()
+            .scan(0, |tick, (wait, id)| {
+                *tick += wait;
+                Some((*tick, copy.get_event(*id).unwrap()))
+            })
+            .collect();
+        assert_eq!(events, vec![
+            (0, TrackName("A Clip With A Long Name".to_string())),
+            (0, SetTempo(Tempo::from_bpm(90_f64).unwrap())),
+            (0, TimeSignature(Meter::new(6, 8).unwrap())),
+            (0, KeySignature("Ebm".parse().unwrap())),
+            (0, ProgramChange(ch(2), u7(40))),
+            (96, NoteOn(ch(2), u7(64), u7(90))),
+            (120, PitchWheelChange(ch(2), PitchBend::MAX)),
+            (192, NoteOff(ch(2), u7(64), u7(0))),
+            (200, SystemExclusive(vec![0x7E, 0x7F, 0x09, 0x01]))
+        ]);
+    }
+
+    // Long waits are split across delta clockstamps, and ticks are rescaled to the MIDI's
+    let mut clip = MIDIClip::new(960);
+    clip.packets.push((0x200000, UMP::from_event(0, &NoteOn(ch(0), u7(60), u7(100))).remove(0)));
+    clip.end = 0x200000;
+    let clip = MIDIClip::from_bytes(&clip.as_bytes()).unwrap();
+    assert_eq!(clip.packets[0].0, 0x200000);
+    let mut midi = MIDI::new();
+    midi.set_ppqn(96);
+    midi.insert_clip(2, &clip).unwrap();
+    assert_eq!(midi.get_tracks()[2], vec![(0x200000 / 10, 1)]);
+}
+
+fn ci_identity() -> CIDeviceIdentity {
+    CIDeviceIdentity {
+        manufacturer: ManufacturerId::Extended(0x21, 0x09),
+        family: u14(0x0102),
+        model: u14(3),
+        version: [1, 0, 0, 0]
+    }
+}
+
+fn ci_discovery() -> CIMessage {
+    CIMessage::new(0x0123_4567, crate::ci::BROADCAST_MUID, CIMessageKind::Discovery {
+        identity: ci_identity(),
+        categories: crate::ci::PROFILE_CONFIGURATION,
+        max_sysex_size: 512,
+        output_path: 0
+    })
+}
+
+#[test]
+fn test_ci_discovery() {
+    let discovery = ci_discovery();
+    let bytes = discovery.as_bytes().unwrap();
+    assert_eq!(bytes[..13], [0x7E, 0x7F, 0x0D, 0x70, 0x02, 0x67, 0x0A, 0x0D, 0x09, 0x7F, 0x7F, 0x7F, 0x7F]);
+    assert_eq!(bytes[13..16], [0x00, 0x21, 0x09]);
+    assert_eq!(CIMessage::from_bytes(&bytes).unwrap(), discovery);
+    assert_eq!(CIMessage::try_from(MIDIEvent::try_from(discovery.clone()).unwrap()).unwrap(), discovery);
+    assert!(CIMessage::from_bytes(&bytes[..20]).is_err());
+
+    // The version a message came in with is kept
+    let mut bytes = bytes;
+    bytes[4] = 0x01;
+    let message = CIMessage::from_bytes(&bytes).unwrap();
+    assert_eq!(message.version, 0x01);
+    assert_eq!(message.as_bytes().unwrap(), bytes);
+
+    let mut discovery = ci_discovery();
+    discovery.source = 0x1000_0000;
+    assert!(discovery.as_bytes().is_err());
+}
+
+#[test]
+fn test_ci_responder() {
+    use crate::ci::BROADCAST_MUID;
+    let discovery = ci_discovery();
+    let profile = ProfileId([0x7E, 0x00, 0x01, 0x00, 0x00]);
+    let mut responder = CIResponder::new(0x42, CIDeviceIdentity { manufacturer: ManufacturerId::Short(0x41), ..ci_identity() });
+    responder.profiles.push((profile, false));
+    match responder.respond(&discovery).as_slice() {
+        [CIMessage { source: 0x42, destination: 0x0123_4567, kind: CIMessageKind::DiscoveryReply { identity, function_block: 0x7F, .. }, .. }] => {
+            assert_eq!(identity.manufacturer, ManufacturerId::Short(0x41));
+        }
+        replies => panic!("{:?}", replies)
+    }
+    assert!(responder.respond(&CIMessage::new(0x0123_4567, 0x43, CIMessageKind::ProfileInquiry)).is_empty());
+
+    let replies = responder.respond(&CIMessage::new(0x0123_4567, 0x42, CIMessageKind::SetProfileOn { profile, channels: u14(0) }));
+    assert_eq!(replies, vec![CIMessage::new(0x42, BROADCAST_MUID, CIMessageKind::ProfileEnabled { profile, channels: u14(0) })]);
+    let replies = responder.respond(&CIMessage::new(0x0123_4567, 0x42, CIMessageKind::ProfileInquiry));
+    assert_eq!(replies[0].kind, CIMessageKind::ProfileInquiryReply { enabled: vec![profile], disabled: Vec::new() });
+    for reply in replies.iter() {
+        assert_eq!(CIMessage::from_bytes(&reply.as_bytes().unwrap()).unwrap(), *reply);
+    }
+}
+
+#[test]
+fn test_ci_property_exchange() {
+    let header = b"{\"resource\":\"DeviceInfo\"}";
+    let data: Vec<u8> = (0..100).collect();
+    let chunks = CIMessage::set_property_data(0x42, 0x0123_4567, 5, header, &data, 40);
+    assert_eq!(chunks.len(), 3);
+    let chunks: Vec<PropertyChunk> = chunks.iter()
+        .map(|message| match CIMessage::from_bytes(&message.as_bytes().unwrap()).unwrap().kind {
+            CIMessageKind::SetPropertyData(chunk) => chunk,
+            kind => panic!("{:?}", kind)
+        })
+        .collect();
+    assert!(chunks[1].header.is_empty());
+    assert!(chunks[2].is_last());
+    assert_eq!(PropertyChunk::join(&chunks).unwrap(), (header.to_vec(), data));
+    assert!(PropertyChunk::join(&chunks[1..]).is_err());
+
+    // Property data isn't masked to 7 bits, and lengths aren't truncated to 14 bits
+    assert!(CIMessage::get_property_data(0x42, 0x0123_4567, 5, &[0x80]).as_bytes().is_err());
+    let data = vec![0; 0x4000];
+    let chunks = CIMessage::get_property_data_reply(0x42, 0x0123_4567, 5, header, &data, data.len());
+    assert!(chunks[0].as_bytes().is_err());
+}
+
+#[test]
+fn test_load_invalid_smpte_offset() {
+    // 25 frames don't fit in a second at 25fps. Only that event is skipped.
+    let midi = load_track(&[
+        0x00, 0xFF, 0x54, 0x05, 0x21, 0x02, 0x03, 0x19, 0x00,
+        0x00, 0x90, 0x40, 0x64,
+        0x00, 0xFF, 0x2F, 0x00
+    ]).unwrap();
+    assert_eq!(get_track_events(&midi, 0), vec![NoteOn(ch(0), u7(0x40), u7(0x64)), EndOfTrack]);
+}
+
+#[test]
+fn test_load_invalid_time_signature() {
+    // A numerator of 0 and a denominator of 2^8 are both skipped
+    let midi = load_track(&[
+        0x00, 0xFF, 0x58, 0x04, 0x00, 0x02, 0x18, 0x08,
+        0x00, 0xFF, 0x58, 0x04, 0x04, 0x08, 0x18, 0x08,
+        0x00, 0xFF, 0x58, 0x04, 0x03, 0x02, 0x18, 0x08,
+        0x00, 0xFF, 0x2F, 0x00
+    ]).unwrap();
+    assert_eq!(get_track_events(&midi, 0), vec![TimeSignature(Meter::new(3, 4).unwrap()), EndOfTrack]);
+}
+
+#[test]
+fn test_load_invalid_channel_prefix() {
+    let midi = load_track(&[
+        0x00, 0xFF, 0x20, 0x01, 0x10,
+        0x00, 0xFF, 0x20, 0x01, 0x0F,
+        0x00, 0xFF, 0x2F, 0x00
+    ]).unwrap();
+    assert_eq!(get_track_events(&midi, 0), vec![ChannelPrefix(ch(15)), EndOfTrack]);
+}
+
+#[test]
+fn test_load_invalid_key_signature() {
+    let midi = load_track(&[
+        0x00, 0xFF, 0x59, 0x02, 0x08, 0x00,
+        0x00, 0xFF, 0x59, 0x02, 0x02, 0x02,
+        0x00, 0xFF, 0x59, 0x02, 0xFE, 0x01,
+        0x00, 0xFF, 0x2F, 0x00
+    ]).unwrap();
+    assert_eq!(get_track_events(&midi, 0), vec![KeySignature("Gm".parse().ok().unwrap()), EndOfTrack]);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde_timecode() {
+    let timecode = Timecode::new(FrameRate::Fps2997DropFrame, 1, 2, 3, 4).unwrap();
+    let json = serde_json::to_string(&timecode).unwrap();
+    assert_eq!(json, "{\"rate\":\"Fps2997DropFrame\",\"hours\":1,\"minutes\":2,\"seconds\":3,\"frames\":4,\"subframes\":0}");
+    assert_eq!(serde_json::from_str::<Timecode>(&json).unwrap(), timecode);
+
+    // 25 frames at 25fps, and a dropped frame label
+    assert!(serde_json::from_str::<Timecode>("{\"rate\":\"Fps25\",\"hours\":1,\"minutes\":0,\"seconds\":0,\"frames\":25,\"subframes\":0}").is_err());
+    assert!(serde_json::from_str::<MIDIEvent>("{\"SMPTEOffset\":{\"rate\":\"Fps2997DropFrame\",\"hours\":0,\"minutes\":1,\"seconds\":0,\"frames\":0,\"subframes\":0}}").is_err());
+}
+
+#[test]
+fn test_pitch_out_of_range() {
+    assert!("C3000".parse::<Pitch>().is_err());
+    assert!("C-3000".parse::<Pitch>().is_err());
+    assert!("C2147483647".parse::<Pitch>().is_err());
+    assert!(Pitch::from_name("C4", i8::MIN).is_err());
+    assert!("NoteOn ch=1 C3000 vel=1".parse::<MIDIEvent>().is_err());
+
+    let sharps = format!("C{}4", "#".repeat(40000));
+    assert!(sharps.parse::<Pitch>().is_err());
+
+    assert_eq!(Pitch::new(127).unwrap().get_octave(i8::MAX), 132);
+    assert_eq!(Pitch::new(0).unwrap().get_octave(i8::MIN), -133);
+}
+
+#[test]
+fn test_malformed_keyboard_mapping() {
+    assert!("1000000000000\n0\n127\n60\n60\n440\n0\n".parse::<KeyboardMapping>().is_err());
+    assert!("129\n0\n127\n60\n60\n440\n0\n0\n".parse::<KeyboardMapping>().is_err());
+    assert!("-1\n0\n127\n60\n60\n440\n0\n".parse::<KeyboardMapping>().is_err());
+    assert!("12\n0\n127\n60\n60\n440\n".parse::<KeyboardMapping>().is_err());
+    assert!("12\n0\n128\n60\n60\n440\n0\n".parse::<KeyboardMapping>().is_err());
+    assert!("2\n0\n127\n60\n60\n440\n0\n0\nfive\n".parse::<KeyboardMapping>().is_err());
+
+    // The largest size, with the entries left out
+    let mapping: KeyboardMapping = "128\n0\n127\n60\n60\n440\n0\n0\n".parse().unwrap();
+    assert_eq!(mapping.mapping.len(), 128);
+    assert_eq!(mapping.mapping[0], Some(0));
+    assert_eq!(mapping.mapping[1], None);
+}
+
+#[test]
+fn test_load_invalid_tempo() {
+    let midi = load_track(&[
+        0x00, 0xFF, 0x51, 0x03, 0x00, 0x00, 0x00,
+        0x00, 0xFF, 0x51, 0x04, 0x01, 0x00, 0x00, 0x00,
+        0x00, 0xFF, 0x51, 0x05, 0x01, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0xFF, 0x51, 0x03, 0x07, 0xA1, 0x20,
+        0x00, 0xFF, 0x2F, 0x00
+    ]).unwrap();
+    assert_eq!(get_track_events(&midi, 0), vec![SetTempo(Tempo::new(500000).unwrap()), EndOfTrack]);
+}
+
+#[test]
+fn test_keys() {
+    assert_eq!(Key::from_sf_mi(-3, 0).ok().unwrap().to_string(), "Eb");
+    assert_eq!(Key::from_sf_mi(7, 1).ok().unwrap().to_string(), "A#m");
+
+    // All 30 keys survive a round trip, through bytes and through names
+    let mut names = HashSet::new();
+    for mi in 0 .. 2 {
+        for sf in -7 ..= 7 {
+            let key = Key::from_sf_mi(sf, mi).ok().unwrap();
+            assert_eq!(key.get_sf_mi(), (sf, mi));
+            assert_eq!(key.to_string().parse::<Key>().ok(), Some(key));
+            names.insert(key.to_string());
+        }
+    }
+    assert_eq!(names.len(), 30);
+
+    let d_sharp: Key = "D# major".parse().ok().unwrap();
+    assert!(!d_sharp.is_representable());
+    assert_eq!(d_sharp.get_enharmonic(), Some("Eb".parse().ok().unwrap()));
+    assert_eq!(d_sharp.get_sf_mi(), (-3, 0));
+    assert_eq!(d_sharp.get_sharps(), 9);
+    assert_eq!(d_sharp.get_pitch_class(), 3);
+    assert_eq!("Cb".parse::<Key>().ok().unwrap().get_sf_mi(), (-7, 0));
+    assert_eq!("G#m".parse::<Key>().ok().unwrap().get_sf_mi(), (5, 1));
+    assert!("H".parse::<Key>().is_err());
+    assert!("Cx".parse::<Key>().is_err());
+}
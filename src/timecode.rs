@@ -0,0 +1,307 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{MIDIEvent, ApresError};
+
+/// SMPTE frame rate, as encoded in bits 5-6 of a timecode's hours byte
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FrameRate {
+    Fps24,
+    Fps25,
+    /// 30000/1001 fps, with frames 0 and 1 skipped at the start of every minute except every tenth
+    Fps2997DropFrame,
+    Fps30
+}
+
+impl FrameRate {
+    pub fn from_bits(bits: u8) -> FrameRate {
+        match bits & 0x03 {
+            0 => FrameRate::Fps24,
+            1 => FrameRate::Fps25,
+            2 => FrameRate::Fps2997DropFrame,
+            _ => FrameRate::Fps30
+        }
+    }
+
+    pub fn get_bits(&self) -> u8 {
+        match self {
+            FrameRate::Fps24 => 0,
+            FrameRate::Fps25 => 1,
+            FrameRate::Fps2997DropFrame => 2,
+            FrameRate::Fps30 => 3
+        }
+    }
+
+    /// Number of frame labels per second (30 for drop-frame)
+    pub fn get_nominal_fps(&self) -> u8 {
+        match self {
+            FrameRate::Fps24 => 24,
+            FrameRate::Fps25 => 25,
+            FrameRate::Fps2997DropFrame | FrameRate::Fps30 => 30
+        }
+    }
+
+    /// Actual number of frames per second
+    pub fn get_fps(&self) -> f64 {
+        match self {
+            FrameRate::Fps2997DropFrame => 30_000_f64 / 1001_f64,
+            _ => self.get_nominal_fps() as f64
+        }
+    }
+}
+
+/// Formats as "24fps", "25fps", "29.97fps" or "30fps"
+impl fmt::Display for FrameRate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            FrameRate::Fps24 => "24fps",
+            FrameRate::Fps25 => "25fps",
+            FrameRate::Fps2997DropFrame => "29.97fps",
+            FrameRate::Fps30 => "30fps"
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for FrameRate {
+    type Err = ApresError;
+    fn from_str(name: &str) -> Result<FrameRate, ApresError> {
+        match name.trim() {
+            "24fps" | "24" => Ok(FrameRate::Fps24),
+            "25fps" | "25" => Ok(FrameRate::Fps25),
+            "29.97fps" | "29.97" | "29.97df" => Ok(FrameRate::Fps2997DropFrame),
+            "30fps" | "30" => Ok(FrameRate::Fps30),
+            _ => Err(ApresError::ParseError(name.to_string()))
+        }
+    }
+}
+
+// Drop-frame constants: frames in a minute that drops 2 labels, and in 10 minutes
+const DROP_FRAME_MINUTE: u64 = 1798;
+const DROP_FRAME_TEN_MINUTES: u64 = 17982;
+
+/// SMPTE timecode, used by SMPTEOffset and MTC messages
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(try_from = "crate::serialization::TimecodeFields"))]
+pub struct Timecode {
+    pub rate: FrameRate,
+    pub hours: u8,
+    pub minutes: u8,
+    pub seconds: u8,
+    pub frames: u8,
+    /// Hundredths of a frame
+    pub subframes: u8
+}
+
+impl Timecode {
+    pub fn new(rate: FrameRate, hours: u8, minutes: u8, seconds: u8, frames: u8) -> Result<Timecode, ApresError> {
+        let timecode = Timecode { rate, hours, minutes, seconds, frames, subframes: 0 };
+        if timecode.is_valid() {
+            Ok(timecode)
+        } else {
+            Err(ApresError::InvalidBytes(vec![timecode.get_hours_byte(), minutes, seconds, frames]))
+        }
+    }
+
+    /// Build from the hours byte (with frame rate bits), minutes, seconds, frames and subframes, as stored in SMPTEOffset
+    pub fn from_bytes(bytes: [u8; 5]) -> Result<Timecode, ApresError> {
+        let timecode = Timecode {
+            rate: FrameRate::from_bits(bytes[0] >> 5),
+            hours: bytes[0] & 0x1F,
+            minutes: bytes[1],
+            seconds: bytes[2],
+            frames: bytes[3],
+            subframes: bytes[4]
+        };
+
+        if timecode.is_valid() {
+            Ok(timecode)
+        } else {
+            Err(ApresError::InvalidBytes(bytes.to_vec()))
+        }
+    }
+
+    /// Get the hours byte, minutes, seconds, frames and subframes, as stored in SMPTEOffset
+    pub fn as_bytes(&self) -> [u8; 5] {
+        [self.get_hours_byte(), self.minutes, self.seconds, self.frames, self.subframes]
+    }
+
+    /// Get the hours with the frame rate encoded in bits 5-6
+    pub fn get_hours_byte(&self) -> u8 {
+        (self.rate.get_bits() << 5) | (self.hours & 0x1F)
+    }
+
+    pub fn is_valid(&self) -> bool {
+        let dropped = self.rate == FrameRate::Fps2997DropFrame
+            && self.seconds == 0
+            && self.frames < 2
+            && self.minutes % 10 != 0;
+
+        self.hours < 24
+            && self.minutes < 60
+            && self.seconds < 60
+            && self.frames < self.rate.get_nominal_fps()
+            && self.subframes < 100
+            && !dropped
+    }
+
+    /// Get the number of whole frames since 00:00:00:00, accounting for dropped frames
+    pub fn get_frame_count(&self) -> u64 {
+        let total_minutes = (self.hours as u64 * 60) + self.minutes as u64;
+        let total_seconds = (total_minutes * 60) + self.seconds as u64;
+        let frames = (total_seconds * self.rate.get_nominal_fps() as u64) + self.frames as u64;
+
+        if self.rate == FrameRate::Fps2997DropFrame {
+            frames - (2 * (total_minutes - (total_minutes / 10)))
+        } else {
+            frames
+        }
+    }
+
+    /// Build from a number of frames since 00:00:00:00, wrapping at 24 hours
+    pub fn from_frame_count(rate: FrameRate, frame_count: u64) -> Timecode {
+        let fps = rate.get_nominal_fps() as u64;
+        let mut frames = frame_count;
+        if rate == FrameRate::Fps2997DropFrame {
+            let ten_minutes = frames / DROP_FRAME_TEN_MINUTES;
+            let remainder = frames % DROP_FRAME_TEN_MINUTES;
+            frames += 18 * ten_minutes;
+            if remainder >= 2 {
+                frames += 2 * ((remainder - 2) / DROP_FRAME_MINUTE);
+            }
+        }
+
+        Timecode {
+            rate,
+            hours: ((frames / (fps * 3600)) % 24) as u8,
+            minutes: ((frames / (fps * 60)) % 60) as u8,
+            seconds: ((frames / fps) % 60) as u8,
+            frames: (frames % fps) as u8,
+            subframes: 0
+        }
+    }
+
+    /// Get the time since 00:00:00:00 in seconds
+    pub fn to_seconds(&self) -> f64 {
+        (self.get_frame_count() as f64 + (self.subframes as f64 / 100_f64)) / self.rate.get_fps()
+    }
+
+    /// Build from a time in seconds, rounded down to the nearest subframe
+    pub fn from_seconds(rate: FrameRate, seconds: f64) -> Timecode {
+        let total_subframes = (seconds.max(0_f64) * rate.get_fps() * 100_f64).floor() as u64;
+        let mut timecode = Timecode::from_frame_count(rate, total_subframes / 100);
+        timecode.subframes = (total_subframes % 100) as u8;
+
+        timecode
+    }
+
+    /// Split into the 8 MTCQuarterFrame messages that transmit this timecode, in order
+    pub fn to_quarter_frames(&self) -> Vec<MIDIEvent> {
+        let hours_byte = self.get_hours_byte();
+        let nibbles = [
+            self.frames & 0x0F,
+            (self.frames >> 4) & 0x01,
+            self.seconds & 0x0F,
+            (self.seconds >> 4) & 0x03,
+            self.minutes & 0x0F,
+            (self.minutes >> 4) & 0x03,
+            hours_byte & 0x0F,
+            (hours_byte >> 4) & 0x07
+        ];
+
+        nibbles.iter().enumerate().map(|(piece, value)| MIDIEvent::MTCQuarterFrame(piece as u8, *value)).collect()
+    }
+}
+
+/// Formats as "hh:mm:ss:ff rate", eg "01:00:00:00 25fps". Drop frame timecodes use ';' before the frames,
+/// and subframes are added after a '.' when there are any.
+impl fmt::Display for Timecode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let separator = if self.rate == FrameRate::Fps2997DropFrame { ';' } else { ':' };
+        write!(f, "{:02}:{:02}:{:02}{}{:02}", self.hours, self.minutes, self.seconds, separator, self.frames)?;
+        if self.subframes != 0 {
+            write!(f, ".{:02}", self.subframes)?;
+        }
+
+        write!(f, " {}", self.rate)
+    }
+}
+
+impl FromStr for Timecode {
+    type Err = ApresError;
+    fn from_str(name: &str) -> Result<Timecode, ApresError> {
+        let parts: Vec<&str> = name.split_whitespace().collect();
+        if parts.len() != 2 {
+            return Err(ApresError::ParseError(name.to_string()));
+        }
+
+        let rate = parts[1].parse::<FrameRate>()?;
+        let (time, subframes) = match parts[0].split_once('.') {
+            Some((time, subframes)) => (time, subframes),
+            None => (parts[0], "0")
+        };
+
+        let mut values = Vec::new();
+        for value in time.split([':', ';']).chain(std::iter::once(subframes)) {
+            match value.parse::<u8>() {
+                Ok(value) => {
+                    values.push(value);
+                }
+                Err(_) => {
+                    return Err(ApresError::ParseError(name.to_string()));
+                }
+            }
+        }
+
+        if values.len() != 5 || values[0] > 0x1F {
+            return Err(ApresError::ParseError(name.to_string()));
+        }
+
+        Timecode::from_bytes([(rate.get_bits() << 5) | values[0], values[1], values[2], values[3], values[4]])
+    }
+}
+
+/// Collects MTCQuarterFrame messages into full timecodes
+#[derive(Clone, Debug, Default)]
+pub struct QuarterFrameDecoder {
+    pieces: [Option<u8>; 8]
+}
+
+impl QuarterFrameDecoder {
+    pub fn new() -> QuarterFrameDecoder {
+        QuarterFrameDecoder::default()
+    }
+
+    /// Pass in a quarter frame's piece number (0-7) and value.
+    /// Returns a timecode once all 8 pieces have been received, ending with piece 7.
+    /// Note that by the time the last piece arrives, the sender has moved on by 2 frames.
+    pub fn push(&mut self, piece: u8, value: u8) -> Option<Timecode> {
+        let piece = (piece & 0x07) as usize;
+        if piece == 0 {
+            self.pieces = [None; 8];
+        }
+        self.pieces[piece] = Some(value & 0x0F);
+
+        if piece != 7 {
+            return None;
+        }
+
+        let mut nibbles = [0; 8];
+        for (i, nibble) in self.pieces.iter().enumerate() {
+            nibbles[i] = (*nibble)?;
+        }
+        self.pieces = [None; 8];
+
+        let hours_byte = nibbles[6] | (nibbles[7] << 4);
+        Some(Timecode {
+            rate: FrameRate::from_bits(hours_byte >> 5),
+            hours: hours_byte & 0x1F,
+            minutes: nibbles[4] | (nibbles[5] << 4),
+            seconds: nibbles[2] | (nibbles[3] << 4),
+            frames: nibbles[0] | (nibbles[1] << 4),
+            subframes: 0
+        })
+    }
+}
@@ -0,0 +1,241 @@
+use std::convert::TryFrom;
+
+use crate::ApresError;
+
+/// MIDI Channel, 0-15
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(try_from = "u8", into = "u8"))]
+pub struct Channel(u8);
+
+/// 7-bit data value, 0-127
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(try_from = "u8", into = "u8"))]
+pub struct U7(u8);
+
+/// 14-bit data value, 0-16383, sent as two 7-bit bytes
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(try_from = "u16", into = "u16"))]
+pub struct U14(u16);
+
+/// Exact 14-bit pitch bend value. 0x2000 is the center (no bend).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(from = "U14", into = "U14"))]
+pub struct PitchBend(U14);
+
+impl Channel {
+    pub const MAX: u8 = 0x0F;
+
+    pub fn new(channel: u8) -> Result<Channel, ApresError> {
+        if channel > Channel::MAX {
+            Err(ApresError::ValueOutOfRange(channel as u32, Channel::MAX as u32))
+        } else {
+            Ok(Channel(channel))
+        }
+    }
+
+    /// Build a Channel from the low nibble of a status byte
+    pub const fn from_status(status_byte: u8) -> Channel {
+        Channel(status_byte & 0x0F)
+    }
+
+    pub fn get(&self) -> u8 {
+        self.0
+    }
+
+    /// Iterate over all 16 channels
+    pub fn all() -> impl Iterator<Item = Channel> {
+        (0..=Channel::MAX).map(Channel)
+    }
+}
+
+impl U7 {
+    pub const MAX: u8 = 0x7F;
+
+    pub fn new(value: u8) -> Result<U7, ApresError> {
+        if value > U7::MAX {
+            Err(ApresError::ValueOutOfRange(value as u32, U7::MAX as u32))
+        } else {
+            Ok(U7(value))
+        }
+    }
+
+    /// Build a U7 from the lowest 7 bits of a byte, ignoring the rest
+    pub const fn new_masked(value: u8) -> U7 {
+        U7(value & U7::MAX)
+    }
+
+    pub fn get(&self) -> u8 {
+        self.0
+    }
+}
+
+impl U14 {
+    pub const MAX: u16 = 0x3FFF;
+
+    pub fn new(value: u16) -> Result<U14, ApresError> {
+        if value > U14::MAX {
+            Err(ApresError::ValueOutOfRange(value as u32, U14::MAX as u32))
+        } else {
+            Ok(U14(value))
+        }
+    }
+
+    /// Build a U14 from the lowest 14 bits of a number, ignoring the rest
+    pub const fn new_masked(value: u16) -> U14 {
+        U14(value & U14::MAX)
+    }
+
+    pub fn from_lsb_msb(lsb: U7, msb: U7) -> U14 {
+        U14(((msb.get() as u16) << 7) | (lsb.get() as u16))
+    }
+
+    pub fn get(&self) -> u16 {
+        self.0
+    }
+
+    pub fn get_lsb(&self) -> U7 {
+        U7((self.0 & 0x7F) as u8)
+    }
+
+    pub fn get_msb(&self) -> U7 {
+        U7((self.0 >> 7) as u8)
+    }
+}
+
+impl PitchBend {
+    pub const CENTER: PitchBend = PitchBend(U14(0x2000));
+    pub const MIN: PitchBend = PitchBend(U14(0));
+    pub const MAX: PitchBend = PitchBend(U14(U14::MAX));
+
+    pub fn new(value: u16) -> Result<PitchBend, ApresError> {
+        Ok(PitchBend(U14::new(value)?))
+    }
+
+    pub fn from_u14(value: U14) -> PitchBend {
+        PitchBend(value)
+    }
+
+    /// Build from a signed offset from center, -8192 to 8191
+    pub fn from_signed(offset: i16) -> Result<PitchBend, ApresError> {
+        if offset < -0x2000 {
+            Err(ApresError::ValueOutOfRange(offset.unsigned_abs() as u32, 0x2000))
+        } else if offset > 0x1FFF {
+            Err(ApresError::ValueOutOfRange(offset as u32, 0x1FFF))
+        } else {
+            Ok(PitchBend(U14((offset + 0x2000) as u16)))
+        }
+    }
+
+    /// Build from a float in [-1, 1]. -1 is the lowest value, 1 the highest and 0 the center.
+    /// Values outside the range are clamped.
+    pub fn from_normalized(value: f64) -> PitchBend {
+        let value = value.clamp(-1_f64, 1_f64);
+        let offset = if value < 0_f64 {
+            (value * 0x2000 as f64).round()
+        } else {
+            (value * 0x1FFF as f64).round()
+        };
+
+        PitchBend(U14((offset as i16 + 0x2000) as u16))
+    }
+
+    /// Build from a bend in semitones, given the receiver's bend range (in semitones) in either direction
+    pub fn from_semitones(semitones: f64, range: f64) -> PitchBend {
+        if range == 0_f64 {
+            PitchBend::CENTER
+        } else {
+            PitchBend::from_normalized(semitones / range)
+        }
+    }
+
+    pub fn get(&self) -> u16 {
+        self.0.get()
+    }
+
+    pub fn get_u14(&self) -> U14 {
+        self.0
+    }
+
+    /// Get the offset from center, -8192 to 8191
+    pub fn get_signed(&self) -> i16 {
+        self.0.get() as i16 - 0x2000
+    }
+
+    /// Get the value as a float in [-1, 1]. Inverse of `from_normalized()`.
+    pub fn get_normalized(&self) -> f64 {
+        let offset = self.get_signed() as f64;
+        if offset < 0_f64 {
+            offset / 0x2000 as f64
+        } else {
+            offset / 0x1FFF as f64
+        }
+    }
+
+    /// Get the bend in semitones, given the receiver's bend range (in semitones) in either direction
+    pub fn get_semitones(&self, range: f64) -> f64 {
+        self.get_normalized() * range
+    }
+}
+
+impl Default for PitchBend {
+    fn default() -> PitchBend {
+        PitchBend::CENTER
+    }
+}
+
+impl TryFrom<u8> for Channel {
+    type Error = ApresError;
+    fn try_from(channel: u8) -> Result<Channel, ApresError> {
+        Channel::new(channel)
+    }
+}
+
+impl TryFrom<u8> for U7 {
+    type Error = ApresError;
+    fn try_from(value: u8) -> Result<U7, ApresError> {
+        U7::new(value)
+    }
+}
+
+impl TryFrom<u16> for U14 {
+    type Error = ApresError;
+    fn try_from(value: u16) -> Result<U14, ApresError> {
+        U14::new(value)
+    }
+}
+
+impl From<Channel> for u8 {
+    fn from(channel: Channel) -> u8 {
+        channel.0
+    }
+}
+
+impl From<U7> for u8 {
+    fn from(value: U7) -> u8 {
+        value.0
+    }
+}
+
+impl From<U7> for U14 {
+    fn from(value: U7) -> U14 {
+        U14(value.0 as u16)
+    }
+}
+
+impl From<U14> for PitchBend {
+    fn from(value: U14) -> PitchBend {
+        PitchBend(value)
+    }
+}
+
+impl From<PitchBend> for U14 {
+    fn from(value: PitchBend) -> U14 {
+        value.0
+    }
+}
+
+impl From<U14> for u16 {
+    fn from(value: U14) -> u16 {
+        value.0
+    }
+}
@@ -0,0 +1,1227 @@
+use std::collections::{HashMap, VecDeque};
+use std::convert::TryFrom;
+
+use crate::{
+    MIDIEvent, MIDIBytes, ApresError, Channel, U7, U14, MSBOnlyMode, ParameterDecoder, ParameterEvent,
+    ChannelMessage, SystemCommon, SystemRealtime, Tempo, Meter, Key, Tonic, build_control_change, build_pitch_wheel_change, build_system_exclusive
+};
+
+const SYSEX7_PACKET_SIZE: usize = 6;
+const SYSEX8_PACKET_SIZE: usize = 13;
+const STREAM_TEXT_SIZE: usize = 14;
+const FUNCTION_BLOCK_NAME_SIZE: usize = 13;
+const FLEX_TEXT_SIZE: usize = 12;
+
+/// Flex Data text banks
+pub const METADATA_TEXT: u8 = 0x01;
+pub const PERFORMANCE_TEXT: u8 = 0x02;
+
+/// Flex Data text kinds, in the metadata and performance text banks
+pub const UNKNOWN_TEXT: u8 = 0x00;
+pub const CLIP_NAME: u8 = 0x03;
+pub const COPYRIGHT_NOTICE: u8 = 0x04;
+pub const LYRICS: u8 = 0x01;
+
+/// MIDI 2.0 pitch bend center
+pub const PITCH_BEND_CENTER: u32 = 0x8000_0000;
+
+/// Which MIDI protocol channel voice messages are sent with
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum UMPProtocol {
+    MIDI1,
+    MIDI2
+}
+
+/// Where a packet falls in a message split across packets (system exclusive and stream text)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PacketStatus {
+    Complete,
+    Start,
+    Continue,
+    End
+}
+
+/// MIDI 2.0 channel voice message. Values are full 16 or 32-bit resolution.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MIDI2ChannelVoice {
+    NoteOff { note: U7, velocity: u16, attribute_type: u8, attribute: u16 },
+    NoteOn { note: U7, velocity: u16, attribute_type: u8, attribute: u16 },
+    PolyPressure { note: U7, pressure: u32 },
+    RegisteredPerNoteController { note: U7, index: u8, value: u32 },
+    AssignablePerNoteController { note: U7, index: u8, value: u32 },
+    /// Detach per-note controllers from previous notes on the same note number, and/or reset them
+    PerNoteManagement { note: U7, detach: bool, reset: bool },
+    ControlChange { index: U7, value: u32 },
+    /// RPN, addressed by bank (MSB) and index (LSB)
+    RegisteredController { bank: U7, index: U7, value: u32 },
+    /// NRPN, addressed by bank (MSB) and index (LSB)
+    AssignableController { bank: U7, index: U7, value: u32 },
+    RelativeRegisteredController { bank: U7, index: U7, value: i32 },
+    RelativeAssignableController { bank: U7, index: U7, value: i32 },
+    ProgramChange { program: U7, bank: Option<U14> },
+    ChannelPressure { pressure: u32 },
+    /// 0x80000000 is center
+    PitchBend { value: u32 },
+    /// 0x80000000 is center
+    PerNotePitchBend { note: U7, value: u32 }
+}
+
+/// UMP Stream message, used to discover and configure endpoints
+#[derive(Clone, Debug, PartialEq)]
+pub enum StreamMessage {
+    /// `filter` bits ask for endpoint info (0x01), device identity (0x02), endpoint name (0x04),
+    /// product instance id (0x08) and stream configuration (0x10)
+    EndpointDiscovery { ump_version: (u8, u8), filter: u8 },
+    EndpointInfo {
+        ump_version: (u8, u8),
+        static_function_blocks: bool,
+        function_block_count: u8,
+        midi2: bool,
+        midi1: bool,
+        receive_jr_timestamps: bool,
+        transmit_jr_timestamps: bool
+    },
+    DeviceIdentity { manufacturer: [u8; 3], family: U14, model: U14, version: [u8; 4] },
+    /// Up to 14 bytes of UTF-8 per packet
+    EndpointName { status: PacketStatus, name: Vec<u8> },
+    /// Up to 14 bytes per packet
+    ProductInstanceId { status: PacketStatus, id: Vec<u8> },
+    /// `protocol` is 0x01 for MIDI 1.0 and 0x02 for MIDI 2.0
+    StreamConfigurationRequest { protocol: u8, receive_jr_timestamps: bool, transmit_jr_timestamps: bool },
+    StreamConfigurationNotification { protocol: u8, receive_jr_timestamps: bool, transmit_jr_timestamps: bool },
+    /// `function_block` 0xFF asks for all of them. `filter` bits ask for info (0x01) and name (0x02).
+    FunctionBlockDiscovery { function_block: u8, filter: u8 },
+    FunctionBlockInfo {
+        active: bool,
+        function_block: u8,
+        ui_hint: u8,
+        midi1: u8,
+        direction: u8,
+        first_group: u8,
+        group_count: u8,
+        midi_ci_version: u8,
+        max_sysex8_streams: u8
+    },
+    /// Up to 13 bytes of UTF-8 per packet
+    FunctionBlockName { status: PacketStatus, function_block: u8, name: Vec<u8> },
+    StartOfClip,
+    EndOfClip
+}
+
+/// Flex Data message, carrying what SMF keeps in meta events
+#[derive(Clone, Debug, PartialEq)]
+pub enum FlexData {
+    /// In units of 10 nanoseconds per quarter note
+    SetTempo(u32),
+    /// The denominator is a power of two, as in SMF
+    TimeSignature { numerator: u8, denominator_exponent: u8, thirty_seconds_per_quarter: u8 },
+    /// Sharps (positive) or flats (negative), -8 if unknown, and the tonic's letter if known.
+    /// There is no mode: it follows from which letter the tonic is.
+    KeySignature { sharps: i8, tonic: Option<Tonic> },
+    /// Up to 12 bytes of UTF-8 per packet. See `METADATA_TEXT` and `PERFORMANCE_TEXT` for the banks.
+    Text { status: PacketStatus, bank: u8, kind: u8, text: Vec<u8> }
+}
+
+/// Universal MIDI Packet. Groups are 0-15.
+#[derive(Clone, Debug, PartialEq)]
+pub enum UMP {
+    NoOp,
+    /// Jitter reduction clock, in units of 1/31250 seconds
+    JRClock(u16),
+    /// Jitter reduction timestamp, in units of 1/31250 seconds
+    JRTimestamp(u16),
+    DeltaClockstampTicksPerQuarterNote(u16),
+    /// Ticks since the last delta clockstamp, up to 20 bits
+    DeltaClockstamp(u32),
+    SystemCommon { group: u8, message: SystemCommon },
+    SystemRealtime { group: u8, message: SystemRealtime },
+    MIDI1ChannelVoice { group: u8, message: ChannelMessage },
+    /// Up to 6 bytes of a system exclusive message
+    SysEx7 { group: u8, status: PacketStatus, data: Vec<u8> },
+    MIDI2ChannelVoice { group: u8, channel: Channel, message: MIDI2ChannelVoice },
+    /// Up to 13 bytes of an 8-bit system exclusive message
+    SysEx8 { group: u8, status: PacketStatus, stream_id: u8, data: Vec<u8> },
+    /// Sent to a channel, or to the whole group when `channel` is None
+    FlexData { group: u8, channel: Option<Channel>, message: FlexData },
+    Stream(StreamMessage),
+    /// Packets of reserved or unsupported types, kept as they are
+    Unknown(Vec<u32>)
+}
+
+/// Converts MIDIEvents to UMP. With the MIDI 2.0 protocol, channel voice messages are translated as the
+/// UMP spec describes: values are scaled up, RPN/NRPN sequences become registered/assignable controller
+/// messages and bank selects are sent with the next program change.
+#[derive(Clone, Debug)]
+pub struct UMPEncoder {
+    group: u8,
+    protocol: UMPProtocol,
+    parameters: ParameterDecoder,
+    /// Last bank select (MSB, LSB) of each channel
+    banks: HashMap<Channel, (U7, U7)>,
+    ready: VecDeque<UMP>
+}
+
+/// Converts UMP to MIDIEvents, joining system exclusive packets and translating MIDI 2.0 channel voice
+/// messages down to MIDI 1.0. Flex Data becomes meta events. Messages with no MIDI 1.0 equivalent (per-note
+/// controllers, relative controllers, stream and utility messages, 8-bit system exclusive) are dropped.
+#[derive(Clone, Debug, Default)]
+pub struct UMPDecoder {
+    sysex: HashMap<u8, Vec<u8>>,
+    /// Flex Data text split across packets, by (group, bank, kind)
+    text: HashMap<(u8, u8, u8), Vec<u8>>,
+    ready: VecDeque<(u8, MIDIEvent)>
+}
+
+/// Scale a value up to more bits, keeping the minimum, center and maximum, as the MIDI 2.0 spec describes
+pub fn scale_up(value: u32, source_bits: u32, destination_bits: u32) -> u32 {
+    let scale_bits = destination_bits - source_bits;
+    let center = 1 << (source_bits - 1);
+    let shifted = value << scale_bits;
+    if value <= center {
+        return shifted;
+    }
+
+    // Above center, repeat the lower bits to fill the new ones, so the maximum maps to the maximum
+    let repeat_bits = source_bits - 1;
+    let mut repeat = value & ((1 << repeat_bits) - 1);
+    if scale_bits > repeat_bits {
+        repeat <<= scale_bits - repeat_bits;
+    } else {
+        repeat >>= repeat_bits - scale_bits;
+    }
+
+    let mut output = shifted;
+    while repeat != 0 {
+        output |= repeat;
+        repeat >>= repeat_bits;
+    }
+
+    output
+}
+
+/// Scale a value down to fewer bits
+pub fn scale_down(value: u32, source_bits: u32, destination_bits: u32) -> u32 {
+    value >> (source_bits - destination_bits)
+}
+
+/// Get the number of 32-bit words in a packet, from its message type (the top 4 bits of its first word)
+pub fn get_word_count(first_word: u32) -> usize {
+    match first_word >> 28 {
+        0x0 | 0x1 | 0x2 | 0x6 | 0x7 => 1,
+        0x3 | 0x4 | 0x8 | 0x9 | 0xA => 2,
+        0xB | 0xC => 3,
+        _ => 4
+    }
+}
+
+impl PacketStatus {
+    fn from_value(value: u32) -> Option<PacketStatus> {
+        match value {
+            0 => Some(PacketStatus::Complete),
+            1 => Some(PacketStatus::Start),
+            2 => Some(PacketStatus::Continue),
+            3 => Some(PacketStatus::End),
+            _ => None
+        }
+    }
+
+    fn get_value(&self) -> u32 {
+        match self {
+            PacketStatus::Complete => 0,
+            PacketStatus::Start => 1,
+            PacketStatus::Continue => 2,
+            PacketStatus::End => 3
+        }
+    }
+}
+
+// Split data into packet sized chunks with their status
+fn split_packets(data: &[u8], size: usize) -> Vec<(PacketStatus, &[u8])> {
+    if data.len() <= size {
+        return vec![(PacketStatus::Complete, data)];
+    }
+
+    let chunks: Vec<&[u8]> = data.chunks(size).collect();
+    let last = chunks.len() - 1;
+    chunks.into_iter()
+        .enumerate()
+        .map(|(i, chunk)| {
+            let status = if i == 0 {
+                PacketStatus::Start
+            } else if i == last {
+                PacketStatus::End
+            } else {
+                PacketStatus::Continue
+            };
+            (status, chunk)
+        })
+        .collect()
+}
+
+// Bytes packed big-endian into words, starting `offset` bytes into the first word
+fn pack_bytes(words: &mut [u32], offset: usize, bytes: &[u8]) {
+    for (i, byte) in bytes.iter().enumerate() {
+        let position = offset + i;
+        words[position / 4] |= (*byte as u32) << (24 - ((position % 4) * 8));
+    }
+}
+
+fn unpack_bytes(words: &[u32], offset: usize, count: usize) -> Vec<u8> {
+    (offset..offset + count)
+        .map(|position| (words[position / 4] >> (24 - ((position % 4) * 8))) as u8)
+        .collect()
+}
+
+// Text in stream messages is padded with zeros
+fn unpack_text(words: &[u32], offset: usize, count: usize) -> Vec<u8> {
+    let mut output = unpack_bytes(words, offset, count);
+    while output.last() == Some(&0) {
+        output.pop();
+    }
+
+    output
+}
+
+fn get_words_error(words: &[u32]) -> ApresError {
+    ApresError::InvalidBytes(words.iter().flat_map(|word| word.to_be_bytes().to_vec()).collect())
+}
+
+// The channel voice event for MIDI 1.0 bytes
+fn get_channel_event(status: u8, data1: u8, data2: u8) -> Option<MIDIEvent> {
+    let channel = Channel::from_status(status);
+    let (data1, data2) = (U7::new_masked(data1), U7::new_masked(data2));
+    match status & 0xF0 {
+        0x80 => Some(MIDIEvent::NoteOff(channel, data1, data2)),
+        0x90 => Some(MIDIEvent::NoteOn(channel, data1, data2)),
+        0xA0 => Some(MIDIEvent::AfterTouch(channel, data1, data2)),
+        0xB0 => Some(build_control_change(channel, data1, data2)),
+        0xC0 => Some(MIDIEvent::ProgramChange(channel, data1)),
+        0xD0 => Some(MIDIEvent::ChannelPressure(channel, data1)),
+        0xE0 => Some(build_pitch_wheel_change(channel, data1, data2)),
+        _ => None
+    }
+}
+
+fn get_system_event(status: u8, data1: u8, data2: u8) -> Option<MIDIEvent> {
+    match status {
+        0xF1 => Some(MIDIEvent::MTCQuarterFrame((data1 >> 4) & 0x07, data1 & 0x0F)),
+        0xF2 => Some(MIDIEvent::SongPositionPointer(U14::from_lsb_msb(U7::new_masked(data1), U7::new_masked(data2)))),
+        0xF3 => Some(MIDIEvent::SongSelect(U7::new_masked(data1))),
+        0xF6 => Some(MIDIEvent::TuneRequest),
+        0xF8 => Some(MIDIEvent::MIDIClock),
+        0xFA => Some(MIDIEvent::MIDIStart),
+        0xFB => Some(MIDIEvent::MIDIContinue),
+        0xFC => Some(MIDIEvent::MIDIStop),
+        0xFE => Some(MIDIEvent::ActiveSense),
+        0xFF => Some(MIDIEvent::Reset),
+        _ => None
+    }
+}
+
+// First word of a 32-bit message carrying MIDI 1.0 bytes
+fn get_bytes_word(message_type: u32, group: u8, bytes: &[u8]) -> u32 {
+    let mut words = [(message_type << 28) | ((group as u32 & 0x0F) << 24)];
+    pack_bytes(&mut words, 1, &bytes[..bytes.len().min(3)]);
+    words[0]
+}
+
+impl MIDI2ChannelVoice {
+    // (opcode, byte 3, byte 4, second word)
+    fn get_fields(&self) -> (u32, u8, u8, u32) {
+        match self {
+            MIDI2ChannelVoice::RegisteredPerNoteController { note, index, value } => (0x0, note.get(), *index, *value),
+            MIDI2ChannelVoice::AssignablePerNoteController { note, index, value } => (0x1, note.get(), *index, *value),
+            MIDI2ChannelVoice::RegisteredController { bank, index, value } => (0x2, bank.get(), index.get(), *value),
+            MIDI2ChannelVoice::AssignableController { bank, index, value } => (0x3, bank.get(), index.get(), *value),
+            MIDI2ChannelVoice::RelativeRegisteredController { bank, index, value } => (0x4, bank.get(), index.get(), *value as u32),
+            MIDI2ChannelVoice::RelativeAssignableController { bank, index, value } => (0x5, bank.get(), index.get(), *value as u32),
+            MIDI2ChannelVoice::PerNotePitchBend { note, value } => (0x6, note.get(), 0, *value),
+            MIDI2ChannelVoice::NoteOff { note, velocity, attribute_type, attribute } => {
+                (0x8, note.get(), *attribute_type, ((*velocity as u32) << 16) | *attribute as u32)
+            }
+            MIDI2ChannelVoice::NoteOn { note, velocity, attribute_type, attribute } => {
+                (0x9, note.get(), *attribute_type, ((*velocity as u32) << 16) | *attribute as u32)
+            }
+            MIDI2ChannelVoice::PolyPressure { note, pressure } => (0xA, note.get(), 0, *pressure),
+            MIDI2ChannelVoice::ControlChange { index, value } => (0xB, index.get(), 0, *value),
+            MIDI2ChannelVoice::ProgramChange { program, bank } => {
+                match bank {
+                    Some(bank) => {
+                        let word = ((program.get() as u32) << 24) | ((bank.get_msb().get() as u32) << 8) | bank.get_lsb().get() as u32;
+                        (0xC, 0, 0x01, word)
+                    }
+                    None => (0xC, 0, 0x00, (program.get() as u32) << 24)
+                }
+            }
+            MIDI2ChannelVoice::ChannelPressure { pressure } => (0xD, 0, 0, *pressure),
+            MIDI2ChannelVoice::PitchBend { value } => (0xE, 0, 0, *value),
+            MIDI2ChannelVoice::PerNoteManagement { note, detach, reset } => {
+                (0xF, note.get(), ((*detach as u8) << 1) | *reset as u8, 0)
+            }
+        }
+    }
+
+    fn from_fields(opcode: u32, byte3: u8, byte4: u8, word: u32) -> Option<MIDI2ChannelVoice> {
+        let note = U7::new_masked(byte3);
+        let (bank, index) = (U7::new_masked(byte3), U7::new_masked(byte4));
+        let message = match opcode {
+            0x0 => MIDI2ChannelVoice::RegisteredPerNoteController { note, index: byte4, value: word },
+            0x1 => MIDI2ChannelVoice::AssignablePerNoteController { note, index: byte4, value: word },
+            0x2 => MIDI2ChannelVoice::RegisteredController { bank, index, value: word },
+            0x3 => MIDI2ChannelVoice::AssignableController { bank, index, value: word },
+            0x4 => MIDI2ChannelVoice::RelativeRegisteredController { bank, index, value: word as i32 },
+            0x5 => MIDI2ChannelVoice::RelativeAssignableController { bank, index, value: word as i32 },
+            0x6 => MIDI2ChannelVoice::PerNotePitchBend { note, value: word },
+            0x8 => MIDI2ChannelVoice::NoteOff { note, velocity: (word >> 16) as u16, attribute_type: byte4, attribute: word as u16 },
+            0x9 => MIDI2ChannelVoice::NoteOn { note, velocity: (word >> 16) as u16, attribute_type: byte4, attribute: word as u16 },
+            0xA => MIDI2ChannelVoice::PolyPressure { note, pressure: word },
+            0xB => MIDI2ChannelVoice::ControlChange { index: note, value: word },
+            0xC => {
+                let bank = if byte4 & 0x01 == 0x01 {
+                    Some(U14::from_lsb_msb(U7::new_masked(word as u8), U7::new_masked((word >> 8) as u8)))
+                } else {
+                    None
+                };
+                MIDI2ChannelVoice::ProgramChange { program: U7::new_masked((word >> 24) as u8), bank }
+            }
+            0xD => MIDI2ChannelVoice::ChannelPressure { pressure: word },
+            0xE => MIDI2ChannelVoice::PitchBend { value: word },
+            0xF => MIDI2ChannelVoice::PerNoteManagement { note, detach: byte4 & 0x02 != 0, reset: byte4 & 0x01 != 0 },
+            _ => {
+                return None;
+            }
+        };
+
+        Some(message)
+    }
+
+    /// Translate to MIDI 1.0 events, scaling values down. Empty for messages with no MIDI 1.0 equivalent.
+    pub fn to_midi1_events(&self, channel: Channel) -> Vec<MIDIEvent> {
+        let to_u7 = |value: u32, bits: u32| U7::new_masked(scale_down(value, bits, 7) as u8);
+        match self {
+            MIDI2ChannelVoice::NoteOff { note, velocity, .. } => {
+                vec![MIDIEvent::NoteOff(channel, *note, to_u7(*velocity as u32, 16))]
+            }
+            MIDI2ChannelVoice::NoteOn { note, velocity, .. } => {
+                // Velocity 0 would be a note off in MIDI 1.0
+                let velocity = to_u7(*velocity as u32, 16).get().max(1);
+                vec![MIDIEvent::NoteOn(channel, *note, U7::new_masked(velocity))]
+            }
+            MIDI2ChannelVoice::PolyPressure { note, pressure } => {
+                vec![MIDIEvent::AfterTouch(channel, *note, to_u7(*pressure, 32))]
+            }
+            MIDI2ChannelVoice::ControlChange { index, value } => {
+                vec![build_control_change(channel, *index, to_u7(*value, 32))]
+            }
+            MIDI2ChannelVoice::RegisteredController { bank, index, value } => {
+                let value = U14::new_masked(scale_down(*value, 32, 14) as u16);
+                vec![
+                    MIDIEvent::RegisteredParameterNumber(channel, *bank),
+                    MIDIEvent::RegisteredParameterNumberLSB(channel, *index),
+                    MIDIEvent::DataEntry(channel, value.get_msb()),
+                    MIDIEvent::DataEntryLSB(channel, value.get_lsb())
+                ]
+            }
+            MIDI2ChannelVoice::AssignableController { bank, index, value } => {
+                let value = U14::new_masked(scale_down(*value, 32, 14) as u16);
+                vec![
+                    MIDIEvent::NonRegisteredParameterNumber(channel, *bank),
+                    MIDIEvent::NonRegisteredParameterNumberLSB(channel, *index),
+                    MIDIEvent::DataEntry(channel, value.get_msb()),
+                    MIDIEvent::DataEntryLSB(channel, value.get_lsb())
+                ]
+            }
+            MIDI2ChannelVoice::ProgramChange { program, bank } => {
+                let mut output = Vec::new();
+                if let Some(bank) = bank {
+                    output.push(MIDIEvent::BankSelect(channel, bank.get_msb()));
+                    output.push(MIDIEvent::BankSelectLSB(channel, bank.get_lsb()));
+                }
+                output.push(MIDIEvent::ProgramChange(channel, *program));
+                output
+            }
+            MIDI2ChannelVoice::ChannelPressure { pressure } => {
+                vec![MIDIEvent::ChannelPressure(channel, to_u7(*pressure, 32))]
+            }
+            MIDI2ChannelVoice::PitchBend { value } => {
+                let value = U14::new_masked(scale_down(*value, 32, 14) as u16);
+                vec![build_pitch_wheel_change(channel, value.get_lsb(), value.get_msb())]
+            }
+            MIDI2ChannelVoice::RegisteredPerNoteController { .. }
+            | MIDI2ChannelVoice::AssignablePerNoteController { .. }
+            | MIDI2ChannelVoice::PerNoteManagement { .. }
+            | MIDI2ChannelVoice::RelativeRegisteredController { .. }
+            | MIDI2ChannelVoice::RelativeAssignableController { .. }
+            | MIDI2ChannelVoice::PerNotePitchBend { .. } => Vec::new()
+        }
+    }
+}
+
+impl StreamMessage {
+    // (format, status, words) with the format and status not yet in the first word
+    fn get_fields(&self) -> (PacketStatus, u32, [u32; 4]) {
+        let mut words = [0_u32; 4];
+        let (format, status) = match self {
+            StreamMessage::EndpointDiscovery { ump_version, filter } => {
+                words[0] = ((ump_version.0 as u32) << 8) | ump_version.1 as u32;
+                words[1] = *filter as u32;
+                (PacketStatus::Complete, 0x000)
+            }
+            StreamMessage::EndpointInfo {
+                ump_version, static_function_blocks, function_block_count, midi2, midi1, receive_jr_timestamps, transmit_jr_timestamps
+            } => {
+                words[0] = ((ump_version.0 as u32) << 8) | ump_version.1 as u32;
+                words[1] = ((*static_function_blocks as u32) << 31)
+                    | ((*function_block_count as u32 & 0x7F) << 24)
+                    | ((*midi2 as u32) << 9)
+                    | ((*midi1 as u32) << 8)
+                    | ((*receive_jr_timestamps as u32) << 1)
+                    | *transmit_jr_timestamps as u32;
+                (PacketStatus::Complete, 0x001)
+            }
+            StreamMessage::DeviceIdentity { manufacturer, family, model, version } => {
+                pack_bytes(&mut words, 5, manufacturer);
+                pack_bytes(&mut words, 8, &[family.get_lsb().get(), family.get_msb().get(), model.get_lsb().get(), model.get_msb().get()]);
+                pack_bytes(&mut words, 12, version);
+                (PacketStatus::Complete, 0x002)
+            }
+            StreamMessage::EndpointName { status, name } => {
+                pack_bytes(&mut words, 2, &name[..name.len().min(STREAM_TEXT_SIZE)]);
+                (*status, 0x003)
+            }
+            StreamMessage::ProductInstanceId { status, id } => {
+                pack_bytes(&mut words, 2, &id[..id.len().min(STREAM_TEXT_SIZE)]);
+                (*status, 0x004)
+            }
+            StreamMessage::StreamConfigurationRequest { protocol, receive_jr_timestamps, transmit_jr_timestamps } => {
+                words[0] = ((*protocol as u32) << 8) | ((*receive_jr_timestamps as u32) << 1) | *transmit_jr_timestamps as u32;
+                (PacketStatus::Complete, 0x005)
+            }
+            StreamMessage::StreamConfigurationNotification { protocol, receive_jr_timestamps, transmit_jr_timestamps } => {
+                words[0] = ((*protocol as u32) << 8) | ((*receive_jr_timestamps as u32) << 1) | *transmit_jr_timestamps as u32;
+                (PacketStatus::Complete, 0x006)
+            }
+            StreamMessage::FunctionBlockDiscovery { function_block, filter } => {
+                words[0] = ((*function_block as u32) << 8) | *filter as u32;
+                (PacketStatus::Complete, 0x010)
+            }
+            StreamMessage::FunctionBlockInfo {
+                active, function_block, ui_hint, midi1, direction, first_group, group_count, midi_ci_version, max_sysex8_streams
+            } => {
+                words[0] = ((*active as u32) << 15)
+                    | ((*function_block as u32 & 0x7F) << 8)
+                    | ((*ui_hint as u32 & 0x03) << 4)
+                    | ((*midi1 as u32 & 0x03) << 2)
+                    | (*direction as u32 & 0x03);
+                pack_bytes(&mut words, 4, &[*first_group, *group_count, *midi_ci_version, *max_sysex8_streams]);
+                (PacketStatus::Complete, 0x011)
+            }
+            StreamMessage::FunctionBlockName { status, function_block, name } => {
+                pack_bytes(&mut words, 2, &[*function_block]);
+                pack_bytes(&mut words, 3, &name[..name.len().min(FUNCTION_BLOCK_NAME_SIZE)]);
+                (*status, 0x012)
+            }
+            StreamMessage::StartOfClip => {
+                (PacketStatus::Complete, 0x020)
+            }
+            StreamMessage::EndOfClip => {
+                (PacketStatus::Complete, 0x021)
+            }
+        };
+
+        (format, status, words)
+    }
+
+    fn from_words(words: &[u32]) -> Option<StreamMessage> {
+        let format = PacketStatus::from_value((words[0] >> 26) & 0x03)?;
+        let get_jr = |word: u32| (word & 0x02 != 0, word & 0x01 != 0);
+        let message = match (words[0] >> 16) & 0x3FF {
+            0x000 => {
+                StreamMessage::EndpointDiscovery {
+                    ump_version: ((words[0] >> 8) as u8, words[0] as u8),
+                    filter: words[1] as u8
+                }
+            }
+            0x001 => {
+                let (receive_jr_timestamps, transmit_jr_timestamps) = get_jr(words[1]);
+                StreamMessage::EndpointInfo {
+                    ump_version: ((words[0] >> 8) as u8, words[0] as u8),
+                    static_function_blocks: words[1] >> 31 == 1,
+                    function_block_count: ((words[1] >> 24) & 0x7F) as u8,
+                    midi2: words[1] & 0x200 != 0,
+                    midi1: words[1] & 0x100 != 0,
+                    receive_jr_timestamps,
+                    transmit_jr_timestamps
+                }
+            }
+            0x002 => {
+                let identity = unpack_bytes(words, 5, 11);
+                StreamMessage::DeviceIdentity {
+                    manufacturer: [identity[0], identity[1], identity[2]],
+                    family: U14::from_lsb_msb(U7::new_masked(identity[3]), U7::new_masked(identity[4])),
+                    model: U14::from_lsb_msb(U7::new_masked(identity[5]), U7::new_masked(identity[6])),
+                    version: [identity[7], identity[8], identity[9], identity[10]]
+                }
+            }
+            0x003 => {
+                StreamMessage::EndpointName { status: format, name: unpack_text(words, 2, STREAM_TEXT_SIZE) }
+            }
+            0x004 => {
+                StreamMessage::ProductInstanceId { status: format, id: unpack_text(words, 2, STREAM_TEXT_SIZE) }
+            }
+            0x005 | 0x006 => {
+                let (receive_jr_timestamps, transmit_jr_timestamps) = get_jr(words[0]);
+                let protocol = (words[0] >> 8) as u8;
+                if (words[0] >> 16) & 0x3FF == 0x005 {
+                    StreamMessage::StreamConfigurationRequest { protocol, receive_jr_timestamps, transmit_jr_timestamps }
+                } else {
+                    StreamMessage::StreamConfigurationNotification { protocol, receive_jr_timestamps, transmit_jr_timestamps }
+                }
+            }
+            0x010 => {
+                StreamMessage::FunctionBlockDiscovery { function_block: (words[0] >> 8) as u8, filter: words[0] as u8 }
+            }
+            0x011 => {
+                StreamMessage::FunctionBlockInfo {
+                    active: words[0] & 0x8000 != 0,
+                    function_block: ((words[0] >> 8) & 0x7F) as u8,
+                    ui_hint: ((words[0] >> 4) & 0x03) as u8,
+                    midi1: ((words[0] >> 2) & 0x03) as u8,
+                    direction: (words[0] & 0x03) as u8,
+                    first_group: (words[1] >> 24) as u8,
+                    group_count: (words[1] >> 16) as u8,
+                    midi_ci_version: (words[1] >> 8) as u8,
+                    max_sysex8_streams: words[1] as u8
+                }
+            }
+            0x012 => {
+                StreamMessage::FunctionBlockName {
+                    status: format,
+                    function_block: (words[0] >> 8) as u8,
+                    name: unpack_text(words, 3, FUNCTION_BLOCK_NAME_SIZE)
+                }
+            }
+            0x020 => StreamMessage::StartOfClip,
+            0x021 => StreamMessage::EndOfClip,
+            _ => {
+                return None;
+            }
+        };
+
+        Some(message)
+    }
+
+    /// Split an endpoint name into as many packets as it needs
+    pub fn endpoint_name(name: &str) -> Vec<StreamMessage> {
+        split_packets(name.as_bytes(), STREAM_TEXT_SIZE)
+            .into_iter()
+            .map(|(status, chunk)| StreamMessage::EndpointName { status, name: chunk.to_vec() })
+            .collect()
+    }
+
+    /// Split a function block name into as many packets as it needs
+    pub fn function_block_name(function_block: u8, name: &str) -> Vec<StreamMessage> {
+        split_packets(name.as_bytes(), FUNCTION_BLOCK_NAME_SIZE)
+            .into_iter()
+            .map(|(status, chunk)| StreamMessage::FunctionBlockName { status, function_block, name: chunk.to_vec() })
+            .collect()
+    }
+}
+
+// Flex Data numbers tonics from A (1) to G (7), with 0 for unknown
+fn get_tonic_number(tonic: Option<Tonic>) -> u8 {
+    match tonic {
+        None => 0,
+        Some(Tonic::A) => 1,
+        Some(Tonic::B) => 2,
+        Some(Tonic::C) => 3,
+        Some(Tonic::D) => 4,
+        Some(Tonic::E) => 5,
+        Some(Tonic::F) => 6,
+        Some(Tonic::G) => 7
+    }
+}
+
+fn get_tonic(number: u8) -> Option<Tonic> {
+    match number {
+        1 => Some(Tonic::A),
+        2 => Some(Tonic::B),
+        3 => Some(Tonic::C),
+        4 => Some(Tonic::D),
+        5 => Some(Tonic::E),
+        6 => Some(Tonic::F),
+        7 => Some(Tonic::G),
+        _ => None
+    }
+}
+
+impl FlexData {
+    // (form, bank, status, words) with the first word not yet filled in
+    fn get_fields(&self) -> (PacketStatus, u8, u8, [u32; 4]) {
+        let mut words = [0_u32; 4];
+        match self {
+            FlexData::SetTempo(tempo) => {
+                words[1] = *tempo;
+                (PacketStatus::Complete, 0x00, 0x00, words)
+            }
+            FlexData::TimeSignature { numerator, denominator_exponent, thirty_seconds_per_quarter } => {
+                pack_bytes(&mut words, 4, &[*numerator, *denominator_exponent, *thirty_seconds_per_quarter]);
+                (PacketStatus::Complete, 0x00, 0x01, words)
+            }
+            FlexData::KeySignature { sharps, tonic } => {
+                words[1] = (((*sharps as u32) & 0x0F) << 28) | ((get_tonic_number(*tonic) as u32) << 24);
+                (PacketStatus::Complete, 0x00, 0x05, words)
+            }
+            FlexData::Text { status, bank, kind, text } => {
+                pack_bytes(&mut words, 4, &text[..text.len().min(FLEX_TEXT_SIZE)]);
+                (*status, *bank, *kind, words)
+            }
+        }
+    }
+
+    fn from_words(words: &[u32]) -> Option<FlexData> {
+        let form = PacketStatus::from_value((words[0] >> 22) & 0x03)?;
+        let message = match ((words[0] >> 8) as u8, words[0] as u8) {
+            (0x00, 0x00) => FlexData::SetTempo(words[1]),
+            (0x00, 0x01) => {
+                let [numerator, denominator_exponent, thirty_seconds_per_quarter, _] = words[1].to_be_bytes();
+                FlexData::TimeSignature { numerator, denominator_exponent, thirty_seconds_per_quarter }
+            }
+            (0x00, 0x05) => {
+                // Sign extend the 4-bit sharps
+                let sharps = ((words[1] >> 24) as u8 as i8) >> 4;
+                FlexData::KeySignature { sharps, tonic: get_tonic((words[1] >> 24) as u8 & 0x0F) }
+            }
+            (METADATA_TEXT, kind) | (PERFORMANCE_TEXT, kind) => {
+                FlexData::Text { status: form, bank: (words[0] >> 8) as u8, kind, text: unpack_text(words, 4, FLEX_TEXT_SIZE) }
+            }
+            _ => {
+                return None;
+            }
+        };
+
+        Some(message)
+    }
+
+    /// Get the Flex Data messages for a meta event, if it has an equivalent
+    pub fn from_event(event: &MIDIEvent) -> Vec<FlexData> {
+        let (bank, kind, text) = match event {
+            MIDIEvent::SetTempo(tempo) => {
+                return vec![FlexData::SetTempo(tempo.get() * 100)];
+            }
+            MIDIEvent::TimeSignature(meter) => {
+                return vec![FlexData::TimeSignature {
+                    numerator: meter.get_numerator(),
+                    denominator_exponent: meter.get_denominator_exponent(),
+                    thirty_seconds_per_quarter: meter.get_thirty_seconds_per_quarter()
+                }];
+            }
+            MIDIEvent::KeySignature(key) => {
+                let key = key.get_representable();
+                return vec![FlexData::KeySignature { sharps: key.get_sharps(), tonic: Some(key.tonic) }];
+            }
+            MIDIEvent::Text(text) => (METADATA_TEXT, UNKNOWN_TEXT, text),
+            MIDIEvent::TrackName(text) => (METADATA_TEXT, CLIP_NAME, text),
+            MIDIEvent::CopyRightNotice(text) => (METADATA_TEXT, COPYRIGHT_NOTICE, text),
+            MIDIEvent::Lyric(text) => (PERFORMANCE_TEXT, LYRICS, text),
+            _ => {
+                return Vec::new();
+            }
+        };
+
+        split_packets(text.as_bytes(), FLEX_TEXT_SIZE)
+            .into_iter()
+            .map(|(status, chunk)| FlexData::Text { status, bank, kind, text: chunk.to_vec() })
+            .collect()
+    }
+
+    // The meta event for a whole (not split) message
+    fn to_event(&self) -> Option<MIDIEvent> {
+        match self {
+            FlexData::SetTempo(tempo) => {
+                Some(MIDIEvent::SetTempo(Tempo::new((tempo / 100).min(Tempo::MAX)).ok()?))
+            }
+            FlexData::TimeSignature { numerator, denominator_exponent, thirty_seconds_per_quarter } => {
+                if *denominator_exponent > 7 {
+                    return None;
+                }
+                let meter = Meter::new(*numerator, 1 << denominator_exponent).ok()?;
+                Some(MIDIEvent::TimeSignature(meter.with_thirty_seconds_per_quarter(*thirty_seconds_per_quarter)))
+            }
+            FlexData::KeySignature { sharps, tonic } => {
+                // The tonic picks major or minor. Without one, major is assumed.
+                let major = Key::from_sf_mi(*sharps, 0).ok()?;
+                let minor = Key::from_sf_mi(*sharps, 1).ok()?;
+                match tonic {
+                    None => Some(MIDIEvent::KeySignature(major)),
+                    Some(tonic) if *tonic == major.tonic => Some(MIDIEvent::KeySignature(major)),
+                    Some(tonic) if *tonic == minor.tonic => Some(MIDIEvent::KeySignature(minor)),
+                    Some(_) => None
+                }
+            }
+            FlexData::Text { bank, kind, text, .. } => {
+                let text = String::from_utf8_lossy(text).to_string();
+                match (*bank, *kind) {
+                    (METADATA_TEXT, CLIP_NAME) => Some(MIDIEvent::TrackName(text)),
+                    (METADATA_TEXT, COPYRIGHT_NOTICE) => Some(MIDIEvent::CopyRightNotice(text)),
+                    (PERFORMANCE_TEXT, LYRICS) => Some(MIDIEvent::Lyric(text)),
+                    _ => Some(MIDIEvent::Text(text))
+                }
+            }
+        }
+    }
+}
+
+impl UMP {
+    /// Parse the packet at the start of `words`, returning it and the number of words it took
+    pub fn from_words(words: &[u32]) -> Result<(UMP, usize), ApresError> {
+        let size = match words.first() {
+            Some(first_word) => get_word_count(*first_word),
+            None => {
+                return Err(get_words_error(words));
+            }
+        };
+
+        if words.len() < size {
+            return Err(get_words_error(words));
+        }
+
+        let packet = &words[..size];
+        let word = packet[0];
+        let group = ((word >> 24) & 0x0F) as u8;
+        let [_, status, data1, data2] = word.to_be_bytes();
+
+        let ump = match word >> 28 {
+            0x0 => {
+                match (word >> 20) & 0x0F {
+                    0x0 => UMP::NoOp,
+                    0x1 => UMP::JRClock(word as u16),
+                    0x2 => UMP::JRTimestamp(word as u16),
+                    0x3 => UMP::DeltaClockstampTicksPerQuarterNote(word as u16),
+                    0x4 => UMP::DeltaClockstamp(word & 0xFFFFF),
+                    _ => UMP::Unknown(packet.to_vec())
+                }
+            }
+            0x1 => {
+                match get_system_event(status, data1, data2) {
+                    Some(event) => {
+                        match SystemRealtime::try_from(event) {
+                            Ok(message) => UMP::SystemRealtime { group, message },
+                            Err(event) => {
+                                match SystemCommon::try_from(event) {
+                                    Ok(message) => UMP::SystemCommon { group, message },
+                                    Err(_) => UMP::Unknown(packet.to_vec())
+                                }
+                            }
+                        }
+                    }
+                    None => UMP::Unknown(packet.to_vec())
+                }
+            }
+            0x2 => {
+                match get_channel_event(status, data1, data2).and_then(|event| ChannelMessage::try_from(event).ok()) {
+                    Some(message) => UMP::MIDI1ChannelVoice { group, message },
+                    None => UMP::Unknown(packet.to_vec())
+                }
+            }
+            0x3 => {
+                let count = ((word >> 16) & 0x0F) as usize;
+                match PacketStatus::from_value((word >> 20) & 0x0F) {
+                    Some(status) if count <= SYSEX7_PACKET_SIZE => {
+                        UMP::SysEx7 { group, status, data: unpack_bytes(packet, 2, count) }
+                    }
+                    _ => UMP::Unknown(packet.to_vec())
+                }
+            }
+            0x4 => {
+                let channel = Channel::from_status(status);
+                match MIDI2ChannelVoice::from_fields((word >> 20) & 0x0F, data1, data2, packet[1]) {
+                    Some(message) => UMP::MIDI2ChannelVoice { group, channel, message },
+                    None => UMP::Unknown(packet.to_vec())
+                }
+            }
+            0x5 => {
+                // The count includes the stream id
+                let count = ((word >> 16) & 0x0F) as usize;
+                match PacketStatus::from_value((word >> 20) & 0x0F) {
+                    Some(status) if (1..=SYSEX8_PACKET_SIZE + 1).contains(&count) => {
+                        UMP::SysEx8 { group, status, stream_id: data1, data: unpack_bytes(packet, 3, count - 1) }
+                    }
+                    _ => UMP::Unknown(packet.to_vec())
+                }
+            }
+            0xD => {
+                let address = (word >> 20) & 0x03;
+                match FlexData::from_words(packet) {
+                    Some(message) if address < 2 => {
+                        let channel = if address == 0 { Some(Channel::from_status(status)) } else { None };
+                        UMP::FlexData { group, channel, message }
+                    }
+                    _ => UMP::Unknown(packet.to_vec())
+                }
+            }
+            0xF => {
+                match StreamMessage::from_words(packet) {
+                    Some(message) => UMP::Stream(message),
+                    None => UMP::Unknown(packet.to_vec())
+                }
+            }
+            _ => UMP::Unknown(packet.to_vec())
+        };
+
+        Ok((ump, size))
+    }
+
+    /// Parse every packet in a stream of words
+    pub fn parse_words(words: &[u32]) -> Result<Vec<UMP>, ApresError> {
+        let mut output = Vec::new();
+        let mut position = 0;
+        while position < words.len() {
+            let (ump, size) = UMP::from_words(&words[position..])?;
+            output.push(ump);
+            position += size;
+        }
+
+        Ok(output)
+    }
+
+    /// Get the packet's words. A system common full frame timecode, which MIDI 1.0 sends as system
+    /// exclusive, is encoded as SysEx7 packets.
+    pub fn as_words(&self) -> Vec<u32> {
+        match self {
+            UMP::NoOp => vec![0],
+            UMP::JRClock(time) => vec![(0x1 << 20) | *time as u32],
+            UMP::JRTimestamp(time) => vec![(0x2 << 20) | *time as u32],
+            UMP::DeltaClockstampTicksPerQuarterNote(ticks) => vec![(0x3 << 20) | *ticks as u32],
+            UMP::DeltaClockstamp(ticks) => vec![(0x4 << 20) | (ticks & 0xFFFFF)],
+            UMP::SystemCommon { group, message } => {
+                vec![get_bytes_word(0x1, *group, &MIDIEvent::from(message.clone()).as_bytes())]
+            }
+            UMP::SystemRealtime { group, message } => {
+                vec![get_bytes_word(0x1, *group, &MIDIEvent::from(*message).as_bytes())]
+            }
+            UMP::MIDI1ChannelVoice { group, message } => {
+                vec![get_bytes_word(0x2, *group, &MIDIEvent::from(message.clone()).as_bytes())]
+            }
+            UMP::SysEx7 { group, status, data } => {
+                let data = &data[..data.len().min(SYSEX7_PACKET_SIZE)];
+                let mut words = [(0x3 << 28) | ((*group as u32 & 0x0F) << 24) | (status.get_value() << 20) | ((data.len() as u32) << 16), 0];
+                pack_bytes(&mut words, 2, data);
+                words.to_vec()
+            }
+            UMP::MIDI2ChannelVoice { group, channel, message } => {
+                let (opcode, byte3, byte4, word) = message.get_fields();
+                vec![
+                    (0x4 << 28) | ((*group as u32 & 0x0F) << 24) | (opcode << 20) | ((channel.get() as u32) << 16) | ((byte3 as u32) << 8) | byte4 as u32,
+                    word
+                ]
+            }
+            UMP::SysEx8 { group, status, stream_id, data } => {
+                let data = &data[..data.len().min(SYSEX8_PACKET_SIZE)];
+                let mut words = [
+                    (0x5 << 28) | ((*group as u32 & 0x0F) << 24) | (status.get_value() << 20) | ((data.len() as u32 + 1) << 16) | ((*stream_id as u32) << 8),
+                    0, 0, 0
+                ];
+                pack_bytes(&mut words, 3, data);
+                words.to_vec()
+            }
+            UMP::FlexData { group, channel, message } => {
+                let (form, bank, status, mut words) = message.get_fields();
+                let address = match channel {
+                    Some(channel) => channel.get() as u32,
+                    None => 0x1 << 4
+                };
+                words[0] = (0xD << 28) | ((*group as u32 & 0x0F) << 24) | (form.get_value() << 22) | (address << 16) | ((bank as u32) << 8) | status as u32;
+                words.to_vec()
+            }
+            UMP::Stream(message) => {
+                let (format, status, mut words) = message.get_fields();
+                words[0] |= (0xF << 28) | (format.get_value() << 26) | (status << 16);
+                words.to_vec()
+            }
+            UMP::Unknown(words) => words.clone()
+        }
+    }
+
+    /// Get the packet's group, if it has one
+    pub fn get_group(&self) -> Option<u8> {
+        match self {
+            UMP::SystemCommon { group, .. }
+            | UMP::SystemRealtime { group, .. }
+            | UMP::MIDI1ChannelVoice { group, .. }
+            | UMP::SysEx7 { group, .. }
+            | UMP::MIDI2ChannelVoice { group, .. }
+            | UMP::SysEx8 { group, .. }
+            | UMP::FlexData { group, .. } => Some(*group),
+            _ => None
+        }
+    }
+
+    /// Get the packets that carry an event in the MIDI 1.0 protocol. System exclusive messages are split
+    /// into SysEx7 packets. Tempo, time signature and text meta events are sent as group-wide Flex Data;
+    /// other meta events give no packets.
+    pub fn from_event(group: u8, event: &MIDIEvent) -> Vec<UMP> {
+        match event {
+            MIDIEvent::SystemExclusive(_) | MIDIEvent::TimeCode(_) => {
+                let bytes = event.as_bytes();
+                let data = &bytes[1..bytes.len() - 1];
+                split_packets(data, SYSEX7_PACKET_SIZE)
+                    .into_iter()
+                    .map(|(status, chunk)| UMP::SysEx7 { group, status, data: chunk.to_vec() })
+                    .collect()
+            }
+            _ => {
+                if let Ok(message) = ChannelMessage::try_from(event.clone()) {
+                    vec![UMP::MIDI1ChannelVoice { group, message }]
+                } else if let Ok(message) = SystemRealtime::try_from(event.clone()) {
+                    vec![UMP::SystemRealtime { group, message }]
+                } else if let Ok(message) = SystemCommon::try_from(event.clone()) {
+                    vec![UMP::SystemCommon { group, message }]
+                } else {
+                    FlexData::from_event(event)
+                        .into_iter()
+                        .map(|message| UMP::FlexData { group, channel: None, message })
+                        .collect()
+                }
+            }
+        }
+    }
+
+    /// Split 8-bit system exclusive data into SysEx8 packets
+    pub fn sysex8(group: u8, stream_id: u8, data: &[u8]) -> Vec<UMP> {
+        split_packets(data, SYSEX8_PACKET_SIZE)
+            .into_iter()
+            .map(|(status, chunk)| UMP::SysEx8 { group, status, stream_id, data: chunk.to_vec() })
+            .collect()
+    }
+}
+
+impl UMPEncoder {
+    pub fn new(group: u8, protocol: UMPProtocol) -> UMPEncoder {
+        UMPEncoder {
+            group: group & 0x0F,
+            protocol,
+            parameters: ParameterDecoder::new(MSBOnlyMode::ResetLSB),
+            banks: HashMap::new(),
+            ready: VecDeque::new()
+        }
+    }
+
+    /// Pass an event in. Any resulting packets can be taken with `pop()`.
+    pub fn push(&mut self, event: MIDIEvent) {
+        if self.protocol == UMPProtocol::MIDI1 {
+            self.ready.extend(UMP::from_event(self.group, &event));
+            return;
+        }
+
+        self.parameters.push(event);
+        while let Some(parameter_event) = self.parameters.pop() {
+            match parameter_event {
+                ParameterEvent::RPN { channel, param, value } => {
+                    let number = param.get_number();
+                    self.push_midi2(channel, MIDI2ChannelVoice::RegisteredController {
+                        bank: number.get_msb(),
+                        index: number.get_lsb(),
+                        value: scale_up(value.get() as u32, 14, 32)
+                    });
+                }
+                ParameterEvent::NRPN { channel, param, value } => {
+                    self.push_midi2(channel, MIDI2ChannelVoice::AssignableController {
+                        bank: param.get_msb(),
+                        index: param.get_lsb(),
+                        value: scale_up(value.get() as u32, 14, 32)
+                    });
+                }
+                ParameterEvent::Event(event) => {
+                    self.push_event(event);
+                }
+            }
+        }
+    }
+
+    /// Take the next resulting packet, if any
+    pub fn pop(&mut self) -> Option<UMP> {
+        self.ready.pop_front()
+    }
+
+    /// Pass an event in and take all the resulting packets
+    pub fn feed(&mut self, event: MIDIEvent) -> Vec<UMP> {
+        self.push(event);
+        self.ready.drain(..).collect()
+    }
+
+    fn push_midi2(&mut self, channel: Channel, message: MIDI2ChannelVoice) {
+        self.ready.push_back(UMP::MIDI2ChannelVoice { group: self.group, channel, message });
+    }
+
+    // Translate a MIDI 1.0 event that isn't part of a parameter number sequence
+    fn push_event(&mut self, event: MIDIEvent) {
+        let message = match ChannelMessage::try_from(event) {
+            Ok(message) => message,
+            Err(event) => {
+                self.ready.extend(UMP::from_event(self.group, &event));
+                return;
+            }
+        };
+
+        let channel = message.channel;
+        let event = MIDIEvent::from(message);
+        let to_u32 = |value: U7| scale_up(value.get() as u32, 7, 32);
+        let to_u16 = |value: U7| scale_up(value.get() as u32, 7, 16) as u16;
+        let translated = match event {
+            MIDIEvent::NoteOn(_, note, velocity) if velocity.get() == 0 => {
+                MIDI2ChannelVoice::NoteOff { note, velocity: 0, attribute_type: 0, attribute: 0 }
+            }
+            MIDIEvent::NoteOn(_, note, velocity) => {
+                MIDI2ChannelVoice::NoteOn { note, velocity: to_u16(velocity), attribute_type: 0, attribute: 0 }
+            }
+            MIDIEvent::NoteOff(_, note, velocity) => {
+                MIDI2ChannelVoice::NoteOff { note, velocity: to_u16(velocity), attribute_type: 0, attribute: 0 }
+            }
+            MIDIEvent::AfterTouch(_, note, pressure) => {
+                MIDI2ChannelVoice::PolyPressure { note, pressure: to_u32(pressure) }
+            }
+            MIDIEvent::ChannelPressure(_, pressure) => {
+                MIDI2ChannelVoice::ChannelPressure { pressure: to_u32(pressure) }
+            }
+            MIDIEvent::PitchWheelChange(_, value) => {
+                MIDI2ChannelVoice::PitchBend { value: scale_up(value.get() as u32, 14, 32) }
+            }
+            MIDIEvent::BankSelect(_, msb) => {
+                let (_, lsb) = self.banks.get(&channel).copied().unwrap_or_default();
+                self.banks.insert(channel, (msb, lsb));
+                return;
+            }
+            MIDIEvent::BankSelectLSB(_, lsb) => {
+                let (msb, _) = self.banks.get(&channel).copied().unwrap_or_default();
+                self.banks.insert(channel, (msb, lsb));
+                return;
+            }
+            MIDIEvent::ProgramChange(_, program) => {
+                let bank = self.banks.get(&channel).map(|(msb, lsb)| U14::from_lsb_msb(*lsb, *msb));
+                MIDI2ChannelVoice::ProgramChange { program, bank }
+            }
+            _ => {
+                match crate::high_resolution::get_control_change(&event) {
+                    Some((_, controller, value)) => {
+                        MIDI2ChannelVoice::ControlChange { index: controller, value: to_u32(value) }
+                    }
+                    None => {
+                        return;
+                    }
+                }
+            }
+        };
+
+        self.push_midi2(channel, translated);
+    }
+}
+
+impl UMPDecoder {
+    pub fn new() -> UMPDecoder {
+        UMPDecoder::default()
+    }
+
+    /// Pass a packet in. Any resulting (group, event) pairs can be taken with `pop()`.
+    pub fn push(&mut self, ump: &UMP) {
+        match ump {
+            UMP::SystemCommon { group, message } => {
+                self.ready.push_back((*group, message.clone().into()));
+            }
+            UMP::SystemRealtime { group, message } => {
+                self.ready.push_back((*group, (*message).into()));
+            }
+            UMP::MIDI1ChannelVoice { group, message } => {
+                self.ready.push_back((*group, message.clone().into()));
+            }
+            UMP::MIDI2ChannelVoice { group, channel, message } => {
+                for event in message.to_midi1_events(*channel) {
+                    self.ready.push_back((*group, event));
+                }
+            }
+            UMP::SysEx7 { group, status, data } => {
+                match status {
+                    PacketStatus::Complete => {
+                        self.sysex.remove(group);
+                        self.ready.push_back((*group, build_system_exclusive(data.clone())));
+                    }
+                    PacketStatus::Start => {
+                        self.sysex.insert(*group, data.clone());
+                    }
+                    PacketStatus::Continue => {
+                        if let Some(buffer) = self.sysex.get_mut(group) {
+                            buffer.extend(data.iter());
+                        }
+                    }
+                    PacketStatus::End => {
+                        if let Some(mut buffer) = self.sysex.remove(group) {
+                            buffer.extend(data.iter());
+                            self.ready.push_back((*group, build_system_exclusive(buffer)));
+                        }
+                    }
+                }
+            }
+            UMP::FlexData { group, message, .. } => {
+                self.push_flex_data(*group, message);
+            }
+            _ => ()
+        }
+    }
+
+    fn push_flex_data(&mut self, group: u8, message: &FlexData) {
+        let (status, bank, kind, text) = match message {
+            FlexData::Text { status, bank, kind, text } => (*status, *bank, *kind, text),
+            _ => {
+                if let Some(event) = message.to_event() {
+                    self.ready.push_back((group, event));
+                }
+                return;
+            }
+        };
+
+        let key = (group, bank, kind);
+        let text = match status {
+            PacketStatus::Complete => {
+                self.text.remove(&key);
+                text.clone()
+            }
+            PacketStatus::Start => {
+                self.text.insert(key, text.clone());
+                return;
+            }
+            PacketStatus::Continue => {
+                if let Some(buffer) = self.text.get_mut(&key) {
+                    buffer.extend(text.iter());
+                }
+                return;
+            }
+            PacketStatus::End => {
+                match self.text.remove(&key) {
+                    Some(mut buffer) => {
+                        buffer.extend(text.iter());
+                        buffer
+                    }
+                    None => {
+                        return;
+                    }
+                }
+            }
+        };
+
+        let whole = FlexData::Text { status: PacketStatus::Complete, bank, kind, text };
+        if let Some(event) = whole.to_event() {
+            self.ready.push_back((group, event));
+        }
+    }
+
+    /// Take the next resulting (group, event) pair, if any
+    pub fn pop(&mut self) -> Option<(u8, MIDIEvent)> {
+        self.ready.pop_front()
+    }
+
+    /// Pass a packet in and take all the resulting (group, event) pairs
+    pub fn feed(&mut self, ump: &UMP) -> Vec<(u8, MIDIEvent)> {
+        self.push(ump);
+        self.ready.drain(..).collect()
+    }
+}
@@ -0,0 +1,259 @@
+use std::convert::TryFrom;
+
+use crate::{MIDIEvent, ApresError, U7};
+
+pub const YAMAHA_ID: u8 = 0x43;
+pub const XG_MODEL_ID: u8 = 0x4C;
+
+const PARAMETER_CHANGE: u8 = 0x10;
+const PARAMETER_REQUEST: u8 = 0x30;
+
+const SYSTEM_ON_ADDRESS: [u8; 3] = [0x00, 0x00, 0x7E];
+const ALL_PARAMETER_RESET_ADDRESS: [u8; 3] = [0x00, 0x00, 0x7F];
+const REVERB_TYPE_ADDRESS: [u8; 3] = [0x02, 0x01, 0x00];
+const CHORUS_TYPE_ADDRESS: [u8; 3] = [0x02, 0x01, 0x20];
+
+/// Yamaha XG system exclusive message. The device number is 0-15.
+#[derive(Clone, Debug, PartialEq)]
+pub enum XGMessage {
+    /// Write data starting at an address
+    ParameterChange { device_number: u8, address: [u8; 3], data: Vec<u8> },
+    /// Ask for the parameter at an address
+    ParameterRequest { device_number: u8, address: [u8; 3] }
+}
+
+/// Reverb or chorus effect type, as sent in the type's two data bytes
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct XGEffectType {
+    pub effect_type: u8,
+    pub variation: u8
+}
+
+/// Multi part parameters, at address 0x08 0xnn 0xpp
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum XGPartParameter {
+    BankSelectMSB,
+    BankSelectLSB,
+    ProgramNumber,
+    /// Channel the part listens on, 0-15, or 0x7F for off
+    ReceiveChannel,
+    /// 0 for a normal part, 1 for a drum part, 2-5 for drum setups 1-4
+    PartMode,
+    /// Semitones, centered at 0x40
+    NoteShift,
+    Volume,
+    /// 0 is random, otherwise centered at 0x40
+    Pan,
+    NoteLimitLow,
+    NoteLimitHigh,
+    DryLevel,
+    ChorusSendLevel,
+    ReverbSendLevel,
+    VariationSendLevel
+}
+
+/// Well known XG parameters, set with a parameter change message
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum XGParameter {
+    /// XG System On, to initialize an XG module
+    SystemOn,
+    AllParameterReset,
+    ReverbType(XGEffectType),
+    ChorusType(XGEffectType),
+    /// Multi part parameter. Parts are counted from 0.
+    Part { part: u8, parameter: XGPartParameter, value: U7 }
+}
+
+impl XGEffectType {
+    pub const NO_EFFECT: XGEffectType = XGEffectType::new(0x00, 0x00);
+    pub const HALL1: XGEffectType = XGEffectType::new(0x01, 0x00);
+    pub const HALL2: XGEffectType = XGEffectType::new(0x01, 0x01);
+    pub const ROOM1: XGEffectType = XGEffectType::new(0x02, 0x00);
+    pub const ROOM2: XGEffectType = XGEffectType::new(0x02, 0x01);
+    pub const ROOM3: XGEffectType = XGEffectType::new(0x02, 0x02);
+    pub const STAGE1: XGEffectType = XGEffectType::new(0x03, 0x00);
+    pub const STAGE2: XGEffectType = XGEffectType::new(0x03, 0x01);
+    pub const PLATE: XGEffectType = XGEffectType::new(0x04, 0x00);
+    pub const CHORUS1: XGEffectType = XGEffectType::new(0x41, 0x00);
+    pub const CHORUS2: XGEffectType = XGEffectType::new(0x41, 0x01);
+    pub const CELESTE1: XGEffectType = XGEffectType::new(0x42, 0x00);
+    pub const CELESTE2: XGEffectType = XGEffectType::new(0x42, 0x01);
+    pub const FLANGER1: XGEffectType = XGEffectType::new(0x43, 0x00);
+    pub const FLANGER2: XGEffectType = XGEffectType::new(0x43, 0x01);
+
+    pub const fn new(effect_type: u8, variation: u8) -> XGEffectType {
+        XGEffectType { effect_type, variation }
+    }
+}
+
+impl XGPartParameter {
+    fn from_address(address: u8) -> Option<XGPartParameter> {
+        match address {
+            0x01 => Some(XGPartParameter::BankSelectMSB),
+            0x02 => Some(XGPartParameter::BankSelectLSB),
+            0x03 => Some(XGPartParameter::ProgramNumber),
+            0x04 => Some(XGPartParameter::ReceiveChannel),
+            0x07 => Some(XGPartParameter::PartMode),
+            0x08 => Some(XGPartParameter::NoteShift),
+            0x0B => Some(XGPartParameter::Volume),
+            0x0E => Some(XGPartParameter::Pan),
+            0x0F => Some(XGPartParameter::NoteLimitLow),
+            0x10 => Some(XGPartParameter::NoteLimitHigh),
+            0x11 => Some(XGPartParameter::DryLevel),
+            0x12 => Some(XGPartParameter::ChorusSendLevel),
+            0x13 => Some(XGPartParameter::ReverbSendLevel),
+            0x14 => Some(XGPartParameter::VariationSendLevel),
+            _ => None
+        }
+    }
+
+    fn get_address(&self) -> u8 {
+        match self {
+            XGPartParameter::BankSelectMSB => 0x01,
+            XGPartParameter::BankSelectLSB => 0x02,
+            XGPartParameter::ProgramNumber => 0x03,
+            XGPartParameter::ReceiveChannel => 0x04,
+            XGPartParameter::PartMode => 0x07,
+            XGPartParameter::NoteShift => 0x08,
+            XGPartParameter::Volume => 0x0B,
+            XGPartParameter::Pan => 0x0E,
+            XGPartParameter::NoteLimitLow => 0x0F,
+            XGPartParameter::NoteLimitHigh => 0x10,
+            XGPartParameter::DryLevel => 0x11,
+            XGPartParameter::ChorusSendLevel => 0x12,
+            XGPartParameter::ReverbSendLevel => 0x13,
+            XGPartParameter::VariationSendLevel => 0x14
+        }
+    }
+}
+
+impl XGMessage {
+    /// Build a parameter change message that sets a well known parameter
+    pub fn from_parameter(device_number: u8, parameter: XGParameter) -> Result<XGMessage, ApresError> {
+        if device_number > 0x0F {
+            return Err(ApresError::ValueOutOfRange(device_number as u32, 0x0F));
+        }
+
+        let (address, data) = match parameter {
+            XGParameter::SystemOn => {
+                (SYSTEM_ON_ADDRESS, vec![0x00])
+            }
+            XGParameter::AllParameterReset => {
+                (ALL_PARAMETER_RESET_ADDRESS, vec![0x00])
+            }
+            XGParameter::ReverbType(effect) => {
+                (REVERB_TYPE_ADDRESS, vec![effect.effect_type & 0x7F, effect.variation & 0x7F])
+            }
+            XGParameter::ChorusType(effect) => {
+                (CHORUS_TYPE_ADDRESS, vec![effect.effect_type & 0x7F, effect.variation & 0x7F])
+            }
+            XGParameter::Part { part, parameter, value } => {
+                if part > 0x3F {
+                    return Err(ApresError::ValueOutOfRange(part as u32, 0x3F));
+                }
+                ([0x08, part, parameter.get_address()], vec![value.get()])
+            }
+        };
+
+        Ok(XGMessage::ParameterChange { device_number, address, data })
+    }
+
+    /// Get the well known parameter a parameter change message sets, if any
+    pub fn get_parameter(&self) -> Option<XGParameter> {
+        let (address, data) = match self {
+            XGMessage::ParameterChange { address, data, .. } => (*address, data.as_slice()),
+            XGMessage::ParameterRequest { .. } => {
+                return None;
+            }
+        };
+
+        match (address, data) {
+            (SYSTEM_ON_ADDRESS, [0x00]) => {
+                Some(XGParameter::SystemOn)
+            }
+            (ALL_PARAMETER_RESET_ADDRESS, [0x00]) => {
+                Some(XGParameter::AllParameterReset)
+            }
+            (REVERB_TYPE_ADDRESS, [effect_type, variation]) => {
+                Some(XGParameter::ReverbType(XGEffectType::new(*effect_type, *variation)))
+            }
+            (CHORUS_TYPE_ADDRESS, [effect_type, variation]) => {
+                Some(XGParameter::ChorusType(XGEffectType::new(*effect_type, *variation)))
+            }
+            ([0x08, part, parameter], [value]) => {
+                Some(XGParameter::Part {
+                    part,
+                    parameter: XGPartParameter::from_address(parameter)?,
+                    value: U7::new(*value).ok()?
+                })
+            }
+            _ => {
+                None
+            }
+        }
+    }
+
+    pub fn get_device_number(&self) -> u8 {
+        match self {
+            XGMessage::ParameterChange { device_number, .. } | XGMessage::ParameterRequest { device_number, .. } => *device_number
+        }
+    }
+
+    /// Parse a system exclusive message's data (not including the 0xF0 and 0xF7 bytes)
+    pub fn from_bytes(bytes: &[u8]) -> Result<XGMessage, ApresError> {
+        if bytes.len() < 6 || bytes[0] != YAMAHA_ID || bytes[2] != XG_MODEL_ID || bytes.iter().any(|byte| *byte > 0x7F) {
+            return Err(ApresError::InvalidBytes(bytes.to_vec()));
+        }
+
+        let device_number = bytes[1] & 0x0F;
+        let address = [bytes[3], bytes[4], bytes[5]];
+        match (bytes[1] & 0x70, &bytes[6..]) {
+            (PARAMETER_CHANGE, data) if !data.is_empty() => {
+                Ok(XGMessage::ParameterChange { device_number, address, data: data.to_vec() })
+            }
+            (PARAMETER_REQUEST, []) => {
+                Ok(XGMessage::ParameterRequest { device_number, address })
+            }
+            _ => {
+                Err(ApresError::InvalidBytes(bytes.to_vec()))
+            }
+        }
+    }
+
+    /// Get the system exclusive message's data (not including the 0xF0 and 0xF7 bytes)
+    pub fn as_bytes(&self) -> Vec<u8> {
+        match self {
+            XGMessage::ParameterChange { device_number, address, data } => {
+                let mut output = vec![YAMAHA_ID, PARAMETER_CHANGE | (device_number & 0x0F), XG_MODEL_ID];
+                output.extend(address.iter());
+                output.extend(data.iter());
+                output
+            }
+            XGMessage::ParameterRequest { device_number, address } => {
+                let mut output = vec![YAMAHA_ID, PARAMETER_REQUEST | (device_number & 0x0F), XG_MODEL_ID];
+                output.extend(address.iter());
+                output
+            }
+        }
+    }
+}
+
+impl From<XGMessage> for MIDIEvent {
+    fn from(message: XGMessage) -> MIDIEvent {
+        MIDIEvent::SystemExclusive(message.as_bytes())
+    }
+}
+
+impl TryFrom<MIDIEvent> for XGMessage {
+    type Error = MIDIEvent;
+    fn try_from(event: MIDIEvent) -> Result<XGMessage, MIDIEvent> {
+        match &event {
+            MIDIEvent::SystemExclusive(data) => {
+                XGMessage::from_bytes(data).map_err(|_| event)
+            }
+            _ => {
+                Err(event)
+            }
+        }
+    }
+}